@@ -20,8 +20,13 @@ struct FlagToken(Flag);
 impl ToTokens for FlagToken {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         let f = match self.0 {
+            Flag::Admin => "Admin",
+            Flag::Asking => "Asking",
             Flag::Fast => "Fast",
+            Flag::NoAuth => "NoAuth",
+            Flag::ReadOnly => "ReadOnly",
             Flag::Sentinel => "Sentinel",
+            Flag::Write => "Write",
         };
 
         let result = format_ident!("{}", f);
@@ -37,7 +42,15 @@ struct AclCategoryToken(AclCategory);
 impl ToTokens for AclCategoryToken {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         let c = match self.0 {
+            AclCategory::Admin => "Admin",
             AclCategory::Connection => "Connection",
+            AclCategory::Dangerous => "Dangerous",
+            AclCategory::Fast => "Fast",
+            AclCategory::KeySpace => "KeySpace",
+            AclCategory::PubSub => "PubSub",
+            AclCategory::Read => "Read",
+            AclCategory::Slow => "Slow",
+            AclCategory::Write => "Write",
         };
 
         let result = format_ident!("{}", c);
@@ -276,7 +289,7 @@ impl FromMeta for Integer {
                 return Err(meta.value_span().error("expected positive or negative integer"));
             };
 
-            return Ok(Integer(v.base10_parse()?))
+            return Ok(Integer(-v.base10_parse::<i64>()?))
         } else if let syn::Lit::Int(v) = meta.lit()? {
             return Ok(Integer(v.base10_parse()?))
         }
@@ -307,6 +320,14 @@ fn command_attribute(attr: proc_macro2::TokenStream, item: proc_macro2::TokenStr
     let command_handler: syn::ItemFn = syn::parse2(item).unwrap();
 
     let function_name = &command_handler.sig.ident;
+
+    // Handlers which take the server and the calling client as well as the arguments need
+    // more than the keyspace.
+    let handler = if command_handler.sig.inputs.len() == 3 {
+        quote! { Handler::Server(#function_name) }
+    } else {
+        quote! { Handler::Keyspace(#function_name) }
+    };
     let struct_name: proc_macro2::TokenStream = function_name.to_string().to_case(Case::UpperCamel).parse().unwrap(); 
 
     let command_name = attribute.name;
@@ -320,7 +341,7 @@ fn command_attribute(attr: proc_macro2::TokenStream, item: proc_macro2::TokenStr
 
     Ok(quote! {
         use sider_command::*;
-        use super::base::Command;
+        use super::base::{Command, Handler};
 
         #command_handler
 
@@ -330,7 +351,7 @@ fn command_attribute(attr: proc_macro2::TokenStream, item: proc_macro2::TokenStr
             pub(crate) const fn into_command() -> Command<'a> {
                 Command {
                     name: #command_name,
-                    handler: #function_name,
+                    handler: #handler,
                     arity: #command_arity,
                     flags: #command_flags,
                     first_key: #first_key,
@@ -97,16 +97,28 @@ impl TryFrom<String> for CommandTip {
 
 #[derive(Debug)]
 pub enum Flag {
+    Admin,
+    /// The command is accepted for a slot being imported without a preceding ASKING.
+    Asking,
     Fast,
-    Sentinel
+    /// The command can be run before the client has authenticated.
+    NoAuth,
+    ReadOnly,
+    Sentinel,
+    Write,
 }
 
 
 impl Flag {
     pub fn to_string(&self) -> String {
         match self {
+            Self::Admin => "admin",
+            Self::Asking => "asking",
             Self::Fast => "fast",
+            Self::NoAuth => "no_auth",
+            Self::ReadOnly => "readonly",
             Self::Sentinel => "sentinel",
+            Self::Write => "write",
         }.to_string()
     }
 }
@@ -117,8 +129,13 @@ impl TryFrom<String> for Flag {
 
     fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
         Ok(match value.as_str() {
+            "admin" => Self::Admin,
+            "asking" => Self::Asking,
             "fast" => Self::Fast,
+            "no_auth" => Self::NoAuth,
+            "readonly" => Self::ReadOnly,
             "sentinel" => Self::Sentinel,
+            "write" => Self::Write,
             _ => return Err("not a valid value".into())
         })
     }
@@ -129,14 +146,30 @@ impl TryFrom<String> for Flag {
 
 #[derive(Debug)]
 pub enum AclCategory {
+    Admin,
     Connection,
+    Dangerous,
+    Fast,
+    KeySpace,
+    PubSub,
+    Read,
+    Slow,
+    Write,
 }
 
 
 impl AclCategory {
     pub fn to_string(&self) -> String {
         match self {
+            Self::Admin => "@admin",
             Self::Connection => "@connection",
+            Self::Dangerous => "@dangerous",
+            Self::Fast => "@fast",
+            Self::KeySpace => "@keyspace",
+            Self::PubSub => "@pubsub",
+            Self::Read => "@read",
+            Self::Slow => "@slow",
+            Self::Write => "@write",
         }.to_string()
     }
 }
@@ -147,7 +180,15 @@ impl TryFrom<String> for AclCategory {
 
     fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
         Ok(match value.as_str() {
+            "admin" => Self::Admin,
             "connection" => Self::Connection,
+            "dangerous" => Self::Dangerous,
+            "fast" => Self::Fast,
+            "keyspace" => Self::KeySpace,
+            "pubsub" => Self::PubSub,
+            "read" => Self::Read,
+            "slow" => Self::Slow,
+            "write" => Self::Write,
             _ => return Err("not a valid value".into())
         })
     }
@@ -8,6 +8,10 @@ pub enum RESPType<T> {
     BulkString(T),
     Array(Vec<RESPType<T>>),
     Null,
+    /// A RESP3 map. Only clients which have switched to RESP3 with HELLO are sent these.
+    Map(Vec<(RESPType<T>, RESPType<T>)>),
+    /// A RESP3 push message, which is sent outside of the request and reply cycle.
+    Push(Vec<RESPType<T>>),
 }
 
 
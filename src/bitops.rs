@@ -0,0 +1,378 @@
+
+use crate::util::from_decimal_bytes;
+
+
+/// Whether the range arguments to BITCOUNT and BITPOS refer to bytes or bits.
+#[derive(PartialEq, Clone, Copy)]
+pub enum BitUnit {
+    Byte,
+    Bit,
+}
+
+impl BitUnit {
+    pub fn parse(b: &[u8]) -> Option<Self> {
+        match &b.to_ascii_uppercase()[..] {
+            b"BYTE" => Some(Self::Byte),
+            b"BIT" => Some(Self::Bit),
+            _ => None,
+        }
+    }
+}
+
+
+/// Return the bit at `offset`, where bit zero is the most significant bit of the first byte.
+/// Bits past the end of the string are zero.
+pub fn get_bit(bytes: &[u8], offset: u64) -> u8 {
+    match bytes.get((offset >> 3) as usize) {
+        Some(b) => (b >> (7 - (offset & 7))) & 1,
+        None => 0,
+    }
+}
+
+
+/// Set the bit at `offset`, returning its previous value. The caller must make sure that the
+/// slice is long enough.
+pub fn set_bit(bytes: &mut [u8], offset: u64, value: u8) -> u8 {
+    let byte = &mut bytes[(offset >> 3) as usize];
+    let shift = 7 - (offset & 7);
+    let previous = (*byte >> shift) & 1;
+
+    *byte = (*byte & !(1 << shift)) | ((value & 1) << shift);
+
+    previous
+}
+
+
+/// Convert an inclusive start and end index, either of which may be negative to count back
+/// from the end, into a range within `len`. Returns None if the range is empty.
+fn normalize_range(start: i64, end: i64, len: i64) -> Option<(u64, u64)> {
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { (len + end).max(0) } else { end.min(len - 1) };
+
+    if start > end || len == 0 {
+        None
+    } else {
+        Some((start as u64, end as u64))
+    }
+}
+
+
+/// Count the set bits between `start` and `end` inclusive, or in the whole string if no range
+/// is given.
+pub fn bit_count(bytes: &[u8], range: Option<(i64, i64, BitUnit)>) -> u64 {
+    let Some((start, end, unit)) = range else {
+        return count_ones(bytes);
+    };
+
+    match unit {
+        BitUnit::Byte => match normalize_range(start, end, bytes.len() as i64) {
+            Some((start, end)) => count_ones(&bytes[start as usize..=end as usize]),
+            None => 0,
+        },
+        BitUnit::Bit => match normalize_range(start, end, bytes.len() as i64 * 8) {
+            Some((start, end)) => {
+                let first_byte = (start >> 3) as usize;
+                let last_byte = (end >> 3) as usize;
+
+                let mut total = count_ones(&bytes[first_byte..=last_byte]);
+
+                // Remove the bits before the start in the first byte, and after the end in
+                // the last byte.
+                let before_start = 0xffu8.checked_shl(8 - (start & 7) as u32).unwrap_or(0);
+                let after_end = 0xffu8.checked_shr((end & 7) as u32 + 1).unwrap_or(0);
+
+                total -= (bytes[first_byte] & before_start).count_ones() as u64;
+                total -= (bytes[last_byte] & after_end).count_ones() as u64;
+
+                total
+            },
+            None => 0,
+        },
+    }
+}
+
+
+fn count_ones(bytes: &[u8]) -> u64 {
+    bytes.iter().map(|b| b.count_ones() as u64).sum()
+}
+
+
+/// Find the position of the first bit with the given value, returning -1 if there is none.
+///
+/// As in Redis, when looking for a clear bit without an explicit end, the string is treated
+/// as though it were padded with zeros on the right, so the first bit past the end is returned
+/// if every bit in the range is set.
+pub fn bit_pos(bytes: &[u8], bit: u8, start: Option<i64>, end: Option<i64>, unit: BitUnit) -> i64 {
+    let len = match unit {
+        BitUnit::Byte => bytes.len() as i64,
+        BitUnit::Bit => bytes.len() as i64 * 8,
+    };
+
+    let end_is_implicit = end.is_none();
+
+    let Some((start, end)) = normalize_range(start.unwrap_or(0), end.unwrap_or(-1), len) else {
+        return -1;
+    };
+
+    let (first, last) = match unit {
+        BitUnit::Byte => (start * 8, end * 8 + 7),
+        BitUnit::Bit => (start, end),
+    };
+
+    let mut position = first;
+
+    while position <= last {
+        // Skip over whole bytes which cannot contain the bit we are looking for.
+        if position & 7 == 0 && position + 7 <= last {
+            let byte = bytes[(position >> 3) as usize];
+
+            if (bit == 1 && byte == 0) || (bit == 0 && byte == 0xff) {
+                position += 8;
+                continue;
+            }
+        }
+
+        if get_bit(bytes, position) == bit {
+            return position as i64;
+        }
+
+        position += 1;
+    }
+
+    if bit == 0 && end_is_implicit {
+        return last as i64 + 1;
+    }
+
+    -1
+}
+
+
+/// The operations supported by BITOP.
+#[derive(PartialEq, Clone, Copy)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+impl BitOperation {
+    pub fn parse(b: &[u8]) -> Option<Self> {
+        match &b.to_ascii_uppercase()[..] {
+            b"AND" => Some(Self::And),
+            b"OR" => Some(Self::Or),
+            b"XOR" => Some(Self::Xor),
+            b"NOT" => Some(Self::Not),
+            _ => None,
+        }
+    }
+}
+
+
+/// Combine the sources with a bitwise operation. Shorter sources are treated as though they
+/// were padded with zero bytes to the length of the longest.
+pub fn bit_op(operation: BitOperation, sources: &[&[u8]]) -> Vec<u8> {
+    let len = sources.iter().map(|s| s.len()).max().unwrap_or(0);
+
+    if operation == BitOperation::Not {
+        return sources[0].iter().map(|b| !b).collect();
+    }
+
+    (0..len).map(|i| {
+        let mut bytes = sources.iter().map(|s| s.get(i).copied().unwrap_or(0));
+        let first = bytes.next().unwrap_or(0);
+
+        bytes.fold(first, |acc, b| match operation {
+            BitOperation::And => acc & b,
+            BitOperation::Or => acc | b,
+            BitOperation::Xor => acc ^ b,
+            BitOperation::Not => unreachable!(),
+        })
+    }).collect()
+}
+
+
+/// The type of an integer field accessed by BITFIELD, such as `i5` or `u16`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl FieldType {
+    /// Parse a field type. Signed fields can be up to 64 bits wide, and unsigned fields up to
+    /// 63 bits, so that every value fits in an i64.
+    pub fn parse(b: &[u8]) -> Option<Self> {
+        let signed = match b.first()? {
+            b'i' | b'I' => true,
+            b'u' | b'U' => false,
+            _ => return None,
+        };
+
+        let bits = from_decimal_bytes(&b[1..]).ok()?;
+
+        if bits < 1 || (signed && bits > 64) || (!signed && bits > 63) {
+            return None;
+        }
+
+        Some(FieldType { signed, bits: bits as u32 })
+    }
+
+    fn max(&self) -> i128 {
+        if self.signed {
+            (1i128 << (self.bits - 1)) - 1
+        } else {
+            (1i128 << self.bits) - 1
+        }
+    }
+
+    fn min(&self) -> i128 {
+        if self.signed {
+            -(1i128 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+}
+
+
+/// Parse a BITFIELD offset. Offsets prefixed with `#` are multiplied by the width of the field.
+pub fn parse_field_offset(b: &[u8], field: FieldType) -> Option<u64> {
+    let (multiplier, b) = match b.first() {
+        Some(b'#') => (field.bits as i64, &b[1..]),
+        _ => (1, b),
+    };
+
+    let offset = from_decimal_bytes(b).ok()?.checked_mul(multiplier)?;
+
+    u64::try_from(offset).ok()
+}
+
+
+/// How BITFIELD handles values that do not fit in the field.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Overflow {
+    /// Wrap around, taking the low bits of the value.
+    Wrap,
+    /// Saturate at the minimum or maximum value of the field.
+    Sat,
+    /// Do not perform the operation.
+    Fail,
+}
+
+impl Overflow {
+    pub fn parse(b: &[u8]) -> Option<Self> {
+        match &b.to_ascii_uppercase()[..] {
+            b"WRAP" => Some(Self::Wrap),
+            b"SAT" => Some(Self::Sat),
+            b"FAIL" => Some(Self::Fail),
+            _ => None,
+        }
+    }
+
+    /// Fit a value into a field, returning None if it does not fit and the behaviour is Fail.
+    pub fn apply(&self, field: FieldType, value: i128) -> Option<i64> {
+        if value >= field.min() && value <= field.max() {
+            return Some(value as i64);
+        }
+
+        match self {
+            Self::Fail => None,
+            Self::Sat => Some(value.clamp(field.min(), field.max()) as i64),
+            Self::Wrap => Some(sign_extend((value as u64) & mask(field.bits), field)),
+        }
+    }
+}
+
+
+fn mask(bits: u32) -> u64 {
+    if bits == 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+
+fn sign_extend(value: u64, field: FieldType) -> i64 {
+    if field.signed && field.bits < 64 && value & (1 << (field.bits - 1)) != 0 {
+        (value | !mask(field.bits)) as i64
+    } else {
+        value as i64
+    }
+}
+
+
+/// Read an integer field starting at the bit `offset`.
+pub fn get_field(bytes: &[u8], offset: u64, field: FieldType) -> i64 {
+    let mut value: u64 = 0;
+
+    for i in 0..field.bits as u64 {
+        value = (value << 1) | get_bit(bytes, offset + i) as u64;
+    }
+
+    sign_extend(value, field)
+}
+
+
+/// Write an integer field starting at the bit `offset`. The caller must make sure that the
+/// slice is long enough.
+pub fn set_field(bytes: &mut [u8], offset: u64, field: FieldType, value: i64) {
+    let value = value as u64;
+
+    for i in 0..field.bits as u64 {
+        set_bit(bytes, offset + i, ((value >> (field.bits as u64 - 1 - i)) & 1) as u8);
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::bitops::*;
+
+    #[test]
+    fn test_bit_count_ranges() {
+        let bytes = b"foobar";
+
+        assert_eq!(bit_count(bytes, None), 26);
+        assert_eq!(bit_count(bytes, Some((0, 0, BitUnit::Byte))), 4);
+        assert_eq!(bit_count(bytes, Some((1, 1, BitUnit::Byte))), 6);
+        assert_eq!(bit_count(bytes, Some((5, 30, BitUnit::Bit))), 17);
+    }
+
+    #[test]
+    fn test_bit_pos() {
+        assert_eq!(bit_pos(&[0xff, 0xf0, 0x00], 0, None, None, BitUnit::Byte), 12);
+        assert_eq!(bit_pos(&[0x00, 0xff, 0xf0], 1, Some(2), Some(-1), BitUnit::Byte), 16);
+        assert_eq!(bit_pos(&[0x00, 0xff, 0xf0], 1, Some(7), Some(15), BitUnit::Bit), 8);
+        assert_eq!(bit_pos(&[0xff], 0, None, None, BitUnit::Byte), 8);
+        assert_eq!(bit_pos(&[0xff], 0, Some(0), Some(0), BitUnit::Byte), -1);
+    }
+
+    #[test]
+    fn test_fields() {
+        let mut bytes = vec![0; 8];
+        let i8 = FieldType::parse(b"i8").unwrap();
+        let u4 = FieldType::parse(b"u4").unwrap();
+
+        set_field(&mut bytes, 4, i8, -100);
+        assert_eq!(get_field(&bytes, 4, i8), -100);
+
+        set_field(&mut bytes, 13, u4, 15);
+        assert_eq!(get_field(&bytes, 13, u4), 15);
+        assert_eq!(get_field(&bytes, 4, i8), -100);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let u2 = FieldType::parse(b"u2").unwrap();
+        let i8 = FieldType::parse(b"i8").unwrap();
+
+        assert_eq!(Overflow::Wrap.apply(u2, 5), Some(1));
+        assert_eq!(Overflow::Sat.apply(u2, 5), Some(3));
+        assert_eq!(Overflow::Fail.apply(u2, 5), None);
+        assert_eq!(Overflow::Wrap.apply(i8, 128), Some(-128));
+        assert_eq!(Overflow::Sat.apply(i8, -200), Some(-128));
+    }
+}
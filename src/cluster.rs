@@ -0,0 +1,825 @@
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use log::{info, warn};
+use mio::{Interest, Registry, Token};
+use mio::net::{TcpListener, TcpStream};
+
+use sider_command::RESPType;
+use crate::parser::QueryBuffer;
+use crate::replication::generate_id;
+use crate::serializer::serialize;
+
+
+pub const SLOTS: usize = 16384;
+/// The cluster bus listens on the client port plus this offset, as in Redis.
+pub const BUS_PORT_OFFSET: u16 = 10000;
+/// Bus links are given tokens from this value upwards, well clear of the client tokens.
+pub const FIRST_LINK: usize = usize::MAX / 2;
+/// How often each node is pinged.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// A link is dropped if a ping goes unanswered for this long, and a node we were asked to
+/// meet is forgotten if it never answers.
+const NODE_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long to wait before reconnecting to a node after its link is lost.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+
+/// The CRC16 variant used by Redis Cluster, XMODEM (polynomial 0x1021, no reflection, zero
+/// initial value).
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+
+    for b in bytes {
+        crc ^= (*b as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+
+    crc
+}
+
+
+/// Find the hash slot of a key. If the key contains a non-empty hash tag, the part between the
+/// first `{` and the following `}`, only the tag is hashed. This lets related keys be placed in
+/// the same slot.
+pub fn key_slot(key: &[u8]) -> u16 {
+    let tag = key.iter().position(|b| *b == b'{').and_then(|start| {
+        let len = key[start + 1..].iter().position(|b| *b == b'}')?;
+        (len > 0).then(|| &key[start + 1..start + 1 + len])
+    });
+
+    crc16(tag.unwrap_or(key)) % SLOTS as u16
+}
+
+
+fn unix_millis(instant: Option<Instant>) -> u128 {
+    let Some(instant) = instant else {
+        return 0;
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    now.saturating_sub(instant.elapsed()).as_millis()
+}
+
+
+#[derive(Debug)]
+pub struct ClusterNode {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    pub bus_port: u16,
+    /// The epoch of the node's claims on its slots. A claim with a greater epoch wins.
+    pub config_epoch: u64,
+    /// Set for a node we were asked to meet, until it answers and we learn its real ID.
+    pub handshake: bool,
+    created: Instant,
+    /// The outbound link to the node.
+    link: Option<usize>,
+    ping_sent: Option<Instant>,
+    pong_received: Option<Instant>,
+    retry_at: Instant,
+}
+
+
+impl ClusterNode {
+    fn new(id: String, ip: String, port: u16, bus_port: u16) -> Self {
+        ClusterNode {
+            id,
+            ip,
+            port,
+            bus_port,
+            config_epoch: 0,
+            handshake: false,
+            created: Instant::now(),
+            link: None,
+            ping_sent: None,
+            pong_received: None,
+            retry_at: Instant::now(),
+        }
+    }
+
+    /// The address clients should be redirected to.
+    pub fn endpoint(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+}
+
+
+/// A connection on the cluster bus. We open a link to every other node to send it pings, and
+/// answer the pings arriving on the links other nodes open to us.
+struct Link {
+    stream: TcpStream,
+    peer: SocketAddr,
+    /// The node an outbound link was opened to. Inbound links are not tied to a node.
+    node: Option<String>,
+    connected: bool,
+    input: QueryBuffer,
+    output: Vec<u8>,
+}
+
+
+impl Link {
+    fn send(&mut self, message: &Message) -> io::Result<()> {
+        serialize(&message.to_resp(), &mut self.output)?;
+
+        self.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.connected {
+            return Ok(());
+        }
+
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.output.drain(..n);
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle the socket becoming writable, which is how a non-blocking connect completes.
+    fn writable(&mut self) -> io::Result<()> {
+        if !self.connected {
+            if let Some(e) = self.stream.take_error()? {
+                return Err(e);
+            }
+
+            match self.stream.peer_addr() {
+                Ok(_) => self.connected = true,
+                Err(e) if e.kind() == ErrorKind::NotConnected => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.flush()
+    }
+
+    fn read(&mut self) -> io::Result<bool> {
+        if !self.connected {
+            return Ok(true);
+        }
+
+        let mut buffer = [0; 1024 * 16];
+
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.input.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MessageKind {
+    Ping,
+    Pong,
+    /// A ping which also asks the receiver to add us to its nodes.
+    Meet,
+}
+
+
+/// What a message tells us about another node.
+#[derive(Debug, PartialEq)]
+struct Gossip {
+    id: String,
+    ip: String,
+    port: u16,
+    bus_port: u16,
+}
+
+
+/// A cluster bus message. Every message describes the sender and the slots it claims, and
+/// gossips about the other nodes the sender knows, which is how nodes find each other.
+///
+/// Messages are sent as RESP arrays, rather than the binary format Redis uses.
+#[derive(Debug, PartialEq)]
+struct Message {
+    kind: MessageKind,
+    sender: String,
+    port: u16,
+    bus_port: u16,
+    config_epoch: u64,
+    current_epoch: u64,
+    /// A bitmap of the slots the sender claims.
+    slots: Bytes,
+    gossip: Vec<Gossip>,
+}
+
+
+impl Message {
+    fn to_resp(&self) -> RESPType<Bytes> {
+        let kind = match self.kind {
+            MessageKind::Ping => "PING",
+            MessageKind::Pong => "PONG",
+            MessageKind::Meet => "MEET",
+        };
+
+        RESPType::Array(vec![
+            RESPType::BulkString(kind.into()),
+            RESPType::BulkString(self.sender.clone().into()),
+            RESPType::Integer(self.port as i64),
+            RESPType::Integer(self.bus_port as i64),
+            RESPType::Integer(self.config_epoch as i64),
+            RESPType::Integer(self.current_epoch as i64),
+            RESPType::BulkString(self.slots.clone()),
+            RESPType::Array(self.gossip.iter().map(|g| RESPType::Array(vec![
+                RESPType::BulkString(g.id.clone().into()),
+                RESPType::BulkString(g.ip.clone().into()),
+                RESPType::Integer(g.port as i64),
+                RESPType::Integer(g.bus_port as i64),
+            ])).collect()),
+        ])
+    }
+
+    fn from_resp(message: RESPType<Bytes>) -> Option<Self> {
+        let RESPType::Array(fields) = message else {
+            return None;
+        };
+
+        let [kind, sender, port, bus_port, config_epoch, current_epoch, slots, gossip] = <[RESPType<Bytes>; 8]>::try_from(fields).ok()?;
+
+        let kind = match &string(kind)?[..] {
+            "PING" => MessageKind::Ping,
+            "PONG" => MessageKind::Pong,
+            "MEET" => MessageKind::Meet,
+            _ => return None,
+        };
+
+        let RESPType::BulkString(slots) = slots else {
+            return None;
+        };
+
+        let RESPType::Array(gossip) = gossip else {
+            return None;
+        };
+
+        if slots.len() != SLOTS / 8 {
+            return None;
+        }
+
+        let gossip = gossip.into_iter().map(|g| {
+            let RESPType::Array(g) = g else {
+                return None;
+            };
+
+            let [id, ip, port, bus_port] = <[RESPType<Bytes>; 4]>::try_from(g).ok()?;
+
+            Some(Gossip { id: string(id)?, ip: string(ip)?, port: integer(port)?, bus_port: integer(bus_port)? })
+        }).collect::<Option<_>>()?;
+
+        Some(Message {
+            kind,
+            sender: string(sender)?,
+            port: integer(port)?,
+            bus_port: integer(bus_port)?,
+            config_epoch: integer(config_epoch)?,
+            current_epoch: integer(current_epoch)?,
+            slots,
+            gossip,
+        })
+    }
+}
+
+
+fn string(value: RESPType<Bytes>) -> Option<String> {
+    match value {
+        RESPType::BulkString(s) => String::from_utf8(s.to_vec()).ok(),
+        _ => None,
+    }
+}
+
+
+fn integer<T: TryFrom<i64>>(value: RESPType<Bytes>) -> Option<T> {
+    match value {
+        RESPType::Integer(i) => T::try_from(i).ok(),
+        _ => None,
+    }
+}
+
+
+/// Our view of the cluster: the nodes we know about, which node serves each hash slot, and
+/// the slots being moved between nodes.
+pub struct Cluster {
+    pub myself: String,
+    nodes: HashMap<String, ClusterNode>,
+    /// The ID of the node serving each slot.
+    owners: Vec<Option<String>>,
+    /// Slots we own which are being moved to another node, and which node that is.
+    pub migrating: HashMap<u16, String>,
+    /// Slots which are being moved to us, and which node they are coming from.
+    pub importing: HashMap<u16, String>,
+    /// The greatest epoch seen in the cluster.
+    pub current_epoch: u64,
+    listener: Option<TcpListener>,
+    links: Vec<Option<Link>>,
+}
+
+
+impl Cluster {
+    pub fn new(port: u16) -> Self {
+        let id = generate_id();
+        let myself = ClusterNode::new(id.clone(), "127.0.0.1".into(), port, port.wrapping_add(BUS_PORT_OFFSET));
+
+        Cluster {
+            myself: id.clone(),
+            nodes: HashMap::from([(id, myself)]),
+            owners: vec![None; SLOTS],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+            current_epoch: 0,
+            listener: None,
+            links: Vec::new(),
+        }
+    }
+
+    /// Start listening on the cluster bus.
+    pub fn listen(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        let myself = self.myself();
+        let port = myself.port.checked_add(BUS_PORT_OFFSET).ok_or(io::Error::new(ErrorKind::InvalidInput, "the port is too high to leave room for the cluster bus port"))?;
+
+        let mut listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port)))?;
+        registry.register(&mut listener, token, Interest::READABLE)?;
+
+        self.listener = Some(listener);
+
+        Ok(())
+    }
+
+    pub fn myself(&self) -> &ClusterNode {
+        &self.nodes[&self.myself]
+    }
+
+    fn myself_mut(&mut self) -> &mut ClusterNode {
+        self.nodes.get_mut(&self.myself).unwrap()
+    }
+
+    pub fn node(&self, id: &str) -> Option<&ClusterNode> {
+        self.nodes.get(id)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &ClusterNode> {
+        self.nodes.values()
+    }
+
+    pub fn owner(&self, slot: u16) -> Option<&ClusterNode> {
+        self.nodes.get(self.owners[slot as usize].as_ref()?)
+    }
+
+    pub fn set_owner(&mut self, slot: u16, id: String) {
+        self.owners[slot as usize] = Some(id);
+    }
+
+    /// Return the contiguous ranges of slots served by a node.
+    pub fn slot_ranges(&self, id: &str) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = vec![];
+
+        for (slot, owner) in self.owners.iter().enumerate() {
+            if owner.as_deref() != Some(id) {
+                continue;
+            }
+
+            match ranges.last_mut() {
+                Some((_, end)) if *end as usize + 1 == slot => *end = slot as u16,
+                _ => ranges.push((slot as u16, slot as u16)),
+            }
+        }
+
+        ranges
+    }
+
+    /// Claim a newer epoch than any in the cluster for our slot configuration, so that our
+    /// claims win over older ones. Redis does the same when it takes over an imported slot.
+    pub fn bump_epoch(&mut self) {
+        self.current_epoch += 1;
+
+        let epoch = self.current_epoch;
+        self.myself_mut().config_epoch = epoch;
+    }
+
+    /// Add a node we were asked to meet. We learn its ID once it answers.
+    pub fn meet(&mut self, ip: String, port: u16, bus_port: u16) {
+        let mut node = ClusterNode::new(generate_id(), ip, port, bus_port);
+        node.handshake = true;
+
+        info!("Meeting the node at {}:{}.", node.ip, node.bus_port);
+
+        self.nodes.insert(node.id.clone(), node);
+    }
+
+    /// The CLUSTER NODES description of every node.
+    pub fn describe_nodes(&self) -> String {
+        let mut out = String::new();
+
+        for node in self.nodes.values() {
+            let myself = node.id == self.myself;
+
+            let flags = match (myself, node.handshake) {
+                (true, _) => "myself,master",
+                (false, true) => "handshake",
+                (false, false) => "master",
+            };
+
+            let connected = myself || node.link.and_then(|l| self.links[l].as_ref()).is_some_and(|l| l.connected);
+
+            out.push_str(&format!(
+                "{} {}:{}@{} {} - {} {} {} {}",
+                node.id,
+                node.ip,
+                node.port,
+                node.bus_port,
+                flags,
+                unix_millis(node.ping_sent),
+                unix_millis(node.pong_received),
+                node.config_epoch,
+                if connected { "connected" } else { "disconnected" },
+            ));
+
+            for (start, end) in self.slot_ranges(&node.id) {
+                match start == end {
+                    true => out.push_str(&format!(" {}", start)),
+                    false => out.push_str(&format!(" {}-{}", start, end)),
+                }
+            }
+
+            if myself {
+                for (slot, id) in &self.migrating {
+                    out.push_str(&format!(" [{}->-{}]", slot, id));
+                }
+
+                for (slot, id) in &self.importing {
+                    out.push_str(&format!(" [{}-<-{}]", slot, id));
+                }
+            }
+
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Accept the links other nodes have opened to us.
+    pub fn accept(&mut self, registry: &Registry) -> io::Result<()> {
+        let Some(listener) = &self.listener else {
+            return Ok(());
+        };
+
+        loop {
+            let (mut stream, peer) = match listener.accept() {
+                Ok(c) => c,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+
+            let token = Token(FIRST_LINK + self.links.len());
+            registry.register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)?;
+
+            self.links.push(Some(Link { stream, peer, node: None, connected: true, input: QueryBuffer::new(), output: Vec::new() }));
+        }
+    }
+
+    pub fn link_event(&mut self, token: Token, readable: bool, writable: bool) {
+        let index = token.0 - FIRST_LINK;
+
+        let Some(Some(link)) = self.links.get_mut(index) else {
+            return;
+        };
+
+        let mut result = Ok(true);
+
+        if writable {
+            result = link.writable().map(|_| true);
+        }
+
+        if readable && result.is_ok() {
+            result = link.read();
+        }
+
+        match result {
+            Ok(true) => {},
+            Ok(false) => return self.close_link(index),
+            Err(e) => {
+                warn!("Error on the cluster bus link to {}: {}", link.peer, e);
+                return self.close_link(index);
+            },
+        }
+
+        loop {
+            let Some(Some(link)) = self.links.get_mut(index) else {
+                return;
+            };
+
+            let Some((message, _)) = link.input.next_value() else {
+                return;
+            };
+
+            let Some(message) = Message::from_resp(message) else {
+                warn!("Invalid message on the cluster bus from {}.", link.peer);
+                return self.close_link(index);
+            };
+
+            self.process_message(index, message);
+        }
+    }
+
+    fn close_link(&mut self, index: usize) {
+        let Some(link) = self.links[index].take() else {
+            return;
+        };
+
+        if let Some(node) = link.node.and_then(|id| self.nodes.get_mut(&id)) {
+            node.link = None;
+            node.ping_sent = None;
+            node.retry_at = Instant::now() + RECONNECT_INTERVAL;
+        }
+    }
+
+    fn process_message(&mut self, index: usize, message: Message) {
+        self.current_epoch = self.current_epoch.max(message.current_epoch);
+
+        let link = self.links[index].as_ref().unwrap();
+        let peer_ip = link.peer.ip().to_string();
+
+        match message.kind {
+            MessageKind::Meet | MessageKind::Ping => {
+                if message.kind == MessageKind::Meet {
+                    // We only know the address other nodes reach us on once one of them
+                    // connects to us.
+                    if let Ok(address) = link.stream.local_addr() {
+                        self.myself_mut().ip = address.ip().to_string();
+                    }
+
+                    if !self.nodes.contains_key(&message.sender) {
+                        info!("Met node {} at {}:{}.", message.sender, peer_ip, message.port);
+
+                        let node = ClusterNode::new(message.sender.clone(), peer_ip.clone(), message.port, message.bus_port);
+                        self.nodes.insert(node.id.clone(), node);
+                    }
+                }
+
+                let pong = self.message(MessageKind::Pong);
+
+                if let Err(e) = self.links[index].as_mut().unwrap().send(&pong) {
+                    warn!("Error on the cluster bus link to {}: {}", peer_ip, e);
+                    return self.close_link(index);
+                }
+            },
+            MessageKind::Pong => {
+                let Some(id) = link.node.clone() else {
+                    return;
+                };
+
+                let Some(node) = self.nodes.get_mut(&id) else {
+                    return;
+                };
+
+                node.ping_sent = None;
+                node.pong_received = Some(Instant::now());
+
+                if node.handshake {
+                    self.complete_handshake(&id, &message.sender, index);
+                }
+            },
+        }
+
+        let Some(sender) = self.nodes.get_mut(&message.sender) else {
+            return;
+        };
+
+        sender.port = message.port;
+        sender.bus_port = message.bus_port;
+        sender.config_epoch = message.config_epoch;
+
+        self.update_slots(&message);
+
+        for gossip in message.gossip {
+            if let Entry::Vacant(entry) = self.nodes.entry(gossip.id.clone()) {
+                info!("Learned about node {} at {}:{}.", gossip.id, gossip.ip, gossip.port);
+
+                entry.insert(ClusterNode::new(gossip.id, gossip.ip, gossip.port, gossip.bus_port));
+            }
+        }
+    }
+
+    /// Replace the placeholder for a node we were asked to meet with its real ID.
+    fn complete_handshake(&mut self, placeholder: &str, id: &str, index: usize) {
+        let mut node = self.nodes.remove(placeholder).unwrap();
+
+        if self.nodes.contains_key(id) || id == self.myself {
+            // We already knew about the node, or we were asked to meet ourselves.
+            self.links[index] = None;
+            return;
+        }
+
+        node.id = id.to_string();
+        node.handshake = false;
+
+        self.links[index].as_mut().unwrap().node = Some(id.to_string());
+        self.nodes.insert(id.to_string(), node);
+    }
+
+    /// Take the slots claimed by the sender of a message, where its claim is newer than the
+    /// current owner's.
+    fn update_slots(&mut self, message: &Message) {
+        for slot in 0..SLOTS {
+            if message.slots[slot / 8] & (1 << (slot % 8)) == 0 {
+                continue;
+            }
+
+            let owner = self.owners[slot].as_ref();
+
+            if owner == Some(&message.sender) {
+                continue;
+            }
+
+            let claim_wins = match owner.and_then(|o| self.nodes.get(o)) {
+                Some(owner) => owner.config_epoch < message.config_epoch,
+                None => true,
+            };
+
+            if claim_wins {
+                if owner == Some(&self.myself) {
+                    info!("Slot {} is now served by {}.", slot, message.sender);
+                }
+
+                self.owners[slot] = Some(message.sender.clone());
+                self.importing.remove(&(slot as u16));
+
+                if self.migrating.get(&(slot as u16)) == Some(&message.sender) {
+                    self.migrating.remove(&(slot as u16));
+                }
+            }
+        }
+    }
+
+    fn message(&self, kind: MessageKind) -> Message {
+        let myself = self.myself();
+        let mut slots = vec![0; SLOTS / 8];
+
+        for (slot, owner) in self.owners.iter().enumerate() {
+            if owner.as_ref() == Some(&self.myself) {
+                slots[slot / 8] |= 1 << (slot % 8);
+            }
+        }
+
+        Message {
+            kind,
+            sender: myself.id.clone(),
+            port: myself.port,
+            bus_port: myself.bus_port,
+            config_epoch: myself.config_epoch,
+            current_epoch: self.current_epoch,
+            slots: slots.into(),
+            gossip: self.nodes.values()
+                .filter(|n| n.id != self.myself && !n.handshake)
+                .map(|n| Gossip { id: n.id.clone(), ip: n.ip.clone(), port: n.port, bus_port: n.bus_port })
+                .collect(),
+        }
+    }
+
+    /// Connect to the nodes we have no link to, ping the nodes which are due a ping, and drop
+    /// the links which have stopped answering.
+    pub fn cron(&mut self, registry: &Registry) {
+        let now = Instant::now();
+        let ids: Vec<String> = self.nodes.keys().filter(|id| **id != self.myself).cloned().collect();
+
+        for id in ids {
+            let node = &self.nodes[&id];
+
+            if node.handshake && node.pong_received.is_none() && node.created.elapsed() > NODE_TIMEOUT {
+                warn!("Forgetting the node at {}:{}, which never answered.", node.ip, node.bus_port);
+
+                if let Some(link) = node.link {
+                    self.links[link] = None;
+                }
+
+                self.nodes.remove(&id);
+                continue;
+            }
+
+            match node.link {
+                None if node.retry_at <= now => {
+                    if let Err(e) = self.connect(&id, registry) {
+                        warn!("Could not connect to the cluster bus of node {}: {}", id, e);
+                        self.nodes.get_mut(&id).unwrap().retry_at = now + RECONNECT_INTERVAL;
+                    }
+                },
+                None => {},
+                Some(link) => match node.ping_sent {
+                    Some(sent) if sent.elapsed() > NODE_TIMEOUT => {
+                        warn!("Node {} stopped answering pings.", id);
+                        self.close_link(link);
+                    },
+                    Some(_) => {},
+                    None if node.pong_received.is_some_and(|p| p.elapsed() < PING_INTERVAL) => {},
+                    None => self.ping(&id, link, MessageKind::Ping),
+                },
+            }
+        }
+    }
+
+    fn connect(&mut self, id: &str, registry: &Registry) -> io::Result<()> {
+        let node = &self.nodes[id];
+        let address: SocketAddr = format!("{}:{}", node.ip, node.bus_port).parse().map_err(|_| io::Error::new(ErrorKind::InvalidInput, "invalid node address"))?;
+
+        let mut stream = TcpStream::connect(address)?;
+        let index = self.links.len();
+        registry.register(&mut stream, Token(FIRST_LINK + index), Interest::READABLE | Interest::WRITABLE)?;
+
+        self.links.push(Some(Link { stream, peer: address, node: Some(id.to_string()), connected: false, input: QueryBuffer::new(), output: Vec::new() }));
+
+        let node = self.nodes.get_mut(id).unwrap();
+        node.link = Some(index);
+
+        // Nodes we have not heard from yet may not know about us.
+        let kind = if node.pong_received.is_none() { MessageKind::Meet } else { MessageKind::Ping };
+
+        self.ping(id, index, kind);
+
+        Ok(())
+    }
+
+    fn ping(&mut self, id: &str, index: usize, kind: MessageKind) {
+        let message = self.message(kind);
+
+        let Some(link) = self.links[index].as_mut() else {
+            return;
+        };
+
+        if let Err(e) = link.send(&message) {
+            warn!("Error on the cluster bus link to {}: {}", link.peer, e);
+            return self.close_link(index);
+        }
+
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.ping_sent = Some(Instant::now());
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::cluster::{crc16, key_slot, Cluster, Gossip, Message, MessageKind, SLOTS};
+
+    #[test]
+    fn test_key_slot() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_slot(b"foo"), 12182);
+        assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"{user1000}.followers"));
+        assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+        assert_eq!(key_slot(b"foo{}{bar}"), key_slot(b"foo{}{bar}"));
+        assert_ne!(key_slot(b"foo{}{bar}"), key_slot(b"bar"));
+        assert_eq!(key_slot(b"foo{{bar}}zap"), key_slot(b"{bar"));
+    }
+
+    #[test]
+    fn test_slot_ranges() {
+        let mut cluster = Cluster::new(7000);
+        let id = cluster.myself.clone();
+
+        for slot in [0, 1, 2, 5, 16383] {
+            cluster.set_owner(slot, id.clone());
+        }
+
+        assert_eq!(cluster.slot_ranges(&id), vec![(0, 2), (5, 5), (16383, 16383)]);
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let message = Message {
+            kind: MessageKind::Meet,
+            sender: "a".repeat(40),
+            port: 7000,
+            bus_port: 17000,
+            config_epoch: 3,
+            current_epoch: 5,
+            slots: Bytes::from(vec![1; SLOTS / 8]),
+            gossip: vec![Gossip { id: "b".repeat(40), ip: "127.0.0.1".into(), port: 7001, bus_port: 17001 }],
+        };
+
+        assert_eq!(Message::from_resp(message.to_resp()), Some(message));
+    }
+}
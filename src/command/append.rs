@@ -0,0 +1,56 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{DBString, ExpiryFlag, ExistenceFlag};
+
+use super::super::db::DB;
+use crate::notify;
+
+
+#[command(
+    name = "append",
+    arity = 3,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "fast"),
+    command_tips = (),
+)]
+pub fn append(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 2 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(value) = args.remove(1) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let RESPType::BulkString(key) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let max_length = db.max_string_length;
+
+    let Ok(e) = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
+        return RESPType::Error("error retrieving key".into());
+    };
+
+    if e.is_nil() {
+        e.set_string(DBString::String(Bytes::new()));
+    }
+
+    let Ok(s) = e.get_mut_string() else {
+        return RESPType::Error("wrong type".into());
+    };
+
+    match s.append(&value, max_length) {
+        Ok(len) => {
+            db.notify(notify::STRING, "append", &key);
+            RESPType::Integer(len as i64)
+        },
+        Err(_) => RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into()),
+    }
+}
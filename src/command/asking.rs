@@ -0,0 +1,30 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+
+
+#[command(
+    name = "asking",
+    arity = 1,
+    flags = ("fast"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("fast", "connection"),
+    command_tips = (),
+)]
+pub fn asking(_: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    if server.cluster.is_none() {
+        return Some(RESPType::Error("This instance has cluster support disabled".into()));
+    }
+
+    if let Some(client) = server.client_mut(token) {
+        client.asking = true;
+    }
+
+    Some(RESPType::SimpleString("OK".into()))
+}
@@ -0,0 +1,67 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+
+
+const WRONGPASS: &str = "WRONGPASS invalid username-password pair or user is disabled.";
+
+
+/// Authenticate a client as `user` with `password`. The only user with a password is the
+/// default one, whose password is `requirepass`. Others are named by client certificates.
+pub fn authenticate(server: &mut Server, token: Token, user: &str, password: &str) -> Result<(), &'static str> {
+    if user != "default" || server.config.requirepass.as_deref().is_some_and(|p| p != password) {
+        return Err(WRONGPASS);
+    }
+
+    if let Some(client) = server.client_mut(token) {
+        client.authenticated = true;
+        client.user = user.into();
+    }
+
+    Ok(())
+}
+
+
+/// Authenticate the connection, with the password set by `requirepass`.
+#[command(
+    name = "auth",
+    arity = -2,
+    flags = ("fast", "no_auth", "sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("fast", "connection"),
+    command_tips = (),
+)]
+pub fn auth(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(String::from_utf8_lossy(&s).into_owned());
+    }
+
+    let (user, password) = match &strings[..] {
+        [password] => {
+            if server.config.requirepass.is_none() {
+                return Some(RESPType::Error("AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".into()));
+            }
+
+            ("default", password)
+        },
+        [user, password] => (user.as_str(), password),
+        _ => return Some(RESPType::Error("syntax error".into())),
+    };
+
+    Some(match authenticate(server, token, user, password) {
+        Ok(()) => RESPType::SimpleString("OK".into()),
+        Err(e) => RESPType::Error(e.into()),
+    })
+}
@@ -1,12 +1,29 @@
-
 use bytes::Bytes;
+use mio::Token;
 use sider_command::*;
 use super::super::db::DB;
+use crate::server::Server;
+
+
+pub type KeyspaceHandler = fn(Vec<RESPType<Bytes>>, &mut DB) -> RESPType<Bytes>;
+pub type ServerHandler = fn(Vec<RESPType<Bytes>>, &mut Server, Token) -> Option<RESPType<Bytes>>;
+
+/// The function implementing a command. Which kind of handler a command has is decided by the
+/// command macro from the handler's signature.
+pub enum Handler {
+    /// A command which only needs the keyspace.
+    Keyspace(KeyspaceHandler),
+    /// A command which needs the rest of the server, such as the replication state or the
+    /// calling client, identified by its token. Returns None if there is no reply yet, for
+    /// example because the client is blocked. Unlike keyspace commands, these are not added to
+    /// the replication stream for them, and propagate whatever their writes need.
+    Server(ServerHandler),
+}
 
 
 pub struct Command<'a> {
     pub name: &'a str,
-    pub handler: fn(Vec<RESPType<Bytes>>, &mut DB) -> RESPType<Bytes>,
+    pub handler: Handler,
     pub arity: i64,
     pub flags: &'a [Flag],
     pub first_key: u64,
@@ -15,3 +32,27 @@ pub struct Command<'a> {
     pub acl_categories: &'a [AclCategory],
     pub tips: &'a [CommandTip],
 }
+
+
+impl<'a> Command<'a> {
+    /// Find the keys in a command's arguments, including the command name, using its key spec.
+    /// A negative last key counts back from the final argument.
+    pub fn keys<'b>(&self, args: &'b [RESPType<Bytes>]) -> Vec<&'b Bytes> {
+        if self.first_key == 0 || self.step == 0 {
+            return vec![];
+        }
+
+        let last_key = match self.last_key {
+            n if n < 0 => args.len() as i64 + n,
+            n => n,
+        };
+
+        (self.first_key as usize..=last_key.max(0) as usize)
+            .step_by(self.step as usize)
+            .filter_map(|i| match args.get(i) {
+                Some(RESPType::BulkString(k)) => Some(k),
+                _ => None,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,65 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::bitops::{bit_count, BitUnit};
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "bitcount",
+    arity = -2,
+    flags = ("readonly"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("read", "slow"),
+    command_tips = (),
+)]
+pub fn bitcount(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() || args.len() > 4 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let range = match &strings[1..] {
+        [] => None,
+        [start, end, unit @ ..] => {
+            let (Ok(start), Ok(end)) = (from_decimal_bytes(start), from_decimal_bytes(end)) else {
+                return RESPType::Error("value is not an integer or out of range".into());
+            };
+
+            let unit = match unit.first() {
+                None => BitUnit::Byte,
+                Some(u) => match BitUnit::parse(u) {
+                    Some(u) => u,
+                    None => return RESPType::Error("Invalid syntax.".into()),
+                },
+            };
+
+            Some((start, end, unit))
+        },
+        _ => return RESPType::Error("Invalid syntax.".into()),
+    };
+
+    let Some(e) = db.get(&strings[0]) else {
+        return RESPType::Integer(0);
+    };
+
+    match e.get_string() {
+        Ok(s) => RESPType::Integer(bit_count(&s.as_slice(), range) as i64),
+        Err(_) => RESPType::Error("wrong type".into()),
+    }
+}
@@ -0,0 +1,195 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::bitops::{get_field, parse_field_offset, set_field, FieldType, Overflow};
+use crate::db::{DBEntry, DBString, ExpiryFlag, ExistenceFlag};
+use crate::notify;
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+/// A single operation in a BITFIELD command.
+pub enum FieldOperation {
+    Get(FieldType, u64),
+    Set(FieldType, u64, i64, Overflow),
+    IncrBy(FieldType, u64, i64, Overflow),
+}
+
+
+/// Parse the operations of a BITFIELD command. If `read_only` is true, only GET operations
+/// are allowed. No field may reach past a string of `max_length` bytes.
+pub fn parse_operations(args: &[Bytes], read_only: bool, max_length: usize) -> Result<Vec<FieldOperation>, RESPType<Bytes>> {
+    let mut operations = vec![];
+    let mut overflow = Overflow::Wrap;
+    let mut remaining = args.iter();
+
+    while let Some(name) = remaining.next() {
+        let name = name.to_ascii_uppercase();
+
+        if &name[..] == b"OVERFLOW" {
+            overflow = match remaining.next().and_then(|o| Overflow::parse(o)) {
+                Some(o) => o,
+                None => return Err(RESPType::Error("Invalid OVERFLOW type specified".into())),
+            };
+
+            continue;
+        }
+
+        let arg_count = match &name[..] {
+            b"GET" => 2,
+            b"SET" | b"INCRBY" => 3,
+            _ => return Err(RESPType::Error("Invalid syntax.".into())),
+        };
+
+        if read_only && &name[..] != b"GET" {
+            return Err(RESPType::Error("BITFIELD_RO only supports the GET subcommand".into()));
+        }
+
+        let operands: Vec<&Bytes> = remaining.by_ref().take(arg_count).collect();
+
+        if operands.len() != arg_count {
+            return Err(RESPType::Error("Invalid syntax.".into()));
+        }
+
+        let Some(field) = FieldType::parse(operands[0]) else {
+            return Err(RESPType::Error("Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.".into()));
+        };
+
+        let Some(offset) = parse_field_offset(operands[1], field).filter(|o| o + field.bits as u64 <= max_length as u64 * 8) else {
+            return Err(RESPType::Error("bit offset is not an integer or out of range".into()));
+        };
+
+        let value = match operands.get(2) {
+            Some(v) => match from_decimal_bytes(v) {
+                Ok(v) => v,
+                Err(_) => return Err(RESPType::Error("value is not an integer or out of range".into())),
+            },
+            None => 0,
+        };
+
+        operations.push(match &name[..] {
+            b"GET" => FieldOperation::Get(field, offset),
+            b"SET" => FieldOperation::Set(field, offset, value, overflow),
+            _ => FieldOperation::IncrBy(field, offset, value, overflow),
+        });
+    }
+
+    Ok(operations)
+}
+
+
+/// Run operations which are all GETs against a string, without modifying it.
+pub fn read_fields(bytes: &[u8], operations: Vec<FieldOperation>) -> RESPType<Bytes> {
+    RESPType::Array(operations.into_iter().map(|o| match o {
+        FieldOperation::Get(field, offset) => RESPType::Integer(get_field(bytes, offset, field)),
+        _ => unreachable!(),
+    }).collect())
+}
+
+
+/// Run the operations against the string `s`, growing it as needed for writes, up to
+/// `max_length` bytes.
+fn run_operations(s: &mut DBString, operations: Vec<FieldOperation>, max_length: usize) -> RESPType<Bytes> {
+    let mut results = Vec::with_capacity(operations.len());
+
+    for operation in operations {
+        let (field, offset) = match &operation {
+            FieldOperation::Get(f, o) | FieldOperation::Set(f, o, _, _) | FieldOperation::IncrBy(f, o, _, _) => (*f, *o),
+        };
+
+        if let FieldOperation::Get(..) = operation {
+            results.push(RESPType::Integer(get_field(&s.as_slice(), offset, field)));
+            continue;
+        }
+
+        let Ok(b) = s.grow_to(((offset + field.bits as u64 + 7) >> 3) as usize, max_length) else {
+            return RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into());
+        };
+
+        let previous = get_field(b, offset, field);
+
+        let (new, reply) = match operation {
+            FieldOperation::Set(_, _, v, overflow) => (overflow.apply(field, v as i128), previous),
+            FieldOperation::IncrBy(_, _, v, overflow) => {
+                let new = overflow.apply(field, previous as i128 + v as i128);
+                (new, new.unwrap_or(0))
+            },
+            FieldOperation::Get(..) => unreachable!(),
+        };
+
+        match new {
+            Some(v) => {
+                set_field(b, offset, field, v);
+                results.push(RESPType::Integer(reply));
+            },
+            None => results.push(RESPType::Null),
+        }
+    }
+
+    RESPType::Array(results)
+}
+
+
+#[command(
+    name = "bitfield",
+    arity = -2,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "slow"),
+    command_tips = (),
+)]
+pub fn bitfield(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let operations = match parse_operations(&strings[1..], false, db.max_string_length) {
+        Ok(o) => o,
+        Err(e) => return e,
+    };
+
+    let key = strings.swap_remove(0);
+
+    // Only create the key if there is something to write to it.
+    if operations.iter().all(|o| matches!(o, FieldOperation::Get(..))) {
+        return match db.get(&key) {
+            None => read_fields(&[], operations),
+            Some(DBEntry::String(s)) => read_fields(&s.as_slice(), operations),
+            Some(_) => RESPType::Error("wrong type".into()),
+        };
+    }
+
+    let max_length = db.max_string_length;
+
+    let Ok(e) = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
+        return RESPType::Error("error retrieving key".into());
+    };
+
+    if e.is_nil() {
+        e.set_string(DBString::String(Bytes::new()));
+    }
+
+    let Ok(s) = e.get_mut_string() else {
+        return RESPType::Error("wrong type".into());
+    };
+
+    let reply = run_operations(s, operations, max_length);
+    db.notify(notify::STRING, "setbit", &key);
+
+    reply
+}
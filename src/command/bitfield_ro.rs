@@ -0,0 +1,47 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::DBEntry;
+
+use super::bitfield::{parse_operations, read_fields};
+use super::super::db::DB;
+
+
+#[command(
+    name = "bitfield_ro",
+    arity = -2,
+    flags = ("readonly", "fast"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("read", "fast"),
+    command_tips = (),
+)]
+pub fn bitfield_ro(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let operations = match parse_operations(&strings[1..], true, db.max_string_length) {
+        Ok(o) => o,
+        Err(e) => return e,
+    };
+
+    match db.get(&strings[0]) {
+        None => read_fields(&[], operations),
+        Some(DBEntry::String(s)) => read_fields(&s.as_slice(), operations),
+        Some(_) => RESPType::Error("wrong type".into()),
+    }
+}
@@ -0,0 +1,72 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::bitops::{bit_op, BitOperation};
+use crate::db::{DBEntry, ExpiryFlag, ExistenceFlag};
+use crate::notify;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "bitop",
+    arity = -4,
+    flags = ("write"),
+    first_key = 2,
+    last_key = -1,
+    step = 1,
+    acl_categories = ("write", "slow"),
+    command_tips = (),
+)]
+pub fn bitop(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() < 3 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let Some(operation) = BitOperation::parse(&strings[0]) else {
+        return RESPType::Error("Invalid syntax.".into());
+    };
+
+    if operation == BitOperation::Not && strings.len() != 3 {
+        return RESPType::Error("BITOP NOT must be called with a single source key.".into());
+    }
+
+    let mut sources = vec![];
+
+    for key in &strings[2..] {
+        sources.push(match db.get(key) {
+            None => Bytes::new(),
+            Some(DBEntry::String(s)) => s.to_bytes(),
+            Some(_) => return RESPType::Error("wrong type".into()),
+        });
+    }
+
+    let result = bit_op(operation, &sources.iter().map(|s| &s[..]).collect::<Vec<_>>());
+    let len = result.len() as i64;
+
+    let destination = strings.swap_remove(1);
+
+    if result.is_empty() {
+        if db.delete(&destination) {
+            db.notify(notify::GENERIC, "del", &destination);
+        }
+    } else {
+        let e = db.get_or_insert(destination.clone(), ExpiryFlag::None, ExistenceFlag::None).unwrap();
+        *e = DBEntry::String(Bytes::from(result).into());
+        db.notify(notify::STRING, "set", &destination);
+    }
+
+    RESPType::Integer(len)
+}
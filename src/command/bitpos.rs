@@ -0,0 +1,70 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::bitops::{bit_pos, BitUnit};
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "bitpos",
+    arity = -3,
+    flags = ("readonly"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("read", "slow"),
+    command_tips = (),
+)]
+pub fn bitpos(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() < 2 || args.len() > 5 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let bit = match &strings[1][..] {
+        b"0" => 0,
+        b"1" => 1,
+        _ => return RESPType::Error("The bit argument must be 1 or 0.".into()),
+    };
+
+    let mut indices = vec![];
+
+    for i in strings.iter().skip(2).take(2) {
+        let Ok(i) = from_decimal_bytes(i) else {
+            return RESPType::Error("value is not an integer or out of range".into());
+        };
+
+        indices.push(i);
+    }
+
+    let unit = match strings.get(4) {
+        None => BitUnit::Byte,
+        Some(u) => match BitUnit::parse(u) {
+            Some(u) => u,
+            None => return RESPType::Error("Invalid syntax.".into()),
+        },
+    };
+
+    let Some(e) = db.get(&strings[0]) else {
+        // A missing key is treated as an empty string padded with zeros.
+        return RESPType::Integer(if bit == 1 { -1 } else { 0 });
+    };
+
+    match e.get_string() {
+        Ok(s) => RESPType::Integer(bit_pos(&s.as_slice(), bit, indices.first().copied(), indices.get(1).copied(), unit)),
+        Err(_) => RESPType::Error("wrong type".into()),
+    }
+}
@@ -0,0 +1,475 @@
+
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::{PauseMode, ReplyMode, Server};
+use crate::tracking::Tracking;
+
+
+const HELP: &[&str] = &[
+    "CLIENT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "CACHING (YES|NO)",
+    "    Enable/disable tracking of the keys for next command in OPTIN/OPTOUT modes.",
+    "GETREDIR",
+    "    Return the client ID we are redirecting to when tracking is enabled.",
+    "GETNAME",
+    "    Return the name of the current connection.",
+    "ID",
+    "    Return the ID of the current connection.",
+    "INFO",
+    "    Return information about the current client connection.",
+    "KILL <ip:port>",
+    "    Kill connection made from <ip:port>.",
+    "KILL <option> <value> [<option> <value> [...]]",
+    "    Kill connections. Options are:",
+    "    * ADDR (<ip:port>|<unixsocket>:0)",
+    "      Kill connections made from the specified address",
+    "    * LADDR (<ip:port>|<unixsocket>:0)",
+    "      Kill connections made to specified local address",
+    "    * TYPE (NORMAL|MASTER|REPLICA|PUBSUB)",
+    "      Kill connections by type.",
+    "    * USER <username>",
+    "      Kill connections authenticated by <username>.",
+    "    * SKIPME (YES|NO)",
+    "      Skip killing current connection (default: yes).",
+    "    * ID <client-id>",
+    "      Kill connections by client id.",
+    "    * MAXAGE <maxage>",
+    "      Kill connections older than the specified age.",
+    "LIST [options ...]",
+    "    Return information about client connections. Options:",
+    "    * TYPE (NORMAL|MASTER|REPLICA|PUBSUB)",
+    "      Return clients of specified type.",
+    "UNPAUSE",
+    "    Stop the current client pause, resuming traffic.",
+    "PAUSE <timeout> [WRITE|ALL]",
+    "    Suspend all, or just write, clients for <timeout> milliseconds.",
+    "REPLY (ON|OFF|SKIP)",
+    "    Control the replies sent to the current connection.",
+    "SETNAME <name>",
+    "    Assign the name <name> to the current connection.",
+    "TRACKING (ON|OFF) [REDIRECT <id>] [BCAST] [PREFIX <prefix> [...]]",
+    "         [OPTIN] [OPTOUT] [NOLOOP]",
+    "    Control server assisted client side caching.",
+    "TRACKINGINFO",
+    "    Report tracking status for the current connection.",
+    "NO-EVICT (ON|OFF)",
+    "    Protect current client connection from eviction.",
+    "HELP",
+    "    Print this help.",
+];
+
+
+/// Which clients CLIENT KILL disconnects. Every filter given has to match.
+#[derive(Default)]
+struct Filter {
+    id: Option<u64>,
+    address: Option<String>,
+    local_address: Option<String>,
+    kind: Option<&'static str>,
+    user: Option<String>,
+    skip_me: bool,
+    max_age: Option<Duration>,
+}
+
+
+fn parse_kind(kind: &str) -> Result<&'static str, String> {
+    match kind.to_ascii_lowercase().as_str() {
+        "normal" => Ok("normal"),
+        "master" => Ok("master"),
+        "replica" | "slave" => Ok("replica"),
+        "pubsub" => Ok("pubsub"),
+        _ => Err(format!("Unknown client type '{}'", kind)),
+    }
+}
+
+
+fn parse_filter(args: &[String]) -> Result<Filter, String> {
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return Err("syntax error".into());
+    }
+
+    let mut filter = Filter { skip_me: true, ..Default::default() };
+
+    for pair in args.chunks(2) {
+        let value = &pair[1];
+
+        match pair[0].to_ascii_uppercase().as_str() {
+            "ID" => match value.parse::<u64>() {
+                Ok(id) if id > 0 => filter.id = Some(id),
+                _ => return Err("client-id should be greater than 0".into()),
+            },
+            "ADDR" => filter.address = Some(value.clone()),
+            "LADDR" => filter.local_address = Some(value.clone()),
+            "TYPE" => filter.kind = Some(parse_kind(value)?),
+            "USER" => filter.user = Some(value.clone()),
+            "SKIPME" => filter.skip_me = match value.to_ascii_lowercase().as_str() {
+                "yes" => true,
+                "no" => false,
+                _ => return Err("syntax error".into()),
+            },
+            "MAXAGE" => match value.parse::<u64>() {
+                Ok(age) => filter.max_age = Some(Duration::from_secs(age)),
+                Err(_) => return Err("value is not an integer or out of range".into()),
+            },
+            _ => return Err("syntax error".into()),
+        }
+    }
+
+    Ok(filter)
+}
+
+
+/// Find the clients matching a filter.
+fn matching(server: &Server, filter: &Filter, me: Token) -> Vec<Token> {
+    server.clients_by_token()
+        .filter(|(token, client)| {
+            !(filter.skip_me && *token == me)
+                && filter.id.is_none_or(|id| client.id == id)
+                && filter.address.as_ref().is_none_or(|a| *a == client.address.to_string())
+                && filter.local_address.as_ref().is_none_or(|a| client.local_address().is_some_and(|l| *a == l.to_string()))
+                && filter.kind.is_none_or(|k| client.kind() == k)
+                && filter.user.as_ref().is_none_or(|u| *u == client.user)
+                && filter.max_age.is_none_or(|age| client.age() > age)
+        })
+        .map(|(token, _)| token)
+        .collect()
+}
+
+
+/// Disconnect clients. A client killing itself is disconnected after it has been sent the
+/// reply.
+fn kill(server: &mut Server, tokens: &[Token], me: Token) {
+    for token in tokens {
+        if *token == me {
+            if let Some(client) = server.client_mut(me) {
+                client.close_after_reply = true;
+            }
+        } else {
+            server.disconnect_client(*token);
+        }
+    }
+}
+
+
+/// Turn CLIENT TRACKING on or off, with the options after ON or OFF.
+fn tracking(server: &mut Server, token: Token, state: &str, options: &[String]) -> Result<(), String> {
+    let mut tracking = Tracking::default();
+    let mut options = options.iter();
+
+    while let Some(option) = options.next() {
+        match option.to_ascii_uppercase().as_str() {
+            "REDIRECT" => {
+                if tracking.redirect.is_some() {
+                    return Err("A client can only redirect to a single other client".into());
+                }
+
+                let id = options.next().ok_or("syntax error")?;
+                let Ok(id) = id.parse::<u64>() else {
+                    return Err("value is not an integer or out of range".into());
+                };
+
+                tracking.redirect = Some(id);
+            },
+            "BCAST" => tracking.bcast = true,
+            "OPTIN" => tracking.optin = true,
+            "OPTOUT" => tracking.optout = true,
+            "NOLOOP" => tracking.noloop = true,
+            "PREFIX" => tracking.prefixes.push(options.next().ok_or("syntax error")?.clone().into()),
+            _ => return Err("syntax error".into()),
+        }
+    }
+
+    match state.to_ascii_uppercase().as_str() {
+        "ON" => {},
+        "OFF" => {
+            server.disable_tracking(token);
+            return Ok(());
+        },
+        _ => return Err("syntax error".into()),
+    }
+
+    if !tracking.bcast && !tracking.prefixes.is_empty() {
+        return Err("PREFIX option requires BCAST mode to be enabled".into());
+    }
+
+    if tracking.bcast && (tracking.optin || tracking.optout) {
+        return Err("OPTIN and OPTOUT are not compatible with BCAST".into());
+    }
+
+    if tracking.optin && tracking.optout {
+        return Err("You can't use both OPTIN and OPTOUT".into());
+    }
+
+    let current = server.client(token).and_then(|c| c.tracking.as_ref());
+
+    if let Some(current) = current {
+        if current.bcast != tracking.bcast {
+            return Err("You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode.".into());
+        }
+
+        if current.optin != tracking.optin || current.optout != tracking.optout {
+            return Err("You can't switch OPTIN/OPTOUT mode before disabling tracking for this client, and then re-enabling it with a different mode.".into());
+        }
+    }
+
+    // A key must not match more than one of a client's prefixes, or it would be told about it
+    // more than once.
+    let existing = current.map(|c| c.prefixes.as_slice()).unwrap_or_default();
+
+    for (i, prefix) in tracking.prefixes.iter().enumerate() {
+        let others = existing.iter().chain(&tracking.prefixes[i + 1..]);
+
+        if let Some(other) = others.into_iter().find(|o| o.starts_with(prefix) || prefix.starts_with(o)) {
+            return Err(format!(
+                "Prefix '{}' overlaps with an existing prefix '{}'. Prefixes for a single client must not overlap.",
+                String::from_utf8_lossy(prefix),
+                String::from_utf8_lossy(other),
+            ));
+        }
+    }
+
+    if tracking.redirect.is_some_and(|id| server.client_by_id(id).is_none()) {
+        return Err("The client ID you want redirect to does not exist".into());
+    }
+
+    server.enable_tracking(token, tracking);
+
+    Ok(())
+}
+
+
+fn tracking_info(server: &Server, token: Token) -> RESPType<Bytes> {
+    let tracking = server.client(token).and_then(|c| c.tracking.as_ref());
+    let mut flags = Vec::new();
+    let mut redirect = -1;
+
+    match tracking {
+        None => flags.push("off"),
+        Some(tracking) => {
+            flags.push("on");
+
+            for (flag, set) in [("bcast", tracking.bcast), ("optin", tracking.optin), ("optout", tracking.optout), ("noloop", tracking.noloop)] {
+                if set {
+                    flags.push(flag);
+                }
+            }
+
+            match tracking.caching {
+                Some(true) => flags.push("caching-yes"),
+                Some(false) => flags.push("caching-no"),
+                None => {},
+            }
+
+            redirect = match tracking.redirect {
+                Some(id) if server.client_by_id(id).is_none() => {
+                    flags.push("broken_redirect");
+                    id as i64
+                },
+                Some(id) => id as i64,
+                None => 0,
+            };
+        },
+    }
+
+    let prefixes = tracking.map(|t| t.prefixes.iter().map(|p| RESPType::BulkString(p.clone())).collect()).unwrap_or_default();
+
+    server.map_reply(token, vec![
+        (RESPType::BulkString("flags".into()), RESPType::Array(flags.into_iter().map(|f| RESPType::BulkString(f.into())).collect())),
+        (RESPType::BulkString("redirect".into()), RESPType::Integer(redirect)),
+        (RESPType::BulkString("prefixes".into()), RESPType::Array(prefixes)),
+    ])
+}
+
+
+fn list(server: &Server, args: &[String]) -> Result<String, String> {
+    let mut kind = None;
+    let mut ids = None;
+
+    match args {
+        [] => {},
+        [option, value] if option.eq_ignore_ascii_case("TYPE") => kind = Some(parse_kind(value)?),
+        [option, values @ ..] if option.eq_ignore_ascii_case("ID") && !values.is_empty() => {
+            let parsed: Result<Vec<u64>, _> = values.iter().map(|v| v.parse::<u64>()).collect();
+            ids = Some(parsed.map_err(|_| "Invalid client ID")?);
+        },
+        _ => return Err("syntax error".into()),
+    }
+
+    let mut out = String::new();
+
+    for client in server.clients() {
+        if kind.is_some_and(|k| client.kind() != k) || ids.as_ref().is_some_and(|ids| !ids.contains(&client.id)) {
+            continue;
+        }
+
+        out.push_str(&client.describe());
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+
+/// Inspect and manage client connections.
+#[command(
+    name = "client",
+    arity = -2,
+    flags = ("sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("slow", "connection"),
+    command_tips = ("non_deterministic_output"),
+)]
+pub fn client(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    if args.is_empty() {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(String::from_utf8_lossy(&s).into_owned());
+    }
+
+    let subcommand = strings.remove(0).to_ascii_uppercase();
+
+    match run(&subcommand, &strings, server, token) {
+        Ok(r) => r,
+        Err(e) => Some(RESPType::Error(e.into())),
+    }
+}
+
+
+fn run(subcommand: &str, args: &[String], server: &mut Server, token: Token) -> Result<Option<RESPType<Bytes>>, String> {
+    let ok = Some(RESPType::SimpleString("OK".into()));
+
+    Ok(match (subcommand, args) {
+        ("ID", []) => Some(RESPType::Integer(server.client(token).map_or(0, |c| c.id as i64))),
+        ("INFO", []) => Some(RESPType::BulkString(server.client(token).map(|c| c.describe() + "\n").unwrap_or_default().into())),
+        ("LIST", options) => Some(RESPType::BulkString(list(server, options)?.into())),
+        ("GETNAME", []) => Some(match server.client(token).and_then(|c| c.name.clone()) {
+            Some(name) => RESPType::BulkString(name),
+            None => RESPType::Null,
+        }),
+        ("SETNAME", [name]) => {
+            if name.bytes().any(|c| !(b'!'..=b'~').contains(&c)) {
+                return Err("Client names cannot contain spaces, newlines or special characters.".into());
+            }
+
+            if let Some(client) = server.client_mut(token) {
+                client.name = (!name.is_empty()).then(|| name.clone().into());
+            }
+
+            ok
+        },
+        ("KILL", [address]) => {
+            let filter = Filter { address: Some(address.clone()), ..Default::default() };
+            let tokens = matching(server, &filter, token);
+
+            if tokens.is_empty() {
+                return Err("No such client".into());
+            }
+
+            kill(server, &tokens, token);
+
+            ok
+        },
+        ("KILL", filters) if !filters.is_empty() => {
+            let tokens = matching(server, &parse_filter(filters)?, token);
+
+            kill(server, &tokens, token);
+
+            Some(RESPType::Integer(tokens.len() as i64))
+        },
+        ("PAUSE", [timeout, mode @ ..]) if mode.len() <= 1 => {
+            let Ok(timeout) = timeout.parse::<i64>() else {
+                return Err("timeout is not an integer or out of range".into());
+            };
+
+            if timeout < 0 {
+                return Err("timeout is negative".into());
+            }
+
+            let mode = match mode.first().map(|m| m.to_ascii_uppercase()).as_deref() {
+                None | Some("ALL") => PauseMode::All,
+                Some("WRITE") => PauseMode::Write,
+                Some(_) => return Err("syntax error".into()),
+            };
+
+            server.pause_clients(mode, Instant::now() + Duration::from_millis(timeout as u64));
+
+            ok
+        },
+        ("UNPAUSE", []) => {
+            server.unpause_clients();
+            ok
+        },
+        ("NO-EVICT", [value]) => {
+            let no_evict = match value.to_ascii_uppercase().as_str() {
+                "ON" => true,
+                "OFF" => false,
+                _ => return Err("syntax error".into()),
+            };
+
+            if let Some(client) = server.client_mut(token) {
+                client.no_evict = no_evict;
+            }
+
+            ok
+        },
+        ("REPLY", [value]) => {
+            let mode = match value.to_ascii_uppercase().as_str() {
+                "ON" => ReplyMode::On,
+                "OFF" => ReplyMode::Off,
+                "SKIP" => ReplyMode::SkipNext,
+                _ => return Err("syntax error".into()),
+            };
+
+            if let Some(client) = server.client_mut(token) {
+                client.reply_mode = mode;
+            }
+
+            // Turning replies off or skipping them starts with this command.
+            (mode == ReplyMode::On).then_some(RESPType::SimpleString("OK".into()))
+        },
+        ("TRACKING", [state, options @ ..]) => {
+            tracking(server, token, state, options)?;
+            ok
+        },
+        ("CACHING", [value]) => {
+            let Some(tracking) = server.client_mut(token).and_then(|c| c.tracking.as_mut()).filter(|t| t.optin || t.optout) else {
+                return Err("CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled".into());
+            };
+
+            tracking.caching = match value.to_ascii_uppercase().as_str() {
+                "YES" if tracking.optin => Some(true),
+                "YES" => return Err("CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode.".into()),
+                "NO" if tracking.optout => Some(false),
+                "NO" => return Err("CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode.".into()),
+                _ => return Err("syntax error".into()),
+            };
+
+            ok
+        },
+        ("GETREDIR", []) => Some(RESPType::Integer(match server.client(token).and_then(|c| c.tracking.as_ref()) {
+            Some(tracking) => tracking.redirect.map_or(0, |id| id as i64),
+            None => -1,
+        })),
+        ("TRACKINGINFO", []) => Some(tracking_info(server, token)),
+        ("HELP", []) => Some(RESPType::Array(HELP.iter().map(|l| RESPType::SimpleString(Bytes::from_static(l.as_bytes()))).collect())),
+        ("ID" | "INFO" | "GETNAME" | "SETNAME" | "KILL" | "PAUSE" | "UNPAUSE" | "NO-EVICT" | "REPLY" | "TRACKING" | "CACHING" | "GETREDIR" | "TRACKINGINFO" | "HELP", _) => {
+            return Err("wrong number of arguments".into());
+        },
+        _ => return Err("unknown subcommand, try CLIENT HELP".into()),
+    })
+}
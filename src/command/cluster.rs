@@ -0,0 +1,254 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::cluster::{key_slot, BUS_PORT_OFFSET, SLOTS};
+use crate::server::Server;
+
+
+const HELP: &[&str] = &[
+    "CLUSTER <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "ADDSLOTS <slot> [<slot> ...]",
+    "    Assign slots to current node.",
+    "COUNTKEYSINSLOT <slot>",
+    "    Return the number of keys in <slot>.",
+    "GETKEYSINSLOT <slot> <count>",
+    "    Return key names stored by current node in a slot.",
+    "KEYSLOT <key>",
+    "    Return the hash slot for <key>.",
+    "MEET <ip> <port> [<bus-port>]",
+    "    Connect nodes into a working cluster.",
+    "MYID",
+    "    Return the node id.",
+    "NODES",
+    "    Return cluster configuration seen by node. Output format:",
+    "    <id> <ip:port@bus-port> <flags> <master> <pings> <pongs> <epoch> <link> <slot> ...",
+    "SETSLOT <slot> (IMPORTING <node-id>|MIGRATING <node-id>|STABLE|NODE <node-id>)",
+    "    Set slot state.",
+    "SHARDS",
+    "    Return information about slot range mappings and the nodes associated with them.",
+    "SLOTS",
+    "    Return information about slots range mappings. Each range is made of:",
+    "    start, end, master and replicas IP addresses, ports and ids",
+    "HELP",
+    "    Print this help.",
+];
+
+
+fn parse_slot(s: &Bytes) -> Result<u16, RESPType<Bytes>> {
+    std::str::from_utf8(s).ok()
+        .and_then(|s| s.parse::<u16>().ok())
+        .filter(|s| (*s as usize) < SLOTS)
+        .ok_or_else(|| RESPType::Error("Invalid or out of range slot".into()))
+}
+
+
+#[command(
+    name = "cluster",
+    arity = -2,
+    flags = (),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("slow"),
+    command_tips = ("non_deterministic_output"),
+)]
+pub fn cluster(args: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    if args.is_empty() {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(s);
+    }
+
+    let subcommand = strings.remove(0).to_ascii_uppercase();
+
+    if &subcommand[..] == b"HELP" {
+        return Some(RESPType::Array(HELP.iter().map(|l| RESPType::SimpleString(Bytes::from_static(l.as_bytes()))).collect()));
+    }
+
+    Some(match run(&subcommand, strings, server) {
+        Ok(r) => r,
+        Err(e) => e,
+    })
+}
+
+
+fn run(subcommand: &[u8], args: Vec<Bytes>, server: &mut Server) -> Result<RESPType<Bytes>, RESPType<Bytes>> {
+    let Some(cluster) = &mut server.cluster else {
+        return Err(RESPType::Error("This instance has cluster support disabled".into()));
+    };
+
+    let wrong_arguments = || RESPType::Error("wrong number of arguments".into());
+
+    Ok(match (subcommand, &args[..]) {
+        (b"MYID", []) => RESPType::BulkString(cluster.myself.clone().into()),
+        (b"KEYSLOT", [key]) => RESPType::Integer(key_slot(key) as i64),
+        (b"COUNTKEYSINSLOT", [slot]) => {
+            let slot = parse_slot(slot)?;
+
+            RESPType::Integer(server.db.entries().filter(|(k, _, _)| key_slot(k) == slot).count() as i64)
+        },
+        (b"GETKEYSINSLOT", [slot, count]) => {
+            let slot = parse_slot(slot)?;
+            let Some(count) = std::str::from_utf8(count).ok().and_then(|c| c.parse::<usize>().ok()) else {
+                return Err(RESPType::Error("Invalid number of keys".into()));
+            };
+
+            RESPType::Array(server.db.entries()
+                .filter(|(k, _, _)| key_slot(k) == slot)
+                .take(count)
+                .map(|(k, _, _)| RESPType::BulkString(k.clone()))
+                .collect())
+        },
+        (b"ADDSLOTS", slots) if !slots.is_empty() => {
+            let slots = slots.iter().map(parse_slot).collect::<Result<Vec<_>, _>>()?;
+
+            for (i, slot) in slots.iter().enumerate() {
+                if cluster.owner(*slot).is_some() {
+                    return Err(RESPType::Error(format!("Slot {} is already busy", slot).into()));
+                }
+
+                if slots[..i].contains(slot) {
+                    return Err(RESPType::Error(format!("Slot {} specified multiple times", slot).into()));
+                }
+            }
+
+            for slot in slots {
+                cluster.set_owner(slot, cluster.myself.clone());
+            }
+
+            RESPType::SimpleString("OK".into())
+        },
+        (b"MEET", [ip, port, rest @ ..]) if rest.len() <= 1 => {
+            let parse_port = |p: &Bytes| std::str::from_utf8(p).ok().and_then(|p| p.parse::<u16>().ok());
+            let ip = String::from_utf8_lossy(ip).into_owned();
+
+            let port = parse_port(port);
+            let bus_port = match rest.first() {
+                Some(p) => parse_port(p),
+                None => port.and_then(|p| p.checked_add(BUS_PORT_OFFSET)),
+            };
+
+            let (Some(port), Some(bus_port), Ok(_)) = (port, bus_port, ip.parse::<std::net::IpAddr>()) else {
+                return Err(RESPType::Error(format!("Invalid node address specified: {}:{}", ip, String::from_utf8_lossy(&args[1])).into()));
+            };
+
+            cluster.meet(ip, port, bus_port);
+
+            RESPType::SimpleString("OK".into())
+        },
+        (b"SETSLOT", [slot, action, rest @ ..]) => {
+            let slot = parse_slot(slot)?;
+            let node = rest.first().map(|id| String::from_utf8_lossy(id).into_owned());
+            let owned = cluster.owner(slot).is_some_and(|o| o.id == cluster.myself);
+
+            let known_node = |node: Option<String>| match node {
+                Some(id) if cluster.node(&id).is_some_and(|n| !n.handshake) => Ok(id),
+                Some(id) => Err(RESPType::Error(format!("I don't know about node {}", id).into())),
+                None => Err(wrong_arguments()),
+            };
+
+            match (&action.to_ascii_uppercase()[..], rest.len()) {
+                (b"MIGRATING", 1) => {
+                    let id = known_node(node)?;
+
+                    if !owned {
+                        return Err(RESPType::Error(format!("I'm not the owner of hash slot {}", slot).into()));
+                    }
+
+                    cluster.migrating.insert(slot, id);
+                },
+                (b"IMPORTING", 1) => {
+                    let id = known_node(node)?;
+
+                    if owned {
+                        return Err(RESPType::Error(format!("I'm already the owner of hash slot {}", slot).into()));
+                    }
+
+                    cluster.importing.insert(slot, id);
+                },
+                (b"STABLE", 0) => {
+                    cluster.migrating.remove(&slot);
+                    cluster.importing.remove(&slot);
+                },
+                (b"NODE", 1) => {
+                    let id = known_node(node)?;
+
+                    if owned && id != cluster.myself && server.db.entries().any(|(k, _, _)| key_slot(k) == slot) {
+                        return Err(RESPType::Error(format!("Can't assign hashslot {} to a different node while I still hold keys for this hash slot.", slot).into()));
+                    }
+
+                    cluster.migrating.remove(&slot);
+
+                    // Taking over an imported slot needs a newer epoch than the old owner's, so
+                    // that the rest of the cluster accepts our claim.
+                    if id == cluster.myself && cluster.importing.remove(&slot).is_some() {
+                        cluster.bump_epoch();
+                    }
+
+                    cluster.set_owner(slot, id);
+                },
+                _ => return Err(RESPType::Error("Invalid CLUSTER SETSLOT action or number of arguments. Try CLUSTER HELP".into())),
+            }
+
+            RESPType::SimpleString("OK".into())
+        },
+        (b"SLOTS", []) => {
+            let mut ranges: Vec<_> = cluster.nodes()
+                .flat_map(|n| cluster.slot_ranges(&n.id).into_iter().map(move |r| (r, n)))
+                .collect();
+
+            ranges.sort_by_key(|(r, _)| *r);
+
+            RESPType::Array(ranges.into_iter().map(|((start, end), node)| RESPType::Array(vec![
+                RESPType::Integer(start as i64),
+                RESPType::Integer(end as i64),
+                RESPType::Array(vec![
+                    RESPType::BulkString(node.ip.clone().into()),
+                    RESPType::Integer(node.port as i64),
+                    RESPType::BulkString(node.id.clone().into()),
+                ]),
+            ])).collect())
+        },
+        (b"SHARDS", []) => {
+            let offset = server.replication.offset as i64;
+
+            RESPType::Array(cluster.nodes().filter(|n| !n.handshake).map(|node| RESPType::Array(vec![
+                RESPType::BulkString("slots".into()),
+                RESPType::Array(cluster.slot_ranges(&node.id).into_iter()
+                    .flat_map(|(start, end)| [RESPType::Integer(start as i64), RESPType::Integer(end as i64)])
+                    .collect()),
+                RESPType::BulkString("nodes".into()),
+                RESPType::Array(vec![RESPType::Array(vec![
+                    RESPType::BulkString("id".into()),
+                    RESPType::BulkString(node.id.clone().into()),
+                    RESPType::BulkString("port".into()),
+                    RESPType::Integer(node.port as i64),
+                    RESPType::BulkString("ip".into()),
+                    RESPType::BulkString(node.ip.clone().into()),
+                    RESPType::BulkString("endpoint".into()),
+                    RESPType::BulkString(node.ip.clone().into()),
+                    RESPType::BulkString("role".into()),
+                    RESPType::BulkString("master".into()),
+                    RESPType::BulkString("replication-offset".into()),
+                    RESPType::Integer(if node.id == cluster.myself { offset } else { 0 }),
+                    RESPType::BulkString("health".into()),
+                    RESPType::BulkString("online".into()),
+                ])]),
+            ])).collect())
+        },
+        (b"NODES", []) => RESPType::BulkString(cluster.describe_nodes().into()),
+        (b"MYID" | b"KEYSLOT" | b"COUNTKEYSINSLOT" | b"GETKEYSINSLOT" | b"ADDSLOTS" | b"MEET" | b"SETSLOT" | b"SLOTS" | b"SHARDS" | b"NODES", _) => return Err(wrong_arguments()),
+        _ => return Err(RESPType::Error("unknown subcommand, try CLUSTER HELP".into())),
+    })
+}
@@ -0,0 +1,80 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+
+
+const HELP: &[&str] = &[
+    "CONFIG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "GET <pattern>",
+    "    Return parameters matching the glob-like <pattern> and their values.",
+    "SET <directive> <value>",
+    "    Set the configuration <directive> to <value>.",
+    "RESETSTAT",
+    "    Reset statistics reported by the INFO command.",
+    "REWRITE",
+    "    Rewrite the configuration file.",
+    "HELP",
+    "    Print this help.",
+];
+
+
+#[command(
+    name = "config",
+    arity = -2,
+    flags = ("admin"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "slow", "dangerous"),
+    command_tips = (),
+)]
+pub fn config(args: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    if args.is_empty() {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(String::from_utf8_lossy(&s).into_owned());
+    }
+
+    let subcommand = strings.remove(0).to_ascii_uppercase();
+    let ok = RESPType::SimpleString("OK".into());
+
+    Some(match (subcommand.as_str(), &strings[..]) {
+        ("GET", patterns) if !patterns.is_empty() => RESPType::Array(server.config.get(patterns).into_iter()
+            .flat_map(|(name, value)| [RESPType::BulkString(Bytes::from_static(name.as_bytes())), RESPType::BulkString(value.into())])
+            .collect()),
+        ("SET", pairs) if !pairs.is_empty() && pairs.len() % 2 == 0 => {
+            let changes: Vec<(String, String)> = pairs.chunks(2).map(|p| (p[0].clone(), p[1].clone())).collect();
+
+            match server.config.set(&changes) {
+                Ok(()) => {
+                    server.apply_config();
+                    ok
+                },
+                Err(e) => RESPType::Error(e.into()),
+            }
+        },
+        ("REWRITE", []) => match server.config.rewrite() {
+            Ok(()) => ok,
+            Err(e) => RESPType::Error(e.into()),
+        },
+        ("RESETSTAT", []) => {
+            server.reset_stats();
+            ok
+        },
+        ("HELP", []) => RESPType::Array(HELP.iter().map(|l| RESPType::SimpleString(Bytes::from_static(l.as_bytes()))).collect()),
+        ("GET" | "SET" | "REWRITE" | "RESETSTAT" | "HELP", _) => RESPType::Error("wrong number of arguments".into()),
+        _ => RESPType::Error("unknown subcommand, try CONFIG HELP".into()),
+    })
+}
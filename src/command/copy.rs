@@ -0,0 +1,104 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::DBError;
+use crate::notify;
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "copy",
+    arity = -3,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 2,
+    step = 1,
+    acl_categories = ("keyspace", "write", "slow"),
+    command_tips = (),
+)]
+pub fn copy(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() < 2 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let mut replace = false;
+    let mut remaining = strings[2..].iter();
+
+    while let Some(option) = remaining.next() {
+        match &option.to_ascii_uppercase()[..] {
+            b"REPLACE" => replace = true,
+            b"DB" => {
+                let Some(Ok(index)) = remaining.next().map(|i| from_decimal_bytes(i)) else {
+                    return RESPType::Error("value is not an integer or out of range".into());
+                };
+
+                // The server only has a single database.
+                if index != 0 {
+                    return RESPType::Error("DB index is out of range".into());
+                }
+            },
+            _ => return RESPType::Error("Invalid syntax.".into()),
+        }
+    }
+
+    let to = strings.swap_remove(1);
+    let from = strings.swap_remove(0);
+
+    if from == to {
+        return RESPType::Error("source and destination objects are the same".into());
+    }
+
+    match db.copy(&from, to.clone(), replace) {
+        Ok(()) => {
+            db.notify(notify::GENERIC, "copy_to", &to);
+            RESPType::Integer(1)
+        },
+        Err(DBError::AlreadyExists | DBError::DoesNotExist) => RESPType::Integer(0),
+        Err(_) => unreachable!(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use super::copy;
+
+    #[test]
+    fn test_copy() {
+        let mut db = DB::new();
+        let expiry = Utc::now() + Duration::hours(1);
+        db.insert("a".into(), DBEntry::String(DBString::String("one".into())), Some(expiry));
+        db.insert("b".into(), DBEntry::String(DBString::String("two".into())), None);
+
+        assert_eq!(copy(args(&["a", "b"]), &mut db), RESPType::Integer(0));
+        assert_eq!(copy(args(&["a", "b", "REPLACE"]), &mut db), RESPType::Integer(1));
+        assert_eq!(db.get(&"b".into()), Some(&DBEntry::String(DBString::String("one".into()))));
+        assert_eq!(db.expiry(&"b".into()), Some(expiry));
+        assert!(db.exists(&"a".into()));
+
+        assert_eq!(copy(args(&["a", "c", "DB", "0"]), &mut db), RESPType::Integer(1));
+        assert_eq!(copy(args(&["a", "d", "DB", "1"]), &mut db), RESPType::Error("DB index is out of range".into()));
+        assert_eq!(copy(args(&["a", "a"]), &mut db), RESPType::Error("source and destination objects are the same".into()));
+        assert_eq!(copy(args(&["missing", "e"]), &mut db), RESPType::Integer(0));
+        assert_eq!(copy(args(&["a", "e", "NOPE"]), &mut db), RESPType::Error("Invalid syntax.".into()));
+    }
+}
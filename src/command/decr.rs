@@ -5,19 +5,20 @@ use command_macro::command;
 
 use sider_command::RESPType;
 use crate::db::{DBError, ExpiryFlag, ExistenceFlag};
+use crate::notify;
 
 use super::super::db::DB;
 
 
 #[command(
     name = "decr",
-    arity = -1,
-    flags = ("fast", "sentinel"),
+    arity = 2,
+    flags = ("write", "fast"),
     first_key = 1,
-    last_key = 2,
+    last_key = 1,
     step = 1,
     acl_categories = ("connection"),
-    command_tips = ("request_policy:all_shards", "response_policy:all_succeeded"),
+    command_tips = (),
 )]
 pub fn decr(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
     if args.len() != 1 {
@@ -28,7 +29,7 @@ pub fn decr(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
         return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
     };
 
-    let Ok(e) = db.get_or_insert(key, ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
+    let Ok(e) = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
         return RESPType::Error("error retrieving key".into());
     };
 
@@ -44,7 +45,11 @@ pub fn decr(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
     };
 
     match v.incr_by(-1) {
-        Ok(i) => RESPType::Integer(i),
-        Err(()) => RESPType::Error("not a valid integer".into()),
+        Ok(i) => {
+            db.notify(notify::STRING, "incrby", &key);
+            RESPType::Integer(i)
+        },
+        Err(DBError::Overflow) => RESPType::Error("increment or decrement would overflow".into()),
+        Err(_) => RESPType::Error("not a valid integer".into()),
     }
 }
\ No newline at end of file
@@ -3,18 +3,19 @@ use bytes::Bytes;
 use command_macro::command;
 
 use sider_command::RESPType;
+use crate::notify;
 use super::super::db::DB;
 
 
 #[command(
     name = "del",
-    arity = -1,
-    flags = ("fast", "sentinel"),
+    arity = -2,
+    flags = ("write", "fast"),
     first_key = 1,
-    last_key = 2,
+    last_key = -1,
     step = 1,
     acl_categories = ("connection"),
-    command_tips = ("request_policy:all_shards", "response_policy:all_succeeded"),
+    command_tips = ("request_policy:multi_shard", "response_policy:agg_sum"),
 )]
 pub fn del(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
     if args.len() < 1 {
@@ -28,7 +29,10 @@ pub fn del(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
             return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
         };
 
-        total += db.delete(&k.into()) as i64;
+        if db.delete(&k) {
+            db.notify(notify::GENERIC, "del", &k);
+            total += 1;
+        }
     }
 
     RESPType::Integer(total)
@@ -0,0 +1,34 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::snapshot;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "dump",
+    arity = 2,
+    flags = ("readonly"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("keyspace", "read", "slow"),
+    command_tips = ("non_deterministic_output"),
+)]
+pub fn dump(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 1 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(key) = &args[0] else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    match db.get(key) {
+        Some(e) if !e.is_nil() => RESPType::BulkString(snapshot::dump_value(e).into()),
+        _ => RESPType::Null,
+    }
+}
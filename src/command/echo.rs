@@ -8,13 +8,13 @@ use super::super::db::DB;
 
 #[command(
     name = "echo",
-    arity = -1,
+    arity = 2,
     flags = ("fast", "sentinel"),
-    first_key = 1,
-    last_key = 2,
-    step = 1,
+    first_key = 0,
+    last_key = 0,
+    step = 0,
     acl_categories = ("connection"),
-    command_tips = ("request_policy:all_shards", "response_policy:all_succeeded"),
+    command_tips = (),
 )]
 pub fn echo(mut args: Vec<RESPType<Bytes>>, _: &mut DB) -> RESPType<Bytes> {
     if args.len() != 1 {
@@ -0,0 +1,117 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{DBEntry, ExpiryFlag, ExistenceFlag};
+use crate::geo;
+use crate::notify;
+use crate::sorted_set::SortedSet;
+use crate::util::from_float_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "geoadd",
+    arity = -5,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "slow"),
+    command_tips = (),
+)]
+pub fn geoadd(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() < 4 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let mut existence_flag = ExistenceFlag::None;
+    let mut count_changed = false;
+    let mut position = 1;
+
+    while let Some(option) = strings.get(position) {
+        match &option.to_ascii_uppercase()[..] {
+            b"NX" if existence_flag.is_none() => existence_flag = ExistenceFlag::Nx,
+            b"XX" if existence_flag.is_none() => existence_flag = ExistenceFlag::Xx,
+            b"NX" | b"XX" => return RESPType::Error("XX and NX options at the same time are not compatible".into()),
+            b"CH" => count_changed = true,
+            _ => break,
+        }
+
+        position += 1;
+    }
+
+    let triples = &strings[position..];
+
+    if triples.is_empty() || !triples.len().is_multiple_of(3) {
+        return RESPType::Error("Invalid syntax.".into());
+    }
+
+    let mut members = Vec::with_capacity(triples.len() / 3);
+
+    for t in triples.chunks_exact(3) {
+        let (Ok(longitude), Ok(latitude)) = (from_float_bytes(&t[0]), from_float_bytes(&t[1])) else {
+            return RESPType::Error("value is not a valid float".into());
+        };
+
+        if !geo::is_valid(longitude, latitude) {
+            return RESPType::Error(format!("invalid longitude,latitude pair {:.6},{:.6}", longitude, latitude).into());
+        }
+
+        let score = geo::encode(longitude, latitude, geo::STEP_MAX).bits as f64;
+        members.push((t[2].clone(), score));
+    }
+
+    let key = strings.swap_remove(0);
+
+    if existence_flag == ExistenceFlag::Xx && db.get(&key).is_none() {
+        return RESPType::Integer(0);
+    }
+
+    let Ok(e) = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
+        return RESPType::Error("error retrieving key".into());
+    };
+
+    if e.is_nil() {
+        *e = DBEntry::SortedSet(SortedSet::new());
+    }
+
+    let Ok(z) = e.get_mut_sorted_set() else {
+        return RESPType::Error("wrong type".into());
+    };
+
+    let mut total = 0;
+    let mut changed = false;
+
+    for (member, score) in members {
+        let previous = z.score(&member);
+
+        match (previous, &existence_flag) {
+            (Some(_), ExistenceFlag::Nx) | (None, ExistenceFlag::Xx) => continue,
+            (Some(p), _) if p == score => continue,
+            (Some(_), _) => total += count_changed as i64,
+            (None, _) => total += 1,
+        }
+
+        z.insert(member, score);
+        changed = true;
+    }
+
+    if changed {
+        db.notify(notify::ZSET, "zadd", &key);
+    }
+
+    RESPType::Integer(total)
+}
@@ -0,0 +1,61 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::DBEntry;
+use crate::geo;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "geodist",
+    arity = -4,
+    flags = ("readonly"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("read", "slow"),
+    command_tips = (),
+)]
+pub fn geodist(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 3 && args.len() != 4 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let unit = match strings.get(3) {
+        None => 1.0,
+        Some(u) => match geo::unit_to_meters(u) {
+            Some(u) => u,
+            None => return RESPType::Error("unsupported unit provided. please use M, KM, FT, MI".into()),
+        },
+    };
+
+    let z = match db.get(&strings[0]) {
+        None => return RESPType::Null,
+        Some(DBEntry::SortedSet(z)) => z,
+        Some(_) => return RESPType::Error("wrong type".into()),
+    };
+
+    let (Some(a), Some(b)) = (z.score(&strings[1]), z.score(&strings[2])) else {
+        return RESPType::Null;
+    };
+
+    let (longitude_a, latitude_a) = geo::decode_score(a);
+    let (longitude_b, latitude_b) = geo::decode_score(b);
+
+    let distance = geo::distance(longitude_a, latitude_a, longitude_b, latitude_b) / unit;
+
+    RESPType::BulkString(format!("{:.4}", distance).into())
+}
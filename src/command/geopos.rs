@@ -0,0 +1,59 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::DBEntry;
+use crate::geo;
+use crate::util::format_float;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "geopos",
+    arity = -2,
+    flags = ("readonly"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("read", "slow"),
+    command_tips = (),
+)]
+pub fn geopos(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(key) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let z = match db.get(&key) {
+        None => None,
+        Some(DBEntry::SortedSet(z)) => Some(z),
+        Some(_) => return RESPType::Error("wrong type".into()),
+    };
+
+    let mut positions = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(member) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        positions.push(match z.and_then(|z| z.score(&member)) {
+            Some(score) => {
+                let (longitude, latitude) = geo::decode_score(score);
+
+                RESPType::Array(vec![
+                    RESPType::BulkString(format_float(longitude).into()),
+                    RESPType::BulkString(format_float(latitude).into()),
+                ])
+            },
+            None => RESPType::Null,
+        });
+    }
+
+    RESPType::Array(positions)
+}
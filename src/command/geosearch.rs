@@ -0,0 +1,269 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::DBEntry;
+use crate::geo::{self, Shape};
+use crate::sorted_set::SortedSet;
+use crate::util::{format_float, from_decimal_bytes, from_float_bytes};
+
+use super::super::db::DB;
+
+
+/// Where a search is centred.
+pub enum Origin {
+    Member(Bytes),
+    Position(f64, f64),
+}
+
+
+/// The parsed options of a GEOSEARCH or GEOSEARCHSTORE command.
+pub struct SearchOptions {
+    pub origin: Origin,
+    pub shape: Shape,
+    /// The number of meters in the unit used for the shape, which is also used for reporting
+    /// distances.
+    pub unit: f64,
+    /// Sort the results in ascending (Some(true)) or descending (Some(false)) order of distance.
+    pub ascending: Option<bool>,
+    pub count: Option<usize>,
+    /// Return as soon as enough results have been found, rather than the closest results.
+    pub any: bool,
+    pub with_coordinates: bool,
+    pub with_distance: bool,
+    pub with_hash: bool,
+    pub store_distance: bool,
+}
+
+
+/// A member found by a search.
+pub struct SearchResult {
+    pub member: Bytes,
+    pub score: f64,
+    /// The distance from the origin, in meters.
+    pub distance: f64,
+}
+
+
+/// Parse the options of a search. `store` is true for GEOSEARCHSTORE, which does not
+/// accept the WITH options but does accept STOREDIST.
+pub fn parse_options(args: &[Bytes], store: bool) -> Result<SearchOptions, RESPType<Bytes>> {
+    let syntax_error = || RESPType::Error("Invalid syntax.".into());
+    let float = |b: Option<&Bytes>| b.and_then(|b| from_float_bytes(b).ok()).ok_or_else(|| RESPType::Error("value is not a valid float".into()));
+    let unit = |b: Option<&Bytes>| b.and_then(|b| geo::unit_to_meters(b)).ok_or_else(|| RESPType::Error("unsupported unit provided. please use M, KM, FT, MI".into()));
+
+    let mut origin = None;
+    let mut shape = None;
+    let mut options = SearchOptions {
+        origin: Origin::Position(0.0, 0.0),
+        shape: Shape::Radius(0.0),
+        unit: 1.0,
+        ascending: None,
+        count: None,
+        any: false,
+        with_coordinates: false,
+        with_distance: false,
+        with_hash: false,
+        store_distance: false,
+    };
+
+    let mut remaining = args.iter().peekable();
+
+    while let Some(option) = remaining.next() {
+        match &option.to_ascii_uppercase()[..] {
+            b"FROMMEMBER" if origin.is_none() => {
+                origin = Some(Origin::Member(remaining.next().ok_or_else(syntax_error)?.clone()));
+            },
+            b"FROMLONLAT" if origin.is_none() => {
+                let longitude = float(remaining.next())?;
+                let latitude = float(remaining.next())?;
+
+                if !geo::is_valid(longitude, latitude) {
+                    return Err(RESPType::Error(format!("invalid longitude,latitude pair {:.6},{:.6}", longitude, latitude).into()));
+                }
+
+                origin = Some(Origin::Position(longitude, latitude));
+            },
+            b"BYRADIUS" if shape.is_none() => {
+                let radius = float(remaining.next())?;
+                options.unit = unit(remaining.next())?;
+
+                if radius < 0.0 {
+                    return Err(RESPType::Error("radius cannot be negative".into()));
+                }
+
+                shape = Some(Shape::Radius(radius * options.unit));
+            },
+            b"BYBOX" if shape.is_none() => {
+                let width = float(remaining.next())?;
+                let height = float(remaining.next())?;
+                options.unit = unit(remaining.next())?;
+
+                if width < 0.0 || height < 0.0 {
+                    return Err(RESPType::Error("height or width cannot be negative".into()));
+                }
+
+                shape = Some(Shape::Box { width: width * options.unit, height: height * options.unit });
+            },
+            b"ASC" => options.ascending = Some(true),
+            b"DESC" => options.ascending = Some(false),
+            b"COUNT" => {
+                let count = remaining.next().and_then(|c| from_decimal_bytes(c).ok()).ok_or_else(|| RESPType::Error("value is not an integer or out of range".into()))?;
+
+                if count <= 0 {
+                    return Err(RESPType::Error("COUNT must be > 0".into()));
+                }
+
+                options.count = Some(count as usize);
+
+                if remaining.peek().is_some_and(|a| a.eq_ignore_ascii_case(b"ANY")) {
+                    remaining.next();
+                    options.any = true;
+                }
+            },
+            b"WITHCOORD" if !store => options.with_coordinates = true,
+            b"WITHDIST" if !store => options.with_distance = true,
+            b"WITHHASH" if !store => options.with_hash = true,
+            b"STOREDIST" if store => options.store_distance = true,
+            _ => return Err(syntax_error()),
+        }
+    }
+
+    let Some(origin) = origin else {
+        return Err(RESPType::Error("exactly one of FROMMEMBER or FROMLONLAT can be specified".into()));
+    };
+
+    let Some(shape) = shape else {
+        return Err(RESPType::Error("exactly one of BYRADIUS and BYBOX can be specified".into()));
+    };
+
+    if options.any && options.count.is_none() {
+        return Err(RESPType::Error("the ANY argument requires COUNT argument".into()));
+    }
+
+    options.origin = origin;
+    options.shape = shape;
+
+    Ok(options)
+}
+
+
+/// Find the members of a sorted set inside the search shape, ordered as requested.
+pub fn search(z: &SortedSet, options: &SearchOptions) -> Result<Vec<SearchResult>, RESPType<Bytes>> {
+    let (longitude, latitude) = match &options.origin {
+        Origin::Position(longitude, latitude) => (*longitude, *latitude),
+        Origin::Member(m) => match z.score(m) {
+            Some(score) => geo::decode_score(score),
+            None => return Err(RESPType::Error("could not decode requested zset member".into())),
+        },
+    };
+
+    let mut results = vec![];
+
+    'areas: for area in geo::search_areas(longitude, latitude, options.shape) {
+        let (min, max) = geo::score_range(area);
+
+        for (member, score) in z.range_by_score(min, max) {
+            let (point_longitude, point_latitude) = geo::decode_score(score);
+
+            if let Some(distance) = geo::distance_if_inside(options.shape, longitude, latitude, point_longitude, point_latitude) {
+                results.push(SearchResult { member: member.clone(), score, distance });
+
+                if options.any && Some(results.len()) == options.count {
+                    break 'areas;
+                }
+            }
+        }
+    }
+
+    // Without ANY, COUNT returns the closest results, so they need to be sorted.
+    let ascending = match options.ascending {
+        None if options.count.is_some() && !options.any => Some(true),
+        a => a,
+    };
+
+    match ascending {
+        Some(true) => results.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+        Some(false) => results.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+        None => {},
+    }
+
+    if let Some(count) = options.count {
+        results.truncate(count);
+    }
+
+    Ok(results)
+}
+
+
+#[command(
+    name = "geosearch",
+    arity = -7,
+    flags = ("readonly"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("read", "slow"),
+    command_tips = (),
+)]
+pub fn geosearch(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() < 6 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let options = match parse_options(&strings[1..], false) {
+        Ok(o) => o,
+        Err(e) => return e,
+    };
+
+    let z = match db.get(&strings[0]) {
+        None => return RESPType::Array(vec![]),
+        Some(DBEntry::SortedSet(z)) => z,
+        Some(_) => return RESPType::Error("wrong type".into()),
+    };
+
+    let results = match search(z, &options) {
+        Ok(r) => r,
+        Err(e) => return e,
+    };
+
+    let plain = !options.with_coordinates && !options.with_distance && !options.with_hash;
+
+    RESPType::Array(results.into_iter().map(|r| {
+        if plain {
+            return RESPType::BulkString(r.member);
+        }
+
+        let mut item = vec![RESPType::BulkString(r.member)];
+
+        if options.with_distance {
+            item.push(RESPType::BulkString(format!("{:.4}", r.distance / options.unit).into()));
+        }
+
+        if options.with_hash {
+            item.push(RESPType::Integer(r.score as i64));
+        }
+
+        if options.with_coordinates {
+            let (longitude, latitude) = geo::decode_score(r.score);
+
+            item.push(RESPType::Array(vec![
+                RESPType::BulkString(format_float(longitude).into()),
+                RESPType::BulkString(format_float(latitude).into()),
+            ]));
+        }
+
+        RESPType::Array(item)
+    }).collect())
+}
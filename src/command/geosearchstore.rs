@@ -0,0 +1,77 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{DBEntry, ExpiryFlag, ExistenceFlag};
+use crate::notify;
+use crate::sorted_set::SortedSet;
+
+use super::geosearch::{parse_options, search};
+use super::super::db::DB;
+
+
+#[command(
+    name = "geosearchstore",
+    arity = -8,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 2,
+    step = 1,
+    acl_categories = ("write", "slow"),
+    command_tips = (),
+)]
+pub fn geosearchstore(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() < 7 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let options = match parse_options(&strings[2..], true) {
+        Ok(o) => o,
+        Err(e) => return e,
+    };
+
+    let results = match db.get(&strings[1]) {
+        None => vec![],
+        Some(DBEntry::SortedSet(z)) => match search(z, &options) {
+            Ok(r) => r,
+            Err(e) => return e,
+        },
+        Some(_) => return RESPType::Error("wrong type".into()),
+    };
+
+    let destination = strings.swap_remove(0);
+
+    if results.is_empty() {
+        if db.delete(&destination) {
+            db.notify(notify::GENERIC, "del", &destination);
+        }
+
+        return RESPType::Integer(0);
+    }
+
+    let mut z = SortedSet::new();
+
+    for r in results {
+        let score = if options.store_distance { r.distance / options.unit } else { r.score };
+        z.insert(r.member, score);
+    }
+
+    let len = z.len() as i64;
+
+    let e = db.get_or_insert(destination.clone(), ExpiryFlag::None, ExistenceFlag::None).unwrap();
+    *e = DBEntry::SortedSet(z);
+    db.notify(notify::ZSET, "geosearchstore", &destination);
+
+    RESPType::Integer(len)
+}
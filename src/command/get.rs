@@ -11,13 +11,13 @@ use super::super::db::DB;
 
 #[command(
     name = "get",
-    arity = -1,
-    flags = ("fast", "sentinel"),
+    arity = 2,
+    flags = ("readonly", "fast"),
     first_key = 1,
-    last_key = 2,
+    last_key = 1,
     step = 1,
     acl_categories = ("connection"),
-    command_tips = ("request_policy:all_shards", "response_policy:all_succeeded"),
+    command_tips = (),
 )]
 pub fn get(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
     if args.len() != 1 {
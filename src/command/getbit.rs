@@ -0,0 +1,43 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::bitops::get_bit;
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "getbit",
+    arity = 3,
+    flags = ("readonly", "fast"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("read", "fast"),
+    command_tips = (),
+)]
+pub fn getbit(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 2 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let (RESPType::BulkString(offset), RESPType::BulkString(key)) = (args.remove(1), args.remove(0)) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let Some(offset) = from_decimal_bytes(&offset).ok().and_then(|o| u64::try_from(o).ok()) else {
+        return RESPType::Error("bit offset is not an integer or out of range".into());
+    };
+
+    let Some(e) = db.get(&key) else {
+        return RESPType::Integer(0);
+    };
+
+    match e.get_string() {
+        Ok(s) => RESPType::Integer(get_bit(&s.as_slice(), offset) as i64),
+        Err(_) => RESPType::Error("wrong type".into()),
+    }
+}
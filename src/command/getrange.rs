@@ -0,0 +1,42 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "getrange",
+    arity = 4,
+    flags = ("readonly"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("read", "slow"),
+    command_tips = (),
+)]
+pub fn getrange(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 3 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let (RESPType::BulkString(end), RESPType::BulkString(start), RESPType::BulkString(key)) = (args.remove(2), args.remove(1), args.remove(0)) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let (Ok(start), Ok(end)) = (from_decimal_bytes(&start), from_decimal_bytes(&end)) else {
+        return RESPType::Error("value is not an integer or out of range".into());
+    };
+
+    let Some(e) = db.get(&key) else {
+        return RESPType::BulkString(Bytes::new());
+    };
+
+    match e.get_string() {
+        Ok(s) => RESPType::BulkString(s.get_range(start, end)),
+        Err(_) => RESPType::Error("wrong type".into()),
+    }
+}
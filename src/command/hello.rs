@@ -0,0 +1,108 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+use super::auth::authenticate;
+
+
+/// Switch the protocol the connection speaks, and describe the server.
+#[command(
+    name = "hello",
+    arity = -1,
+    flags = ("fast", "no_auth", "sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("fast", "connection"),
+    command_tips = (),
+)]
+pub fn hello(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(String::from_utf8_lossy(&s).into_owned());
+    }
+
+    let mut protocol = None;
+    let mut name = None;
+    let mut credentials = None;
+
+    if let Some((version, options)) = strings.split_first() {
+        let Ok(version) = version.parse::<i64>() else {
+            return Some(RESPType::Error("Protocol version is not an integer or out of range".into()));
+        };
+
+        if !(2..=3).contains(&version) {
+            return Some(RESPType::Error("NOPROTO unsupported protocol version".into()));
+        }
+
+        protocol = Some(version as u8);
+
+        let mut options = options.iter();
+
+        while let Some(option) = options.next() {
+            match (option.to_ascii_uppercase().as_str(), options.len()) {
+                ("AUTH", 2..) => credentials = Some((options.next().unwrap(), options.next().unwrap())),
+                ("SETNAME", 1..) => {
+                    let value = options.next().unwrap();
+
+                    if value.bytes().any(|c| !(b'!'..=b'~').contains(&c)) {
+                        return Some(RESPType::Error("Client names cannot contain spaces, newlines or special characters.".into()));
+                    }
+
+                    name = Some(value.clone());
+                },
+                _ => return Some(RESPType::Error(format!("Syntax error in HELLO option '{}'", option).into())),
+            }
+        }
+    }
+
+    // Nothing changes unless the credentials are right.
+    if let Some((user, password)) = credentials {
+        if let Err(e) = authenticate(server, token, user, password) {
+            return Some(RESPType::Error(e.into()));
+        }
+    }
+
+    let client = server.client_mut(token)?;
+
+    if !client.authenticated {
+        return Some(RESPType::Error("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".into()));
+    }
+
+    if let Some(protocol) = protocol {
+        client.protocol = protocol;
+    }
+
+    if let Some(name) = name {
+        client.name = (!name.is_empty()).then(|| name.into());
+    }
+
+    let (id, protocol) = (client.id as i64, client.protocol as i64);
+
+    let mode = match (&server.cluster, &server.sentinel) {
+        (Some(_), _) => "cluster",
+        (_, Some(_)) => "sentinel",
+        _ => "standalone",
+    };
+
+    let role = if server.replication.is_replica() { "replica" } else { "master" };
+
+    Some(server.map_reply(token, vec![
+        (RESPType::BulkString("server".into()), RESPType::BulkString("sider".into())),
+        (RESPType::BulkString("version".into()), RESPType::BulkString(env!("CARGO_PKG_VERSION").into())),
+        (RESPType::BulkString("proto".into()), RESPType::Integer(protocol)),
+        (RESPType::BulkString("id".into()), RESPType::Integer(id)),
+        (RESPType::BulkString("mode".into()), RESPType::BulkString(mode.into())),
+        (RESPType::BulkString("role".into()), RESPType::BulkString(role.into())),
+        (RESPType::BulkString("modules".into()), RESPType::Array(Vec::new())),
+    ]))
+}
+
@@ -6,6 +6,7 @@ use command_macro::command;
 
 use sider_command::RESPType;
 use crate::db::DBError;
+use crate::notify;
 
 use super::super::db::DB;
 
@@ -13,13 +14,13 @@ use super::super::db::DB;
 
 #[command(
     name = "incr",
-    arity = -1,
-    flags = ("fast", "sentinel"),
+    arity = 2,
+    flags = ("write", "fast"),
     first_key = 1,
-    last_key = 2,
+    last_key = 1,
     step = 1,
     acl_categories = ("connection"),
-    command_tips = ("request_policy:all_shards", "response_policy:all_succeeded"),
+    command_tips = (),
 )]
 pub fn incr(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
     if args.len() != 1 {
@@ -30,7 +31,7 @@ pub fn incr(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
         return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
     };
 
-    let Ok(e) = db.get_or_insert(key, crate::db::ExpiryFlag::KeepTTL, crate::db::ExistenceFlag::None) else {
+    let Ok(e) = db.get_or_insert(key.clone(), crate::db::ExpiryFlag::KeepTTL, crate::db::ExistenceFlag::None) else {
         return RESPType::Error("error retrieving key".into());
     };
 
@@ -46,7 +47,11 @@ pub fn incr(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
     };
 
     match v.incr_by(1) {
-        Ok(i) => RESPType::Integer(i),
-        Err(()) => RESPType::Error("value at key is not a valid integer".into())
+        Ok(i) => {
+            db.notify(notify::STRING, "incrby", &key);
+            RESPType::Integer(i)
+        },
+        Err(DBError::Overflow) => RESPType::Error("increment or decrement would overflow".into()),
+        Err(_) => RESPType::Error("value at key is not a valid integer".into())
     }
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{DBError, ExpiryFlag, ExistenceFlag};
+use crate::notify;
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "incrby",
+    arity = 3,
+    flags = ("write", "fast"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "fast"),
+    command_tips = (),
+)]
+pub fn incrby(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 2 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(increment) = args.remove(1) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let RESPType::BulkString(key) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let Ok(increment) = from_decimal_bytes(&increment) else {
+        return RESPType::Error("value is not an integer or out of range".into());
+    };
+
+    let Ok(e) = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
+        return RESPType::Error("error retrieving key".into());
+    };
+
+    if e.is_nil() {
+        e.set_string(0.into());
+    }
+
+    let Ok(v) = e.get_mut_string() else {
+        return RESPType::Error("wrong type".into());
+    };
+
+    match v.incr_by(increment) {
+        Ok(i) => {
+            db.notify(notify::STRING, "incrby", &key);
+            RESPType::Integer(i)
+        },
+        Err(DBError::Overflow) => RESPType::Error("increment or decrement would overflow".into()),
+        Err(_) => RESPType::Error("value is not an integer or out of range".into()),
+    }
+}
@@ -0,0 +1,88 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{DBError, ExpiryFlag, ExistenceFlag};
+use crate::notify;
+use crate::util::from_float_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "incrbyfloat",
+    arity = 3,
+    flags = ("write", "fast"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "fast"),
+    command_tips = (),
+)]
+pub fn incrbyfloat(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 2 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(increment) = args.remove(1) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let RESPType::BulkString(key) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    if from_float_bytes(&increment).is_err() {
+        return RESPType::Error("value is not a valid float".into());
+    }
+
+    let Ok(e) = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
+        return RESPType::Error("error retrieving key".into());
+    };
+
+    if e.is_nil() {
+        e.set_string(0.into());
+    }
+
+    let Ok(v) = e.get_mut_string() else {
+        return RESPType::Error("wrong type".into());
+    };
+
+    match v.incr_by_float(&increment) {
+        Ok(value) => {
+            db.notify(notify::STRING, "incrbyfloat", &key);
+            RESPType::BulkString(value)
+        },
+        Err(DBError::Overflow) => RESPType::Error("increment would produce NaN or Infinity".into()),
+        Err(_) => RESPType::Error("value is not a valid float".into()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use super::incrbyfloat;
+
+    #[test]
+    fn test_incrbyfloat() {
+        let mut db = DB::new();
+
+        assert_eq!(incrbyfloat(args(&["k", "0.1"]), &mut db), RESPType::BulkString("0.1".into()));
+        assert_eq!(incrbyfloat(args(&["k", "0.2"]), &mut db), RESPType::BulkString("0.3".into()));
+        assert_eq!(db.get(&"k".into()), Some(&DBEntry::String(DBString::String("0.3".into()))));
+
+        assert_eq!(incrbyfloat(args(&["k", "-0.3"]), &mut db), RESPType::BulkString("0".into()));
+        assert_eq!(incrbyfloat(args(&["k", "10.5"]), &mut db), RESPType::BulkString("10.5".into()));
+        assert_eq!(incrbyfloat(args(&["k", "5.0e3"]), &mut db), RESPType::BulkString("5010.5".into()));
+
+        assert_eq!(incrbyfloat(args(&["big", "1e300"]), &mut db), RESPType::BulkString("1e+300".into()));
+        assert_eq!(incrbyfloat(args(&["big", "1e300"]), &mut db), RESPType::BulkString("2e+300".into()));
+        assert_eq!(incrbyfloat(args(&["max", "1e308"]), &mut db), RESPType::BulkString("1e+308".into()));
+        assert_eq!(incrbyfloat(args(&["max", "1e308"]), &mut db), RESPType::Error("increment would produce NaN or Infinity".into()));
+        assert_eq!(incrbyfloat(args(&["k", "abc"]), &mut db), RESPType::Error("value is not a valid float".into()));
+    }
+}
@@ -0,0 +1,264 @@
+
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::memory;
+use crate::server::Server;
+
+
+/// The sections reported when no section is asked for. INFO ALL adds the command stats.
+const DEFAULT_SECTIONS: &[&str] = &["server", "clients", "memory", "persistence", "stats", "replication", "sentinel", "keyspace", "errorstats"];
+
+
+fn server_section(server: &Server, out: &mut String) {
+    let mode = match (&server.cluster, &server.sentinel) {
+        (Some(_), _) => "cluster",
+        (_, Some(_)) => "sentinel",
+        _ => "standalone",
+    };
+
+    let uptime = server.started.elapsed().as_secs();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros();
+    let executable = std::env::current_exe().map(|p| p.display().to_string()).unwrap_or_default();
+    let config_file = server.config.file.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+
+    writeln!(out, "sider_version:{}\r", env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(out, "redis_mode:{}\r", mode).unwrap();
+    writeln!(out, "os:{} {}\r", std::env::consts::OS, std::env::consts::ARCH).unwrap();
+    writeln!(out, "arch_bits:{}\r", usize::BITS).unwrap();
+    writeln!(out, "process_id:{}\r", std::process::id()).unwrap();
+    writeln!(out, "run_id:{}\r", server.run_id).unwrap();
+    writeln!(out, "tcp_port:{}\r", server.config.port).unwrap();
+    writeln!(out, "server_time_usec:{}\r", now).unwrap();
+    writeln!(out, "uptime_in_seconds:{}\r", uptime).unwrap();
+    writeln!(out, "uptime_in_days:{}\r", uptime / (60 * 60 * 24)).unwrap();
+    writeln!(out, "hz:{}\r", server.config.hz).unwrap();
+    writeln!(out, "executable:{}\r", executable).unwrap();
+    writeln!(out, "config_file:{}\r", config_file).unwrap();
+}
+
+
+fn clients_section(server: &Server, out: &mut String) {
+    writeln!(out, "connected_clients:{}\r", server.clients().count()).unwrap();
+    writeln!(out, "blocked_clients:{}\r", server.clients().filter(|c| c.is_blocked()).count()).unwrap();
+    writeln!(out, "pubsub_clients:{}\r", server.clients().filter(|c| !c.subscriptions.is_empty()).count()).unwrap();
+    writeln!(out, "maxclients:{}\r", server.config.maxclients).unwrap();
+    writeln!(out, "tracking_clients:{}\r", server.clients().filter(|c| c.tracking.is_some()).count()).unwrap();
+}
+
+
+fn memory_section(out: &mut String) {
+    let used = memory::used_memory();
+    let peak = memory::peak_memory();
+
+    writeln!(out, "used_memory:{}\r", used).unwrap();
+    writeln!(out, "used_memory_human:{}\r", memory::human_bytes(used)).unwrap();
+
+    if let Some(rss) = memory::rss() {
+        writeln!(out, "used_memory_rss:{}\r", rss).unwrap();
+        writeln!(out, "used_memory_rss_human:{}\r", memory::human_bytes(rss)).unwrap();
+    }
+
+    writeln!(out, "used_memory_peak:{}\r", peak).unwrap();
+    writeln!(out, "used_memory_peak_human:{}\r", memory::human_bytes(peak)).unwrap();
+}
+
+
+fn persistence_section(out: &mut String) {
+    // Snapshots are only made to send to replicas, and there is no append only file.
+    writeln!(out, "loading:0\r").unwrap();
+    writeln!(out, "aof_enabled:0\r").unwrap();
+}
+
+
+fn stats_section(server: &Server, out: &mut String) {
+    let stats = &server.stats;
+
+    writeln!(out, "total_connections_received:{}\r", stats.total_connections_received).unwrap();
+    writeln!(out, "total_commands_processed:{}\r", stats.total_commands_processed).unwrap();
+    writeln!(out, "instantaneous_ops_per_sec:{}\r", stats.instantaneous_ops_per_sec()).unwrap();
+    writeln!(out, "rejected_connections:{}\r", stats.rejected_connections).unwrap();
+    writeln!(out, "expired_keys:{}\r", server.db.stats.expired).unwrap();
+    // There is no memory limit, so keys are never evicted.
+    writeln!(out, "evicted_keys:0\r").unwrap();
+    writeln!(out, "keyspace_hits:{}\r", server.db.stats.hits).unwrap();
+    writeln!(out, "keyspace_misses:{}\r", server.db.stats.misses).unwrap();
+    writeln!(out, "pubsub_channels:{}\r", server.channel_count()).unwrap();
+    writeln!(out, "tracking_total_keys:{}\r", server.tracking.key_count()).unwrap();
+    writeln!(out, "tracking_total_prefixes:{}\r", server.tracking.prefix_count()).unwrap();
+    writeln!(out, "total_error_replies:{}\r", stats.total_error_replies).unwrap();
+}
+
+
+fn replication_section(server: &Server, out: &mut String) {
+    let replication = &server.replication;
+
+    match &replication.primary {
+        Some(link) => {
+            writeln!(out, "role:slave\r").unwrap();
+            writeln!(out, "master_host:{}\r", link.host).unwrap();
+            writeln!(out, "master_port:{}\r", link.port).unwrap();
+            writeln!(out, "master_link_status:{}\r", if link.state.name() == "connected" { "up" } else { "down" }).unwrap();
+            writeln!(out, "master_last_io_seconds_ago:{}\r", link.last_received.elapsed().as_secs()).unwrap();
+            writeln!(out, "slave_repl_offset:{}\r", replication.offset).unwrap();
+            writeln!(out, "slave_read_only:{}\r", server.config.replica_read_only as u8).unwrap();
+        },
+        None => writeln!(out, "role:master\r").unwrap(),
+    }
+
+    let replicas: Vec<_> = server.clients().filter_map(|c| Some((c, c.replica.as_ref()?))).collect();
+
+    writeln!(out, "connected_slaves:{}\r", replicas.len()).unwrap();
+
+    for (i, (client, replica)) in replicas.iter().enumerate() {
+        let state = if replica.online { "online" } else { "wait_bgsave" };
+
+        writeln!(out, "slave{}:ip={},port={},state={},offset={}\r", i, client.address.host(), replica.listening_port, state, replica.ack_offset).unwrap();
+    }
+
+    writeln!(out, "master_replid:{}\r", replication.id).unwrap();
+    writeln!(out, "master_replid2:{}\r", replication.second_id).unwrap();
+    writeln!(out, "master_repl_offset:{}\r", replication.offset).unwrap();
+    writeln!(out, "second_repl_offset:{}\r", replication.second_id_offset.map_or(-1, |o| o as i64)).unwrap();
+
+    match &replication.backlog {
+        Some(backlog) => {
+            writeln!(out, "repl_backlog_active:1\r").unwrap();
+            writeln!(out, "repl_backlog_size:{}\r", backlog.size()).unwrap();
+            writeln!(out, "repl_backlog_first_byte_offset:{}\r", backlog.first_offset()).unwrap();
+            writeln!(out, "repl_backlog_histlen:{}\r", backlog.history_len()).unwrap();
+        },
+        None => writeln!(out, "repl_backlog_active:0\r").unwrap(),
+    }
+}
+
+
+fn sentinel_section(server: &Server, out: &mut String) {
+    let Some(sentinel) = &server.sentinel else {
+        return;
+    };
+
+    let mut masters: Vec<_> = sentinel.masters.values().collect();
+    masters.sort_by(|a, b| a.name.cmp(&b.name));
+
+    writeln!(out, "sentinel_masters:{}\r", masters.len()).unwrap();
+
+    for (i, master) in masters.into_iter().enumerate() {
+        let status = if master.odown { "odown" } else if master.instance.sdown { "sdown" } else { "ok" };
+
+        writeln!(
+            out,
+            "master{}:name={},status={},address={},slaves={},sentinels={}\r",
+            i, master.name, status, master.instance.address(), master.replicas.len(), master.sentinels.len() + 1,
+        ).unwrap();
+    }
+}
+
+
+fn keyspace_section(server: &Server, out: &mut String) {
+    let keys = server.db.key_count();
+
+    // There is only one database.
+    if keys > 0 {
+        let average_ttl = server.db.average_ttl().map_or(0, |t| t.as_millis());
+
+        writeln!(out, "db0:keys={},expires={},avg_ttl={}\r", keys, server.db.volatile_count(), average_ttl).unwrap();
+    }
+}
+
+
+fn commandstats_section(server: &Server, out: &mut String) {
+    let mut commands: Vec<_> = server.stats.commands.iter().collect();
+    commands.sort_by_key(|(name, _)| **name);
+
+    for (name, stats) in commands {
+        let per_call = if stats.calls > 0 { stats.usec as f64 / stats.calls as f64 } else { 0.0 };
+
+        writeln!(
+            out,
+            "cmdstat_{}:calls={},usec={},usec_per_call={:.2},rejected_calls={},failed_calls={}\r",
+            name.replace('-', "|"), stats.calls, stats.usec, per_call, stats.rejected_calls, stats.failed_calls,
+        ).unwrap();
+    }
+}
+
+
+fn errorstats_section(server: &Server, out: &mut String) {
+    let mut errors: Vec<_> = server.stats.errors.iter().collect();
+    errors.sort();
+
+    for (code, count) in errors {
+        writeln!(out, "errorstat_{}:count={}\r", code, count).unwrap();
+    }
+}
+
+
+/// Report on the state of the server. Sections can be picked by name, and `all` or
+/// `everything` gives every section.
+#[command(
+    name = "info",
+    arity = -1,
+    flags = ("sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("slow", "dangerous"),
+    command_tips = ("non_deterministic_output"),
+)]
+pub fn info(args: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    let mut requested = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        requested.push(String::from_utf8_lossy(&s).to_ascii_lowercase());
+    }
+
+    let everything = requested.iter().any(|s| s == "all" || s == "everything");
+    let default = requested.is_empty() || requested.iter().any(|s| s == "default");
+
+    let wanted = |section: &str| {
+        everything
+            || (default && DEFAULT_SECTIONS.contains(&section))
+            || requested.iter().any(|s| s == section)
+    };
+
+    type Section = fn(&Server, &mut String);
+
+    let sections: &[(&str, &str, Section)] = &[
+        ("server", "Server", server_section),
+        ("clients", "Clients", clients_section),
+        ("memory", "Memory", |_, out| memory_section(out)),
+        ("persistence", "Persistence", |_, out| persistence_section(out)),
+        ("stats", "Stats", stats_section),
+        ("replication", "Replication", replication_section),
+        ("sentinel", "Sentinel", sentinel_section),
+        ("commandstats", "Commandstats", commandstats_section),
+        ("errorstats", "Errorstats", errorstats_section),
+        ("keyspace", "Keyspace", keyspace_section),
+    ];
+
+    let mut out = String::new();
+
+    for (name, title, section) in sections {
+        if !wanted(name) || (*name == "sentinel" && server.sentinel.is_none()) {
+            continue;
+        }
+
+        if !out.is_empty() {
+            out.push_str("\r\n");
+        }
+
+        writeln!(out, "# {}\r", title).unwrap();
+        section(server, &mut out);
+    }
+
+    Some(RESPType::BulkString(out.into()))
+}
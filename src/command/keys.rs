@@ -0,0 +1,36 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::util::glob_match;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "keys",
+    arity = 2,
+    flags = ("readonly"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("keyspace", "read", "slow"),
+    command_tips = ("request_policy:all_shards", "non_deterministic_output_order"),
+)]
+pub fn keys(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 1 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(pattern) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    RESPType::Array(
+        db.keys()
+            .filter(|k| glob_match(&pattern, k))
+            .map(|k| RESPType::BulkString(k.clone()))
+            .collect()
+    )
+}
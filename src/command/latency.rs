@@ -0,0 +1,135 @@
+
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::latency::History;
+use crate::server::Server;
+
+
+const HELP: &[&str] = &[
+    "LATENCY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "DOCTOR",
+    "    Return a human readable latency analysis report.",
+    "HISTORY <event>",
+    "    Return time-latency samples for the <event> class.",
+    "LATEST",
+    "    Return the latest latency samples for all events.",
+    "RESET [<event> ...]",
+    "    Reset latency data of one or more <event> classes.",
+    "    (default: reset all data for all event classes)",
+    "HELP",
+    "    Print this help.",
+];
+
+
+/// What to look into for each kind of event, for LATENCY DOCTOR.
+fn advice(event: &str) -> &'static str {
+    match event {
+        "command" | "fast-command" => "Check SLOWLOG GET for the commands which are slow. Commands like KEYS take time in proportion to the size of the data set.",
+        "expire-cycle" => "Many keys are expiring at the same time. Spreading out their expiry times avoids this.",
+        "snapshot" => "Making a snapshot for a replica to sync from takes time in proportion to the size of the data set. Check why replicas need full resyncs.",
+        _ => "",
+    }
+}
+
+
+fn doctor(server: &Server) -> String {
+    if server.latency.events.is_empty() {
+        return match server.config.latency_monitor_threshold {
+            0 => "Latency monitoring is disabled. Use \"CONFIG SET latency-monitor-threshold <milliseconds>\" to enable it.\n".into(),
+            _ => "No latency spikes have been observed since the latency monitor was enabled.\n".into(),
+        };
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let mut events: Vec<(&&str, &History)> = server.latency.events.iter().collect();
+    events.sort_by_key(|(event, _)| **event);
+
+    let mut out = String::from("Latency spikes were observed for the following events:\n\n");
+
+    for (i, (event, history)) in events.iter().enumerate() {
+        let count = history.samples.len().max(1) as f64;
+        let average = history.samples.iter().map(|s| s.latency).sum::<u64>() as f64 / count;
+        let deviation = history.samples.iter().map(|s| (s.latency as f64 - average).abs()).sum::<f64>() / count;
+        let period = history.samples.front().map_or(0.0, |s| now.saturating_sub(s.time) as f64 / count);
+
+        writeln!(
+            out,
+            "{}. {}: {} latency spikes (average {:.0}ms, mean deviation {:.0}ms, period {:.2} sec). Worst all time event {}ms.",
+            i + 1, event, history.samples.len(), average, deviation, period, history.max,
+        ).unwrap();
+    }
+
+    out.push_str("\nAdvice:\n\n");
+
+    for (event, _) in events {
+        writeln!(out, "- {}: {}", event, advice(event)).unwrap();
+    }
+
+    out
+}
+
+
+/// Inspect spikes in latency, recorded when they are over `latency-monitor-threshold`.
+#[command(
+    name = "latency",
+    arity = -2,
+    flags = ("admin"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "slow", "dangerous"),
+    command_tips = ("non_deterministic_output"),
+)]
+pub fn latency(args: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    if args.is_empty() {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(String::from_utf8_lossy(&s).into_owned());
+    }
+
+    let subcommand = strings.remove(0).to_ascii_uppercase();
+
+    Some(match (subcommand.as_str(), &strings[..]) {
+        ("LATEST", []) => {
+            let mut events: Vec<_> = server.latency.events.iter().collect();
+            events.sort_by_key(|(event, _)| **event);
+
+            RESPType::Array(events.into_iter()
+                .filter_map(|(event, history)| {
+                    let latest = history.samples.back()?;
+
+                    Some(RESPType::Array(vec![
+                        RESPType::BulkString(Bytes::from_static(event.as_bytes())),
+                        RESPType::Integer(latest.time as i64),
+                        RESPType::Integer(latest.latency as i64),
+                        RESPType::Integer(history.max as i64),
+                    ]))
+                })
+                .collect())
+        },
+        ("HISTORY", [event]) => RESPType::Array(server.latency.events.get(event.as_str())
+            .map(|h| h.samples.iter()
+                .map(|s| RESPType::Array(vec![RESPType::Integer(s.time as i64), RESPType::Integer(s.latency as i64)]))
+                .collect())
+            .unwrap_or_default()),
+        ("RESET", events) => RESPType::Integer(server.latency.reset(events) as i64),
+        ("DOCTOR", []) => RESPType::BulkString(doctor(server).into()),
+        ("HELP", []) => RESPType::Array(HELP.iter().map(|l| RESPType::SimpleString(Bytes::from_static(l.as_bytes()))).collect()),
+        ("LATEST" | "HISTORY" | "DOCTOR" | "HELP", _) => RESPType::Error("wrong number of arguments".into()),
+        _ => RESPType::Error("unknown subcommand, try LATENCY HELP".into()),
+    })
+}
@@ -7,20 +7,21 @@ use command_macro::command;
 
 use sider_command::RESPType;
 use crate::db::{ExpiryFlag, ExistenceFlag, DBEntry};
+use crate::notify;
 
 use super::super::db::DB;
 
 
 
 #[command(
-    name = "incr",
-    arity = -1,
-    flags = ("fast", "sentinel"),
+    name = "lpush",
+    arity = -3,
+    flags = ("write", "fast"),
     first_key = 1,
-    last_key = 2,
+    last_key = 1,
     step = 1,
     acl_categories = ("connection"),
-    command_tips = ("request_policy:all_shards", "response_policy:all_succeeded"),
+    command_tips = (),
 )]
 pub fn lpush(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
     if args.len() < 2 {
@@ -31,7 +32,7 @@ pub fn lpush(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
         return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
     };
 
-    let entry = db.get_or_insert(key, ExpiryFlag::KeepTTL, ExistenceFlag::None).unwrap();
+    let entry = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None).unwrap();
 
     let l = match entry {
         DBEntry::List(l) => l,
@@ -46,6 +47,7 @@ pub fn lpush(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
     };
 
     l.push_front("v".into());
+    db.notify(notify::LIST, "lpush", &key);
 
 
     // let entry = match db.get_list(key) {
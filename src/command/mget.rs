@@ -0,0 +1,41 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::DBEntry;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "mget",
+    arity = -2,
+    flags = ("readonly", "fast"),
+    first_key = 1,
+    last_key = -1,
+    step = 1,
+    acl_categories = ("read", "fast"),
+    command_tips = ("request_policy:multi_shard"),
+)]
+pub fn mget(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut values = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(key) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        // Keys which do not exist or do not hold strings are both reported as null.
+        values.push(match db.get(&key) {
+            Some(DBEntry::String(s)) => RESPType::BulkString(s.to_bytes()),
+            _ => RESPType::Null,
+        });
+    }
+
+    RESPType::Array(values)
+}
@@ -0,0 +1,168 @@
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::Utc;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::notify;
+use crate::parser::QueryBuffer;
+use crate::serializer::serialize;
+use crate::server::Server;
+use crate::snapshot;
+use crate::util::from_decimal_bytes;
+
+
+/// Move keys to another instance, by sending them with RESTORE-ASKING and then deleting them
+/// here. This blocks the server until the target has replied or the timeout has passed, as it
+/// does in Redis.
+#[command(
+    name = "migrate",
+    arity = -6,
+    flags = ("write"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("keyspace", "write", "slow", "dangerous"),
+    command_tips = ("non_deterministic_output"),
+)]
+pub fn migrate(args: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    if args.len() < 5 {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(s);
+    }
+
+    let mut copy = false;
+    let mut replace = false;
+    let mut keys = vec![];
+
+    let mut options = strings[5..].iter();
+
+    while let Some(option) = options.next() {
+        match &option.to_ascii_uppercase()[..] {
+            b"COPY" => copy = true,
+            b"REPLACE" => replace = true,
+            b"KEYS" if strings[2].is_empty() => {
+                keys.extend(options.by_ref().cloned());
+            },
+            b"KEYS" => return Some(RESPType::Error("When using MIGRATE KEYS option, the key argument must be set to the empty string".into())),
+            _ => return Some(RESPType::Error("Invalid syntax.".into())),
+        }
+    }
+
+    if !strings[2].is_empty() {
+        keys.push(strings[2].clone());
+    }
+
+    let (Ok(port), Ok(db), Ok(timeout)) = (from_decimal_bytes(&strings[1]), from_decimal_bytes(&strings[3]), from_decimal_bytes(&strings[4])) else {
+        return Some(RESPType::Error("value is not an integer or out of range".into()));
+    };
+
+    // There is only one database.
+    if db != 0 {
+        return Some(RESPType::Error("DB index is out of range".into()));
+    }
+
+    let timeout = Duration::from_millis(if timeout > 0 { timeout as u64 } else { 1000 });
+
+    let mut payloads = vec![];
+
+    for key in keys {
+        let Some(payload) = server.db.get(&key).filter(|e| !e.is_nil()).map(snapshot::dump_value) else {
+            continue;
+        };
+
+        let ttl = server.db.expiry(&key).map_or(0, |e| (e - Utc::now()).num_milliseconds().max(1));
+
+        payloads.push((key, ttl, payload));
+    }
+
+    if payloads.is_empty() {
+        return Some(RESPType::SimpleString("NOKEY".into()));
+    }
+
+    let host = String::from_utf8_lossy(&strings[0]).into_owned();
+
+    let mut stream = match connect(&host, port, timeout) {
+        Ok(s) => s,
+        Err(_) => return Some(RESPType::Error("IOERR error or timeout connecting to the client".into())),
+    };
+
+    let mut request = Vec::new();
+
+    for (key, ttl, payload) in &payloads {
+        let mut command = vec![
+            RESPType::BulkString(Bytes::from("RESTORE-ASKING")),
+            RESPType::BulkString(key.clone()),
+            RESPType::BulkString(ttl.to_string().into()),
+            RESPType::BulkString(payload.clone().into()),
+        ];
+
+        if replace {
+            command.push(RESPType::BulkString(Bytes::from("REPLACE")));
+        }
+
+        serialize(&RESPType::Array(command), &mut request).unwrap();
+    }
+
+    if stream.write_all(&request).is_err() {
+        return Some(RESPType::Error("IOERR error or timeout writing to target instance".into()));
+    }
+
+    let mut replies = QueryBuffer::new();
+    let mut buffer = [0; 1024];
+    let mut error = None;
+
+    for (key, _, _) in payloads {
+        let reply = loop {
+            if let Some((reply, _)) = replies.next_value() {
+                break reply;
+            }
+
+            match stream.read(&mut buffer) {
+                Ok(n) if n > 0 => replies.extend_from_slice(&buffer[..n]),
+                _ => return Some(RESPType::Error("IOERR error or timeout reading to target instance".into())),
+            }
+        };
+
+        match reply {
+            RESPType::Error(e) => error = Some(e),
+            _ if copy => {},
+            _ => {
+                server.db.delete(&key);
+                server.db.notify(notify::GENERIC, "del", &key);
+                server.propagate(vec![RESPType::BulkString(Bytes::from("DEL")), RESPType::BulkString(key)]);
+            },
+        }
+    }
+
+    Some(match error {
+        Some(e) => RESPType::Error(format!("Target instance replied with error: {}", String::from_utf8_lossy(&e)).into()),
+        None => RESPType::SimpleString("OK".into()),
+    })
+}
+
+
+fn connect(host: &str, port: i64, timeout: Duration) -> std::io::Result<TcpStream> {
+    let port = u16::try_from(port).map_err(|_| ErrorKind::InvalidInput)?;
+    let address = (host, port).to_socket_addrs()?.next().ok_or(ErrorKind::NotFound)?;
+
+    let stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    Ok(stream)
+}
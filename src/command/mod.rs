@@ -2,30 +2,161 @@
 use phf::Map;
 use phf_macros::phf_map;
 
+pub(crate) use base::{Command, Handler};
+
 mod base;
 mod responses;
 
+mod append;
+mod asking;
+mod auth;
+mod bitcount;
+mod bitfield;
+mod bitfield_ro;
+mod bitop;
+mod bitpos;
+mod client;
+mod cluster;
 mod command;
+mod config;
+mod copy;
 mod decr;
+mod decrby;
 mod del;
+mod dump;
 mod echo;
 mod exists;
+mod geoadd;
+mod geodist;
+mod geopos;
+mod geosearch;
+mod geosearchstore;
 mod get;
+mod getbit;
+mod getrange;
+mod hello;
 mod incr;
+mod incrby;
+mod incrbyfloat;
+mod info;
+mod keys;
+mod latency;
 mod lpush;
+mod mget;
+mod migrate;
+mod monitor;
+mod mset;
+mod msetnx;
+mod object;
+mod pfadd;
+mod pfcount;
+mod pfmerge;
 mod ping;
+mod psync;
+mod publish;
+mod randomkey;
+mod rename;
+mod renamenx;
+mod replconf;
+mod replicaof;
+mod restore;
+mod restore_asking;
+mod role;
+mod scan;
+mod sentinel;
 mod set;
+mod setbit;
+mod setex;
+mod setnx;
+mod setrange;
+mod shutdown;
+mod slowlog;
+mod strlen;
+mod subscribe;
+mod touch;
+mod r#type;
+mod unlink;
+mod unsubscribe;
+mod wait;
 
 
 pub(crate) const COMMAND_TABLE: Map<&'static [u8], base::Command> = phf_map! {
     // b"command" => command::CommandImpl::into_command(),
+    b"append" => append::Append::into_command(),
+    b"asking" => asking::Asking::into_command(),
+    b"auth" => auth::Auth::into_command(),
+    b"bitcount" => bitcount::Bitcount::into_command(),
+    b"bitfield" => bitfield::Bitfield::into_command(),
+    b"bitfield_ro" => bitfield_ro::BitfieldRo::into_command(),
+    b"bitop" => bitop::Bitop::into_command(),
+    b"bitpos" => bitpos::Bitpos::into_command(),
+    b"client" => client::Client::into_command(),
+    b"cluster" => cluster::Cluster::into_command(),
+    b"config" => config::Config::into_command(),
+    b"copy" => copy::Copy::into_command(),
     b"decr" => decr::Decr::into_command(),
+    b"decrby" => decrby::Decrby::into_command(),
     b"del" => del::Del::into_command(),
+    b"dump" => dump::Dump::into_command(),
     b"echo" => echo::Echo::into_command(),
     b"exists" => exists::Exists::into_command(),
+    b"geoadd" => geoadd::Geoadd::into_command(),
+    b"geodist" => geodist::Geodist::into_command(),
+    b"geopos" => geopos::Geopos::into_command(),
+    b"geosearch" => geosearch::Geosearch::into_command(),
+    b"geosearchstore" => geosearchstore::Geosearchstore::into_command(),
     b"get" => get::Get::into_command(),
+    b"getbit" => getbit::Getbit::into_command(),
+    b"getrange" => getrange::Getrange::into_command(),
+    b"hello" => hello::Hello::into_command(),
     b"incr" => incr::Incr::into_command(),
+    b"incrby" => incrby::Incrby::into_command(),
+    b"incrbyfloat" => incrbyfloat::Incrbyfloat::into_command(),
+    b"info" => info::Info::into_command(),
+    b"keys" => keys::Keys::into_command(),
+    b"latency" => latency::Latency::into_command(),
     b"lpush" => lpush::Lpush::into_command(),
+    b"mget" => mget::Mget::into_command(),
+    b"migrate" => migrate::Migrate::into_command(),
+    b"monitor" => monitor::Monitor::into_command(),
+    b"mset" => mset::Mset::into_command(),
+    b"msetnx" => msetnx::Msetnx::into_command(),
+    b"object" => object::Object::into_command(),
+    b"pfadd" => pfadd::Pfadd::into_command(),
+    b"pfcount" => pfcount::Pfcount::into_command(),
+    b"pfmerge" => pfmerge::Pfmerge::into_command(),
     b"ping" => ping::Ping::into_command(),
+    b"psync" => psync::Psync::into_command(),
+    b"publish" => publish::Publish::into_command(),
+    b"randomkey" => randomkey::Randomkey::into_command(),
+    b"rename" => rename::Rename::into_command(),
+    b"renamenx" => renamenx::Renamenx::into_command(),
+    b"replconf" => replconf::Replconf::into_command(),
+    b"replicaof" => replicaof::Replicaof::into_command(),
+    b"restore" => restore::Restore::into_command(),
+    b"restore-asking" => restore_asking::RestoreAsking::into_command(),
+    b"role" => role::Role::into_command(),
+    b"scan" => scan::Scan::into_command(),
+    b"sentinel" => sentinel::Sentinel::into_command(),
     b"set" => set::Set::into_command(),
-};
\ No newline at end of file
+    b"setbit" => setbit::Setbit::into_command(),
+    b"setex" => setex::Setex::into_command(),
+    b"setnx" => setnx::Setnx::into_command(),
+    b"setrange" => setrange::Setrange::into_command(),
+    b"shutdown" => shutdown::Shutdown::into_command(),
+    b"slowlog" => slowlog::Slowlog::into_command(),
+    b"strlen" => strlen::Strlen::into_command(),
+    b"subscribe" => subscribe::Subscribe::into_command(),
+    b"touch" => touch::Touch::into_command(),
+    b"type" => r#type::Type::into_command(),
+    b"unlink" => unlink::Unlink::into_command(),
+    b"unsubscribe" => unsubscribe::Unsubscribe::into_command(),
+    b"wait" => wait::Wait::into_command(),
+};
+
+
+/// Build the arguments of a command from strings, for tests.
+#[cfg(test)]
+fn args(args: &[&str]) -> Vec<sider_command::RESPType<bytes::Bytes>> {
+    args.iter().map(|a| sider_command::RESPType::BulkString(bytes::Bytes::copy_from_slice(a.as_bytes()))).collect()
+}
@@ -0,0 +1,34 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+
+
+/// Stream every command the server runs to this connection, for debugging.
+#[command(
+    name = "monitor",
+    arity = 1,
+    flags = ("admin"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "slow", "dangerous"),
+    command_tips = (),
+)]
+pub fn monitor(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    if !args.is_empty() {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    // The connection to a replica carries the replication stream.
+    if server.client(token).is_some_and(|c| c.replica.is_some()) {
+        return None;
+    }
+
+    server.add_monitor(token);
+
+    Some(RESPType::SimpleString("OK".into()))
+}
@@ -0,0 +1,78 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{check_string_length, DBEntry, ExpiryFlag, ExistenceFlag};
+
+use super::super::db::DB;
+use crate::notify;
+
+
+#[command(
+    name = "mset",
+    arity = -3,
+    flags = ("write"),
+    first_key = 1,
+    last_key = -1,
+    step = 2,
+    acl_categories = ("write", "slow"),
+    command_tips = ("request_policy:multi_shard", "response_policy:all_succeeded"),
+)]
+pub fn mset(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    // A key without a value fails the whole command, before anything is written.
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut pairs = Vec::with_capacity(args.len() / 2);
+    let mut args = args.into_iter();
+
+    while let Some(key) = args.next() {
+        let (RESPType::BulkString(key), Some(RESPType::BulkString(value))) = (key, args.next()) else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        if check_string_length(value.len(), db.max_string_length).is_err() {
+            return RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into());
+        }
+
+        pairs.push((key, value));
+    }
+
+    for (key, value) in pairs {
+        let e = db.get_or_insert(key.clone(), ExpiryFlag::None, ExistenceFlag::None).unwrap();
+        *e = DBEntry::String(value.into());
+        db.notify(notify::STRING, "set", &key);
+    }
+
+    RESPType::SimpleString(Bytes::from("OK"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use super::mset;
+
+    #[test]
+    fn test_mset() {
+        let mut db = DB::new();
+
+        assert_eq!(mset(args(&["a", "1", "b", "2"]), &mut db), RESPType::SimpleString("OK".into()));
+        assert_eq!(db.get(&"b".into()), Some(&DBEntry::String(DBString::String("2".into()))));
+
+        // A trailing key without a value is an error, and nothing is set.
+        assert_eq!(mset(args(&["a", "3", "c"]), &mut db), RESPType::Error("wrong number of arguments".into()));
+        assert_eq!(db.get(&"a".into()), Some(&DBEntry::String(DBString::String("1".into()))));
+        assert!(!db.exists(&"c".into()));
+
+        let mut v = args(&["a", "3"]);
+        v.push(RESPType::Integer(4));
+        v.push(RESPType::BulkString("d".into()));
+        assert!(matches!(mset(v, &mut db), RESPType::Error(_)));
+        assert_eq!(db.get(&"a".into()), Some(&DBEntry::String(DBString::String("1".into()))));
+    }
+}
@@ -0,0 +1,80 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{check_string_length, DBEntry, ExpiryFlag, ExistenceFlag};
+
+use super::super::db::DB;
+use crate::notify;
+
+
+#[command(
+    name = "msetnx",
+    arity = -3,
+    flags = ("write"),
+    first_key = 1,
+    last_key = -1,
+    step = 2,
+    acl_categories = ("write", "slow"),
+    command_tips = (),
+)]
+pub fn msetnx(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    // A key without a value fails the whole command, before anything is written.
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut pairs = Vec::with_capacity(args.len() / 2);
+    let mut args = args.into_iter();
+
+    while let Some(key) = args.next() {
+        let (RESPType::BulkString(key), Some(RESPType::BulkString(value))) = (key, args.next()) else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        if check_string_length(value.len(), db.max_string_length).is_err() {
+            return RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into());
+        }
+
+        pairs.push((key, value));
+    }
+
+    // Check every key before setting any of them, so that either all or none of the keys are
+    // set. Commands are executed one at a time, so nothing can change in between.
+    for (key, _) in &pairs {
+        if db.get(key).is_some() {
+            return RESPType::Integer(0);
+        }
+    }
+
+    for (key, value) in pairs {
+        let e = db.get_or_insert(key.clone(), ExpiryFlag::None, ExistenceFlag::None).unwrap();
+        *e = DBEntry::String(value.into());
+        db.notify(notify::STRING, "set", &key);
+    }
+
+    RESPType::Integer(1)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::DB;
+    use super::msetnx;
+
+    #[test]
+    fn test_msetnx() {
+        let mut db = DB::new();
+
+        assert_eq!(msetnx(args(&["a", "1", "b"]), &mut db), RESPType::Error("wrong number of arguments".into()));
+        assert_eq!(db.key_count(), 0);
+
+        assert_eq!(msetnx(args(&["a", "1", "b", "2"]), &mut db), RESPType::Integer(1));
+        // One existing key means that none are set.
+        assert_eq!(msetnx(args(&["c", "3", "a", "4"]), &mut db), RESPType::Integer(0));
+        assert!(!db.exists(&"c".into()));
+    }
+}
@@ -0,0 +1,117 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use super::super::db::DB;
+
+
+const HELP: &[&str] = &[
+    "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of the <key>. The returned integer is",
+    "    proportional to the logarithm of the recent access frequency of the key.",
+    "IDLETIME <key>",
+    "    Return the idle time of the <key>, that is the approximated number of",
+    "    seconds elapsed since the last access to the key.",
+    "REFCOUNT <key>",
+    "    Return the number of references of the value associated with the specified",
+    "    <key>.",
+    "HELP",
+    "    Print this help.",
+];
+
+
+#[command(
+    name = "object",
+    arity = -2,
+    flags = ("readonly"),
+    first_key = 2,
+    last_key = 2,
+    step = 1,
+    acl_categories = ("keyspace", "read", "slow"),
+    command_tips = ("non_deterministic_output"),
+)]
+pub fn object(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(subcommand) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let subcommand = subcommand.to_ascii_uppercase();
+
+    if &subcommand[..] == b"HELP" {
+        return RESPType::Array(HELP.iter().map(|l| RESPType::SimpleString(Bytes::from_static(l.as_bytes()))).collect());
+    }
+
+    if args.len() != 1 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(key) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let Some(o) = db.object(&key) else {
+        return RESPType::Null;
+    };
+
+    match &subcommand[..] {
+        b"ENCODING" => RESPType::BulkString(Bytes::from(o.entry().encoding())),
+        b"IDLETIME" => RESPType::Integer(o.idle_time().as_secs() as i64),
+        b"FREQ" => RESPType::Integer(o.frequency() as i64),
+        b"REFCOUNT" => RESPType::Integer(1),
+        _ => RESPType::Error("unknown subcommand, try OBJECT HELP".into()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use bytes::BytesMut;
+
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use crate::sorted_set::SortedSet;
+    use super::object;
+
+    #[test]
+    fn test_encoding() {
+        let mut db = DB::new();
+        db.insert("int".into(), DBEntry::String(DBString::Integer(12)), None);
+        db.insert("short".into(), DBEntry::String(DBString::String("hello".into())), None);
+        db.insert("long".into(), DBEntry::String(DBString::String(vec![b'x'; 45].into())), None);
+        db.insert("appended".into(), DBEntry::String(DBString::Buffer(BytesMut::from("hi"))), None);
+        db.insert("list".into(), DBEntry::List(VecDeque::new()), None);
+        db.insert("zset".into(), DBEntry::SortedSet(SortedSet::new()), None);
+
+        // The names are those of the closest Redis encodings, rather than of our own types.
+        for (key, expected) in [("int", "int"), ("short", "embstr"), ("long", "raw"), ("appended", "raw"), ("list", "quicklist"), ("zset", "skiplist")] {
+            assert_eq!(object(args(&["ENCODING", key]), &mut db), RESPType::BulkString(expected.into()));
+        }
+
+        assert_eq!(object(args(&["encoding", "missing"]), &mut db), RESPType::Null);
+    }
+
+    #[test]
+    fn test_object() {
+        let mut db = DB::new();
+        db.insert("a".into(), DBEntry::String(DBString::Integer(1)), None);
+
+        assert_eq!(object(args(&["IDLETIME", "a"]), &mut db), RESPType::Integer(0));
+        assert_eq!(object(args(&["REFCOUNT", "a"]), &mut db), RESPType::Integer(1));
+        assert!(matches!(object(args(&["FREQ", "a"]), &mut db), RESPType::Integer(n) if n > 0));
+        assert!(matches!(object(args(&["HELP"]), &mut db), RESPType::Array(_)));
+        assert_eq!(object(args(&["NOPE", "a"]), &mut db), RESPType::Error("unknown subcommand, try OBJECT HELP".into()));
+        assert_eq!(object(args(&["ENCODING"]), &mut db), RESPType::Error("wrong number of arguments".into()));
+    }
+}
@@ -0,0 +1,74 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{DBString, ExpiryFlag, ExistenceFlag};
+use crate::hyperloglog::{self, HllError, HyperLogLog};
+use crate::notify;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "pfadd",
+    arity = -2,
+    flags = ("write", "fast"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "fast"),
+    command_tips = (),
+)]
+pub fn pfadd(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(key) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let mut elements = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(e) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        elements.push(e);
+    }
+
+    let Ok(e) = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
+        return RESPType::Error("error retrieving key".into());
+    };
+
+    let created = e.is_nil();
+
+    if created {
+        e.set_string(DBString::Buffer(HyperLogLog::empty()));
+    }
+
+    let Ok(s) = e.get_mut_string() else {
+        return RESPType::Error("wrong type".into());
+    };
+
+    // Check the value before converting it to a buffer, so that invalid values are left alone.
+    if hyperloglog::validate(&s.as_slice()).is_err() {
+        return RESPType::Error("Key is not a valid HyperLogLog string value.".into());
+    }
+
+    let mut hll = HyperLogLog::from_bytes(s.as_mut_buffer()).unwrap();
+
+    match hll.add(elements.iter().map(|e| &e[..])) {
+        Ok(changed) => {
+            if created || changed {
+                db.notify(notify::STRING, "pfadd", &key);
+            }
+
+            RESPType::Integer((created || changed) as i64)
+        },
+        Err(HllError::Invalid) => RESPType::Error("Key is not a valid HyperLogLog string value.".into()),
+        Err(HllError::Corrupted) => RESPType::Error("Corrupted HLL object detected".into()),
+    }
+}
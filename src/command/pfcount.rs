@@ -0,0 +1,112 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{DBEntry, ExpiryFlag, ExistenceFlag};
+use crate::hyperloglog::{self, HllError, HyperLogLog};
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "pfcount",
+    arity = -2,
+    flags = ("readonly"),
+    first_key = 1,
+    last_key = -1,
+    step = 1,
+    acl_categories = ("read", "slow"),
+    command_tips = (),
+)]
+pub fn pfcount(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut keys = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(k) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        keys.push(k);
+    }
+
+    let result = if keys.len() == 1 {
+        match db.get(&keys[0]) {
+            None => Ok(0),
+            Some(DBEntry::String(_)) => count_one(keys.swap_remove(0), db),
+            Some(_) => return RESPType::Error("wrong type".into()),
+        }
+    } else {
+        // With several keys, the HyperLogLogs are merged on the fly. Nothing is cached, since
+        // the result does not belong to any single key.
+        let mut values = vec![];
+
+        for k in &keys {
+            match db.get(k) {
+                None => continue,
+                Some(DBEntry::String(s)) => values.push(s.to_bytes()),
+                Some(_) => return RESPType::Error("wrong type".into()),
+            }
+        }
+
+        hyperloglog::count_union(values.iter().map(|v| &v[..]))
+    };
+
+    match result {
+        Ok(count) => RESPType::Integer(count as i64),
+        Err(HllError::Invalid) => RESPType::Error("Key is not a valid HyperLogLog string value.".into()),
+        Err(HllError::Corrupted) => RESPType::Error("Corrupted HLL object detected".into()),
+    }
+}
+
+
+/// Count a single HyperLogLog, updating its cached cardinality.
+fn count_one(key: Bytes, db: &mut DB) -> Result<u64, HllError> {
+    let Ok(DBEntry::String(s)) = db.get_or_insert(key, ExpiryFlag::KeepTTL, ExistenceFlag::Xx) else {
+        return Err(HllError::Invalid);
+    };
+
+    hyperloglog::validate(&s.as_slice())?;
+
+    HyperLogLog::from_bytes(s.as_mut_buffer())?.count()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use super::pfcount;
+
+    #[test]
+    fn test_corrupted_dense() {
+        let mut db = DB::new();
+
+        // A dense HyperLogLog with every register set to 63, more than a hash can produce.
+        let mut value = b"HYLL\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x80".to_vec();
+        value.resize(16 + 16384 * 6 / 8, 0xff);
+        db.insert("hll".into(), DBEntry::String(DBString::String(value.into())), None);
+
+        assert!(matches!(pfcount(args(&["hll"]), &mut db), RESPType::Integer(_)));
+        assert!(matches!(pfcount(args(&["hll", "missing"]), &mut db), RESPType::Integer(_)));
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut db = DB::new();
+        db.insert("list".into(), DBEntry::List(VecDeque::new()), None);
+        db.insert("string".into(), DBEntry::String(DBString::String("hello".into())), None);
+
+        assert_eq!(pfcount(args(&["list"]), &mut db), RESPType::Error("wrong type".into()));
+        assert_eq!(pfcount(args(&["list", "missing"]), &mut db), RESPType::Error("wrong type".into()));
+        assert_eq!(pfcount(args(&["string"]), &mut db), RESPType::Error("Key is not a valid HyperLogLog string value.".into()));
+        assert_eq!(pfcount(args(&["missing"]), &mut db), RESPType::Integer(0));
+    }
+}
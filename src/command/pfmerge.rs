@@ -0,0 +1,61 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{DBEntry, DBString, ExpiryFlag, ExistenceFlag};
+use crate::hyperloglog::{self, HllError};
+use crate::notify;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "pfmerge",
+    arity = -2,
+    flags = ("write"),
+    first_key = 1,
+    last_key = -1,
+    step = 1,
+    acl_categories = ("write", "slow"),
+    command_tips = (),
+)]
+pub fn pfmerge(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut keys = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(k) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        keys.push(k);
+    }
+
+    // The destination is merged along with the sources if it already exists.
+    let mut values = vec![];
+
+    for k in &keys {
+        match db.get(k) {
+            None => continue,
+            Some(DBEntry::String(s)) => values.push(s.to_bytes()),
+            Some(_) => return RESPType::Error("wrong type".into()),
+        }
+    }
+
+    let registers = match hyperloglog::union_registers(values.iter().map(|v| &v[..])) {
+        Ok(r) => r,
+        Err(HllError::Invalid) => return RESPType::Error("Key is not a valid HyperLogLog string value.".into()),
+        Err(HllError::Corrupted) => return RESPType::Error("Corrupted HLL object detected".into()),
+    };
+
+    let destination = keys.swap_remove(0);
+    let e = db.get_or_insert(destination.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None).unwrap();
+    *e = DBEntry::String(DBString::Buffer(hyperloglog::dense(&registers)));
+    db.notify(notify::STRING, "pfadd", &destination);
+
+    RESPType::SimpleString(Bytes::from("OK"))
+}
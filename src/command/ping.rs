@@ -10,9 +10,9 @@ use super::{super::db::DB, responses};
     name = "ping",
     arity = -1,
     flags = ("fast", "sentinel"),
-    first_key = 1,
-    last_key = 2,
-    step = 1,
+    first_key = 0,
+    last_key = 0,
+    step = 0,
     acl_categories = ("connection"),
     command_tips = ("request_policy:all_shards", "response_policy:all_succeeded"),
 )]
@@ -0,0 +1,79 @@
+
+use std::time::Instant;
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::replication::{Backlog, LinkState, ReplicaClient, BACKLOG_SIZE};
+use crate::server::Server;
+use crate::snapshot;
+
+
+#[command(
+    name = "psync",
+    arity = -3,
+    flags = ("admin"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "slow", "dangerous"),
+    command_tips = (),
+)]
+pub fn psync(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    if args.len() < 2 {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let (RESPType::BulkString(id), RESPType::BulkString(offset)) = (&args[0], &args[1]) else {
+        return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+    };
+
+    // A replica which is not following its own primary has no consistent history to serve.
+    if server.replication.primary.as_ref().is_some_and(|p| p.state != LinkState::Connected) {
+        return Some(RESPType::Error("NOMASTERLINK Can't SYNC while not connected with my master".into()));
+    }
+
+    // A malformed offset, including the -1 sent to force a full resync, cannot be resumed from.
+    let offset = std::str::from_utf8(offset).ok().and_then(|o| o.parse::<u64>().ok());
+
+    if let Some(missed) = offset.and_then(|o| server.replication.resume_from(id, o)) {
+        let header = format!("+CONTINUE {}\r\n", server.replication.id);
+
+        server.send(token, header.as_bytes());
+        server.send(token, &missed);
+        attach_replica(server, token, offset.unwrap() - 1);
+
+        return None;
+    }
+
+    if server.replication.backlog.is_none() {
+        server.replication.backlog = Some(Backlog::new(BACKLOG_SIZE, server.replication.offset));
+    }
+
+    let start = Instant::now();
+    let dump = snapshot::dump(&server.db);
+    server.record_latency("snapshot", start.elapsed());
+    let header = format!("+FULLRESYNC {} {}\r\n${}\r\n", server.replication.id, server.replication.offset, dump.len());
+
+    server.send(token, header.as_bytes());
+    server.send(token, &dump);
+
+    let offset = server.replication.offset;
+    attach_replica(server, token, offset);
+
+    None
+}
+
+
+/// Start sending the replication stream to the client.
+fn attach_replica(server: &mut Server, token: Token, ack_offset: u64) {
+    let Some(client) = server.client_mut(token) else {
+        return;
+    };
+
+    let replica = client.replica.get_or_insert_with(ReplicaClient::new);
+    replica.online = true;
+    replica.ack_offset = ack_offset;
+}
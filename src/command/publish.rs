@@ -0,0 +1,38 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+
+
+#[command(
+    name = "publish",
+    arity = 3,
+    flags = ("fast", "sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("pubsub", "fast"),
+    command_tips = (),
+)]
+pub fn publish(mut args: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    if args.len() != 2 {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let (RESPType::BulkString(channel), RESPType::BulkString(message)) = (args.remove(0), args.remove(0)) else {
+        return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+    };
+
+    let receivers = server.publish(&channel, message.clone());
+
+    // Our replicas' subscribers get the message too. Replicas pass on their primary's stream
+    // as it is instead.
+    if !server.replication.is_replica() {
+        server.propagate(vec![RESPType::BulkString(Bytes::from("PUBLISH")), RESPType::BulkString(channel), RESPType::BulkString(message)]);
+    }
+
+    Some(RESPType::Integer(receivers as i64))
+}
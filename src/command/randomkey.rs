@@ -0,0 +1,28 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use super::super::db::DB;
+
+
+#[command(
+    name = "randomkey",
+    arity = 1,
+    flags = ("readonly"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("keyspace", "read", "slow"),
+    command_tips = ("request_policy:all_shards", "non_deterministic_output"),
+)]
+pub fn randomkey(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if !args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    match db.random_key() {
+        Some(k) => RESPType::BulkString(k),
+        None => RESPType::Null,
+    }
+}
@@ -0,0 +1,73 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::DBError;
+use crate::notify;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "rename",
+    arity = 3,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 2,
+    step = 1,
+    acl_categories = ("keyspace", "write", "slow"),
+    command_tips = (),
+)]
+pub fn rename(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 2 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(to) = args.remove(1) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let RESPType::BulkString(from) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    match db.rename(&from, to.clone(), true) {
+        Ok(()) => {
+            db.notify(notify::GENERIC, "rename_from", &from);
+            db.notify(notify::GENERIC, "rename_to", &to);
+            RESPType::SimpleString(Bytes::from("OK"))
+        },
+        Err(DBError::DoesNotExist) => RESPType::Error("no such key".into()),
+        Err(_) => unreachable!(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use super::rename;
+
+    #[test]
+    fn test_rename() {
+        let mut db = DB::new();
+        let expiry = Utc::now() + Duration::hours(1);
+        db.insert("a".into(), DBEntry::String(DBString::Integer(1)), Some(expiry));
+        db.insert("b".into(), DBEntry::String(DBString::Integer(2)), None);
+
+        // The destination is replaced, and the expiry time moves with the value.
+        assert_eq!(rename(args(&["a", "b"]), &mut db), RESPType::SimpleString("OK".into()));
+        assert!(!db.exists(&"a".into()));
+        assert_eq!(db.get(&"b".into()), Some(&DBEntry::String(DBString::Integer(1))));
+        assert_eq!(db.expiry(&"b".into()), Some(expiry));
+        assert_eq!(db.expiry(&"a".into()), None);
+
+        assert_eq!(rename(args(&["a", "c"]), &mut db), RESPType::Error("no such key".into()));
+        assert_eq!(rename(args(&["b", "b"]), &mut db), RESPType::SimpleString("OK".into()));
+    }
+}
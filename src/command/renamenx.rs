@@ -0,0 +1,71 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::DBError;
+use crate::notify;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "renamenx",
+    arity = 3,
+    flags = ("write", "fast"),
+    first_key = 1,
+    last_key = 2,
+    step = 1,
+    acl_categories = ("keyspace", "write", "fast"),
+    command_tips = (),
+)]
+pub fn renamenx(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 2 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(to) = args.remove(1) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let RESPType::BulkString(from) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    match db.rename(&from, to.clone(), false) {
+        Ok(()) => {
+            db.notify(notify::GENERIC, "rename_from", &from);
+            db.notify(notify::GENERIC, "rename_to", &to);
+            RESPType::Integer(1)
+        },
+        Err(DBError::AlreadyExists) => RESPType::Integer(0),
+        Err(DBError::DoesNotExist) => RESPType::Error("no such key".into()),
+        Err(_) => unreachable!(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use super::renamenx;
+
+    #[test]
+    fn test_renamenx() {
+        let mut db = DB::new();
+        db.insert("a".into(), DBEntry::String(DBString::Integer(1)), None);
+        db.insert("b".into(), DBEntry::String(DBString::Integer(2)), None);
+
+        // An existing destination is left alone.
+        assert_eq!(renamenx(args(&["a", "b"]), &mut db), RESPType::Integer(0));
+        assert_eq!(db.get(&"b".into()), Some(&DBEntry::String(DBString::Integer(2))));
+
+        assert_eq!(renamenx(args(&["a", "c"]), &mut db), RESPType::Integer(1));
+        assert_eq!(db.get(&"c".into()), Some(&DBEntry::String(DBString::Integer(1))));
+        assert!(!db.exists(&"a".into()));
+
+        assert_eq!(renamenx(args(&["a", "d"]), &mut db), RESPType::Error("no such key".into()));
+    }
+}
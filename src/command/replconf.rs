@@ -0,0 +1,71 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::replication::ReplicaClient;
+use crate::server::Server;
+
+
+#[command(
+    name = "replconf",
+    arity = -1,
+    flags = ("admin"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "slow", "dangerous"),
+    command_tips = (),
+)]
+pub fn replconf(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    if !args.len().is_multiple_of(2) {
+        return Some(RESPType::Error("Invalid syntax.".into()));
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(s);
+    }
+
+    for pair in strings.chunks(2) {
+        let value = std::str::from_utf8(&pair[1]).ok();
+
+        match &pair[0].to_ascii_lowercase()[..] {
+            b"listening-port" => {
+                let Some(port) = value.and_then(|v| v.parse().ok()) else {
+                    return Some(RESPType::Error("value is not an integer or out of range".into()));
+                };
+
+                if let Some(client) = server.client_mut(token) {
+                    client.replica.get_or_insert_with(ReplicaClient::new).listening_port = port;
+                }
+            },
+            // We only speak PSYNC2, and there is nothing else worth knowing about.
+            b"capa" => {},
+            b"ack" => {
+                let offset = value?.parse().ok()?;
+
+                if let Some(replica) = server.client_mut(token).and_then(|c| c.replica.as_mut()) {
+                    replica.ack_offset = offset;
+                }
+
+                server.check_waiters();
+
+                // Acknowledgements are never replied to.
+                return None;
+            },
+            // Only a replica is asked for acknowledgements, and that is handled as part of the
+            // replication stream.
+            b"getack" => return None,
+            _ => return Some(RESPType::Error(format!("Unrecognized REPLCONF option: {}", String::from_utf8_lossy(&pair[0])).into())),
+        }
+    }
+
+    Some(RESPType::SimpleString("OK".into()))
+}
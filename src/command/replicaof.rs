@@ -0,0 +1,67 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use log::info;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::replication::PrimaryLink;
+use crate::server::Server;
+
+
+#[command(
+    name = "replicaof",
+    arity = 3,
+    flags = ("admin"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "slow", "dangerous"),
+    command_tips = (),
+)]
+pub fn replicaof(args: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    if args.len() != 2 {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let (RESPType::BulkString(host), RESPType::BulkString(port)) = (&args[0], &args[1]) else {
+        return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+    };
+
+    if server.cluster.is_some() {
+        return Some(RESPType::Error("REPLICAOF not allowed in cluster mode.".into()));
+    }
+
+    if host.eq_ignore_ascii_case(b"no") && port.eq_ignore_ascii_case(b"one") {
+        if server.replication.primary.take().is_some() {
+            info!("Promoted to primary.");
+
+            server.replication.shift_id();
+
+            // Our replicas need to find out about the new history.
+            server.disconnect_replicas();
+        }
+
+        return Some(RESPType::SimpleString("OK".into()));
+    }
+
+    let Some(port) = std::str::from_utf8(port).ok().and_then(|p| p.parse::<u16>().ok()) else {
+        return Some(RESPType::Error("Invalid master port".into()));
+    };
+
+    let host = String::from_utf8_lossy(host).into_owned();
+
+    if server.replication.primary.as_ref().is_some_and(|p| p.host == host && p.port == port) {
+        return Some(RESPType::SimpleString("OK Already connected to specified master".into()));
+    }
+
+    info!("Replicating from {}:{}.", host, port);
+
+    if let Some(mut link) = server.replication.primary.replace(PrimaryLink::new(host, port)) {
+        link.disconnect();
+    }
+
+    server.disconnect_replicas();
+
+    Some(RESPType::SimpleString("OK".into()))
+}
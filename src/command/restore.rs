@@ -0,0 +1,82 @@
+
+use bytes::Bytes;
+use chrono::{Duration, TimeZone, Utc};
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::notify;
+use crate::snapshot;
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "restore",
+    arity = -4,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("keyspace", "write", "slow", "dangerous"),
+    command_tips = (),
+)]
+pub fn restore(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() < 3 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let mut replace = false;
+    let mut absolute = false;
+
+    for option in &strings[3..] {
+        match &option.to_ascii_uppercase()[..] {
+            b"REPLACE" => replace = true,
+            b"ABSTTL" => absolute = true,
+            _ => return RESPType::Error("Invalid syntax.".into()),
+        }
+    }
+
+    let ttl = match from_decimal_bytes(&strings[1]) {
+        Ok(t) if t >= 0 => t,
+        _ => return RESPType::Error("Invalid TTL value, must be >= 0".into()),
+    };
+
+    let Ok(entry) = snapshot::load_value(&strings[2]) else {
+        return RESPType::Error("DUMP payload version or checksum are wrong".into());
+    };
+
+    let key = strings.swap_remove(0);
+
+    if !replace && db.get(&key).is_some() {
+        return RESPType::Error("BUSYKEY Target key name already exists.".into());
+    }
+
+    let expiry = match (ttl, absolute) {
+        (0, _) => None,
+        (t, true) => Utc.timestamp_millis_opt(t).single(),
+        (t, false) => Some(Utc::now() + Duration::milliseconds(t)),
+    };
+
+    // A value which has already expired is not worth storing.
+    if expiry.is_some_and(|e| e <= Utc::now()) {
+        if db.delete(&key) {
+            db.notify(notify::GENERIC, "del", &key);
+        }
+    } else {
+        db.insert(key.clone(), entry, expiry);
+        db.notify(notify::GENERIC, "restore", &key);
+    }
+
+    RESPType::SimpleString("OK".into())
+}
@@ -0,0 +1,23 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+
+use super::super::db::DB;
+
+
+/// RESTORE, sent by MIGRATE so that the target accepts keys for a slot it is still importing.
+#[command(
+    name = "restore-asking",
+    arity = -4,
+    flags = ("write", "asking"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("keyspace", "write", "slow", "dangerous"),
+    command_tips = (),
+)]
+pub fn restore_asking(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    super::restore::restore(args, db)
+}
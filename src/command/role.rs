@@ -0,0 +1,47 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+
+
+#[command(
+    name = "role",
+    arity = 1,
+    flags = ("fast", "sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "fast", "dangerous"),
+    command_tips = (),
+)]
+pub fn role(_: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    let offset = server.replication.offset as i64;
+
+    if let Some(link) = &server.replication.primary {
+        return Some(RESPType::Array(vec![
+            RESPType::BulkString("slave".into()),
+            RESPType::BulkString(link.host.clone().into()),
+            RESPType::Integer(link.port as i64),
+            RESPType::BulkString(link.state.name().into()),
+            RESPType::Integer(offset),
+        ]));
+    }
+
+    let replicas = server.clients()
+        .filter_map(|c| Some((&c.address, c.replica.as_ref().filter(|r| r.online)?)))
+        .map(|(address, replica)| RESPType::Array(vec![
+            RESPType::BulkString(address.host().into()),
+            RESPType::BulkString(replica.listening_port.to_string().into()),
+            RESPType::BulkString(replica.ack_offset.to_string().into()),
+        ]))
+        .collect();
+
+    Some(RESPType::Array(vec![
+        RESPType::BulkString("master".into()),
+        RESPType::Integer(offset),
+        RESPType::Array(replicas),
+    ]))
+}
@@ -0,0 +1,126 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::util::{from_decimal_bytes, glob_match};
+
+use super::super::db::DB;
+
+
+const DEFAULT_COUNT: i64 = 10;
+/// The most buckets one call visits, however large COUNT is.
+const MAX_ITERATIONS: i64 = 100_000;
+
+
+#[command(
+    name = "scan",
+    arity = -2,
+    flags = ("readonly"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("keyspace", "read", "slow"),
+    command_tips = ("non_deterministic_output", "request_policy:special"),
+)]
+pub fn scan(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        strings.push(s);
+    }
+
+    let Some(cursor) = std::str::from_utf8(&strings[0]).ok().and_then(|s| s.parse::<u64>().ok()) else {
+        return RESPType::Error("invalid cursor".into());
+    };
+
+    let mut pattern = None;
+    let mut count = DEFAULT_COUNT;
+    let mut type_name = None;
+
+    let mut remaining = strings[1..].iter();
+
+    while let Some(option) = remaining.next() {
+        let Some(value) = remaining.next() else {
+            return RESPType::Error("Invalid syntax.".into());
+        };
+
+        match &option.to_ascii_uppercase()[..] {
+            b"MATCH" => pattern = Some(value),
+            b"COUNT" => {
+                count = match from_decimal_bytes(value) {
+                    Ok(c) if c >= 1 => c,
+                    _ => return RESPType::Error("Invalid syntax.".into()),
+                };
+            },
+            b"TYPE" => type_name = Some(value.to_ascii_lowercase()),
+            _ => return RESPType::Error("Invalid syntax.".into()),
+        }
+    }
+
+    let mut keys = vec![];
+    let mut cursor = cursor;
+
+    // Like Redis, COUNT is only a hint. We keep visiting buckets until we have at least COUNT
+    // candidate keys, giving up after a bounded number of buckets so that sparse tables
+    // do not block the server.
+    let mut iterations = count.saturating_mul(10).min(MAX_ITERATIONS);
+
+    loop {
+        cursor = db.scan(cursor, |k, v| {
+            if pattern.is_some_and(|p| !glob_match(p, k)) {
+                return;
+            }
+
+            if type_name.as_ref().is_some_and(|t| t[..] != *v.type_name().as_bytes()) {
+                return;
+            }
+
+            keys.push(RESPType::BulkString(k.clone()));
+        });
+
+        iterations -= 1;
+
+        if cursor == 0 || iterations == 0 || keys.len() as i64 >= count {
+            break;
+        }
+    }
+
+    RESPType::Array(vec![
+        RESPType::BulkString(Bytes::from(cursor.to_string())),
+        RESPType::Array(keys),
+    ])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use super::scan;
+
+    #[test]
+    fn test_huge_count() {
+        let mut db = DB::new();
+
+        for i in 0..100 {
+            db.insert(format!("key:{}", i).into(), DBEntry::String(DBString::Integer(i)), None);
+        }
+
+        let RESPType::Array(reply) = scan(args(&["0", "COUNT", "9223372036854775807"]), &mut db) else {
+            panic!("expected an array");
+        };
+
+        assert_eq!(reply[0], RESPType::BulkString("0".into()));
+        assert!(matches!(&reply[1], RESPType::Array(keys) if keys.len() == 100));
+    }
+}
@@ -0,0 +1,222 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::sentinel::{Instance, Master, ReportedRole};
+use crate::server::Server;
+
+
+const HELP: &[&str] = &[
+    "SENTINEL <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "FAILOVER <master-name>",
+    "    Manually failover a master node without asking for agreement from other",
+    "    Sentinels.",
+    "GET-MASTER-ADDR-BY-NAME <master-name>",
+    "    Return the ip and port number of the master with that name.",
+    "IS-MASTER-DOWN-BY-ADDR <ip> <port> <current-epoch> <runid>",
+    "    Check if the master specified by ip:port is down from current Sentinel's",
+    "    point of view.",
+    "MASTER <master-name>",
+    "    Show the state and info of the specified master.",
+    "MASTERS",
+    "    Show a list of monitored masters and their state.",
+    "MONITOR <name> <ip> <port> <quorum>",
+    "    Start monitoring a new master with the specified name, ip, port and quorum.",
+    "MYID",
+    "    Return the ID of the Sentinel instance.",
+    "REMOVE <master-name>",
+    "    Remove master from Sentinel's monitor list.",
+    "REPLICAS <master-name>",
+    "    Show a list of replicas for this master and their state.",
+    "SENTINELS <master-name>",
+    "    Show a list of Sentinel instances for this master and their state.",
+    "SET <master-name> <option> <value>",
+    "    Set configuration parameters for certain masters.",
+    "HELP",
+    "    Print this help.",
+];
+
+
+fn fields(pairs: Vec<(&str, String)>) -> RESPType<Bytes> {
+    RESPType::Array(pairs.into_iter()
+        .flat_map(|(k, v)| [RESPType::BulkString(Bytes::copy_from_slice(k.as_bytes())), RESPType::BulkString(v.into())])
+        .collect())
+}
+
+
+fn describe_master(master: &Master) -> RESPType<Bytes> {
+    fields(vec![
+        ("name", master.name.clone()),
+        ("ip", master.instance.ip.clone()),
+        ("port", master.instance.port.to_string()),
+        ("flags", master.flags()),
+        ("config-epoch", master.config_epoch.to_string()),
+        ("num-slaves", master.replicas.len().to_string()),
+        ("num-other-sentinels", master.sentinels.len().to_string()),
+        ("quorum", master.quorum.to_string()),
+        ("down-after-milliseconds", master.down_after.as_millis().to_string()),
+        ("failover-timeout", master.failover_timeout.as_millis().to_string()),
+        ("failover-state", master.failover.as_ref().map_or("none".into(), |f| format!("{:?}", f.state).to_lowercase())),
+    ])
+}
+
+
+fn describe_instance(instance: &Instance, name: String, kind: &str) -> RESPType<Bytes> {
+    let mut flags = String::from(kind);
+
+    if instance.sdown {
+        flags.push_str(",s_down");
+    }
+
+    if !instance.connected() {
+        flags.push_str(",disconnected");
+    }
+
+    let mut pairs = vec![
+        ("name", name),
+        ("ip", instance.ip.clone()),
+        ("port", instance.port.to_string()),
+        ("flags", flags),
+    ];
+
+    match &instance.role {
+        ReportedRole::Replica { host, port, link_up } => pairs.extend([
+            ("master-host", host.clone()),
+            ("master-port", port.to_string()),
+            ("master-link-status", if *link_up { "ok" } else { "err" }.into()),
+            ("slave-repl-offset", instance.offset.to_string()),
+        ]),
+        _ if kind == "sentinel" => pairs.extend([
+            ("leader", instance.leader.clone().unwrap_or_else(|| "*".into())),
+            ("leader-epoch", instance.leader_epoch.to_string()),
+        ]),
+        _ => {},
+    }
+
+    fields(pairs)
+}
+
+
+/// Inspect and control the primaries a sentinel monitors.
+#[command(
+    name = "sentinel",
+    arity = -2,
+    flags = ("admin", "sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "slow", "dangerous"),
+    command_tips = (),
+)]
+pub fn sentinel(args: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    if args.is_empty() {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(String::from_utf8_lossy(&s).into_owned());
+    }
+
+    let subcommand = strings.remove(0).to_ascii_uppercase();
+
+    if subcommand == "HELP" {
+        return Some(RESPType::Array(HELP.iter().map(|l| RESPType::SimpleString(Bytes::from_static(l.as_bytes()))).collect()));
+    }
+
+    Some(match run(&subcommand, &strings, server) {
+        Ok(r) => r,
+        Err(e) => RESPType::Error(e.into()),
+    })
+}
+
+
+fn run(subcommand: &str, args: &[String], server: &mut Server) -> Result<RESPType<Bytes>, String> {
+    let Some(sentinel) = &mut server.sentinel else {
+        return Err("This instance is not running in sentinel mode".into());
+    };
+
+    let ok = || RESPType::SimpleString("OK".into());
+    let no_such_master = || String::from("No such master with that name");
+
+    Ok(match (subcommand, args) {
+        ("MYID", []) => RESPType::BulkString(sentinel.id.clone().into()),
+        ("MASTERS", []) => {
+            let mut masters: Vec<_> = sentinel.masters.values().collect();
+            masters.sort_by(|a, b| a.name.cmp(&b.name));
+
+            RESPType::Array(masters.into_iter().map(describe_master).collect())
+        },
+        ("MASTER", [name]) => describe_master(sentinel.masters.get(name).ok_or_else(no_such_master)?),
+        ("REPLICAS" | "SLAVES", [name]) => {
+            let master = sentinel.masters.get(name).ok_or_else(no_such_master)?;
+
+            RESPType::Array(master.replicas.iter().map(|(address, r)| describe_instance(r, address.clone(), "slave")).collect())
+        },
+        ("SENTINELS", [name]) => {
+            let master = sentinel.masters.get(name).ok_or_else(no_such_master)?;
+
+            RESPType::Array(master.sentinels.iter().map(|(id, s)| describe_instance(s, id.clone(), "sentinel")).collect())
+        },
+        ("GET-MASTER-ADDR-BY-NAME", [name]) => match sentinel.masters.get(name) {
+            Some(master) => RESPType::Array(vec![
+                RESPType::BulkString(master.instance.ip.clone().into()),
+                RESPType::BulkString(master.instance.port.to_string().into()),
+            ]),
+            None => RESPType::Null,
+        },
+        ("MONITOR", [name, ip, port, quorum]) => {
+            let Ok(port) = port.parse::<u16>() else {
+                return Err("Invalid port number".into());
+            };
+
+            let Ok(quorum) = quorum.parse::<usize>() else {
+                return Err("value is not an integer or out of range".into());
+            };
+
+            sentinel.monitor(name.clone(), ip.clone(), port, quorum)?;
+
+            ok()
+        },
+        ("REMOVE", [name]) => match sentinel.remove(name) {
+            true => ok(),
+            false => return Err(no_such_master()),
+        },
+        ("SET", [name, rest @ ..]) if !rest.is_empty() && rest.len() % 2 == 0 => {
+            for pair in rest.chunks(2) {
+                sentinel.set_option(name, &pair[0], &pair[1])?;
+            }
+
+            ok()
+        },
+        ("IS-MASTER-DOWN-BY-ADDR", [ip, port, epoch, id]) => {
+            let (Ok(port), Ok(epoch)) = (port.parse::<u16>(), epoch.parse::<u64>()) else {
+                return Err("value is not an integer or out of range".into());
+            };
+
+            let (down, leader, leader_epoch) = sentinel.is_master_down_by_addr(ip, port, epoch, id);
+
+            RESPType::Array(vec![
+                RESPType::Integer(down as i64),
+                RESPType::BulkString(leader.into()),
+                RESPType::Integer(leader_epoch as i64),
+            ])
+        },
+        ("FAILOVER", [name]) => {
+            sentinel.force_failover(name)?;
+
+            ok()
+        },
+        ("MYID" | "MASTERS" | "MASTER" | "REPLICAS" | "SLAVES" | "SENTINELS" | "GET-MASTER-ADDR-BY-NAME" | "MONITOR" | "REMOVE" | "SET" | "IS-MASTER-DOWN-BY-ADDR" | "FAILOVER", _) => {
+            return Err("wrong number of arguments".into());
+        },
+        _ => return Err("unknown subcommand, try SENTINEL HELP".into()),
+    })
+}
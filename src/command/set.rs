@@ -5,7 +5,8 @@ use chrono::{TimeZone, Utc, LocalResult, Duration};
 use command_macro::command;
 
 use sider_command::RESPType;
-use crate::{db::{ExpiryFlag, ExistenceFlag, DBError, DBEntry}, util::from_decimal_bytes};
+use crate::notify;
+use crate::{db::{check_string_length, ExpiryFlag, ExistenceFlag, DBError, DBEntry}, util::from_decimal_bytes};
 
 use super::super::db::DB;
 
@@ -13,13 +14,13 @@ use super::super::db::DB;
 
 #[command(
     name = "set",
-    arity = -1,
-    flags = ("fast", "sentinel"),
+    arity = -3,
+    flags = ("write", "fast"),
     first_key = 1,
-    last_key = 2,
+    last_key = 1,
     step = 1,
     acl_categories = ("connection"),
-    command_tips = ("request_policy:all_shards", "response_policy:all_succeeded"),
+    command_tips = (),
 )]
 pub fn set(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
     if args.len() < 2 {
@@ -36,6 +37,10 @@ pub fn set(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
         return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
     };
 
+    if check_string_length(value.len(), db.max_string_length).is_err() {
+        return RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into());
+    }
+
     let mut remaining = args.iter().peekable();
 
     let mut expiry = ExpiryFlag::None;
@@ -100,7 +105,9 @@ pub fn set(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
         }
     }
 
-    let entry = match db.get_or_insert(key, expiry, existence_flag) {
+    let expires = matches!(expiry, ExpiryFlag::Some(_));
+
+    let entry = match db.get_or_insert(key.clone(), expiry, existence_flag) {
         Ok(e) => e,
         Err(DBError::AlreadyExists | DBError::DoesNotExist) => return RESPType::Null,
         Err(_) => panic!()
@@ -112,6 +119,12 @@ pub fn set(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
 
     let previous = entry.set_string(value.into());
 
+    db.notify(notify::STRING, "set", &key);
+
+    if expires {
+        db.notify(notify::GENERIC, "expire", &key);
+    }
+
     if return_previous_value {
         match previous {
             DBEntry::Nil => RESPType::Null,
@@ -0,0 +1,65 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::bitops::set_bit;
+use crate::db::{DBString, ExpiryFlag, ExistenceFlag};
+use crate::notify;
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "setbit",
+    arity = 4,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "slow"),
+    command_tips = (),
+)]
+pub fn setbit(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 3 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let (RESPType::BulkString(value), RESPType::BulkString(offset), RESPType::BulkString(key)) = (args.remove(2), args.remove(1), args.remove(0)) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let Some(offset) = from_decimal_bytes(&offset).ok().and_then(|o| u64::try_from(o).ok()).filter(|o| *o < db.max_string_length as u64 * 8) else {
+        return RESPType::Error("bit offset is not an integer or out of range".into());
+    };
+
+    let value = match &value[..] {
+        b"0" => 0,
+        b"1" => 1,
+        _ => return RESPType::Error("bit is not an integer or out of range".into()),
+    };
+
+    let max_length = db.max_string_length;
+
+    let Ok(e) = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
+        return RESPType::Error("error retrieving key".into());
+    };
+
+    if e.is_nil() {
+        e.set_string(DBString::String(Bytes::new()));
+    }
+
+    let Ok(s) = e.get_mut_string() else {
+        return RESPType::Error("wrong type".into());
+    };
+
+    match s.grow_to((offset >> 3) as usize + 1, max_length) {
+        Ok(b) => {
+            let previous = set_bit(b, offset, value);
+            db.notify(notify::STRING, "setbit", &key);
+            RESPType::Integer(previous as i64)
+        },
+        Err(_) => RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into()),
+    }
+}
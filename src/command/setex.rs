@@ -0,0 +1,52 @@
+
+use bytes::Bytes;
+use chrono::{Duration, Utc};
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{check_string_length, DBEntry, ExpiryFlag, ExistenceFlag};
+use crate::notify;
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "setex",
+    arity = 4,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "slow"),
+    command_tips = (),
+)]
+pub fn setex(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 3 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let (RESPType::BulkString(value), RESPType::BulkString(seconds), RESPType::BulkString(key)) = (args.remove(2), args.remove(1), args.remove(0)) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let Ok(seconds) = from_decimal_bytes(&seconds) else {
+        return RESPType::Error("value is not an integer or out of range".into());
+    };
+
+    let Some(expiry) = seconds.checked_mul(1000).filter(|_| seconds > 0).and_then(|ms| Utc::now().checked_add_signed(Duration::milliseconds(ms))) else {
+        return RESPType::Error("invalid expire time in 'setex' command".into());
+    };
+
+    if check_string_length(value.len(), db.max_string_length).is_err() {
+        return RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into());
+    }
+
+    let e = db.get_or_insert(key.clone(), ExpiryFlag::Some(expiry), ExistenceFlag::None).unwrap();
+    *e = DBEntry::String(value.into());
+
+    db.notify(notify::STRING, "set", &key);
+    db.notify(notify::GENERIC, "expire", &key);
+
+    RESPType::SimpleString(Bytes::from("OK"))
+}
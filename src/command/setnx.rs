@@ -0,0 +1,52 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{check_string_length, ExpiryFlag, ExistenceFlag};
+
+use super::super::db::DB;
+use crate::notify;
+
+
+#[command(
+    name = "setnx",
+    arity = 3,
+    flags = ("write", "fast"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "fast"),
+    command_tips = (),
+)]
+pub fn setnx(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 2 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(value) = args.remove(1) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let RESPType::BulkString(key) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    if check_string_length(value.len(), db.max_string_length).is_err() {
+        return RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into());
+    }
+
+    // Expired keys are removed on access, so that they do not count as existing.
+    if db.get(&key).is_some() {
+        return RESPType::Integer(0);
+    }
+
+    match db.get_or_insert(key.clone(), ExpiryFlag::None, ExistenceFlag::Nx) {
+        Ok(e) => {
+            e.set_string(value.into());
+            db.notify(notify::STRING, "set", &key);
+            RESPType::Integer(1)
+        },
+        Err(_) => RESPType::Integer(0),
+    }
+}
@@ -0,0 +1,70 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::db::{check_string_length, DBString, ExpiryFlag, ExistenceFlag};
+use crate::notify;
+use crate::util::from_decimal_bytes;
+
+use super::super::db::DB;
+
+
+#[command(
+    name = "setrange",
+    arity = 4,
+    flags = ("write"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("write", "slow"),
+    command_tips = (),
+)]
+pub fn setrange(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 3 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let (RESPType::BulkString(value), RESPType::BulkString(offset), RESPType::BulkString(key)) = (args.remove(2), args.remove(1), args.remove(0)) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let Some(offset) = from_decimal_bytes(&offset).ok().and_then(|o| usize::try_from(o).ok()) else {
+        return RESPType::Error("offset is out of range".into());
+    };
+
+    if check_string_length(offset + value.len(), db.max_string_length).is_err() {
+        return RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into());
+    }
+
+    // Setting an empty range never creates the key.
+    if value.is_empty() {
+        return match db.get(&key).map(|e| e.get_string()) {
+            None => RESPType::Integer(0),
+            Some(Ok(s)) => RESPType::Integer(s.len() as i64),
+            Some(Err(_)) => RESPType::Error("wrong type".into()),
+        };
+    }
+
+    let max_length = db.max_string_length;
+
+    let Ok(e) = db.get_or_insert(key.clone(), ExpiryFlag::KeepTTL, ExistenceFlag::None) else {
+        return RESPType::Error("error retrieving key".into());
+    };
+
+    if e.is_nil() {
+        e.set_string(DBString::String(Bytes::new()));
+    }
+
+    let Ok(s) = e.get_mut_string() else {
+        return RESPType::Error("wrong type".into());
+    };
+
+    match s.set_range(offset, &value, max_length) {
+        Ok(len) => {
+            db.notify(notify::STRING, "setrange", &key);
+            RESPType::Integer(len as i64)
+        },
+        Err(_) => RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into()),
+    }
+}
@@ -0,0 +1,57 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use log::warn;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::{Server, ShutdownFlags};
+
+
+/// Save the data set if asked to, wait for lagging replicas unless NOW is given, and exit.
+#[command(
+    name = "shutdown",
+    arity = -1,
+    flags = ("admin", "sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "slow", "dangerous"),
+    command_tips = (),
+)]
+pub fn shutdown(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    let mut flags = ShutdownFlags::default();
+    let mut abort = false;
+
+    for arg in &args {
+        let RESPType::BulkString(arg) = arg else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        match arg.to_ascii_uppercase().as_slice() {
+            b"NOSAVE" if flags.save.is_none() => flags.save = Some(false),
+            b"SAVE" if flags.save.is_none() => flags.save = Some(true),
+            b"NOW" => flags.now = true,
+            b"FORCE" => flags.force = true,
+            b"ABORT" => abort = true,
+            _ => return Some(RESPType::Error("syntax error".into())),
+        }
+    }
+
+    if abort {
+        if args.len() != 1 || !server.abort_shutdown() {
+            return Some(RESPType::Error("Errors trying to SHUTDOWN. Check logs.".into()));
+        }
+
+        return Some(RESPType::SimpleString("OK".into()));
+    }
+
+    if let Err(e) = server.shutdown(flags, Some(token)) {
+        warn!("Errors trying to shut down the server: {}", e);
+        return Some(RESPType::Error("Errors trying to SHUTDOWN. Check logs.".into()));
+    }
+
+    // The connection is closed when we exit, or the client is blocked while we wait for
+    // replicas.
+    None
+}
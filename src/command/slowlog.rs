@@ -0,0 +1,83 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+
+
+const HELP: &[&str] = &[
+    "SLOWLOG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "GET [<count>]",
+    "    Return top <count> entries from the slowlog (default: 10, -1 mean all).",
+    "    Entries are made of:",
+    "    id, timestamp, time in microseconds, arguments array, client IP and port,",
+    "    client name",
+    "LEN",
+    "    Return the length of the slowlog.",
+    "RESET",
+    "    Reset the slowlog.",
+    "HELP",
+    "    Print this help.",
+];
+
+
+/// Inspect the log of commands which took longer than `slowlog-log-slower-than` to run.
+#[command(
+    name = "slowlog",
+    arity = -2,
+    flags = ("admin"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("admin", "slow", "dangerous"),
+    command_tips = ("non_deterministic_output"),
+)]
+pub fn slowlog(args: Vec<RESPType<Bytes>>, server: &mut Server, _: Token) -> Option<RESPType<Bytes>> {
+    if args.is_empty() {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let mut strings = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        strings.push(String::from_utf8_lossy(&s).into_owned());
+    }
+
+    let subcommand = strings.remove(0).to_ascii_uppercase();
+
+    Some(match (subcommand.as_str(), &strings[..]) {
+        ("GET", count) if count.len() <= 1 => {
+            let count = match count.first().map(|c| c.parse::<i64>()) {
+                None => 10,
+                Some(Ok(-1)) => usize::MAX,
+                Some(Ok(c)) if c >= 0 => c as usize,
+                Some(_) => return Some(RESPType::Error("count should be greater than or equal to -1".into())),
+            };
+
+            RESPType::Array(server.slowlog.entries().take(count)
+                .map(|e| RESPType::Array(vec![
+                    RESPType::Integer(e.id as i64),
+                    RESPType::Integer(e.time as i64),
+                    RESPType::Integer(e.duration.as_micros() as i64),
+                    RESPType::Array(e.args.iter().map(|a| RESPType::BulkString(a.clone())).collect()),
+                    RESPType::BulkString(e.address.clone().into()),
+                    RESPType::BulkString(e.name.clone()),
+                ]))
+                .collect())
+        },
+        ("LEN", []) => RESPType::Integer(server.slowlog.len() as i64),
+        ("RESET", []) => {
+            server.slowlog.reset();
+            RESPType::SimpleString("OK".into())
+        },
+        ("HELP", []) => RESPType::Array(HELP.iter().map(|l| RESPType::SimpleString(Bytes::from_static(l.as_bytes()))).collect()),
+        ("GET" | "LEN" | "RESET" | "HELP", _) => RESPType::Error("wrong number of arguments".into()),
+        _ => RESPType::Error("unknown subcommand, try SLOWLOG HELP".into()),
+    })
+}
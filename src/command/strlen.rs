@@ -0,0 +1,36 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use super::super::db::DB;
+
+
+#[command(
+    name = "strlen",
+    arity = 2,
+    flags = ("readonly", "fast"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("read", "fast"),
+    command_tips = (),
+)]
+pub fn strlen(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 1 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(key) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let Some(e) = db.get(&key) else {
+        return RESPType::Integer(0);
+    };
+
+    match e.get_string() {
+        Ok(s) => RESPType::Integer(s.len() as i64),
+        Err(_) => RESPType::Error("wrong type".into()),
+    }
+}
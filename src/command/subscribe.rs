@@ -0,0 +1,47 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+
+
+#[command(
+    name = "subscribe",
+    arity = -2,
+    flags = ("sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("pubsub", "slow"),
+    command_tips = (),
+)]
+pub fn subscribe(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    if args.is_empty() {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let mut channels = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        channels.push(s);
+    }
+
+    // Each channel is confirmed with its own reply.
+    for channel in channels {
+        let count = server.subscribe(token, channel.clone());
+
+        server.reply(token, &RESPType::Array(vec![
+            RESPType::BulkString(Bytes::from("subscribe")),
+            RESPType::BulkString(channel),
+            RESPType::Integer(count as i64),
+        ]));
+    }
+
+    None
+}
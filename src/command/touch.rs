@@ -0,0 +1,54 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use super::super::db::DB;
+
+
+#[command(
+    name = "touch",
+    arity = -2,
+    flags = ("readonly", "fast"),
+    first_key = 1,
+    last_key = -1,
+    step = 1,
+    acl_categories = ("keyspace", "read", "fast"),
+    command_tips = ("request_policy:multi_shard", "response_policy:agg_sum"),
+)]
+pub fn touch(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut total = 0;
+
+    for a in args {
+        let RESPType::BulkString(k) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        total += db.get(&k).is_some() as i64;
+    }
+
+    RESPType::Integer(total)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use super::touch;
+
+    #[test]
+    fn test_touch() {
+        let mut db = DB::new();
+        db.insert("a".into(), DBEntry::String(DBString::Integer(1)), None);
+        db.insert("b".into(), DBEntry::String(DBString::Integer(2)), None);
+
+        assert_eq!(touch(args(&["a", "b", "missing", "a"]), &mut db), RESPType::Integer(3));
+        assert_eq!(db.object(&"a".into()).unwrap().idle_time().as_secs(), 0);
+    }
+}
@@ -0,0 +1,60 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use super::super::db::DB;
+
+
+#[command(
+    name = "type",
+    arity = 2,
+    flags = ("readonly", "fast"),
+    first_key = 1,
+    last_key = 1,
+    step = 1,
+    acl_categories = ("keyspace", "read", "fast"),
+    command_tips = (),
+)]
+pub fn type_(mut args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.len() != 1 {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let RESPType::BulkString(key) = args.remove(0) else {
+        return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+    };
+
+    let type_name = match db.get(&key) {
+        Some(e) => e.type_name(),
+        None => "none",
+    };
+
+    RESPType::SimpleString(Bytes::from(type_name))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use crate::sorted_set::SortedSet;
+    use super::type_;
+
+    #[test]
+    fn test_type() {
+        let mut db = DB::new();
+        db.insert("s".into(), DBEntry::String(DBString::Integer(1)), None);
+        db.insert("l".into(), DBEntry::List(VecDeque::from([b"a".to_vec()])), None);
+        db.insert("z".into(), DBEntry::SortedSet(SortedSet::new()), None);
+
+        for (key, expected) in [("s", "string"), ("l", "list"), ("z", "zset"), ("missing", "none")] {
+            assert_eq!(type_(args(&[key]), &mut db), RESPType::SimpleString(expected.into()));
+        }
+
+        assert!(matches!(type_(args(&[]), &mut db), RESPType::Error(_)));
+    }
+}
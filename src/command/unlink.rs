@@ -0,0 +1,62 @@
+
+use bytes::Bytes;
+use command_macro::command;
+
+use sider_command::RESPType;
+use crate::notify;
+use super::super::db::DB;
+
+
+#[command(
+    name = "unlink",
+    arity = -2,
+    flags = ("write", "fast"),
+    first_key = 1,
+    last_key = -1,
+    step = 1,
+    acl_categories = ("keyspace", "write", "fast"),
+    command_tips = ("request_policy:multi_shard", "response_policy:agg_sum"),
+)]
+pub fn unlink(args: Vec<RESPType<Bytes>>, db: &mut DB) -> RESPType<Bytes> {
+    if args.is_empty() {
+        return RESPType::Error("wrong number of arguments".into());
+    }
+
+    let mut total = 0;
+
+    for a in args {
+        let RESPType::BulkString(k) = a else {
+            return RESPType::Error("Invalid command format, expecting array of bulk strings.".into());
+        };
+
+        if db.unlink(&k) {
+            db.notify(notify::GENERIC, "del", &k);
+            total += 1;
+        }
+    }
+
+    RESPType::Integer(total)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use sider_command::RESPType;
+    use crate::command::args;
+    use crate::db::{DB, DBEntry, DBString};
+    use super::unlink;
+
+    #[test]
+    fn test_unlink() {
+        let mut db = DB::new();
+        db.insert("a".into(), DBEntry::String(DBString::Integer(1)), None);
+        // Large enough to be freed on the lazyfree thread.
+        db.insert("b".into(), DBEntry::List((0..1000).map(|i: u32| i.to_be_bytes().to_vec()).collect::<VecDeque<_>>()), None);
+
+        assert_eq!(unlink(args(&["a", "b", "missing"]), &mut db), RESPType::Integer(2));
+        assert_eq!(db.key_count(), 0);
+        assert_eq!(unlink(args(&["a"]), &mut db), RESPType::Integer(0));
+    }
+}
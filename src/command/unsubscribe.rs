@@ -0,0 +1,55 @@
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+
+
+#[command(
+    name = "unsubscribe",
+    arity = -1,
+    flags = ("sentinel"),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("pubsub", "slow"),
+    command_tips = (),
+)]
+pub fn unsubscribe(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    let mut channels = Vec::with_capacity(args.len());
+
+    for a in args {
+        let RESPType::BulkString(s) = a else {
+            return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+        };
+
+        channels.push(s);
+    }
+
+    // Without any channels, the client is unsubscribed from all of them.
+    if channels.is_empty() {
+        channels = server.client(token).map(|c| c.subscriptions.iter().cloned().collect()).unwrap_or_default();
+    }
+
+    if channels.is_empty() {
+        return Some(RESPType::Array(vec![
+            RESPType::BulkString(Bytes::from("unsubscribe")),
+            RESPType::Null,
+            RESPType::Integer(0),
+        ]));
+    }
+
+    for channel in channels {
+        let count = server.unsubscribe(token, &channel);
+
+        server.reply(token, &RESPType::Array(vec![
+            RESPType::BulkString(Bytes::from("unsubscribe")),
+            RESPType::BulkString(channel),
+            RESPType::Integer(count as i64),
+        ]));
+    }
+
+    None
+}
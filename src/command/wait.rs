@@ -0,0 +1,57 @@
+
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use command_macro::command;
+use mio::Token;
+
+use sider_command::RESPType;
+use crate::server::Server;
+use crate::util::from_decimal_bytes;
+
+
+#[command(
+    name = "wait",
+    arity = 3,
+    flags = (),
+    first_key = 0,
+    last_key = 0,
+    step = 0,
+    acl_categories = ("slow", "connection"),
+    command_tips = ("request_policy:all_shards", "response_policy:agg_min"),
+)]
+pub fn wait(args: Vec<RESPType<Bytes>>, server: &mut Server, token: Token) -> Option<RESPType<Bytes>> {
+    if args.len() != 2 {
+        return Some(RESPType::Error("wrong number of arguments".into()));
+    }
+
+    let (RESPType::BulkString(replicas), RESPType::BulkString(timeout)) = (&args[0], &args[1]) else {
+        return Some(RESPType::Error("Invalid command format, expecting array of bulk strings.".into()));
+    };
+
+    if server.replication.is_replica() {
+        return Some(RESPType::Error("WAIT cannot be used with replica instances.".into()));
+    }
+
+    let (Ok(replicas), Ok(timeout)) = (from_decimal_bytes(replicas), from_decimal_bytes(timeout)) else {
+        return Some(RESPType::Error("value is not an integer or out of range".into()));
+    };
+
+    if timeout < 0 {
+        return Some(RESPType::Error("timeout is negative".into()));
+    }
+
+    let offset = server.client(token).map_or(0, |c| c.write_offset);
+    let acks = server.count_acks(offset);
+
+    if acks as i64 >= replicas {
+        return Some(RESPType::Integer(acks as i64));
+    }
+
+    // A timeout of zero blocks forever.
+    let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout as u64));
+
+    server.block_for_acks(token, offset, replicas as usize, deadline);
+
+    None
+}
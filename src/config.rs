@@ -0,0 +1,858 @@
+
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use log::LevelFilter;
+
+use crate::notify;
+use crate::tls::{AuthClients, AuthClientsUser};
+use crate::util::{glob_match, split_args};
+
+
+/// The ways a configuration file or command line can be wrong.
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io(String),
+    /// A line could not be applied. Holds the line number, the line and the reason.
+    Directive(usize, String, String),
+}
+
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Fatal error, can't open config file: {}", e),
+            Self::Directive(number, line, reason) => write!(
+                f,
+                "\n*** FATAL CONFIG FILE ERROR ***\nReading the configuration file, at line {}\n>>> '{}'\n{}",
+                number, line, reason,
+            ),
+        }
+    }
+}
+
+
+/// A configuration parameter. `set` parses a value in the same format `get` produces, which
+/// for parameters taking several arguments is the arguments separated by spaces.
+pub struct Parameter {
+    pub name: &'static str,
+    /// Whether the parameter can be changed by CONFIG SET.
+    pub mutable: bool,
+    pub get: fn(&Config) -> String,
+    set: fn(&mut Config, &str) -> Result<(), String>,
+}
+
+
+fn yes_no(b: bool) -> String {
+    if b { "yes" } else { "no" }.into()
+}
+
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".into()),
+    }
+}
+
+
+/// Parameters naming files are empty when unset.
+fn parse_path(value: &str) -> Option<PathBuf> {
+    (!value.is_empty()).then(|| value.into())
+}
+
+
+fn path_to_string(path: &Option<PathBuf>) -> String {
+    path.as_ref().map(|p| p.display().to_string()).unwrap_or_default()
+}
+
+
+fn parse_number<T: FromStr + PartialOrd + Display>(value: &str, min: T, max: T) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(n) if min <= n && n <= max => Ok(n),
+        _ => Err(format!("argument must be between {} and {} inclusive", min, max)),
+    }
+}
+
+
+/// Parse a number of bytes, which may have a unit as in redis.conf, like `16kb` or `1gb`. The
+/// units without a `b` are powers of ten, and those with one powers of two.
+fn parse_memory(value: &str, min: usize, max: usize) -> Result<usize, String> {
+    let lower = value.to_ascii_lowercase();
+    let digits = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+
+    let unit = match &lower[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err("argument must be a memory value".into()),
+    };
+
+    match digits.parse::<usize>().ok().and_then(|n| n.checked_mul(unit)) {
+        Some(n) if min <= n && n <= max => Ok(n),
+        Some(_) => Err(format!("argument must be between {} and {} inclusive", min, max)),
+        None => Err("argument must be a memory value".into()),
+    }
+}
+
+
+/// The most addresses `bind` takes, as in Redis.
+pub const MAX_BIND_ADDRESSES: usize = 16;
+
+
+/// Parse an address given to `bind`. `*` and `::*` stand for every IPv4 and IPv6 address, and
+/// addresses starting with `-` are optional, so failing to listen on them is not an error.
+/// Returns the address and whether it is optional.
+pub fn parse_bind_address(address: &str) -> Option<(IpAddr, bool)> {
+    let (address, optional) = match address.strip_prefix('-') {
+        Some(address) => (address, true),
+        None => (address, false),
+    };
+
+    let ip = match address {
+        "*" => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        "::*" => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        address => address.parse().ok()?,
+    };
+
+    Some((ip, optional))
+}
+
+
+/// The log levels of redis.conf, in increasing order of severity.
+const LOG_LEVELS: &[(&str, LevelFilter)] = &[
+    ("debug", LevelFilter::Trace),
+    ("verbose", LevelFilter::Debug),
+    ("notice", LevelFilter::Info),
+    ("warning", LevelFilter::Warn),
+    ("nothing", LevelFilter::Off),
+];
+
+
+pub const PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "bind",
+        mutable: false,
+        get: |c| c.bind.join(" "),
+        set: |c, v| {
+            let addresses: Vec<String> = v.split_whitespace().map(String::from).collect();
+
+            if addresses.len() > MAX_BIND_ADDRESSES {
+                return Err("Too many bind addresses specified.".into());
+            }
+
+            if let Some(invalid) = addresses.iter().find(|a| parse_bind_address(a).is_none()) {
+                return Err(format!("Invalid bind address '{}'", invalid));
+            }
+
+            c.bind = addresses;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "tcp-backlog",
+        mutable: false,
+        get: |c| c.tcp_backlog.to_string(),
+        set: |c, v| {
+            c.tcp_backlog = parse_number(v, 0, i32::MAX)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "protected-mode",
+        mutable: true,
+        get: |c| yes_no(c.protected_mode),
+        set: |c, v| {
+            c.protected_mode = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "requirepass",
+        mutable: true,
+        get: |c| c.requirepass.clone().unwrap_or_default(),
+        set: |c, v| {
+            c.requirepass = (!v.is_empty()).then(|| v.into());
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "port",
+        mutable: false,
+        get: |c| c.port.to_string(),
+        set: |c, v| {
+            c.port = parse_number(v, 0, u16::MAX)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "unixsocket",
+        mutable: false,
+        get: |c| path_to_string(&c.unixsocket),
+        set: |c, v| {
+            c.unixsocket = parse_path(v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "unixsocketperm",
+        mutable: false,
+        get: |c| format!("{:o}", c.unixsocketperm),
+        set: |c, v| {
+            c.unixsocketperm = match u32::from_str_radix(v, 8) {
+                Ok(mode) if mode <= 0o777 => mode,
+                _ => return Err("argument must be an octal number between 0 and 777".into()),
+            };
+
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "tls-port",
+        mutable: false,
+        get: |c| c.tls_port.to_string(),
+        set: |c, v| {
+            c.tls_port = parse_number(v, 0, u16::MAX)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "tls-cert-file",
+        mutable: false,
+        get: |c| path_to_string(&c.tls_cert_file),
+        set: |c, v| {
+            c.tls_cert_file = parse_path(v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "tls-key-file",
+        mutable: false,
+        get: |c| path_to_string(&c.tls_key_file),
+        set: |c, v| {
+            c.tls_key_file = parse_path(v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "tls-ca-cert-file",
+        mutable: false,
+        get: |c| path_to_string(&c.tls_ca_cert_file),
+        set: |c, v| {
+            c.tls_ca_cert_file = parse_path(v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "tls-auth-clients",
+        mutable: false,
+        get: |c| match c.tls_auth_clients {
+            AuthClients::No => "no".into(),
+            AuthClients::Yes => "yes".into(),
+            AuthClients::Optional => "optional".into(),
+        },
+        set: |c, v| {
+            c.tls_auth_clients = match v.to_ascii_lowercase().as_str() {
+                "no" => AuthClients::No,
+                "yes" => AuthClients::Yes,
+                "optional" => AuthClients::Optional,
+                _ => return Err("argument(s) must be one of the following: no, yes, optional".into()),
+            };
+
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "tls-auth-clients-user",
+        mutable: true,
+        get: |c| match c.tls_auth_clients_user {
+            AuthClientsUser::Off => "off".into(),
+            AuthClientsUser::CommonName => "CN".into(),
+        },
+        set: |c, v| {
+            c.tls_auth_clients_user = match v.to_ascii_lowercase().as_str() {
+                "off" => AuthClientsUser::Off,
+                "cn" => AuthClientsUser::CommonName,
+                _ => return Err("argument(s) must be one of the following: off, CN".into()),
+            };
+
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "tls-replication",
+        mutable: true,
+        get: |c| yes_no(c.tls_replication),
+        set: |c, v| {
+            c.tls_replication = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "pidfile",
+        mutable: false,
+        get: |c| path_to_string(&c.pidfile),
+        set: |c, v| {
+            c.pidfile = parse_path(v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "dbfilename",
+        mutable: true,
+        get: |c| path_to_string(&c.dbfilename),
+        set: |c, v| {
+            c.dbfilename = parse_path(v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "shutdown-timeout",
+        mutable: true,
+        get: |c| c.shutdown_timeout.to_string(),
+        set: |c, v| {
+            c.shutdown_timeout = parse_number(v, 0, i32::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "hz",
+        mutable: true,
+        get: |c| c.hz.to_string(),
+        set: |c, v| {
+            c.hz = parse_number(v, 1, 500)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxclients",
+        mutable: true,
+        get: |c| c.maxclients.to_string(),
+        set: |c, v| {
+            c.maxclients = parse_number(v, 1, 1_000_000)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "timeout",
+        mutable: true,
+        get: |c| c.timeout.to_string(),
+        set: |c, v| {
+            c.timeout = parse_number(v, 0, i32::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "tcp-keepalive",
+        mutable: true,
+        get: |c| c.tcp_keepalive.to_string(),
+        set: |c, v| {
+            c.tcp_keepalive = parse_number(v, 0, i32::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "read-buffer-size",
+        mutable: true,
+        get: |c| c.read_buffer_size.to_string(),
+        set: |c, v| {
+            c.read_buffer_size = parse_memory(v, 1024, 64 * 1024 * 1024)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "proto-max-bulk-len",
+        mutable: true,
+        get: |c| c.proto_max_bulk_len.to_string(),
+        set: |c, v| {
+            c.proto_max_bulk_len = parse_memory(v, 1024 * 1024, i64::MAX as usize)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "proto-max-multibulk-len",
+        mutable: true,
+        get: |c| c.proto_max_multibulk_len.to_string(),
+        set: |c, v| {
+            c.proto_max_multibulk_len = parse_number(v, 1024, i32::MAX as usize)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "proto-max-nesting-depth",
+        mutable: true,
+        get: |c| c.proto_max_nesting_depth.to_string(),
+        set: |c, v| {
+            c.proto_max_nesting_depth = parse_number(v, 1, 128)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "client-query-buffer-limit",
+        mutable: true,
+        get: |c| c.client_query_buffer_limit.to_string(),
+        set: |c, v| {
+            c.client_query_buffer_limit = parse_memory(v, 1024 * 1024, i64::MAX as usize)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "cluster-enabled",
+        mutable: false,
+        get: |c| yes_no(c.cluster_enabled),
+        set: |c, v| {
+            c.cluster_enabled = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "replicaof",
+        mutable: false,
+        get: |c| c.replicaof.as_ref().map_or(String::new(), |(host, port)| format!("{} {}", host, port)),
+        set: |c, v| {
+            c.replicaof = match v.split_whitespace().collect::<Vec<_>>()[..] {
+                [no, one] if no.eq_ignore_ascii_case("no") && one.eq_ignore_ascii_case("one") => None,
+                [host, port] => Some((host.into(), parse_number(port, 1, u16::MAX)?)),
+                _ => return Err("wrong number of arguments".into()),
+            };
+
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "masterauth",
+        mutable: true,
+        get: |c| c.masterauth.clone().unwrap_or_default(),
+        set: |c, v| {
+            c.masterauth = (!v.is_empty()).then(|| v.into());
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "replica-read-only",
+        mutable: true,
+        get: |c| yes_no(c.replica_read_only),
+        set: |c, v| {
+            c.replica_read_only = parse_bool(v)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "slowlog-log-slower-than",
+        mutable: true,
+        get: |c| c.slowlog_log_slower_than.to_string(),
+        set: |c, v| {
+            c.slowlog_log_slower_than = parse_number(v, -1, i64::MAX)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "slowlog-max-len",
+        mutable: true,
+        get: |c| c.slowlog_max_len.to_string(),
+        set: |c, v| {
+            c.slowlog_max_len = parse_number(v, 0, i64::MAX as usize)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "latency-monitor-threshold",
+        mutable: true,
+        get: |c| c.latency_monitor_threshold.to_string(),
+        set: |c, v| {
+            c.latency_monitor_threshold = parse_number(v, 0, i64::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "notify-keyspace-events",
+        mutable: true,
+        get: |c| notify::flags_to_string(c.notify_keyspace_events),
+        set: |c, v| {
+            c.notify_keyspace_events = notify::parse_flags(v).ok_or("Invalid event class character. Use 'Ag$lshzxeKEtm'.")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "loglevel",
+        mutable: true,
+        get: |c| LOG_LEVELS.iter().find(|(_, l)| *l == c.loglevel).unwrap().0.into(),
+        set: |c, v| {
+            let Some((_, level)) = LOG_LEVELS.iter().find(|(name, _)| name.eq_ignore_ascii_case(v)) else {
+                return Err("argument(s) must be one of the following: debug, verbose, notice, warning, nothing".into());
+            };
+
+            c.loglevel = *level;
+            Ok(())
+        },
+    },
+];
+
+
+/// The server's configuration, which is read from a file in the redis.conf format and the
+/// command line, and can be changed at runtime by CONFIG SET.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The addresses to listen on, in the format `parse_bind_address` takes. None turns the
+    /// TCP listeners off.
+    pub bind: Vec<String>,
+    /// Zero picks a free port.
+    pub port: u16,
+    /// The length of the queue of connections waiting to be accepted.
+    pub tcp_backlog: i32,
+    /// Refuse clients which do not connect over the loopback interface while there is no
+    /// password, which protects servers which are reachable from other machines by mistake.
+    pub protected_mode: bool,
+    /// The password clients have to give with AUTH before running other commands. None lets
+    /// every client in.
+    pub requirepass: Option<String>,
+    /// The path of a Unix socket to accept clients on as well.
+    pub unixsocket: Option<PathBuf>,
+    /// The permissions to give the Unix socket, or zero to leave them to the umask.
+    pub unixsocketperm: u32,
+    /// The port to accept TLS connections on. Zero turns the TLS listener off.
+    pub tls_port: u16,
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    /// The certificates of the authorities which sign the certificates of clients, and of our
+    /// primary when replicating over TLS.
+    pub tls_ca_cert_file: Option<PathBuf>,
+    pub tls_auth_clients: AuthClients,
+    /// Whether clients with a verified certificate are authenticated as the user it names.
+    pub tls_auth_clients_user: AuthClientsUser,
+    /// Connect to our primary over TLS.
+    pub tls_replication: bool,
+    /// The file our process ID is written to while we run.
+    pub pidfile: Option<PathBuf>,
+    /// The file the data set is loaded from on startup and saved to on shutdown. Without one,
+    /// nothing is kept between runs.
+    pub dbfilename: Option<PathBuf>,
+    /// The most seconds to wait for lagging replicas to catch up when shutting down.
+    pub shutdown_timeout: u64,
+    /// How many times a second background tasks like expiry run.
+    pub hz: u32,
+    /// The most clients which may be connected at once.
+    pub maxclients: usize,
+    /// Clients which have not sent anything for this many seconds are disconnected. Zero
+    /// lets them idle forever.
+    pub timeout: u64,
+    /// The seconds a TCP connection is idle before keepalive probes are sent. Zero turns
+    /// keepalive off.
+    pub tcp_keepalive: u64,
+    /// How much is read from a client's socket at a time.
+    pub read_buffer_size: usize,
+    /// The longest bulk string a client may send.
+    pub proto_max_bulk_len: usize,
+    /// The most elements in an array a client may send.
+    pub proto_max_multibulk_len: usize,
+    /// How deeply the arrays a client sends may be nested.
+    pub proto_max_nesting_depth: usize,
+    /// Clients whose unparsed input grows past this many bytes are disconnected.
+    pub client_query_buffer_limit: usize,
+    pub cluster_enabled: bool,
+    /// The primary to replicate from on startup.
+    pub replicaof: Option<(String, u16)>,
+    /// The password to give our primary, if it has one.
+    pub masterauth: Option<String>,
+    pub replica_read_only: bool,
+    pub loglevel: LevelFilter,
+    /// Commands taking at least this many microseconds are put in the slow log. Negative
+    /// values turn the slow log off.
+    pub slowlog_log_slower_than: i64,
+    pub slowlog_max_len: usize,
+    /// Latency spikes of at least this many milliseconds are recorded. Zero turns the latency
+    /// monitor off.
+    pub latency_monitor_threshold: u64,
+    /// The classes of keyspace notifications to publish, from `notify`.
+    pub notify_keyspace_events: u32,
+    /// The arguments of each `sentinel` directive, which are applied on startup when running as
+    /// a sentinel.
+    pub sentinel: Vec<Vec<String>>,
+    /// The file the configuration was loaded from, which CONFIG REWRITE updates.
+    pub file: Option<PathBuf>,
+}
+
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: vec!["127.0.0.1".into(), "-::1".into()],
+            port: 6379,
+            tcp_backlog: 511,
+            protected_mode: true,
+            requirepass: None,
+            unixsocket: None,
+            unixsocketperm: 0,
+            tls_port: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: AuthClients::Yes,
+            tls_auth_clients_user: AuthClientsUser::Off,
+            tls_replication: false,
+            pidfile: None,
+            dbfilename: None,
+            shutdown_timeout: 10,
+            hz: 10,
+            maxclients: 10000,
+            timeout: 0,
+            tcp_keepalive: 300,
+            read_buffer_size: 1024 * 16,
+            proto_max_bulk_len: 512 * 1024 * 1024,
+            proto_max_multibulk_len: i32::MAX as usize,
+            proto_max_nesting_depth: 8,
+            client_query_buffer_limit: 1024 * 1024 * 1024,
+            cluster_enabled: false,
+            replicaof: None,
+            masterauth: None,
+            replica_read_only: true,
+            loglevel: LevelFilter::Info,
+            slowlog_log_slower_than: 10000,
+            slowlog_max_len: 128,
+            latency_monitor_threshold: 0,
+            notify_keyspace_events: 0,
+            sentinel: Vec::new(),
+            file: None,
+        }
+    }
+}
+
+
+impl Config {
+    /// Read the configuration file at `path`, which CONFIG REWRITE will then write to.
+    pub fn load_file(&mut self, path: PathBuf) -> Result<(), ConfigError> {
+        let text = fs::read_to_string(&path).map_err(|e| ConfigError::Io(e.to_string()))?;
+
+        self.file = Some(path);
+        self.load(&text)
+    }
+
+    /// Apply each line of configuration in turn. Blank lines and comments are skipped.
+    pub fn load(&mut self, text: &str) -> Result<(), ConfigError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |reason: &str| ConfigError::Directive(i + 1, line.into(), reason.into());
+
+            let Some(args) = split_args(line.as_bytes()) else {
+                return Err(error("Unbalanced quotes in configuration line"));
+            };
+
+            let args: Vec<String> = args.into_iter().map(|a| String::from_utf8_lossy(&a).into_owned()).collect();
+
+            self.apply(&args).map_err(|e| error(&e))?;
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self, args: &[String]) -> Result<(), String> {
+        let name = args[0].to_ascii_lowercase();
+
+        if name == "sentinel" {
+            if args.len() < 2 {
+                return Err("wrong number of arguments".into());
+            }
+
+            self.sentinel.push(args[1..].to_vec());
+
+            return Ok(());
+        }
+
+        match PARAMETERS.iter().find(|p| p.name == name) {
+            Some(parameter) if args.len() > 1 => (parameter.set)(self, &args[1..].join(" ")),
+            Some(_) => Err("wrong number of arguments".into()),
+            None => Err("Bad directive or wrong number of arguments".into()),
+        }
+    }
+
+    /// The parameters whose names match any of the glob style patterns, with their values.
+    pub fn get(&self, patterns: &[String]) -> Vec<(&'static str, String)> {
+        PARAMETERS.iter()
+            .filter(|p| patterns.iter().any(|pattern| glob_match(pattern.to_ascii_lowercase().as_bytes(), p.name.as_bytes())))
+            .map(|p| (p.name, (p.get)(self)))
+            .collect()
+    }
+
+    /// Change parameters at runtime. Either every change is made, or none are.
+    pub fn set(&mut self, changes: &[(String, String)]) -> Result<(), String> {
+        let mut updated = self.clone();
+
+        for (i, (name, value)) in changes.iter().enumerate() {
+            let name = name.to_ascii_lowercase();
+
+            let Some(parameter) = PARAMETERS.iter().find(|p| p.name == name) else {
+                return Err(format!("Unknown option or number of arguments for CONFIG SET - '{}'", name));
+            };
+
+            if changes[..i].iter().any(|(n, _)| n.eq_ignore_ascii_case(&name)) {
+                return Err(format!("CONFIG SET failed (possibly related to argument '{}') - duplicate parameter", name));
+            }
+
+            if !parameter.mutable {
+                return Err(format!("CONFIG SET failed (possibly related to argument '{}') - can't set immutable config", name));
+            }
+
+            (parameter.set)(&mut updated, value).map_err(|e| format!("CONFIG SET failed (possibly related to argument '{}') - {}", name, e))?;
+        }
+
+        *self = updated;
+
+        Ok(())
+    }
+
+    /// Write the current configuration back to the file it was loaded from. Lines setting
+    /// parameters are replaced by their current values, and parameters which are not in the
+    /// file but differ from their defaults are added at the end, as are the `sentinel`
+    /// directives, which replace the ones in the file. Comments and other directives are kept
+    /// as they are.
+    pub fn rewrite(&self) -> Result<(), String> {
+        let Some(path) = &self.file else {
+            return Err("The server is running without a config file".into());
+        };
+
+        let old = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Rewriting config file: {}", e)),
+        };
+
+        let line = |p: &Parameter| Some((p.get)(self)).filter(|v| !v.is_empty()).map(|v| format!("{} {}", p.name, v));
+        let sentinel_lines = || self.sentinel.iter().map(|args| format!("sentinel {}", args.join(" ")));
+
+        let mut lines = Vec::new();
+        let mut written = HashSet::new();
+
+        for text in old.lines() {
+            let name = split_args(text.trim().as_bytes())
+                .and_then(|args| args.into_iter().next())
+                .map(|name| String::from_utf8_lossy(&name).to_ascii_lowercase());
+
+            match PARAMETERS.iter().find(|p| Some(p.name) == name.as_deref()) {
+                // Only the first line setting a parameter is kept.
+                Some(p) if written.insert(p.name) => lines.extend(line(p)),
+                Some(_) => {},
+                // The sentinel state replaces the old directives, where the first of them was.
+                None if name.as_deref() == Some("sentinel") => {
+                    if written.insert("sentinel") {
+                        lines.extend(sentinel_lines());
+                    }
+                },
+                None => lines.push(text.to_string()),
+            }
+        }
+
+        let defaults = Config::default();
+        let mut added = PARAMETERS.iter()
+            .filter(|p| !written.contains(p.name) && (p.get)(self) != (p.get)(&defaults))
+            .filter_map(line)
+            .chain(sentinel_lines().filter(|_| !written.contains("sentinel")))
+            .peekable();
+
+        if added.peek().is_some() {
+            lines.push("# Generated by CONFIG REWRITE".into());
+            lines.extend(added);
+        }
+
+        // Write to a temporary file first, so that a failure part way through leaves the old
+        // file intact.
+        let temporary = path.with_extension("tmp");
+        let mut text = lines.join("\n");
+        text.push('\n');
+
+        fs::write(&temporary, text)
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|e| format!("Rewriting config file: {}", e))
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use crate::config::{parse_bind_address, Config, ConfigError};
+    use crate::tls::AuthClients;
+
+    #[test]
+    fn test_load() {
+        let mut config = Config::default();
+        config.load("# comment\nport 7000\n\nhz 20\nread-buffer-size 32kb\nreplicaof \"127.0.0.1\" 6379\n").unwrap();
+
+        assert_eq!((config.port, config.hz, config.read_buffer_size), (7000, 20, 32 * 1024));
+        assert_eq!(config.replicaof, Some(("127.0.0.1".into(), 6379)));
+
+        config.load("tls-port 6380\ntls-cert-file /etc/sider/sider.crt\ntls-auth-clients optional\n").unwrap();
+        assert_eq!((config.tls_port, config.tls_auth_clients), (6380, AuthClients::Optional));
+        assert_eq!(config.get(&["tls-cert-file".into(), "tls-key-file".into()]), vec![("tls-cert-file", "/etc/sider/sider.crt".into()), ("tls-key-file", "".into())]);
+
+        assert_eq!(
+            config.load("hz 10\nhz 1000"),
+            Err(ConfigError::Directive(2, "hz 1000".into(), "argument must be between 1 and 500 inclusive".into())),
+        );
+
+        assert!(matches!(config.load("unknown yes"), Err(ConfigError::Directive(1, _, _))));
+    }
+
+    #[test]
+    fn test_get_and_set() {
+        let mut config = Config::default();
+
+        assert_eq!(config.get(&["h?".into(), "PORT".into()]), vec![("port", "6379".into()), ("hz", "10".into())]);
+
+        config.set(&[("hz".into(), "50".into()), ("loglevel".into(), "warning".into())]).unwrap();
+        assert_eq!(config.get(&["hz".into(), "loglevel".into()]), vec![("hz", "50".into()), ("loglevel", "warning".into())]);
+
+        // A failed change leaves every parameter as it was.
+        assert!(config.set(&[("hz".into(), "20".into()), ("hz".into(), "30".into())]).is_err());
+        assert!(config.set(&[("hz".into(), "20".into()), ("port".into(), "1".into())]).is_err());
+        assert_eq!(config.hz, 50);
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut config = Config::default();
+        assert_eq!(config.get(&["bind".into()]), vec![("bind", "127.0.0.1 -::1".into())]);
+
+        config.load("bind 0.0.0.0 -::*\n").unwrap();
+        assert_eq!(config.bind, vec!["0.0.0.0", "-::*"]);
+        assert!(config.load("bind 127.0.0.1 localhost\n").is_err());
+
+        assert_eq!(parse_bind_address("*"), Some((IpAddr::V4(Ipv4Addr::UNSPECIFIED), false)));
+        assert_eq!(parse_bind_address("-::*"), Some((IpAddr::V6(Ipv6Addr::UNSPECIFIED), true)));
+        assert_eq!(parse_bind_address("-::1"), Some((IpAddr::V6(Ipv6Addr::LOCALHOST), true)));
+        assert_eq!(parse_bind_address("localhost"), None);
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let path = std::env::temp_dir().join(format!("sider-rewrite-{}.conf", std::process::id()));
+        std::fs::write(&path, "# comment\nhz 20\nsentinel monitor m 127.0.0.1 6379 2\nhz 30\nsentinel current-epoch 1\n").unwrap();
+
+        let mut config = Config::default();
+        config.load_file(path.clone()).unwrap();
+        config.set(&[("hz".into(), "40".into()), ("replica-read-only".into(), "no".into())]).unwrap();
+        config.sentinel = vec![vec!["monitor".into(), "m".into(), "127.0.0.1".into(), "6380".into(), "2".into()], vec!["current-epoch".into(), "2".into()]];
+        config.rewrite().unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(text, "# comment\nhz 40\nsentinel monitor m 127.0.0.1 6380 2\nsentinel current-epoch 2\n# Generated by CONFIG REWRITE\nreplica-read-only no\n");
+    }
+}
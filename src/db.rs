@@ -1,16 +1,35 @@
-use std::collections::{HashMap, VecDeque, hash_map::Entry};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
+use rand::Rng;
 
-use crate::util::from_decimal_bytes;
+use crate::dict::Dict;
+use crate::lazyfree;
+use crate::notify::{self, Notifier};
+use crate::sorted_set::SortedSet;
+use crate::util::{add_decimal_strings, format_float, from_decimal_bytes, from_float_bytes};
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 
 
-#[derive(Debug, PartialEq)]
+/// The maximum length of a string value until `proto-max-bulk-len` says otherwise, the same
+/// limit as Redis.
+const DEFAULT_MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+/// Strings which have never been modified in place are reported as "embstr" up to this
+/// length, the longest string Redis allocates together with its object.
+const EMBSTR_SIZE_LIMIT: usize = 44;
+
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum DBString {
     Integer(i64),
-    String(Bytes)
+    String(Bytes),
+    /// A string which is modified in place, such as by APPEND or SETRANGE. Values are
+    /// converted to this representation the first time they are modified so that repeated
+    /// modification does not copy the whole value every time.
+    Buffer(BytesMut),
 }
 
 impl DBString {
@@ -18,25 +37,138 @@ impl DBString {
         match self {
             Self::String(v) => v.clone(),
             Self::Integer(i) => Bytes::from(i.to_string()),
+            Self::Buffer(b) => Bytes::copy_from_slice(b),
+        }
+    }
+
+    /// The length of the string representation of the value.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::String(v) => v.len(),
+            Self::Integer(i) => i.to_string().len(),
+            Self::Buffer(b) => b.len(),
         }
     }
 
-    pub fn incr_by(&mut self, n: i64) -> Result<i64, ()> {
-        let mut i = match self {
+    /// Borrow the bytes of the value, only allocating if the value is stored as an integer.
+    pub fn as_slice(&self) -> Cow<'_, [u8]> {
+        match self {
+            Self::String(v) => Cow::Borrowed(v),
+            Self::Integer(i) => Cow::Owned(i.to_string().into_bytes()),
+            Self::Buffer(b) => Cow::Borrowed(b),
+        }
+    }
+
+    /// Return the value as a mutable buffer which is at least `len` bytes long, padding it with
+    /// zero bytes if necessary. The value may not grow past `max` bytes.
+    pub fn grow_to(&mut self, len: usize, max: usize) -> Result<&mut BytesMut, DBError> {
+        check_string_length(len, max)?;
+
+        let b = self.as_mut_buffer();
+
+        if b.len() < len {
+            b.resize(len, 0);
+        }
+
+        Ok(b)
+    }
+
+    /// Return the value as a mutable buffer, converting it if necessary.
+    pub fn as_mut_buffer(&mut self) -> &mut BytesMut {
+        if !matches!(self, Self::Buffer(_)) {
+            *self = Self::Buffer(BytesMut::from(&self.to_bytes()[..]));
+        }
+
+        match self {
+            Self::Buffer(b) => b,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Return the bytes between `start` and `end` inclusive. Negative indices count back from
+    /// the end of the string.
+    pub fn get_range(&self, start: i64, end: i64) -> Bytes {
+        let bytes = self.to_bytes();
+        let len = bytes.len() as i64;
+
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let end = if end < 0 { len + end } else { end.min(len - 1) };
+
+        if start > end || len == 0 {
+            return Bytes::new();
+        }
+
+        bytes.slice(start as usize..=end as usize)
+    }
+
+    /// Append bytes to the value, returning the new length, which may not be more than `max`.
+    pub fn append(&mut self, v: &[u8], max: usize) -> Result<usize, DBError> {
+        check_string_length(self.len() + v.len(), max)?;
+
+        let b = self.as_mut_buffer();
+        b.extend_from_slice(v);
+
+        Ok(b.len())
+    }
+
+    /// Overwrite part of the value starting at `offset`, padding with zero bytes if the value
+    /// is not long enough. Returns the new length, which may not be more than `max`.
+    pub fn set_range(&mut self, offset: usize, v: &[u8], max: usize) -> Result<usize, DBError> {
+        if v.is_empty() {
+            return Ok(self.len());
+        }
+
+        let b = self.grow_to(offset + v.len(), max)?;
+
+        b[offset..offset + v.len()].copy_from_slice(v);
+
+        Ok(b.len())
+    }
+
+    pub fn incr_by(&mut self, n: i64) -> Result<i64, DBError> {
+        let i = match self {
             Self::Integer(i) => *i,
-            Self::String(s) => {
-                from_decimal_bytes(s)?
-            }
+            Self::String(s) => from_decimal_bytes(s).map_err(|_| DBError::NotAnInteger)?,
+            Self::Buffer(b) => from_decimal_bytes(b).map_err(|_| DBError::NotAnInteger)?,
         };
 
-
-        i += n;
+        let Some(i) = i.checked_add(n) else {
+            return Err(DBError::Overflow);
+        };
 
         *self = Self::Integer(i);
 
         Ok(i)
     }
 
+    /// Add to the value as a floating point number, returning the new value as it is stored.
+    pub fn incr_by_float(&mut self, increment: &[u8]) -> Result<Bytes, DBError> {
+        let current = self.as_slice();
+        let f = from_float_bytes(&current).map_err(|_| DBError::NotAFloat)?;
+        let n = from_float_bytes(increment).map_err(|_| DBError::NotAFloat)?;
+
+        let f = f + n;
+
+        if !f.is_finite() {
+            return Err(DBError::Overflow);
+        }
+
+        let value = Bytes::from(add_decimal_strings(&current, increment).unwrap_or_else(|| format_float(f)));
+        *self = Self::String(value.clone());
+
+        Ok(value)
+    }
+}
+
+
+/// Assert that a string of the given length would not exceed the maximum string length, which
+/// callers take from `DB::max_string_length`.
+pub fn check_string_length(len: usize, max: usize) -> Result<(), DBError> {
+    if len > max {
+        Err(DBError::TooLarge)
+    } else {
+        Ok(())
+    }
 }
 
 
@@ -75,10 +207,29 @@ impl From<Bytes> for DBString {
 #[derive(Debug)]
 pub struct DB {
     /// The money. This map stores all of the data that is stored in the database.
-    map: HashMap<Bytes, DBEntry>,
+    map: Dict<Bytes, Object>,
     /// Maintains track of all of the key/value pairs in the map which have expiry
     /// values set.
     expiring_entries: HashMap<Bytes, DateTime<Utc>>,
+    /// Keys which have been removed because they expired, since the last call to
+    /// `take_expired`. Replicas do not expire keys on their own, so these deletions need to
+    /// be sent to them.
+    expired: Vec<Bytes>,
+    pub stats: KeyspaceStats,
+    pub notifier: Notifier,
+    /// The longest a string value may be, set by `proto-max-bulk-len`.
+    pub max_string_length: usize,
+}
+
+
+/// Counters reported by INFO.
+#[derive(Debug, Default)]
+pub struct KeyspaceStats {
+    /// Lookups which found a key, and which did not.
+    pub hits: u64,
+    pub misses: u64,
+    /// Keys removed because their expiry time passed.
+    pub expired: u64,
 }
 
 
@@ -87,15 +238,89 @@ pub enum DBError {
     AlreadyExists,
     DoesNotExist,
     WrongType,
+    /// The value could not be interpreted as an integer.
+    NotAnInteger,
+    /// The value could not be interpreted as a floating point number.
+    NotAFloat,
+    /// The operation would overflow the numeric value, or produce NaN or infinity.
+    Overflow,
+    /// The operation would make a string longer than `DB::max_string_length`.
+    TooLarge,
+}
+
+
+/// The initial access frequency of a new object, so that new keys have a chance to accumulate
+/// accesses before they look less valuable than older keys.
+const FREQUENCY_INITIAL: u8 = 5;
+/// Controls how quickly the logarithmic access frequency counter saturates.
+const FREQUENCY_LOG_FACTOR: f64 = 10.0;
+/// The access frequency counter is decremented once for every period that passes without access.
+const FREQUENCY_DECAY_PERIOD: Duration = Duration::from_secs(60);
+
+
+/// A value stored in the database, along with the access statistics reported by the OBJECT
+/// command.
+#[derive(Debug)]
+pub struct Object {
+    entry: DBEntry,
+    last_access: Instant,
+    /// A logarithmic counter approximating how frequently the object is accessed, in the same
+    /// way as the Redis LFU counter.
+    frequency: u8,
+}
+
+
+impl Object {
+    fn new(entry: DBEntry) -> Self {
+        Object {
+            entry,
+            last_access: Instant::now(),
+            frequency: FREQUENCY_INITIAL,
+        }
+    }
+
+    pub fn entry(&self) -> &DBEntry {
+        &self.entry
+    }
+
+    /// The time since the object was last read or written.
+    pub fn idle_time(&self) -> Duration {
+        self.last_access.elapsed()
+    }
+
+    /// The access frequency counter, after decaying it for the time since the last access.
+    pub fn frequency(&self) -> u8 {
+        let periods = self.idle_time().as_secs() / FREQUENCY_DECAY_PERIOD.as_secs();
+
+        self.frequency.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+
+    /// Record an access to the object. The frequency counter is incremented with a probability
+    /// that decreases as the counter grows, so that it can represent a large number of accesses.
+    fn touch(&mut self) {
+        let mut frequency = self.frequency();
+
+        if frequency < u8::MAX {
+            let base = frequency.saturating_sub(FREQUENCY_INITIAL) as f64;
+
+            if rand::thread_rng().gen::<f64>() < 1.0 / (base * FREQUENCY_LOG_FACTOR + 1.0) {
+                frequency += 1;
+            }
+        }
+
+        self.frequency = frequency;
+        self.last_access = Instant::now();
+    }
 }
 
 
 /// An entry in the database.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum DBEntry {
     Nil,
     String(DBString),
     List(VecDeque<Vec<u8>>),
+    SortedSet(SortedSet),
 }
 
 
@@ -129,6 +354,13 @@ impl DBEntry {
         *self = Self::List(l);
     }
 
+    pub fn get_mut_sorted_set(&mut self) -> Result<&mut SortedSet, DBError> {
+        match self {
+            Self::SortedSet(z) => Ok(z),
+            _ => Err(DBError::WrongType),
+        }
+    }
+
     pub fn is_nil(&self) -> bool {
         self == &Self::Nil
     }
@@ -139,6 +371,48 @@ impl DBEntry {
             _ => false,
         }
     }
+
+    /// The name of the type of the entry, as reported by the TYPE command.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "none",
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+            Self::SortedSet(_) => "zset",
+        }
+    }
+
+    /// The name of the internal representation of the entry, as reported by OBJECT ENCODING.
+    ///
+    /// These are the names of the Redis encodings closest to each representation, so that
+    /// clients and tools which check them keep working:
+    ///
+    ///   * `int` for `DBString::Integer`.
+    ///   * `embstr` or `raw` for other strings, split at the length Redis would use.
+    ///   * `quicklist` for lists, which are a deque of elements, as a quicklist is a deque of
+    ///     packed nodes.
+    ///   * `skiplist` for sorted sets, which pair a map of scores with an ordered index, as
+    ///     the Redis skiplist encoding pairs a dict with a skiplist.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Self::Nil => "none",
+            Self::String(DBString::Integer(_)) => "int",
+            Self::String(DBString::String(s)) if s.len() <= EMBSTR_SIZE_LIMIT => "embstr",
+            Self::String(DBString::String(_) | DBString::Buffer(_)) => "raw",
+            Self::List(_) => "quicklist",
+            Self::SortedSet(_) => "skiplist",
+        }
+    }
+
+    /// An estimate of the amount of work required to free the entry, roughly the number
+    /// of separate allocations it owns.
+    pub fn free_effort(&self) -> usize {
+        match self {
+            Self::Nil | Self::String(_) => 1,
+            Self::List(l) => l.len(),
+            Self::SortedSet(z) => z.len(),
+        }
+    }
 }
 
 /// The ExpiryFlag enum is used to indicate expiry settings when setting a value in
@@ -184,11 +458,49 @@ impl DB {
     /// Construct a new instance of the database. Should only be required on startup.
     pub fn new() -> Self {
         DB {
-            map: HashMap::new(),
+            map: Dict::new(),
             expiring_entries: HashMap::new(),
+            expired: Vec::new(),
+            stats: KeyspaceStats::default(),
+            notifier: Notifier::default(),
+            max_string_length: DEFAULT_MAX_STRING_LENGTH,
         }
     }
 
+    /// Queue a keyspace notification about a key, if its class is turned on.
+    pub fn notify(&mut self, class: u32, event: &'static str, key: &Bytes) {
+        self.notifier.notify(class, event, key);
+    }
+
+    /// Record that a key has been removed because it expired.
+    fn expired(&mut self, key: Bytes) {
+        self.notify(notify::EXPIRED, "expired", &key);
+        self.expired.push(key);
+        self.stats.expired += 1;
+    }
+
+    /// The number of keys, including any which have expired but not been removed yet.
+    pub fn key_count(&self) -> usize {
+        self.map.len()
+    }
+
+    /// The number of keys with an expiry time.
+    pub fn volatile_count(&self) -> usize {
+        self.expiring_entries.len()
+    }
+
+    /// Estimate the average time to live of the keys with an expiry time, from a sample of
+    /// them.
+    pub fn average_ttl(&self) -> Option<Duration> {
+        let now = Utc::now();
+        let sample: Vec<i64> = self.expiring_entries.values()
+            .take(1000)
+            .map(|e| (*e - now).num_milliseconds().max(0))
+            .collect();
+
+        (!sample.is_empty()).then(|| Duration::from_millis((sample.iter().sum::<i64>() / sample.len() as i64) as u64))
+    }
+
     /// Determine whether or not a key exists in the database. Returns a boolean indicating
     /// whether or not this is the case.
     pub fn exists(&self, key: &Bytes) -> bool {
@@ -202,18 +514,106 @@ impl DB {
         self.map.remove(key).is_some()
     }
 
+    /// Delete a key from the database like `delete`, but hand large values off to a background
+    /// thread to be freed so that the server is not blocked.
+    pub fn unlink(&mut self, key: &Bytes) -> bool {
+        self.expiring_entries.remove(key);
+
+        match self.map.remove(key) {
+            Some(o) => {
+                lazyfree::free(o.entry);
+                true
+            },
+            None => false,
+        }
+    }
+
     pub fn get(&mut self, key: &Bytes) -> Option<&DBEntry> {
         if let Some(e) = self.expiring_entries.get(key) {
             if e <= &Utc::now() {
                 self.expiring_entries.remove(key);
                 self.map.remove(key);
+                self.expired(key.clone());
+                self.stats.misses += 1;
+                self.notify(notify::KEY_MISS, "keymiss", key);
                 return None;
             }
         };
 
+        let Some(o) = self.map.get_mut(key) else {
+            self.stats.misses += 1;
+            self.notifier.notify(notify::KEY_MISS, "keymiss", key);
+            return None;
+        };
+
+        o.touch();
+        self.stats.hits += 1;
+
+        Some(&o.entry)
+    }
+
+    /// Return the object stored at a key without counting it as an access.
+    pub fn object(&self, key: &Bytes) -> Option<&Object> {
+        if self.is_expired(key) {
+            return None;
+        }
+
         self.map.get(key)
     }
 
+    /// Rename a key, keeping its expiry time. If `replace` is false and the destination
+    /// already exists, nothing is changed and AlreadyExists is returned.
+    pub fn rename(&mut self, from: &Bytes, to: Bytes, replace: bool) -> Result<(), DBError> {
+        if self.get(from).is_none() {
+            return Err(DBError::DoesNotExist);
+        }
+
+        if from == &to {
+            return if replace { Ok(()) } else { Err(DBError::AlreadyExists) };
+        }
+
+        if self.get(&to).is_some() && !replace {
+            return Err(DBError::AlreadyExists);
+        }
+
+        let object = self.map.remove(from).unwrap();
+        let expiry = self.expiring_entries.remove(from);
+
+        self.unlink(&to);
+
+        if let Some(e) = expiry {
+            self.expiring_entries.insert(to.clone(), e);
+        }
+
+        self.map.insert(to, object);
+
+        Ok(())
+    }
+
+    /// Copy the value at a key to another key, including its expiry time. If `replace` is false
+    /// and the destination already exists, nothing is changed and AlreadyExists is returned.
+    pub fn copy(&mut self, from: &Bytes, to: Bytes, replace: bool) -> Result<(), DBError> {
+        let Some(entry) = self.get(from).cloned() else {
+            return Err(DBError::DoesNotExist);
+        };
+
+        if self.get(&to).is_some() {
+            if !replace {
+                return Err(DBError::AlreadyExists);
+            }
+
+            self.unlink(&to);
+        }
+
+        if let Some(e) = self.expiring_entries.get(from).cloned() {
+            self.expiring_entries.insert(to.clone(), e);
+        }
+
+        self.map.insert(to, Object::new(entry));
+
+        Ok(())
+    }
+
     pub fn get_or_insert(&mut self, key: Bytes, expiry: ExpiryFlag, existence_check: ExistenceFlag) -> Result<&mut DBEntry, DBError> {
         match expiry {
             ExpiryFlag::KeepTTL => {},
@@ -225,37 +625,107 @@ impl DB {
             }
         }
         
-        match self.map.entry(key) {
-            Entry::Occupied(e) => {
-                if existence_check == ExistenceFlag::Nx {
-                    return Err(DBError::AlreadyExists);
-                }
+        let exists = self.map.contains_key(&key);
 
-                return Ok(e.into_mut());
-            },
-            Entry::Vacant(e) => {
-                if existence_check == ExistenceFlag::Xx {
-                    return Err(DBError::DoesNotExist);
-                }
+        if exists && existence_check == ExistenceFlag::Nx {
+            return Err(DBError::AlreadyExists);
+        }
 
-                let v = DBEntry::Nil;
+        if !exists && existence_check == ExistenceFlag::Xx {
+            return Err(DBError::DoesNotExist);
+        }
 
-                Ok(e.insert(v))
-            },
+        let o = self.map.get_or_insert_with(key, || Object::new(DBEntry::Nil));
+        o.touch();
+
+        Ok(&mut o.entry)
+    }
+
+    /// Determine whether a key has an expiry time which has already passed. Keys like this
+    /// are still in the map until they are accessed or removed by the background task, but
+    /// should be treated as though they do not exist.
+    fn is_expired(&self, key: &Bytes) -> bool {
+        match self.expiring_entries.get(key) {
+            Some(e) => e <= &Utc::now(),
+            None => false,
+        }
+    }
+
+    /// Perform one step of a cursor based iteration over the keyspace, calling `f` with
+    /// every unexpired entry in the bucket addressed by the cursor. Returns the cursor to be
+    /// used for the next step, which is zero once the iteration is complete.
+    pub fn scan<F: FnMut(&Bytes, &DBEntry)>(&self, cursor: u64, mut f: F) -> u64 {
+        self.map.scan(cursor, |k, o| {
+            if !self.is_expired(k) {
+                f(k, &o.entry);
+            }
+        })
+    }
+
+    /// Return every unexpired key in the database.
+    pub fn keys(&self) -> impl Iterator<Item = &Bytes> {
+        self.map.keys().filter(|k| !self.is_expired(k))
+    }
+
+    /// Return a random unexpired key from the database, removing any expired keys that are
+    /// chosen along the way.
+    pub fn random_key(&mut self) -> Option<Bytes> {
+        loop {
+            let key = self.map.random_entry()?.0.clone();
+
+            if !self.is_expired(&key) {
+                return Some(key);
+            }
+
+            self.delete(&key);
+            self.expired(key);
         }
     }
 
     pub fn expire_keys(&mut self) {
         let now = Utc::now();
+        let mut expired = Vec::new();
         
         self.expiring_entries.retain(|k, e| {
             if *e < now {
                 println!("Removing key from map {:?}", k);
                 self.map.remove(k);
+                expired.push(k.clone());
                 false
             } else {
                 true
             }
         });
+
+        for key in expired {
+            self.expired(key);
+        }
+    }
+
+    /// Return the keys which have expired since the last call.
+    pub fn take_expired(&mut self) -> Vec<Bytes> {
+        std::mem::take(&mut self.expired)
+    }
+
+    /// Iterate over every unexpired entry in the database along with its expiry time.
+    pub fn entries(&self) -> impl Iterator<Item = (&Bytes, &DBEntry, Option<DateTime<Utc>>)> {
+        self.map.iter()
+            .filter(|(k, _)| !self.is_expired(k))
+            .map(|(k, o)| (k, &o.entry, self.expiring_entries.get(k).copied()))
+    }
+
+    /// Return the expiry time of a key, if it has one.
+    pub fn expiry(&self, key: &Bytes) -> Option<DateTime<Utc>> {
+        self.expiring_entries.get(key).copied()
+    }
+
+    /// Store an entry, replacing any existing value and expiry time.
+    pub fn insert(&mut self, key: Bytes, entry: DBEntry, expiry: Option<DateTime<Utc>>) {
+        match expiry {
+            Some(e) => self.expiring_entries.insert(key.clone(), e),
+            None => self.expiring_entries.remove(&key),
+        };
+
+        self.map.insert(key, Object::new(entry));
     }
 }
\ No newline at end of file
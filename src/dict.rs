@@ -0,0 +1,298 @@
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use rand::Rng;
+
+
+const INITIAL_SIZE: usize = 4;
+
+
+/// A chained hash table with a power-of-two number of buckets.
+///
+/// The standard library HashMap does not expose its bucket layout, which we need in order to
+/// support cursor based iteration with the same guarantees as Redis' SCAN command. Every key
+/// that is present for the full duration of an iteration is guaranteed to be returned at least
+/// once, even if the table grows or shrinks between calls.
+///
+/// This works by incrementing the cursor with its bits reversed. Because the table size is
+/// always a power of two, the bucket a key lives in after a resize is either the same bucket
+/// index with additional high bits set (when growing), or the same index with high bits masked
+/// off (when shrinking). Iterating the high bits first means that all of the buckets which a
+/// visited bucket expands into or collapses from have already been visited.
+///
+/// Resizing is not incremental, the table is rehashed in one go when it crosses a load factor
+/// threshold.
+#[derive(Debug)]
+pub struct Dict<K, V, S = RandomState> {
+    buckets: Vec<Vec<(K, V)>>,
+    len: usize,
+    hash_builder: S,
+}
+
+
+impl<K: Hash + Eq, V> Dict<K, V, RandomState> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+
+impl<K: Hash + Eq, V> Default for Dict<K, V, RandomState> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl<K: Hash + Eq, V, S: BuildHasher> Dict<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Dict {
+            buckets: Self::empty_buckets(INITIAL_SIZE),
+            len: 0,
+            hash_builder,
+        }
+    }
+
+    fn empty_buckets(size: usize) -> Vec<Vec<(K, V)>> {
+        (0..size).map(|_| Vec::new()).collect()
+    }
+
+    fn mask(&self) -> u64 {
+        self.buckets.len() as u64 - 1
+    }
+
+    fn bucket_index<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        (self.hash_builder.hash_one(key) & self.mask()) as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.buckets[self.bucket_index(key)]
+            .iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.bucket_index(key);
+
+        self.buckets[index]
+            .iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Insert a key/value pair, returning the previous value if the key already existed.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(v) = self.get_mut(&key) {
+            return Some(std::mem::replace(v, value));
+        }
+
+        self.insert_new(key, value);
+
+        None
+    }
+
+    /// Return a mutable reference to the value for a key, inserting the result of `f` first
+    /// if the key does not exist.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let index = match self.buckets[self.bucket_index(&key)].iter().position(|(k, _)| k == &key) {
+            Some(position) => (self.bucket_index(&key), position),
+            None => self.insert_new(key, f()),
+        };
+
+        &mut self.buckets[index.0][index.1].1
+    }
+
+    fn insert_new(&mut self, key: K, value: V) -> (usize, usize) {
+        if self.len >= self.buckets.len() {
+            self.resize(self.buckets.len() * 2);
+        }
+
+        let index = self.bucket_index(&key);
+        self.buckets[index].push((key, value));
+        self.len += 1;
+
+        (index, self.buckets[index].len() - 1)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.bucket_index(key);
+        let position = self.buckets[index].iter().position(|(k, _)| k.borrow() == key)?;
+        let entry = self.buckets[index].swap_remove(position);
+        self.len -= 1;
+
+        if self.buckets.len() > INITIAL_SIZE && self.len * 8 < self.buckets.len() {
+            self.resize(self.buckets.len() / 2);
+        }
+
+        Some(entry)
+    }
+
+    fn resize(&mut self, size: usize) {
+        let old = std::mem::replace(&mut self.buckets, Self::empty_buckets(size));
+
+        for (k, v) in old.into_iter().flatten() {
+            let index = self.bucket_index(&k);
+            self.buckets[index].push((k, v));
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Visit every entry in the bucket addressed by `cursor`, returning the cursor for the next
+    /// bucket. A returned cursor of zero means that the iteration is complete.
+    pub fn scan<F: FnMut(&K, &V)>(&self, cursor: u64, mut f: F) -> u64 {
+        let mask = self.mask();
+
+        for (k, v) in &self.buckets[(cursor & mask) as usize] {
+            f(k, v);
+        }
+
+        // Set the unmasked bits so that incrementing the reversed cursor carries
+        // straight into the masked bits.
+        let mut cursor = cursor | !mask;
+        cursor = cursor.reverse_bits();
+        cursor = cursor.wrapping_add(1);
+        cursor.reverse_bits()
+    }
+
+    /// Pick a random entry from the table. Entries in sparsely populated buckets are slightly
+    /// more likely to be chosen than those in crowded buckets.
+    pub fn random_entry(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut rng = rand::thread_rng();
+
+        loop {
+            let bucket = &self.buckets[rng.gen_range(0..self.buckets.len())];
+
+            if !bucket.is_empty() {
+                let (k, v) = &bucket[rng.gen_range(0..bucket.len())];
+                return Some((k, v));
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::dict::Dict;
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut d = Dict::new();
+
+        assert_eq!(d.insert("a", 1), None);
+        assert_eq!(d.insert("a", 2), Some(1));
+        assert_eq!(d.get("a"), Some(&2));
+        assert_eq!(d.remove("a"), Some(2));
+        assert!(d.is_empty());
+    }
+
+    #[test]
+    fn test_scan_returns_every_key() {
+        let mut d = Dict::new();
+
+        for i in 0..1000 {
+            d.insert(i, ());
+        }
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+
+        loop {
+            cursor = d.scan(cursor, |k, _| { seen.insert(*k); });
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        assert_eq!(seen.len(), 1000);
+    }
+
+    #[test]
+    fn test_scan_across_resize() {
+        let mut d = Dict::new();
+
+        for i in 0..100 {
+            d.insert(i, ());
+        }
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut steps = 0;
+
+        loop {
+            cursor = d.scan(cursor, |k, _| { seen.insert(*k); });
+            steps += 1;
+
+            // Grow the table part way through the iteration, then shrink it again.
+            if steps == 10 {
+                for i in 100..2000 {
+                    d.insert(i, ());
+                }
+            } else if steps == 200 {
+                for i in 100..2000 {
+                    d.remove(&i);
+                }
+            }
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        assert!((0..100).all(|i| seen.contains(&i)));
+    }
+}
@@ -0,0 +1,353 @@
+//! Geohash encoding and distance calculations, following the implementation in Redis so that
+//! scores are interchangeable.
+//!
+//! A position is encoded by interleaving the bits of its latitude and longitude offsets, with
+//! each coordinate using `step` bits. The full precision encoding uses 26 bits each, which
+//! fits exactly into the 52 bit mantissa of the sorted set scores the positions are stored as.
+
+
+pub const STEP_MAX: u32 = 26;
+
+pub const LONGITUDE_MIN: f64 = -180.0;
+pub const LONGITUDE_MAX: f64 = 180.0;
+/// The latitude limits of the Web Mercator projection.
+pub const LATITUDE_MIN: f64 = -85.05112878;
+pub const LATITUDE_MAX: f64 = 85.05112878;
+
+/// The earth's quadratic mean radius for WGS-84, as used by Redis.
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const MERCATOR_MAX: f64 = 20037726.37;
+
+
+/// A geohash with a given precision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GeoHash {
+    pub bits: u64,
+    pub step: u32,
+}
+
+
+/// The rectangle of coordinates covered by a geohash.
+#[derive(Clone, Copy, Debug)]
+pub struct Area {
+    pub longitude_min: f64,
+    pub longitude_max: f64,
+    pub latitude_min: f64,
+    pub latitude_max: f64,
+}
+
+
+/// The shape to search within, with dimensions in meters.
+#[derive(Clone, Copy, Debug)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+
+/// Check that a position can be encoded.
+pub fn is_valid(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude) && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude)
+}
+
+
+/// Spread the low 32 bits of `v` out into the even bits of the result.
+fn spread(v: u32) -> u64 {
+    let mut v = v as u64;
+
+    v = (v | (v << 16)) & 0x0000FFFF0000FFFF;
+    v = (v | (v << 8)) & 0x00FF00FF00FF00FF;
+    v = (v | (v << 4)) & 0x0F0F0F0F0F0F0F0F;
+    v = (v | (v << 2)) & 0x3333333333333333;
+    (v | (v << 1)) & 0x5555555555555555
+}
+
+
+/// The inverse of spread, gathering the even bits of `v`.
+fn squash(v: u64) -> u32 {
+    let mut v = v & 0x5555555555555555;
+
+    v = (v | (v >> 1)) & 0x3333333333333333;
+    v = (v | (v >> 2)) & 0x0F0F0F0F0F0F0F0F;
+    v = (v | (v >> 4)) & 0x00FF00FF00FF00FF;
+    v = (v | (v >> 8)) & 0x0000FFFF0000FFFF;
+    ((v | (v >> 16)) & 0x00000000FFFFFFFF) as u32
+}
+
+
+pub fn encode(longitude: f64, latitude: f64, step: u32) -> GeoHash {
+    let latitude_offset = (latitude - LATITUDE_MIN) / (LATITUDE_MAX - LATITUDE_MIN);
+    let longitude_offset = (longitude - LONGITUDE_MIN) / (LONGITUDE_MAX - LONGITUDE_MIN);
+
+    let scale = (1u64 << step) as f64;
+
+    // The maximum values would otherwise overflow into the next bit.
+    let latitude_bits = ((latitude_offset * scale) as u64).min((1 << step) - 1) as u32;
+    let longitude_bits = ((longitude_offset * scale) as u64).min((1 << step) - 1) as u32;
+
+    GeoHash {
+        bits: spread(latitude_bits) | (spread(longitude_bits) << 1),
+        step,
+    }
+}
+
+
+pub fn decode(hash: GeoHash) -> Area {
+    let latitude_bits = squash(hash.bits) as f64;
+    let longitude_bits = squash(hash.bits >> 1) as f64;
+
+    let scale = (1u64 << hash.step) as f64;
+    let latitude_range = LATITUDE_MAX - LATITUDE_MIN;
+    let longitude_range = LONGITUDE_MAX - LONGITUDE_MIN;
+
+    Area {
+        latitude_min: LATITUDE_MIN + (latitude_bits / scale) * latitude_range,
+        latitude_max: LATITUDE_MIN + ((latitude_bits + 1.0) / scale) * latitude_range,
+        longitude_min: LONGITUDE_MIN + (longitude_bits / scale) * longitude_range,
+        longitude_max: LONGITUDE_MIN + ((longitude_bits + 1.0) / scale) * longitude_range,
+    }
+}
+
+
+/// Decode a full precision geohash score into the longitude and latitude at the centre of
+/// its area.
+pub fn decode_score(score: f64) -> (f64, f64) {
+    let area = decode(GeoHash { bits: score as u64, step: STEP_MAX });
+
+    let longitude = ((area.longitude_min + area.longitude_max) / 2.0).clamp(LONGITUDE_MIN, LONGITUDE_MAX);
+    let latitude = ((area.latitude_min + area.latitude_max) / 2.0).clamp(LATITUDE_MIN, LATITUDE_MAX);
+
+    (longitude, latitude)
+}
+
+
+/// Move a geohash by one cell east (positive) or west (negative).
+fn move_x(hash: GeoHash, d: i8) -> GeoHash {
+    if d == 0 {
+        return hash;
+    }
+
+    let x = hash.bits & 0xaaaaaaaaaaaaaaaa;
+    let y = hash.bits & 0x5555555555555555;
+    let zz = 0x5555555555555555u64 >> (64 - hash.step * 2);
+
+    let x = if d > 0 { x.wrapping_add(zz + 1) } else { (x | zz).wrapping_sub(zz + 1) };
+    let x = x & (0xaaaaaaaaaaaaaaaa >> (64 - hash.step * 2));
+
+    GeoHash { bits: x | y, step: hash.step }
+}
+
+
+/// Move a geohash by one cell north (positive) or south (negative).
+fn move_y(hash: GeoHash, d: i8) -> GeoHash {
+    if d == 0 {
+        return hash;
+    }
+
+    let x = hash.bits & 0xaaaaaaaaaaaaaaaa;
+    let y = hash.bits & 0x5555555555555555;
+    let zz = 0xaaaaaaaaaaaaaaaau64 >> (64 - hash.step * 2);
+
+    let y = if d > 0 { y.wrapping_add(zz + 1) } else { (y | zz).wrapping_sub(zz + 1) };
+    let y = y & (0x5555555555555555 >> (64 - hash.step * 2));
+
+    GeoHash { bits: x | y, step: hash.step }
+}
+
+
+/// Estimate the geohash precision where a cell is about the size of the search radius.
+fn estimate_steps_by_radius(mut range: f64, latitude: f64) -> u32 {
+    if range == 0.0 {
+        return STEP_MAX;
+    }
+
+    let mut step: i32 = 1;
+
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+
+    // Make sure the range is included in most of the base cases.
+    step -= 2;
+
+    // Cells are narrower near the poles.
+    if !(-66.0..=66.0).contains(&latitude) {
+        step -= 1;
+
+        if !(-80.0..=80.0).contains(&latitude) {
+            step -= 1;
+        }
+    }
+
+    step.clamp(1, STEP_MAX as i32) as u32
+}
+
+
+/// Return the bounding box of a shape centred on a position, as
+/// (longitude_min, latitude_min, longitude_max, latitude_max).
+fn bounding_box(longitude: f64, latitude: f64, shape: Shape) -> (f64, f64, f64, f64) {
+    let (width, height) = match shape {
+        Shape::Radius(r) => (r, r),
+        Shape::Box { width, height } => (width / 2.0, height / 2.0),
+    };
+
+    let latitude_delta = (height / EARTH_RADIUS_IN_METERS).to_degrees();
+    let longitude_delta_top = (width / EARTH_RADIUS_IN_METERS / (latitude + latitude_delta).to_radians().cos()).to_degrees();
+    let longitude_delta_bottom = (width / EARTH_RADIUS_IN_METERS / (latitude - latitude_delta).to_radians().cos()).to_degrees();
+
+    // Use the edge furthest from the equator, where the same width spans the most longitude.
+    let longitude_delta = if latitude < 0.0 { longitude_delta_bottom } else { longitude_delta_top };
+
+    (longitude - longitude_delta, latitude - latitude_delta, longitude + longitude_delta, latitude + latitude_delta)
+}
+
+
+/// Return the geohash cells which need to be searched to find every position inside a shape.
+/// This is the cell containing the centre and those of its eight neighbours which overlap the
+/// shape's bounding box.
+pub fn search_areas(longitude: f64, latitude: f64, shape: Shape) -> Vec<GeoHash> {
+    let radius = match shape {
+        Shape::Radius(r) => r,
+        Shape::Box { width, height } => ((width / 2.0).powi(2) + (height / 2.0).powi(2)).sqrt(),
+    };
+
+    let (min_lon, min_lat, max_lon, max_lat) = bounding_box(longitude, latitude, shape);
+
+    let mut step = estimate_steps_by_radius(radius, latitude);
+    let mut centre = encode(longitude, latitude, step);
+
+    // If the neighbouring cells do not reach the edges of the bounding box, use bigger cells.
+    let north = decode(move_y(centre, 1));
+    let south = decode(move_y(centre, -1));
+    let east = decode(move_x(centre, 1));
+    let west = decode(move_x(centre, -1));
+
+    if step > 1 && (north.latitude_max < max_lat || south.latitude_min > min_lat || east.longitude_max < max_lon || west.longitude_min > min_lon) {
+        step -= 1;
+        centre = encode(longitude, latitude, step);
+    }
+
+    let area = decode(centre);
+
+    let mut areas = vec![centre];
+
+    for dx in [-1i8, 0, 1] {
+        for dy in [-1i8, 0, 1] {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+
+            // Skip neighbours which are entirely outside the bounding box.
+            if step >= 2 && ((dy < 0 && area.latitude_min < min_lat)
+                || (dy > 0 && area.latitude_max > max_lat)
+                || (dx < 0 && area.longitude_min < min_lon)
+                || (dx > 0 && area.longitude_max > max_lon)) {
+                continue;
+            }
+
+            let neighbour = move_y(move_x(centre, dx), dy);
+
+            // At low precision the neighbours wrap around and may repeat.
+            if !areas.contains(&neighbour) {
+                areas.push(neighbour);
+            }
+        }
+    }
+
+    areas
+}
+
+
+/// The range of full precision scores covered by a geohash cell, as `min..max`.
+pub fn score_range(hash: GeoHash) -> (f64, f64) {
+    let shift = (STEP_MAX - hash.step) * 2;
+
+    ((hash.bits << shift) as f64, ((hash.bits + 1) << shift) as f64)
+}
+
+
+/// The great circle distance between two positions in meters, using the haversine formula.
+pub fn distance(longitude_1: f64, latitude_1: f64, longitude_2: f64, latitude_2: f64) -> f64 {
+    let latitude_1 = latitude_1.to_radians();
+    let latitude_2 = latitude_2.to_radians();
+
+    let u = ((latitude_2 - latitude_1) / 2.0).sin();
+    let v = ((longitude_2.to_radians() - longitude_1.to_radians()) / 2.0).sin();
+
+    2.0 * EARTH_RADIUS_IN_METERS * (u * u + latitude_1.cos() * latitude_2.cos() * v * v).sqrt().asin()
+}
+
+
+/// Return the distance from the centre of a shape to a position, if the position is inside
+/// the shape.
+pub fn distance_if_inside(shape: Shape, longitude: f64, latitude: f64, point_longitude: f64, point_latitude: f64) -> Option<f64> {
+    match shape {
+        Shape::Radius(r) => {
+            let d = distance(longitude, latitude, point_longitude, point_latitude);
+            (d <= r).then_some(d)
+        },
+        Shape::Box { width, height } => {
+            // The latitude distance is cheaper to calculate, so check it first.
+            let latitude_distance = EARTH_RADIUS_IN_METERS * (point_latitude.to_radians() - latitude.to_radians()).abs();
+
+            if latitude_distance > height / 2.0 {
+                return None;
+            }
+
+            if distance(point_longitude, point_latitude, longitude, point_latitude) > width / 2.0 {
+                return None;
+            }
+
+            Some(distance(longitude, latitude, point_longitude, point_latitude))
+        },
+    }
+}
+
+
+/// The number of meters in a distance unit, or None if the unit is not recognised.
+pub fn unit_to_meters(unit: &[u8]) -> Option<f64> {
+    match &unit.to_ascii_lowercase()[..] {
+        b"m" => Some(1.0),
+        b"km" => Some(1000.0),
+        b"ft" => Some(0.3048),
+        b"mi" => Some(1609.34),
+        _ => None,
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let hash = encode(13.361389, 38.115556, STEP_MAX);
+        let (longitude, latitude) = decode_score(hash.bits as f64);
+
+        assert!((longitude - 13.361389).abs() < 0.00001);
+        assert!((latitude - 38.115556).abs() < 0.00001);
+        assert_eq!(hash.bits, 3479099956230698);
+    }
+
+    #[test]
+    fn test_distance() {
+        // GEODIST measures between the stored positions, so compare against those.
+        let (longitude_1, latitude_1) = decode_score(encode(13.361389, 38.115556, STEP_MAX).bits as f64);
+        let (longitude_2, latitude_2) = decode_score(encode(15.087269, 37.502669, STEP_MAX).bits as f64);
+        let d = distance(longitude_1, latitude_1, longitude_2, latitude_2);
+
+        assert!((d - 166274.1516).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let hash = encode(0.0, 0.0, 10);
+
+        assert_eq!(move_x(move_x(hash, 1), -1), hash);
+        assert_eq!(move_y(move_y(hash, -1), 1), hash);
+        assert!(decode(move_x(hash, 1)).longitude_min >= decode(hash).longitude_max - 1e-9);
+    }
+}
@@ -0,0 +1,491 @@
+
+use bytes::{BufMut, BytesMut};
+
+
+/// The number of bits of the hash used to select a register.
+const P: u32 = 14;
+/// The number of bits of the hash used to count leading zeros.
+const Q: u32 = 64 - P;
+const REGISTERS: usize = 1 << P;
+const BITS_PER_REGISTER: usize = 6;
+const REGISTER_MAX: u8 = (1 << BITS_PER_REGISTER) - 1;
+
+const HEADER_SIZE: usize = 16;
+const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * BITS_PER_REGISTER).div_ceil(8);
+const MAGIC: &[u8] = b"HYLL";
+
+const ENCODING_DENSE: u8 = 0;
+const ENCODING_SPARSE: u8 = 1;
+
+/// Sparse representations longer than this are converted to the dense representation.
+const SPARSE_MAX_BYTES: usize = 3000;
+/// The largest register value which can be stored in the sparse representation.
+const SPARSE_VALUE_MAX: u8 = 32;
+const SPARSE_ZERO_MAX_LEN: usize = 64;
+const SPARSE_XZERO_MAX_LEN: usize = 16384;
+const SPARSE_VALUE_MAX_LEN: usize = 4;
+
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+const HASH_SEED: u64 = 0xadc83b19;
+
+
+#[derive(Debug, PartialEq)]
+pub enum HllError {
+    /// The value is not a HyperLogLog at all.
+    Invalid,
+    /// The value has a HyperLogLog header, but the registers could not be decoded.
+    Corrupted,
+}
+
+
+/// A HyperLogLog, stored in exactly the same layout as Redis so that the raw string values are
+/// interchangeable.
+///
+/// The layout is a 16 byte header followed by the registers:
+///
+///   * The magic string "HYLL".
+///   * One byte for the encoding, either dense or sparse.
+///   * Three unused bytes.
+///   * The cached cardinality as a little endian 64 bit integer. The most significant bit of
+///     the last byte is set when the cache is invalid.
+///
+/// The dense encoding packs the 16384 six bit registers one after the other, starting from
+/// the least significant bits of each byte. The sparse encoding is a run length encoding of
+/// the registers using three opcodes:
+///
+///   * ZERO `00xxxxxx`, a run of 1-64 zero registers.
+///   * XZERO `01xxxxxx yyyyyyyy`, a run of 1-16384 zero registers.
+///   * VAL `1vvvvvxx`, a run of 1-4 registers with the value 1-32.
+///
+/// Rather than editing the sparse representation in place, it is decoded into a full register
+/// array, modified, and encoded again. This keeps the code simple at the cost of some extra
+/// work when adding to small HyperLogLogs.
+pub struct HyperLogLog<'a> {
+    bytes: &'a mut BytesMut,
+}
+
+
+impl<'a> HyperLogLog<'a> {
+    /// Wrap an existing value, checking that it looks like a HyperLogLog.
+    pub fn from_bytes(bytes: &'a mut BytesMut) -> Result<Self, HllError> {
+        validate(bytes)?;
+
+        Ok(HyperLogLog { bytes })
+    }
+
+    /// Create an empty HyperLogLog using the sparse encoding.
+    pub fn empty() -> BytesMut {
+        let mut bytes = header(ENCODING_SPARSE);
+        encode_sparse(&[0; REGISTERS], &mut bytes);
+        bytes
+    }
+
+    /// Add elements, returning true if any register was changed and so the approximated
+    /// cardinality may have changed.
+    pub fn add<'e, I: IntoIterator<Item = &'e [u8]>>(&mut self, elements: I) -> Result<bool, HllError> {
+        let mut registers = self.registers()?;
+        let mut changed = false;
+
+        for element in elements {
+            let (index, count) = pattern_length(element);
+
+            if registers[index] < count {
+                registers[index] = count;
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.store(&registers);
+        }
+
+        Ok(changed)
+    }
+
+    /// Return the estimated cardinality, using the cached value if there is one.
+    pub fn count(&mut self) -> Result<u64, HllError> {
+        let cached = &self.bytes[8..16];
+
+        if cached[7] & 0x80 == 0 {
+            return Ok(u64::from_le_bytes(cached.try_into().unwrap()));
+        }
+
+        let count = estimate(&self.registers()?);
+        self.bytes[8..16].copy_from_slice(&count.to_le_bytes());
+
+        Ok(count)
+    }
+
+    fn registers(&self) -> Result<Vec<u8>, HllError> {
+        let mut registers = vec![0; REGISTERS];
+        merge_registers(&mut registers, self.bytes)?;
+        Ok(registers)
+    }
+
+    /// Replace the contents with the given registers, using the sparse encoding if possible.
+    /// Once a HyperLogLog is dense, it stays dense.
+    fn store(&mut self, registers: &[u8]) {
+        let mut bytes = header(ENCODING_SPARSE);
+
+        let fits_sparse = self.bytes[4] == ENCODING_SPARSE
+            && registers.iter().all(|r| *r <= SPARSE_VALUE_MAX)
+            && encode_sparse(registers, &mut bytes) <= SPARSE_MAX_BYTES;
+
+        if !fits_sparse {
+            bytes = dense(registers);
+        }
+
+        invalidate_cache(&mut bytes);
+        *self.bytes = bytes;
+    }
+}
+
+
+/// Build a dense HyperLogLog from a register array.
+pub fn dense(registers: &[u8]) -> BytesMut {
+    let mut bytes = header(ENCODING_DENSE);
+    bytes.resize(DENSE_SIZE, 0);
+
+    for (i, r) in registers.iter().enumerate() {
+        set_dense_register(&mut bytes[HEADER_SIZE..], i, *r);
+    }
+
+    invalidate_cache(&mut bytes);
+    bytes
+}
+
+
+/// Estimate the cardinality of the union of several HyperLogLogs, without modifying them.
+pub fn count_union<'e, I: IntoIterator<Item = &'e [u8]>>(values: I) -> Result<u64, HllError> {
+    Ok(estimate(&union_registers(values)?))
+}
+
+
+/// Return the registers of the union of several HyperLogLogs.
+pub fn union_registers<'e, I: IntoIterator<Item = &'e [u8]>>(values: I) -> Result<Vec<u8>, HllError> {
+    let mut registers = vec![0; REGISTERS];
+
+    for v in values {
+        validate(v)?;
+        merge_registers(&mut registers, v)?;
+    }
+
+    Ok(registers)
+}
+
+
+pub fn validate(bytes: &[u8]) -> Result<(), HllError> {
+    if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC || bytes[4] > ENCODING_SPARSE {
+        return Err(HllError::Invalid);
+    }
+
+    if bytes[4] == ENCODING_DENSE && bytes.len() != DENSE_SIZE {
+        return Err(HllError::Invalid);
+    }
+
+    Ok(())
+}
+
+
+fn header(encoding: u8) -> BytesMut {
+    let mut bytes = BytesMut::with_capacity(HEADER_SIZE);
+    bytes.put_slice(MAGIC);
+    bytes.put_u8(encoding);
+    bytes.put_bytes(0, 3);
+    bytes.put_bytes(0, 8);
+    bytes
+}
+
+
+fn invalidate_cache(bytes: &mut [u8]) {
+    bytes[15] |= 0x80;
+}
+
+
+/// Hash an element, returning the index of the register it belongs to and the number of
+/// trailing zeros in the rest of the hash, plus one.
+fn pattern_length(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash_64a(element, HASH_SEED);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+
+    // Set a bit past the end of the bits being counted, so that the count is bounded.
+    let hash = (hash >> P) | (1 << Q);
+
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+
+/// Set each register to the maximum of its value and the corresponding register in `bytes`.
+fn merge_registers(registers: &mut [u8], bytes: &[u8]) -> Result<(), HllError> {
+    let data = &bytes[HEADER_SIZE..];
+
+    if bytes[4] == ENCODING_DENSE {
+        for (i, r) in registers.iter_mut().enumerate() {
+            *r = (*r).max(get_dense_register(data, i));
+        }
+
+        return Ok(());
+    }
+
+    let mut index = 0;
+    let mut position = 0;
+
+    while position < data.len() {
+        let opcode = data[position];
+
+        let (value, len) = if opcode & 0x80 != 0 {
+            position += 1;
+            (((opcode >> 2) & 0x1f) + 1, (opcode & 0x3) as usize + 1)
+        } else if opcode & 0x40 != 0 {
+            let Some(next) = data.get(position + 1) else {
+                return Err(HllError::Corrupted);
+            };
+
+            position += 2;
+            (0, ((((opcode & 0x3f) as usize) << 8) | *next as usize) + 1)
+        } else {
+            position += 1;
+            (0, (opcode & 0x3f) as usize + 1)
+        };
+
+        if index + len > REGISTERS {
+            return Err(HllError::Corrupted);
+        }
+
+        for r in &mut registers[index..index + len] {
+            *r = (*r).max(value);
+        }
+
+        index += len;
+    }
+
+    if index != REGISTERS {
+        return Err(HllError::Corrupted);
+    }
+
+    Ok(())
+}
+
+
+/// Append the sparse encoding of the registers, which must all be at most SPARSE_VALUE_MAX,
+/// returning the total length of the encoded HyperLogLog.
+fn encode_sparse(registers: &[u8], bytes: &mut BytesMut) -> usize {
+    let mut index = 0;
+
+    while index < registers.len() {
+        let value = registers[index];
+        let run = registers[index..].iter().take_while(|r| **r == value).count();
+
+        let len = if value == 0 {
+            let len = run.min(SPARSE_XZERO_MAX_LEN);
+
+            if len > SPARSE_ZERO_MAX_LEN {
+                bytes.put_u8(0x40 | ((len - 1) >> 8) as u8);
+                bytes.put_u8(((len - 1) & 0xff) as u8);
+            } else {
+                bytes.put_u8((len - 1) as u8);
+            }
+
+            len
+        } else {
+            let len = run.min(SPARSE_VALUE_MAX_LEN);
+            bytes.put_u8(0x80 | ((value - 1) << 2) | (len - 1) as u8);
+            len
+        };
+
+        index += len;
+    }
+
+    bytes.len()
+}
+
+
+fn get_dense_register(data: &[u8], i: usize) -> u8 {
+    let byte = i * BITS_PER_REGISTER / 8;
+    let shift = (i * BITS_PER_REGISTER) & 7;
+
+    let low = data[byte] as u16;
+    let high = data.get(byte + 1).copied().unwrap_or(0) as u16;
+
+    (((low | (high << 8)) >> shift) as u8) & REGISTER_MAX
+}
+
+
+fn set_dense_register(data: &mut [u8], i: usize, value: u8) {
+    let byte = i * BITS_PER_REGISTER / 8;
+    let shift = (i * BITS_PER_REGISTER) & 7;
+
+    let mask = (REGISTER_MAX as u16) << shift;
+    let value = (value as u16) << shift;
+
+    data[byte] = (data[byte] & !(mask as u8)) | value as u8;
+
+    // The register may span two bytes, except for the very last one.
+    if let Some(next) = data.get_mut(byte + 1) {
+        *next = (*next & !((mask >> 8) as u8)) | (value >> 8) as u8;
+    }
+}
+
+
+/// Estimate the cardinality from the registers, using the improved estimator by Otmar Ertl
+/// that Redis uses, in "New cardinality estimation algorithms for HyperLogLog sketches".
+fn estimate(registers: &[u8]) -> u64 {
+    let m = REGISTERS as f64;
+    // Registers only reach Q + 1 when built by us, but a crafted string can hold any six bit
+    // value. As in Redis, those above Q + 1 are counted and then ignored.
+    let mut histogram = [0u32; REGISTER_MAX as usize + 1];
+
+    for r in registers {
+        histogram[*r as usize] += 1;
+    }
+
+    let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+
+    for j in (1..=Q as usize).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+
+    z += m * sigma(histogram[0] as f64 / m);
+
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut y = 1.0;
+    let mut z = x;
+
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+
+/// MurmurHash2, 64 bit version, as used by Redis. Blocks are always read as little endian so
+/// that the result is the same on every platform.
+fn murmur_hash_64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+
+    let mut chunks = key.chunks_exact(8);
+
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+
+    if !tail.is_empty() {
+        for (i, b) in tail.iter().enumerate() {
+            h ^= (*b as u64) << (8 * i);
+        }
+
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+
+    h
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::hyperloglog::*;
+
+    #[test]
+    fn test_empty() {
+        let mut bytes = HyperLogLog::empty();
+
+        assert_eq!(&bytes[..], b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff");
+        assert_eq!(HyperLogLog::from_bytes(&mut bytes).unwrap().count(), Ok(0));
+    }
+
+    #[test]
+    fn test_dense_registers() {
+        let mut data = vec![0; DENSE_SIZE - HEADER_SIZE];
+
+        for i in 0..REGISTERS {
+            set_dense_register(&mut data, i, (i % 64) as u8);
+        }
+
+        assert!((0..REGISTERS).all(|i| get_dense_register(&data, i) == (i % 64) as u8));
+    }
+
+    #[test]
+    fn test_count_accuracy() {
+        let mut bytes = HyperLogLog::empty();
+        let mut hll = HyperLogLog::from_bytes(&mut bytes).unwrap();
+
+        let elements: Vec<Vec<u8>> = (0..100_000).map(|i| format!("element:{}", i).into_bytes()).collect();
+        hll.add(elements.iter().map(|e| &e[..])).unwrap();
+
+        let count = hll.count().unwrap() as f64;
+
+        assert!((count - 100_000.0).abs() / 100_000.0 < 0.02);
+        assert_eq!(bytes[4], ENCODING_DENSE);
+    }
+
+    #[test]
+    fn test_sparse_and_dense_agree() {
+        let elements: Vec<Vec<u8>> = (0..100).map(|i| format!("{}", i).into_bytes()).collect();
+
+        let mut sparse = HyperLogLog::empty();
+        HyperLogLog::from_bytes(&mut sparse).unwrap().add(elements.iter().map(|e| &e[..])).unwrap();
+
+        assert_eq!(sparse[4], ENCODING_SPARSE);
+
+        let mut dense = dense(&union_registers([&sparse[..]]).unwrap());
+
+        assert_eq!(
+            HyperLogLog::from_bytes(&mut sparse).unwrap().count(),
+            HyperLogLog::from_bytes(&mut dense).unwrap().count(),
+        );
+    }
+}
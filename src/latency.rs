@@ -0,0 +1,106 @@
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// How many samples are kept for each event. There is at most one sample a second.
+const HISTORY_LEN: usize = 160;
+
+
+/// The worst latency seen in one second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// A Unix timestamp.
+    pub time: u64,
+    pub latency: u64,
+}
+
+
+#[derive(Debug, Default)]
+pub struct History {
+    pub samples: VecDeque<Sample>,
+    /// The worst latency seen since the event was last reset, in milliseconds.
+    pub max: u64,
+}
+
+
+/// Spikes in latency, by the kind of event which caused them, as reported by LATENCY. Only
+/// spikes above `latency-monitor-threshold` are recorded.
+#[derive(Debug, Default)]
+pub struct LatencyMonitor {
+    pub events: HashMap<&'static str, History>,
+}
+
+
+impl LatencyMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: &'static str, duration: Duration) {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.record_at(event, time, duration.as_millis() as u64);
+    }
+
+    fn record_at(&mut self, event: &'static str, time: u64, latency: u64) {
+        let history = self.events.entry(event).or_default();
+        history.max = history.max.max(latency);
+
+        // Keep the worst spike in each second.
+        if let Some(last) = history.samples.back_mut().filter(|s| s.time == time) {
+            last.latency = last.latency.max(latency);
+            return;
+        }
+
+        if history.samples.len() == HISTORY_LEN {
+            history.samples.pop_front();
+        }
+
+        history.samples.push_back(Sample { time, latency });
+    }
+
+    /// Forget the given events, or all of them if none are given, returning how many were
+    /// forgotten.
+    pub fn reset(&mut self, events: &[String]) -> usize {
+        if events.is_empty() {
+            let count = self.events.len();
+            self.events.clear();
+            return count;
+        }
+
+        events.iter().filter(|e| self.events.remove(e.as_str()).is_some()).count()
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::latency::{LatencyMonitor, Sample, HISTORY_LEN};
+
+    #[test]
+    fn test_record() {
+        let mut monitor = LatencyMonitor::new();
+
+        monitor.record_at("command", 100, 5);
+        monitor.record_at("command", 100, 12);
+        monitor.record_at("command", 100, 7);
+        monitor.record_at("command", 101, 3);
+
+        let history = &monitor.events["command"];
+        assert_eq!(history.samples, [Sample { time: 100, latency: 12 }, Sample { time: 101, latency: 3 }]);
+        assert_eq!(history.max, 12);
+
+        for i in 0..HISTORY_LEN as u64 {
+            monitor.record_at("expire-cycle", i, 1);
+        }
+
+        monitor.record_at("expire-cycle", 1000, 1);
+        assert_eq!(monitor.events["expire-cycle"].samples.len(), HISTORY_LEN);
+        assert_eq!(monitor.events["expire-cycle"].samples[0].time, 1);
+
+        assert_eq!(monitor.reset(&["command".into(), "fork".into()]), 1);
+        assert_eq!(monitor.reset(&[]), 1);
+        assert!(monitor.events.is_empty());
+    }
+}
@@ -0,0 +1,41 @@
+
+use std::sync::OnceLock;
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+
+use crate::db::DBEntry;
+
+
+/// Entries which would take less effort than this to free are freed immediately, as it is
+/// cheaper than sending them to the background thread.
+const LAZYFREE_THRESHOLD: usize = 64;
+
+
+static LAZYFREE_QUEUE: OnceLock<Sender<DBEntry>> = OnceLock::new();
+
+
+/// Free a database entry. Large entries are sent to a background thread so that dropping them
+/// does not block the event loop, the thread is started the first time it is needed.
+pub fn free(entry: DBEntry) {
+    if entry.free_effort() <= LAZYFREE_THRESHOLD {
+        return;
+    }
+
+    let queue = LAZYFREE_QUEUE.get_or_init(|| {
+        let (sender, receiver) = channel::<DBEntry>();
+
+        thread::Builder::new()
+            .name("lazyfree".into())
+            .spawn(move || {
+                for entry in receiver {
+                    drop(entry);
+                }
+            })
+            .expect("unable to start the lazyfree thread");
+
+        sender
+    });
+
+    // If the thread has gone away the entry is handed back to us, and is dropped here instead.
+    let _ = queue.send(entry);
+}
@@ -1,23 +1,110 @@
+#![cfg_attr(test, feature(test))]
 #![feature(const_mut_refs)]
 
+mod bitops;
+mod cluster;
+mod config;
 mod db;
+mod dict;
+mod geo;
+mod hyperloglog;
+mod latency;
+mod lazyfree;
+mod memory;
+mod net;
+mod notify;
 mod parser;
+mod replication;
+mod sentinel;
+mod slab;
+mod slowlog;
 mod serializer;
+mod signal;
 mod server;
+mod snapshot;
+mod sorted_set;
+mod stats;
+mod tls;
+mod tracking;
 mod command;
 mod util;
 
-use env_logger;
+use log::LevelFilter;
+
+use crate::config::Config;
+use crate::server::{Mode, Server};
+
+#[global_allocator]
+static ALLOCATOR: memory::CountingAllocator = memory::CountingAllocator;
+
+const USAGE: &str = "Usage: sider [/path/to/sider.conf] [--<option> <value> ...] [--sentinel]";
 
-use crate::server::Server;
 
 fn main() {
-    env_logger::init();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut config = Config::default();
+
+    // Sentinel mode is a flag, as in Redis, and changes the default port.
+    let sentinel = args.iter().any(|a| a == "--sentinel");
+    args.retain(|a| a != "--sentinel");
+
+    if sentinel {
+        config.port = 26379;
+    }
+
+    if args.first().is_some_and(|a| !a.starts_with("--")) {
+        if let Err(e) = config.load_file(args.remove(0).into()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // The remaining arguments are options which override the file, like `--port 7000`. They
+    // are turned into configuration lines, as Redis does.
+    let mut overrides = String::new();
+
+    for arg in args {
+        match arg.strip_prefix("--") {
+            Some(name) => {
+                if !overrides.is_empty() {
+                    overrides.push('\n');
+                }
+
+                overrides.push_str(name);
+            },
+            None if !overrides.is_empty() => {
+                overrides.push_str(&format!(" \"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\"")));
+            },
+            None => {
+                println!("{}", USAGE);
+                std::process::exit(1);
+            },
+        }
+    }
+
+    if let Err(e) = config.load(&overrides) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Trace)
+        .parse_default_env()
+        .init();
+
+    log::set_max_level(config.loglevel);
+
+    let mode = match (sentinel, config.cluster_enabled) {
+        (true, _) => Mode::Sentinel,
+        (false, true) => Mode::Cluster,
+        (false, false) => Mode::Standalone,
+    };
 
-    match Server::build().start() {
+    match Server::build(config, mode).start() {
         Ok(()) => (),
         Err(e) => {
-            println!("Server encountered an error: {:?}", e)
+            println!("Server encountered an error: {:?}", e);
+            std::process::exit(1);
         }
     }
 }
@@ -0,0 +1,97 @@
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+
+/// The system allocator, keeping count of how much memory is allocated so that INFO can report
+/// it.
+pub struct CountingAllocator;
+
+
+impl CountingAllocator {
+    fn add(size: usize) {
+        let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(allocated, Ordering::Relaxed);
+    }
+
+    fn sub(size: usize) {
+        ALLOCATED.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc(layout);
+
+        if !p.is_null() {
+            Self::add(layout.size());
+        }
+
+        p
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc_zeroed(layout);
+
+        if !p.is_null() {
+            Self::add(layout.size());
+        }
+
+        p
+    }
+
+    unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+        System.dealloc(p, layout);
+        Self::sub(layout.size());
+    }
+
+    unsafe fn realloc(&self, p: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(p, layout, new_size);
+
+        if !new.is_null() {
+            Self::sub(layout.size());
+            Self::add(new_size);
+        }
+
+        new
+    }
+}
+
+
+/// The number of bytes currently allocated.
+pub fn used_memory() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+
+/// The most bytes that have been allocated at once.
+pub fn peak_memory() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+
+/// The resident set size of the process, as the operating system sees it. Only available on
+/// Linux.
+pub fn rss() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+
+    Some(kilobytes * 1024)
+}
+
+
+/// Format a number of bytes the way INFO does, like `1.50M`.
+pub fn human_bytes(bytes: usize) -> String {
+    let units = [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+
+    match units.iter().find(|(_, size)| bytes >= *size) {
+        Some((unit, size)) => format!("{:.2}{}", bytes as f64 / *size as f64, unit),
+        None => format!("{}B", bytes),
+    }
+}
@@ -0,0 +1,286 @@
+//! The kinds of socket clients connect over: TCP, or a Unix socket for clients on the same
+//! machine.
+
+use std::fmt::{self, Display};
+use std::fs::{self, Permissions};
+use std::io::{self, ErrorKind, IoSlice, Read, Write};
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, BorrowedFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+
+use mio::event::Source;
+use mio::net::{SocketAddr as UnixAddr, TcpListener, TcpStream, UnixListener, UnixStream};
+use mio::{Interest, Registry, Token};
+use socket2::{Domain, Protocol, SockRef, TcpKeepalive, Type};
+
+
+/// The address of one end of a connection. Unix sockets are shown as `path:0`, as in Redis,
+/// or as an empty string for the unnamed sockets clients usually connect from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Address {
+    Tcp(SocketAddr),
+    Unix(String),
+}
+
+
+impl Address {
+    /// The IP address, or the path of a Unix socket.
+    pub fn host(&self) -> String {
+        match self {
+            Address::Tcp(address) => address.ip().to_string(),
+            Address::Unix(path) => path.clone(),
+        }
+    }
+}
+
+
+impl Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Tcp(address) => write!(f, "{}", address),
+            Address::Unix(path) if path.is_empty() => Ok(()),
+            Address::Unix(path) => write!(f, "{}:0", path),
+        }
+    }
+}
+
+
+fn unix_address(address: &UnixAddr) -> Address {
+    Address::Unix(address.as_pathname().map(|p| p.display().to_string()).unwrap_or_default())
+}
+
+
+#[derive(Debug)]
+pub enum Socket {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+
+impl Socket {
+    pub fn local_addr(&self) -> io::Result<Address> {
+        match self {
+            Socket::Tcp(s) => s.local_addr().map(Address::Tcp),
+            Socket::Unix(s) => s.local_addr().map(|a| unix_address(&a)),
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<Address> {
+        match self {
+            Socket::Tcp(s) => s.peer_addr().map(Address::Tcp),
+            Socket::Unix(s) => s.peer_addr().map(|a| unix_address(&a)),
+        }
+    }
+
+    /// Send keepalive probes once the connection has been idle for `seconds`, so that peers
+    /// which vanished without closing the connection are noticed. Unix sockets need none.
+    pub fn set_keepalive(&self, seconds: u64) -> io::Result<()> {
+        let Socket::Tcp(s) = self else {
+            return Ok(());
+        };
+
+        let time = Duration::from_secs(seconds);
+        // As in Redis, the peer has three probes a third of the idle time apart to answer.
+        let keepalive = TcpKeepalive::new().with_time(time).with_interval((time / 3).max(Duration::from_secs(1))).with_retries(3);
+
+        // SAFETY: the socket outlives the borrow, which only lasts for this call. mio's sockets
+        // do not implement AsFd themselves.
+        let fd = unsafe { BorrowedFd::borrow_raw(s.as_raw_fd()) };
+
+        SockRef::from(&fd).set_tcp_keepalive(&keepalive)
+    }
+
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        match self {
+            Socket::Tcp(s) => s.take_error(),
+            Socket::Unix(s) => s.take_error(),
+        }
+    }
+}
+
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(s) => s.read(buf),
+            Socket::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(s) => s.write(buf),
+            Socket::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(s) => s.write_vectored(bufs),
+            Socket::Unix(s) => s.write_vectored(bufs),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Socket::Tcp(s) => s.flush(),
+            Socket::Unix(s) => s.flush(),
+        }
+    }
+}
+
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Socket::Tcp(s) => s.as_raw_fd(),
+            Socket::Unix(s) => s.as_raw_fd(),
+        }
+    }
+}
+
+
+impl Source for Socket {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        match self {
+            Socket::Tcp(s) => s.register(registry, token, interests),
+            Socket::Unix(s) => s.register(registry, token, interests),
+        }
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        match self {
+            Socket::Tcp(s) => s.reregister(registry, token, interests),
+            Socket::Unix(s) => s.reregister(registry, token, interests),
+        }
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        match self {
+            Socket::Tcp(s) => s.deregister(registry),
+            Socket::Unix(s) => s.deregister(registry),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+
+impl Listener {
+    /// Listen on a TCP address. IPv6 listeners only take IPv6 connections, so that the same
+    /// port can be bound for IPv4 as well.
+    pub fn bind_tcp(address: SocketAddr, backlog: i32) -> io::Result<Self> {
+        let socket = socket2::Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+
+        // Restarting should not have to wait for the connections of the last run to time out.
+        socket.set_reuse_address(true)?;
+
+        if address.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+
+        socket.bind(&address.into())?;
+        socket.listen(backlog)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Listener::Tcp(TcpListener::from_std(socket.into())))
+    }
+
+    /// Listen on a Unix socket, replacing any socket file left behind by an earlier run. The
+    /// file is given `permissions` if they are not zero.
+    pub fn bind_unix(path: &Path, permissions: u32) -> io::Result<Self> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {},
+        }
+
+        let listener = UnixListener::bind(path)?;
+
+        if permissions != 0 {
+            fs::set_permissions(path, Permissions::from_mode(permissions))?;
+        }
+
+        Ok(Listener::Unix(listener))
+    }
+
+    pub fn local_addr(&self) -> io::Result<Address> {
+        match self {
+            Listener::Tcp(l) => l.local_addr().map(Address::Tcp),
+            Listener::Unix(l) => l.local_addr().map(|a| unix_address(&a)),
+        }
+    }
+
+    pub fn accept(&self) -> io::Result<(Socket, Address)> {
+        match self {
+            Listener::Tcp(l) => l.accept().map(|(s, a)| (Socket::Tcp(s), Address::Tcp(a))),
+            Listener::Unix(l) => l.accept().map(|(s, a)| (Socket::Unix(s), unix_address(&a))),
+        }
+    }
+}
+
+
+impl Source for Listener {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => l.register(registry, token, interests),
+            Listener::Unix(l) => l.register(registry, token, interests),
+        }
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => l.reregister(registry, token, interests),
+            Listener::Unix(l) => l.reregister(registry, token, interests),
+        }
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => l.deregister(registry),
+            Listener::Unix(l) => l.deregister(registry),
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+
+    use crate::net::{Address, Listener};
+
+    #[test]
+    fn test_unix_listener() {
+        let path = std::env::temp_dir().join(format!("sider-net-{}.sock", std::process::id()));
+
+        // A file left behind by an earlier run is replaced.
+        fs::write(&path, "").unwrap();
+        let listener = Listener::bind_unix(&path, 0o700).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o700);
+        assert_eq!(listener.local_addr().unwrap().to_string(), format!("{}:0", path.display()));
+
+        let _client = UnixStream::connect(&path).unwrap();
+        let (socket, address) = listener.accept().unwrap();
+
+        // The client's socket is unnamed, so it has no address, rather than the listener's.
+        assert_eq!(address, Address::Unix(String::new()));
+        assert_eq!(address.to_string(), "");
+        assert_eq!(socket.local_addr().unwrap(), listener.local_addr().unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+//! Keyspace notifications, and the classes of them which `notify-keyspace-events` turns on
+//! and off.
+
+use bytes::Bytes;
+
+
+/// Publish to `__keyspace@0__:<key>`, with the event as the message.
+pub const KEYSPACE: u32 = 1 << 0;
+/// Publish to `__keyevent@0__:<event>`, with the key as the message.
+pub const KEYEVENT: u32 = 1 << 1;
+/// Commands which work on any type, like DEL and RENAME.
+pub const GENERIC: u32 = 1 << 2;
+pub const STRING: u32 = 1 << 3;
+pub const LIST: u32 = 1 << 4;
+pub const SET: u32 = 1 << 5;
+pub const HASH: u32 = 1 << 6;
+pub const ZSET: u32 = 1 << 7;
+pub const EXPIRED: u32 = 1 << 8;
+pub const EVICTED: u32 = 1 << 9;
+pub const STREAM: u32 = 1 << 10;
+/// Reads of keys which do not exist.
+pub const KEY_MISS: u32 = 1 << 11;
+/// Every class apart from key misses, as `A` selects.
+pub const ALL: u32 = GENERIC | STRING | LIST | SET | HASH | ZSET | EXPIRED | EVICTED | STREAM;
+
+const CLASSES: &[(char, u32)] = &[
+    ('g', GENERIC),
+    ('$', STRING),
+    ('l', LIST),
+    ('s', SET),
+    ('h', HASH),
+    ('z', ZSET),
+    ('x', EXPIRED),
+    ('e', EVICTED),
+    ('t', STREAM),
+];
+
+
+/// Parse the class letters of `notify-keyspace-events`, like `KEA` or `Elx`.
+pub fn parse_flags(classes: &str) -> Option<u32> {
+    classes.chars().try_fold(0, |flags, c| Some(flags | match c {
+        'A' => ALL,
+        'K' => KEYSPACE,
+        'E' => KEYEVENT,
+        'm' => KEY_MISS,
+        c => CLASSES.iter().find(|(letter, _)| *letter == c)?.1,
+    }))
+}
+
+
+/// Write flags as class letters, the way CONFIG GET shows them.
+pub fn flags_to_string(flags: u32) -> String {
+    let mut out = String::new();
+
+    if flags & ALL == ALL {
+        out.push('A');
+    } else {
+        out.extend(CLASSES.iter().filter(|(_, class)| flags & class != 0).map(|(letter, _)| letter));
+    }
+
+    for (letter, class) in [('K', KEYSPACE), ('E', KEYEVENT), ('m', KEY_MISS)] {
+        if flags & class != 0 {
+            out.push(letter);
+        }
+    }
+
+    out
+}
+
+
+/// Queues the keyspace notifications raised while a command runs, for the server to publish
+/// once it has finished.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    /// The classes which are turned on.
+    pub flags: u32,
+    queued: Vec<(&'static str, Bytes)>,
+    /// Set while any client has CLIENT TRACKING on, so that modified keys are collected to
+    /// invalidate.
+    pub tracking: bool,
+    modified: Vec<Bytes>,
+}
+
+
+impl Notifier {
+    pub fn notify(&mut self, class: u32, event: &'static str, key: &Bytes) {
+        if self.tracking && class != KEY_MISS {
+            self.modified.push(key.clone());
+        }
+
+        if self.flags & class != 0 && self.flags & (KEYSPACE | KEYEVENT) != 0 {
+            self.queued.push((event, key.clone()));
+        }
+    }
+
+    /// Return the notifications queued since the last call, as the event and the key.
+    pub fn take(&mut self) -> Vec<(&'static str, Bytes)> {
+        std::mem::take(&mut self.queued)
+    }
+
+    /// Return the keys modified since the last call, while tracking is on.
+    pub fn take_modified(&mut self) -> Vec<Bytes> {
+        std::mem::take(&mut self.modified)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::notify::{flags_to_string, parse_flags, Notifier, ALL, EXPIRED, KEYEVENT, KEYSPACE, KEY_MISS, LIST, STRING};
+
+    #[test]
+    fn test_flags() {
+        assert_eq!(parse_flags("Elx"), Some(KEYEVENT | LIST | EXPIRED));
+        assert_eq!(parse_flags("KEA"), Some(KEYSPACE | KEYEVENT | ALL));
+        assert_eq!(parse_flags(""), Some(0));
+        assert_eq!(parse_flags("Kq"), None);
+
+        assert_eq!(flags_to_string(parse_flags("xlE").unwrap()), "lxE");
+        assert_eq!(flags_to_string(parse_flags("g$lshzxetKEm").unwrap()), "AKEm");
+        assert_eq!(flags_to_string(KEY_MISS), "m");
+    }
+
+    #[test]
+    fn test_notifier() {
+        let key = Bytes::from("k");
+        let mut notifier = Notifier { flags: STRING | LIST, ..Default::default() };
+
+        // Nothing is published without K or E.
+        notifier.notify(STRING, "set", &key);
+        assert!(notifier.take().is_empty());
+
+        notifier.flags |= KEYEVENT;
+        notifier.notify(STRING, "set", &key);
+        notifier.notify(EXPIRED, "expired", &key);
+        notifier.notify(LIST, "lpush", &key);
+        assert_eq!(notifier.take(), vec![("set", key.clone()), ("lpush", key.clone())]);
+        assert!(notifier.take().is_empty());
+        assert!(notifier.take_modified().is_empty());
+
+        // Modified keys are collected for client tracking whatever the flags are.
+        notifier.flags = 0;
+        notifier.tracking = true;
+        notifier.notify(EXPIRED, "expired", &key);
+        notifier.notify(KEY_MISS, "keymiss", &key);
+        assert_eq!(notifier.take_modified(), vec![key]);
+    }
+}
@@ -1,45 +1,196 @@
 
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use log::debug;
 
 use sider_command::RESPType;
+use crate::util;
+
+
+/// The largest values a connection may send, so that a declared length cannot make us buffer
+/// or allocate without bound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// The longest bulk string, set by `proto-max-bulk-len`.
+    pub max_bulk_len: usize,
+    /// The most elements in an array.
+    pub max_multibulk_len: usize,
+    /// How deeply arrays may be nested. Commands are flat arrays, so only replies from other
+    /// servers nest at all.
+    pub max_depth: usize,
+    /// The longest inline command, which has to be buffered until its line ending arrives.
+    pub max_inline_len: usize,
+}
+
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_multibulk_len: i32::MAX as usize,
+            max_depth: 8,
+            max_inline_len: 64 * 1024,
+        }
+    }
+}
 
 
 #[derive(Default)]
 pub struct RESPParser {
     position: usize,
-    bytes: Bytes
+    bytes: Bytes,
+    /// Set when the input ends part way through a value, so that partial input can be told
+    /// apart from invalid input.
+    incomplete: bool,
+    /// Once the input is found to be incomplete, how long it has to be before the value can
+    /// be complete.
+    needed: usize,
+    limits: Limits,
+    /// The arrays the parser is inside, outermost first, with the elements parsed so far and
+    /// the number of elements each has.
+    arrays: Vec<(Vec<RESPType<Bytes>>, usize)>,
 }
 
 
 impl RESPParser {
+    /// Parse a single complete value. The server reads through `QueryBuffer` instead, which
+    /// copes with values split across reads.
+    #[cfg(test)]
     pub fn parse(s: Bytes) -> RESPType<Bytes> {
         let mut parser = Self {
-            position: 0,
             bytes: s,
+            ..Default::default()
         };
 
         parser.parse_until_complete()
     }
 
+    /// Parse the first value in `s`, returning it along with the number of bytes it took up.
+    /// Returns None if `s` ends before the value is complete, in which case the caller should
+    /// try again once more input has arrived.
+    #[cfg(test)]
+    pub fn parse_prefix(s: &Bytes, limits: Limits) -> Option<(RESPType<Bytes>, usize)> {
+        let mut parser = Self {
+            bytes: s.clone(),
+            limits,
+            ..Default::default()
+        };
+
+        let value = parser.parse_until_complete();
+
+        if parser.incomplete {
+            return None;
+        }
+
+        Some((value, parser.position.min(s.len())))
+    }
+
     fn parse_until_complete(&mut self) -> RESPType<Bytes> {
         debug!("Parse until complete.");
 
-        if let Some(first_byte) = self.bytes.get(self.position) {
-            debug!("First byte was {:?}", char::from_u32(*first_byte as u32).unwrap());
-            self.position += 1;
-
-            match first_byte {
-                b'+' => self.parse_simple_string(),
-                b'*' => self.parse_array(),
-                b'$' => self.parse_bulk_string(),
-                b':' => self.parse_integer(),
-                b'-' => self.parse_error(),
-                _ => RESPType::Error("Unable to parse input due to invalid byte.".into()),
+        loop {
+            let start = self.position;
+
+            let Some(mut value) = self.parse_value() else {
+                continue;
+            };
+
+            if self.incomplete {
+                // Go back to the start of the element. The elements before it and the arrays
+                // they are in are kept, so parsing can carry on from here later.
+                self.position = start;
+                self.needed = self.needed.max(self.bytes.len() + 1);
+                return value;
+            }
+
+            if let RESPType::Error(_) = value {
+                self.arrays.clear();
+                return value;
+            }
+
+            // Finish the arrays which this was the last element of.
+            loop {
+                let Some((items, len)) = self.arrays.last_mut() else {
+                    return value;
+                };
+
+                items.push(value);
+
+                if items.len() < *len {
+                    break;
+                }
+
+                value = RESPType::Array(self.arrays.pop().unwrap().0);
+            }
+        }
+    }
+
+    /// Parse the value at the current position. Returns None if there is no value yet but
+    /// parsing should go on, as when an array has been started and its elements follow.
+    fn parse_value(&mut self) -> Option<RESPType<Bytes>> {
+        let Some(first_byte) = self.bytes.get(self.position) else {
+            self.incomplete = true;
+            return Some(RESPType::Error("Unable to parse, no byte at position.".into()));
+        };
+
+        debug!("First byte was {:?}", char::from_u32(*first_byte as u32).unwrap());
+        self.position += 1;
+
+        Some(match first_byte {
+            b'+' => self.parse_simple_string(),
+            b'*' => return self.parse_array(),
+            b'$' => self.parse_bulk_string(),
+            b':' => self.parse_integer(),
+            b'-' => self.parse_error(),
+            _ if self.arrays.is_empty() => {
+                self.position -= 1;
+                return self.parse_inline();
+            },
+            _ => RESPType::Error("Unable to parse input due to invalid byte.".into()),
+        })
+    }
+
+    /// Parse an inline command, for clients like telnet which cannot send RESP. The arguments
+    /// are separated by spaces and may be quoted as in redis-cli, and the line ends with `\n`
+    /// or `\r\n`. Empty lines are skipped, returning None.
+    fn parse_inline(&mut self) -> Option<RESPType<Bytes>> {
+        debug!("Parsing inline command.");
+
+        let rest = &self.bytes[self.position..];
+
+        let Some(end) = rest.iter().position(|b| *b == b'\n') else {
+            if rest.len() > self.limits.max_inline_len {
+                return Some(RESPType::Error("too big inline request".into()));
+            }
+
+            self.incomplete = true;
+            return Some(RESPType::Error("Unable to parse inline command, no line ending.".into()));
+        };
+
+        let line = rest[..end].strip_suffix(b"\r").unwrap_or(&rest[..end]);
+
+        let Some(args) = util::split_args(line) else {
+            return Some(RESPType::Error("unbalanced quotes in request".into()));
+        };
+
+        self.position += end + 1;
+
+        if args.is_empty() {
+            return None;
+        }
+
+        Some(RESPType::Array(args.into_iter().map(|a| RESPType::BulkString(a.into())).collect()))
+    }
+
+    /// Check whether the byte at the current position is `b`, recording whether the input
+    /// ran out.
+    fn at(&mut self, b: u8) -> bool {
+        match self.bytes.get(self.position) {
+            Some(c) => *c == b,
+            None => {
+                self.incomplete = true;
+                false
             }
-        } else {
-            RESPType::Error("Unable to parse, no byte at position.".into())
         }
     }
     
@@ -62,7 +213,7 @@ impl RESPParser {
         
         self.position += 1;
 
-        if Some(&b'\n') != self.bytes.get(self.position) {
+        if !self.at(b'\n') {
             return RESPType::Error("Missing newline.".into())
         }
 
@@ -80,7 +231,7 @@ impl RESPParser {
         
         self.position += 1;
 
-        if Some(&b'\n') != self.bytes.get(self.position) {
+        if !self.at(b'\n') {
             return RESPType::Error("Missing newline.".into())
         }
 
@@ -107,7 +258,7 @@ impl RESPParser {
         
         self.position += 1;
 
-        if Some(&b'\n') != self.bytes.get(self.position) {
+        if !self.at(b'\n') {
             return RESPType::Error("Missing newline.".into())
         }
 
@@ -116,7 +267,9 @@ impl RESPParser {
         RESPType::Error(self.bytes.slice(start..self.position - 2))
     }
 
-    fn parse_array(&mut self) -> RESPType<Bytes> {
+    /// Parse the length of an array, which is then filled in with the elements which follow
+    /// it. Returns None once the array has been started.
+    fn parse_array(&mut self) -> Option<RESPType<Bytes>> {
         debug!("Parsing array.");
 
         let start = self.position;
@@ -127,8 +280,8 @@ impl RESPParser {
         // Check for newline
         self.position += 1;
 
-        if Some(&b'\n') != self.bytes.get(self.position) {
-            return RESPType::Error("Missing newline.".into())
+        if !self.at(b'\n') {
+            return Some(RESPType::Error("Missing newline.".into()))
         }
 
         debug!("Got to position {}", self.position);
@@ -137,42 +290,41 @@ impl RESPParser {
             if let Ok(v) = str::parse::<i64>(parsed_length_string) {
                 v
             } else {
-                return RESPType::Error("Unable to parse array length, string not an integer.".into());
+                return Some(RESPType::Error("Unable to parse array length, string not an integer.".into()));
             }
         } else {
-            return RESPType::Error("Unable to parse array length, invalid byte sequence.".into());
+            return Some(RESPType::Error("Unable to parse array length, invalid byte sequence.".into()));
         };
 
         debug!("Parsed length: {}", array_length);
 
-        let mut items = vec![];
-
         if array_length == -1 {
-            return RESPType::Null;
+            return Some(RESPType::Null);
         }
 
         let array_length = if let Ok(v) = usize::try_from(array_length) {
             v
         } else {
-            return RESPType::Error("Invalid array length, length was negative and not -1.".into());
+            return Some(RESPType::Error("Invalid array length, length was negative and not -1.".into()));
         };
 
-        self.position += 1;
-
-        while items.len() < array_length {
-            debug!("Parsing item {}", items.len());
+        if array_length > self.limits.max_multibulk_len {
+            return Some(RESPType::Error("invalid multibulk length".into()));
+        }
 
-            let item = self.parse_until_complete();
+        if self.arrays.len() >= self.limits.max_depth {
+            return Some(RESPType::Error("too many nested arrays".into()));
+        }
 
-            if let RESPType::Error(_) = item {
-                debug!("Got error for item. Returning.");
-                return item;
-            }
+        self.position += 1;
 
-            items.push(item);
+        if array_length == 0 {
+            return Some(RESPType::Array(Vec::new()));
         }
 
-        RESPType::Array(items)
+        self.arrays.push((Vec::new(), array_length));
+
+        None
     }
 
     fn parse_bulk_string(&mut self) -> RESPType<Bytes> {
@@ -186,7 +338,7 @@ impl RESPParser {
         // Check for newline
         self.position += 1;
 
-        if Some(&b'\n') != self.bytes.get(self.position) {
+        if !self.at(b'\n') {
             return RESPType::Error("Missing newline.".into())
         }
 
@@ -214,16 +366,23 @@ impl RESPParser {
             return RESPType::Error("Unable to parse bulk string length, integer is negative and not -1.".into());
         };
 
+        // Checked before the string has arrived, so that it is never buffered.
+        if string_length > self.limits.max_bulk_len {
+            return RESPType::Error("invalid bulk length".into());
+        }
+
         let string_start = self.position + 1;
         self.position += 1 + string_length;
+        // Nothing more can be parsed until the whole string has arrived.
+        self.needed = self.position + 2;
 
-        if Some(&b'\r') != self.bytes.get(self.position) {
+        if !self.at(b'\r') {
             return RESPType::Error("Unable to parse bulk string, invalid string length.".into())
         }
 
         self.position += 1;
 
-        if Some(&b'\n') != self.bytes.get(self.position) {
+        if !self.at(b'\n') {
             return RESPType::Error("Unable to parse bulk string, missing newline.".into())
         }
 
@@ -235,9 +394,151 @@ impl RESPParser {
 
 
 
+/// Buffers the input received on a connection, so that values which arrive split across
+/// several reads, or several values which arrive in one read, can be parsed.
+///
+/// The elements of a value which has only partly arrived are kept once they are parsed, and
+/// nothing is parsed again until the element the input ended in can be complete, so a large
+/// value is parsed once however finely it is split.
+#[derive(Debug, Default)]
+pub struct QueryBuffer {
+    /// Input which has not been parsed yet.
+    received: BytesMut,
+    /// Input which has been frozen so that parsed values can refer to it without copying.
+    frozen: Bytes,
+    pub limits: Limits,
+    /// The arrays of a value which has partly arrived, with the elements parsed so far.
+    arrays: Vec<(Vec<RESPType<Bytes>>, usize)>,
+    /// The input those elements were parsed from.
+    parsed: Vec<Bytes>,
+    /// How much unparsed input there has to be before the value can be complete.
+    needed: usize,
+}
+
+
+impl QueryBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limits(limits: Limits) -> Self {
+        QueryBuffer { limits, ..Default::default() }
+    }
+
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.received.extend_from_slice(bytes);
+    }
+
+    /// The number of bytes received for values which have not been returned yet.
+    pub fn len(&self) -> usize {
+        self.received.len() + self.frozen.len() + self.parsed.iter().map(|p| p.len()).sum::<usize>()
+    }
+
+    pub fn clear(&mut self) {
+        *self = QueryBuffer::with_limits(self.limits);
+    }
+
+    /// Parse the next complete value, returning it along with the raw bytes it was parsed
+    /// from. Returns None if the buffer does not contain a complete value.
+    pub fn next_value(&mut self) -> Option<(RESPType<Bytes>, Bytes)> {
+        if self.received.len() + self.frozen.len() < self.needed {
+            return None;
+        }
+
+        if self.frozen.is_empty() {
+            self.frozen = self.received.split().freeze();
+        }
+
+        let mut parser = RESPParser {
+            bytes: self.frozen.clone(),
+            limits: self.limits,
+            arrays: std::mem::take(&mut self.arrays),
+            ..Default::default()
+        };
+
+        let value = parser.parse_until_complete();
+        let raw = self.frozen.split_to(parser.position.min(self.frozen.len()));
+
+        if !parser.incomplete {
+            self.needed = 0;
+            return Some((value, self.join_parsed(raw)));
+        }
+
+        self.arrays = parser.arrays;
+        self.needed = parser.needed - raw.len();
+
+        if !raw.is_empty() {
+            self.parsed.push(raw);
+        }
+
+        if self.received.is_empty() {
+            return None;
+        }
+
+        // The rest of the value may have arrived since the input was frozen.
+        self.unfreeze();
+        self.next_value()
+    }
+
+    /// The raw bytes of a value, from the parts of it parsed before the rest arrived and the
+    /// last part.
+    fn join_parsed(&mut self, last: Bytes) -> Bytes {
+        if self.parsed.is_empty() {
+            return last;
+        }
+
+        let mut raw = BytesMut::with_capacity(self.parsed.iter().map(|p| p.len()).sum::<usize>() + last.len());
+
+        for part in self.parsed.drain(..) {
+            raw.extend_from_slice(&part);
+        }
+
+        raw.extend_from_slice(&last);
+        raw.freeze()
+    }
+
+    /// Take the next line, without its line ending.
+    pub fn next_line(&mut self) -> Option<Bytes> {
+        self.unfreeze();
+
+        let end = self.received.windows(2).position(|w| w == b"\r\n")?;
+        let line = self.received.split_to(end + 2).freeze();
+
+        Some(line.slice(..end))
+    }
+
+    /// Take exactly `len` bytes.
+    pub fn take(&mut self, len: usize) -> Option<Bytes> {
+        self.unfreeze();
+
+        if self.received.len() < len {
+            return None;
+        }
+
+        Some(self.received.split_to(len).freeze())
+    }
+
+    fn unfreeze(&mut self) {
+        if self.frozen.is_empty() {
+            return;
+        }
+
+        let mut received = BytesMut::with_capacity(self.frozen.len() + self.received.len());
+        received.extend_from_slice(&self.frozen);
+        received.extend_from_slice(&self.received);
+
+        self.received = received;
+        self.frozen = Bytes::new();
+    }
+}
+
+
+
 #[cfg(test)]
 mod tests {
-    use crate::parser::{RESPParser, RESPType};
+    use bytes::Bytes;
+
+    use crate::parser::{Limits, QueryBuffer, RESPParser, RESPType};
 
     #[test]
     fn test_null() {
@@ -300,13 +601,126 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_parse_prefix() {
+        let input = Bytes::from("*1\r\n$4\r\nping\r\n*1\r\n$4\r\npi");
+
+        assert_eq!(
+            RESPParser::parse_prefix(&input, Limits::default()),
+            Some((RESPType::Array(vec![RESPType::BulkString("ping".into())]), 14)),
+        );
+        assert_eq!(RESPParser::parse_prefix(&input.slice(14..), Limits::default()), None);
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits { max_bulk_len: 4, max_multibulk_len: 2, max_depth: 1, ..Limits::default() };
+        let parse = |s: &'static str| RESPParser::parse_prefix(&Bytes::from(s), limits).map(|(v, _)| v);
+
+        // The lengths are refused as soon as they arrive, without waiting for the values.
+        assert_eq!(parse("*3\r\n"), Some(RESPType::Error("invalid multibulk length".into())));
+        assert_eq!(parse("*1\r\n$5\r\n"), Some(RESPType::Error("invalid bulk length".into())));
+        assert_eq!(parse("*1\r\n*1\r\n$4\r\nping\r\n"), Some(RESPType::Error("too many nested arrays".into())));
+        assert_eq!(parse("*2\r\n$4\r\necho\r\n$4\r\nhiya\r\n").map(|v| matches!(v, RESPType::Array(_))), Some(true));
+    }
+
+    #[test]
+    fn test_query_buffer_split_input() {
+        let mut buffer = QueryBuffer::new();
+        let ping = RESPType::Array(vec![RESPType::BulkString("ping".into())]);
+
+        buffer.extend_from_slice(b"*1\r\n$4\r\nping\r\n*1\r\n$4");
+        assert_eq!(buffer.next_value(), Some((ping.clone(), Bytes::from("*1\r\n$4\r\nping\r\n"))));
+        assert_eq!(buffer.next_value(), None);
+
+        buffer.extend_from_slice(b"\r\nping\r\n");
+        assert_eq!(buffer.next_value().map(|(v, _)| v), Some(ping));
+        assert_eq!(buffer.next_value(), None);
+    }
+
+    #[test]
+    fn test_query_buffer_large_value() {
+        let value = vec![b'x'; 8 * 1024 * 1024];
+        let mut input = format!("*3\r\n$3\r\nset\r\n$3\r\nkey\r\n${}\r\n", value.len()).into_bytes();
+        input.extend_from_slice(&value);
+        input.extend_from_slice(b"\r\n");
+
+        // Parsing everything received so far on every read would copy terabytes here.
+        let mut buffer = QueryBuffer::new();
+        let mut received = 0;
+
+        for chunk in input.chunks(1024) {
+            assert!(buffer.next_value().is_none());
+            buffer.extend_from_slice(chunk);
+            received += chunk.len();
+            assert_eq!(buffer.len(), received);
+        }
+
+        let (command, raw) = buffer.next_value().unwrap();
+        assert_eq!(command, RESPType::Array(vec![
+            RESPType::BulkString("set".into()),
+            RESPType::BulkString("key".into()),
+            RESPType::BulkString(value.into()),
+        ]));
+        assert_eq!(raw, input);
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn test_query_buffer_many_elements() {
+        let mut input = b"*100000\r\n".to_vec();
+        (0..100000).for_each(|_| input.extend_from_slice(b"$1\r\nx\r\n"));
+
+        let mut buffer = QueryBuffer::new();
+
+        for chunk in input.chunks(5) {
+            assert!(buffer.next_value().is_none());
+            buffer.extend_from_slice(chunk);
+        }
+
+        buffer.extend_from_slice(b"*1\r\n$4\r\nping\r\n");
+
+        let (command, raw) = buffer.next_value().unwrap();
+        assert!(matches!(command, RESPType::Array(v) if v.len() == 100000 && v[99999] == RESPType::BulkString("x".into())));
+        assert_eq!(raw, input);
+        assert_eq!(buffer.next_value().map(|(v, _)| v), Some(RESPType::Array(vec![RESPType::BulkString("ping".into())])));
+    }
+
     #[test]
     fn test_invalid_input() {
         assert_eq!(
-            RESPParser::parse("bad string".into()),
+            RESPParser::parse("*1\r\nbad string\r\n".into()),
             RESPType::Error("Unable to parse input due to invalid byte.".into()),
-        )
+        );
+        assert_eq!(
+            RESPParser::parse("bad \"string\r\n".into()),
+            RESPType::Error("unbalanced quotes in request".into()),
+        );
+    }
+
+    #[test]
+    fn test_inline() {
+        let input = Bytes::from("\r\n  \nSET key \"hello\\x20world\" 'it\\'s'\r\nPING\nECHO");
 
+        let (value, len) = RESPParser::parse_prefix(&input, Limits::default()).unwrap();
+        assert_eq!(value, RESPType::Array(vec![
+            RESPType::BulkString("SET".into()),
+            RESPType::BulkString("key".into()),
+            RESPType::BulkString("hello world".into()),
+            RESPType::BulkString("it's".into()),
+        ]));
+
+        let (value, _) = RESPParser::parse_prefix(&input.slice(len..), Limits::default()).unwrap();
+        assert_eq!(value, RESPType::Array(vec![RESPType::BulkString("PING".into())]));
+
+        // The line has not ended yet.
+        assert_eq!(RESPParser::parse_prefix(&Bytes::from("ECHO"), Limits::default()), None);
+
+        let limits = Limits { max_inline_len: 4, ..Limits::default() };
+        assert_eq!(
+            RESPParser::parse_prefix(&Bytes::from("ECHO hello"), limits),
+            Some((RESPType::Error("too big inline request".into()), 0)),
+        );
     }
 }
 
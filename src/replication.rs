@@ -0,0 +1,558 @@
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use mio::{Interest, Registry, Token};
+use mio::net::TcpStream;
+use rand::Rng;
+use rustls::ClientConfig;
+
+use sider_command::RESPType;
+use crate::net::Socket;
+use crate::parser::QueryBuffer;
+use crate::serializer::serialize;
+use crate::tls::Stream;
+
+
+/// The size of the replication backlog, the same default as Redis.
+pub const BACKLOG_SIZE: usize = 1024 * 1024;
+/// How often replicas acknowledge the offset they have processed.
+pub const ACK_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the primary pings its replicas, so that they can tell the link is still alive.
+pub const PING_INTERVAL: Duration = Duration::from_secs(10);
+/// The link to the primary is dropped if nothing has been received for this long.
+pub const TIMEOUT: Duration = Duration::from_secs(60);
+/// How long to wait before reconnecting to the primary after the link is lost.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+
+/// Generate a random 40 character replication ID.
+pub fn generate_id() -> String {
+    let mut rng = rand::thread_rng();
+
+    (0..40).map(|_| char::from_digit(rng.gen_range(0..16), 16).unwrap()).collect()
+}
+
+
+/// The replication state of the server.
+///
+/// Every server, whether it is a primary or a replica, has a replication ID and an offset
+/// which together identify a position in a history of writes. A replica takes on the ID of
+/// its primary, and its offset is the number of bytes of the primary's replication stream
+/// that it has processed. This means that a replica can serve the same history to its own
+/// replicas, and that after a failover, the replicas of the old primary can continue from
+/// the new primary without a full resync.
+pub struct Replication {
+    pub id: String,
+    /// The ID of the history this server followed before it was promoted, which replicas
+    /// of the old primary can still resume from up to `second_id_offset`.
+    pub second_id: String,
+    pub second_id_offset: Option<u64>,
+    /// The number of bytes in the replication stream so far.
+    pub offset: u64,
+    /// The recent replication stream. This is only created once the first replica attaches,
+    /// there is no need to keep a history before then.
+    pub backlog: Option<Backlog>,
+    /// The link to our primary, if we are a replica.
+    pub primary: Option<PrimaryLink>,
+    pub last_ack: Instant,
+    pub last_ping: Instant,
+}
+
+
+impl Replication {
+    pub fn new() -> Self {
+        Replication {
+            id: generate_id(),
+            second_id: "0".repeat(40),
+            second_id_offset: None,
+            offset: 0,
+            backlog: None,
+            primary: None,
+            last_ack: Instant::now(),
+            last_ping: Instant::now(),
+        }
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.primary.is_some()
+    }
+
+    /// Add to the replication stream, returning false if there is no backlog and so nothing
+    /// is listening.
+    pub fn feed(&mut self, bytes: &[u8]) -> bool {
+        let Some(backlog) = &mut self.backlog else {
+            return false;
+        };
+
+        backlog.append(bytes);
+        self.offset += bytes.len() as u64;
+
+        true
+    }
+
+    /// Start a new history, for when we become a primary. Replicas which were following the
+    /// same primary as us can still resume from the old history up to the current offset.
+    pub fn shift_id(&mut self) {
+        self.second_id = std::mem::replace(&mut self.id, generate_id());
+        self.second_id_offset = Some(self.offset + 1);
+    }
+
+    /// Return the replication stream from `offset` onwards, if a replica asking to resume
+    /// from it can be served from the backlog.
+    pub fn resume_from(&self, id: &[u8], offset: u64) -> Option<Vec<u8>> {
+        let same_history = id == self.id.as_bytes()
+            || (id == self.second_id.as_bytes() && self.second_id_offset.is_some_and(|o| offset <= o));
+
+        if !same_history {
+            return None;
+        }
+
+        self.backlog.as_ref()?.read_from(offset)
+    }
+}
+
+
+/// A circular buffer holding the most recent part of the replication stream, so that a
+/// replica which briefly loses its link can resume from where it left off rather than
+/// needing a full resync.
+#[derive(Debug)]
+pub struct Backlog {
+    buffer: Vec<u8>,
+    /// The index in the buffer that the next byte will be written to.
+    position: usize,
+    /// The number of bytes of history in the buffer.
+    len: usize,
+    /// The replication offset of the last byte written.
+    end: u64,
+}
+
+
+impl Backlog {
+    /// Create an empty backlog which continues the stream after `offset`.
+    pub fn new(size: usize, offset: u64) -> Self {
+        Backlog {
+            buffer: vec![0; size.max(1)],
+            position: 0,
+            len: 0,
+            end: offset,
+        }
+    }
+
+    pub fn append(&mut self, bytes: &[u8]) {
+        let size = self.buffer.len();
+
+        self.end += bytes.len() as u64;
+
+        // Only the end of a write larger than the whole buffer would survive.
+        let bytes = &bytes[bytes.len().saturating_sub(size)..];
+
+        let first = (size - self.position).min(bytes.len());
+        self.buffer[self.position..self.position + first].copy_from_slice(&bytes[..first]);
+        self.buffer[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+
+        self.position = (self.position + bytes.len()) % size;
+        self.len = (self.len + bytes.len()).min(size);
+    }
+
+    /// The most history the backlog can hold.
+    pub fn size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The number of bytes of history held.
+    pub fn history_len(&self) -> usize {
+        self.len
+    }
+
+    /// The offset of the oldest byte held.
+    pub fn first_offset(&self) -> u64 {
+        self.end + 1 - self.len as u64
+    }
+
+    /// Return the stream starting from the byte at `offset`, or None if that is no longer
+    /// in the backlog.
+    pub fn read_from(&self, offset: u64) -> Option<Vec<u8>> {
+        let size = self.buffer.len();
+        let start = self.first_offset();
+
+        if offset < start || offset > self.end + 1 {
+            return None;
+        }
+
+        let skip = (offset - start) as usize;
+        let count = self.len - skip;
+        let index = (self.position + size - self.len + skip) % size;
+
+        let first = (size - index).min(count);
+
+        let mut out = Vec::with_capacity(count);
+        out.extend_from_slice(&self.buffer[index..index + first]);
+        out.extend_from_slice(&self.buffer[..count - first]);
+
+        Some(out)
+    }
+}
+
+
+/// The replication state of a client which is one of our replicas.
+#[derive(Debug)]
+pub struct ReplicaClient {
+    /// The port the replica accepts connections on, as given by REPLCONF listening-port.
+    pub listening_port: u16,
+    /// Whether the replica has started receiving the replication stream.
+    pub online: bool,
+    /// The offset the replica last acknowledged processing.
+    pub ack_offset: u64,
+}
+
+
+impl ReplicaClient {
+    pub fn new() -> Self {
+        ReplicaClient {
+            listening_port: 0,
+            online: false,
+            ack_offset: 0,
+        }
+    }
+}
+
+
+/// The state of the link to our primary, following the same handshake as Redis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkState {
+    /// Waiting to connect, or to reconnect after the link was lost.
+    Connect,
+    Connecting,
+    ReceivePong,
+    ReceiveAuth,
+    ReceivePort,
+    ReceiveCapa,
+    ReceivePsync,
+    /// Receiving the snapshot for a full resync.
+    Transfer,
+    /// Receiving the replication stream.
+    Connected,
+}
+
+
+impl LinkState {
+    /// The name of the state, as reported by ROLE.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Connect => "connect",
+            Self::Connecting => "connecting",
+            Self::ReceivePong | Self::ReceiveAuth | Self::ReceivePort | Self::ReceiveCapa | Self::ReceivePsync => "handshake",
+            Self::Transfer => "sync",
+            Self::Connected => "connected",
+        }
+    }
+}
+
+
+pub enum LinkEvent {
+    /// The primary is starting a full resync from its current ID and offset. The snapshot
+    /// follows.
+    FullResync(String, u64),
+    Snapshot(Bytes),
+    /// The primary is continuing from our offset, possibly with a new replication ID.
+    Continue(Option<String>),
+    /// A command from the replication stream, along with its raw bytes.
+    Command(RESPType<Bytes>, Bytes),
+}
+
+
+/// The connection from a replica to its primary.
+pub struct PrimaryLink {
+    pub host: String,
+    pub port: u16,
+    pub state: LinkState,
+    stream: Option<Stream>,
+    input: QueryBuffer,
+    output: Vec<u8>,
+    /// The size of the snapshot being transferred, once its header has been received.
+    transfer_size: Option<usize>,
+    pub last_received: Instant,
+    retry_at: Instant,
+}
+
+
+impl PrimaryLink {
+    pub fn new(host: String, port: u16) -> Self {
+        PrimaryLink {
+            host,
+            port,
+            state: LinkState::Connect,
+            stream: None,
+            input: QueryBuffer::new(),
+            output: Vec::new(),
+            transfer_size: None,
+            last_received: Instant::now(),
+            retry_at: Instant::now(),
+        }
+    }
+
+    /// Whether we are not connected and it is time to retry.
+    pub fn is_due(&self) -> bool {
+        self.state == LinkState::Connect && Instant::now() >= self.retry_at
+    }
+
+    /// Start connecting to the primary if it is time to, over TLS if there is a TLS
+    /// configuration.
+    pub fn connect(&mut self, registry: &Registry, token: Token, tls_config: Option<Arc<ClientConfig>>) -> io::Result<()> {
+        if !self.is_due() {
+            return Ok(());
+        }
+
+        let Some(address) = (self.host.as_str(), self.port).to_socket_addrs()?.next() else {
+            return Err(io::Error::new(ErrorKind::NotFound, "could not resolve the primary's address"));
+        };
+
+        let socket = Socket::Tcp(TcpStream::connect(address)?);
+
+        let mut stream = match tls_config {
+            Some(tls_config) => Stream::connect(socket, tls_config, &self.host)?,
+            None => Stream::plain(socket),
+        };
+
+        registry.register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)?;
+
+        self.stream = Some(stream);
+        self.state = LinkState::Connecting;
+        self.last_received = Instant::now();
+
+        Ok(())
+    }
+
+    /// Close the connection, and try again after a short wait.
+    pub fn disconnect(&mut self) {
+        self.stream = None;
+        self.state = LinkState::Connect;
+        self.input.clear();
+        self.output.clear();
+        self.transfer_size = None;
+        self.retry_at = Instant::now() + RECONNECT_INTERVAL;
+    }
+
+    /// Handle the socket becoming writable, which is how a non-blocking connect completes.
+    pub fn writable(&mut self) -> io::Result<()> {
+        if self.state == LinkState::Connecting {
+            let Some(stream) = &self.stream else {
+                return Ok(());
+            };
+
+            if let Some(e) = stream.socket().take_error()? {
+                return Err(e);
+            }
+
+            match stream.socket().peer_addr() {
+                Ok(_) => {},
+                Err(e) if e.kind() == ErrorKind::NotConnected => return Ok(()),
+                Err(e) => return Err(e),
+            }
+
+            self.state = LinkState::ReceivePong;
+            self.send(&[b"PING"])?;
+        }
+
+        self.flush()
+    }
+
+    /// Send a command to the primary.
+    pub fn send(&mut self, args: &[&[u8]]) -> io::Result<()> {
+        let command = RESPType::Array(args.iter().map(|a| RESPType::BulkString(Bytes::copy_from_slice(a))).collect());
+
+        serialize(&command, &mut self.output)?;
+
+        self.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let Some(stream) = &mut self.stream else {
+            return Ok(());
+        };
+
+        while !self.output.is_empty() {
+            match stream.write(&self.output) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.output.drain(..n);
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        stream.flush()
+    }
+
+    /// Read everything that is available from the primary. Returns false if the primary
+    /// closed the connection.
+    pub fn read(&mut self) -> io::Result<bool> {
+        let Some(stream) = &mut self.stream else {
+            return Ok(true);
+        };
+
+        if self.state == LinkState::Connecting {
+            return Ok(true);
+        }
+
+        let mut buffer = [0; 1024 * 16];
+
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => return Ok(false),
+                Ok(n) => {
+                    self.input.extend_from_slice(&buffer[..n]);
+                    self.last_received = Instant::now();
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Process the next reply or command from the primary, continuing the handshake where
+    /// necessary. The replication ID and offset are those to resume from, and `auth` the
+    /// password to give the primary, if any. Returns None once more input is needed.
+    pub fn next_event(&mut self, listening_port: u16, auth: Option<&str>, id: &str, offset: u64) -> Result<Option<LinkEvent>, String> {
+        loop {
+            match self.state {
+                LinkState::Connect | LinkState::Connecting => return Ok(None),
+                LinkState::ReceivePong | LinkState::ReceiveAuth | LinkState::ReceivePort | LinkState::ReceiveCapa => {
+                    let Some(line) = self.input.next_line() else {
+                        return Ok(None);
+                    };
+
+                    let result = match self.state {
+                        LinkState::ReceivePong => {
+                            // A primary with a password refuses the PING until we have given
+                            // it, which comes next.
+                            if line.starts_with(b"-") && !line.starts_with(b"-NOAUTH") {
+                                return Err(format!("error reply to PING: {}", String::from_utf8_lossy(&line)));
+                            }
+
+                            match auth {
+                                Some(password) => {
+                                    self.state = LinkState::ReceiveAuth;
+                                    self.send(&[b"AUTH", password.as_bytes()])
+                                },
+                                None => {
+                                    self.state = LinkState::ReceivePort;
+                                    self.send(&[b"REPLCONF", b"listening-port", listening_port.to_string().as_bytes()])
+                                },
+                            }
+                        },
+                        LinkState::ReceiveAuth => {
+                            if line.starts_with(b"-") {
+                                return Err(format!("unable to AUTH to the primary: {}", String::from_utf8_lossy(&line)));
+                            }
+
+                            self.state = LinkState::ReceivePort;
+                            self.send(&[b"REPLCONF", b"listening-port", listening_port.to_string().as_bytes()])
+                        },
+                        // Errors from REPLCONF are not fatal, older primaries may not
+                        // understand every option.
+                        LinkState::ReceivePort => {
+                            self.state = LinkState::ReceiveCapa;
+                            self.send(&[b"REPLCONF", b"capa", b"psync2"])
+                        },
+                        _ => {
+                            self.state = LinkState::ReceivePsync;
+                            self.send(&[b"PSYNC", id.as_bytes(), (offset + 1).to_string().as_bytes()])
+                        },
+                    };
+
+                    result.map_err(|e| e.to_string())?;
+                },
+                LinkState::ReceivePsync => {
+                    let Some(line) = self.input.next_line() else {
+                        return Ok(None);
+                    };
+
+                    let line = String::from_utf8_lossy(&line).into_owned();
+                    let parts: Vec<&str> = line.split(' ').collect();
+
+                    return match &parts[..] {
+                        ["+FULLRESYNC", id, offset] => {
+                            let offset = offset.parse().map_err(|_| format!("invalid PSYNC reply: {}", line))?;
+
+                            self.state = LinkState::Transfer;
+                            Ok(Some(LinkEvent::FullResync(id.to_string(), offset)))
+                        },
+                        ["+CONTINUE"] => {
+                            self.state = LinkState::Connected;
+                            Ok(Some(LinkEvent::Continue(None)))
+                        },
+                        ["+CONTINUE", id] => {
+                            self.state = LinkState::Connected;
+                            Ok(Some(LinkEvent::Continue(Some(id.to_string()))))
+                        },
+                        _ => Err(format!("invalid PSYNC reply: {}", line)),
+                    };
+                },
+                LinkState::Transfer => {
+                    let Some(size) = self.transfer_size else {
+                        let Some(line) = self.input.next_line() else {
+                            return Ok(None);
+                        };
+
+                        // Newlines may be sent to keep the link alive while the snapshot is
+                        // being prepared.
+                        let header = &line[line.iter().take_while(|b| **b == b'\n').count()..];
+
+                        let size = header.strip_prefix(b"$")
+                            .and_then(|s| std::str::from_utf8(s).ok())
+                            .and_then(|s| s.parse().ok())
+                            .ok_or_else(|| format!("invalid snapshot header: {}", String::from_utf8_lossy(&line)))?;
+
+                        self.transfer_size = Some(size);
+                        continue;
+                    };
+
+                    let Some(snapshot) = self.input.take(size) else {
+                        return Ok(None);
+                    };
+
+                    self.transfer_size = None;
+                    self.state = LinkState::Connected;
+
+                    return Ok(Some(LinkEvent::Snapshot(snapshot)));
+                },
+                LinkState::Connected => {
+                    return Ok(self.input.next_value().map(|(command, raw)| LinkEvent::Command(command, raw)));
+                },
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::replication::Backlog;
+
+    #[test]
+    fn test_backlog_wraps() {
+        let mut backlog = Backlog::new(8, 100);
+
+        assert_eq!(backlog.read_from(101), Some(vec![]));
+
+        backlog.append(b"abcde");
+        assert_eq!(backlog.read_from(101), Some(b"abcde".to_vec()));
+        assert_eq!(backlog.read_from(104), Some(b"de".to_vec()));
+
+        backlog.append(b"fghij");
+        assert_eq!(backlog.read_from(101), None);
+        assert_eq!(backlog.read_from(103), Some(b"cdefghij".to_vec()));
+        assert_eq!(backlog.read_from(111), Some(vec![]));
+        assert_eq!(backlog.read_from(112), None);
+
+        backlog.append(b"0123456789");
+        assert_eq!(backlog.read_from(113), Some(b"23456789".to_vec()));
+    }
+}
@@ -0,0 +1,1418 @@
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::ToSocketAddrs;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use log::{info, warn};
+use mio::{Interest, Registry, Token};
+use mio::net::TcpStream;
+
+use sider_command::RESPType;
+use crate::parser::QueryBuffer;
+use crate::replication::generate_id;
+use crate::serializer::serialize;
+
+
+/// Links to monitored instances and other sentinels are given tokens from this value upwards,
+/// above the range used by the cluster bus.
+pub const FIRST_LINK: usize = usize::MAX / 4 * 3;
+/// The channel sentinels announce themselves and their configuration on, on every monitored
+/// instance. This is how sentinels monitoring the same primary find each other.
+const HELLO_CHANNEL: &str = "__sentinel__:hello";
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// How often primaries and replicas are asked for their role, and how often once the primary
+/// is down and the replicas need watching more closely.
+const ROLE_INTERVAL: Duration = Duration::from_secs(10);
+const FAST_ROLE_INTERVAL: Duration = Duration::from_secs(1);
+const HELLO_INTERVAL: Duration = Duration::from_secs(2);
+/// How often other sentinels are asked whether they agree the primary is down.
+const ASK_INTERVAL: Duration = Duration::from_secs(1);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a replica has to report the wrong role or primary before it is reconfigured. This
+/// gives the sentinel which made the change time to announce it.
+const RECONFIGURE_DELAY: Duration = Duration::from_secs(8);
+pub const DEFAULT_DOWN_AFTER: Duration = Duration::from_secs(30);
+pub const DEFAULT_FAILOVER_TIMEOUT: Duration = Duration::from_secs(180);
+
+
+/// Identifies a monitored instance or another sentinel, by the name of the primary it belongs
+/// to and its address or, for sentinels, its ID.
+#[derive(Debug, Clone, PartialEq)]
+enum InstanceRef {
+    Master(String),
+    Replica(String, String),
+    Sentinel(String, String),
+}
+
+
+impl InstanceRef {
+    fn master(&self) -> &str {
+        match self {
+            Self::Master(m) | Self::Replica(m, _) | Self::Sentinel(m, _) => m,
+        }
+    }
+}
+
+
+/// A request whose reply we are waiting for.
+#[derive(Debug)]
+enum Request {
+    Ping,
+    Role,
+    IsMasterDown,
+    /// A request whose reply only matters if it is an error.
+    Other,
+}
+
+
+struct Link {
+    stream: TcpStream,
+    connected: bool,
+    input: QueryBuffer,
+    output: Vec<u8>,
+    pending: VecDeque<Request>,
+    owner: InstanceRef,
+    /// Whether the link is subscribed to the hello channel. Subscribed links only receive
+    /// messages, not replies.
+    pubsub: bool,
+}
+
+
+impl Link {
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.connected {
+            return Ok(());
+        }
+
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.output.drain(..n);
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle the socket becoming writable, which is how a non-blocking connect completes.
+    fn writable(&mut self) -> io::Result<()> {
+        if !self.connected {
+            if let Some(e) = self.stream.take_error()? {
+                return Err(e);
+            }
+
+            match self.stream.peer_addr() {
+                Ok(_) => self.connected = true,
+                Err(e) if e.kind() == ErrorKind::NotConnected => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.flush()
+    }
+
+    fn read(&mut self) -> io::Result<bool> {
+        if !self.connected {
+            return Ok(true);
+        }
+
+        let mut buffer = [0; 1024 * 16];
+
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.input.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+
+/// The role an instance last reported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportedRole {
+    Unknown,
+    Master,
+    Replica { host: String, port: u16, link_up: bool },
+}
+
+
+/// A monitored primary or replica, or another sentinel.
+pub struct Instance {
+    pub ip: String,
+    pub port: u16,
+    link: Option<usize>,
+    pubsub: Option<usize>,
+    retry_at: Instant,
+    last_ping: Instant,
+    /// When the oldest unanswered ping was sent.
+    ping_sent: Option<Instant>,
+    last_ok_ping: Instant,
+    last_role: Option<Instant>,
+    last_hello: Instant,
+    pub role: ReportedRole,
+    role_changed: Instant,
+    pub offset: u64,
+    /// Whether the instance is subjectively down, which is our opinion alone.
+    pub sdown: bool,
+    /// When we last told the instance which primary to follow.
+    reconfigured: Option<Instant>,
+    /// For other sentinels, whether they think the primary is down, and who they voted for
+    /// to lead its failover.
+    pub master_down: bool,
+    pub leader: Option<String>,
+    pub leader_epoch: u64,
+}
+
+
+impl Instance {
+    fn new(ip: String, port: u16) -> Self {
+        let now = Instant::now();
+
+        Instance {
+            ip,
+            port,
+            link: None,
+            pubsub: None,
+            retry_at: now,
+            last_ping: now - PING_INTERVAL,
+            ping_sent: None,
+            last_ok_ping: now,
+            last_role: None,
+            last_hello: now,
+            role: ReportedRole::Unknown,
+            role_changed: now,
+            offset: 0,
+            sdown: false,
+            reconfigured: None,
+            master_down: false,
+            leader: None,
+            leader_epoch: 0,
+        }
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+
+    pub fn connected(&self) -> bool {
+        self.link.is_some()
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailoverState {
+    /// Waiting to be elected leader by the other sentinels.
+    WaitStart,
+    SelectReplica,
+    /// Waiting for the chosen replica to report that it has become a primary.
+    WaitPromotion,
+    ReconfigureReplicas,
+}
+
+
+pub struct Failover {
+    pub epoch: u64,
+    pub state: FailoverState,
+    started: Instant,
+    /// The address of the replica being promoted.
+    promoted: Option<String>,
+    /// Set for a failover started by SENTINEL FAILOVER, which does not need agreement.
+    forced: bool,
+}
+
+
+/// A monitored primary, along with its replicas and the other sentinels monitoring it.
+pub struct Master {
+    pub name: String,
+    pub instance: Instance,
+    pub quorum: usize,
+    pub down_after: Duration,
+    pub failover_timeout: Duration,
+    /// The epoch of the failover which made the primary what it is. Announcements with a
+    /// greater epoch describe a newer primary.
+    pub config_epoch: u64,
+    /// Replicas by address.
+    pub replicas: HashMap<String, Instance>,
+    /// Other sentinels by ID.
+    pub sentinels: HashMap<String, Instance>,
+    /// Whether the primary is objectively down, that is enough sentinels agree it is down.
+    pub odown: bool,
+    /// Who we voted for to lead a failover, and in which epoch.
+    pub leader: Option<String>,
+    pub leader_epoch: u64,
+    pub failover: Option<Failover>,
+    last_failover: Option<Instant>,
+    last_ask: Instant,
+}
+
+
+impl Master {
+    fn new(name: String, ip: String, port: u16, quorum: usize) -> Self {
+        Master {
+            name,
+            instance: Instance::new(ip, port),
+            quorum,
+            down_after: DEFAULT_DOWN_AFTER,
+            failover_timeout: DEFAULT_FAILOVER_TIMEOUT,
+            config_epoch: 0,
+            replicas: HashMap::new(),
+            sentinels: HashMap::new(),
+            odown: false,
+            leader: None,
+            leader_epoch: 0,
+            failover: None,
+            last_failover: None,
+            last_ask: Instant::now(),
+        }
+    }
+
+    /// The flags describing the primary's state, as reported by SENTINEL MASTERS.
+    pub fn flags(&self) -> String {
+        let mut flags = String::from("master");
+
+        if self.instance.sdown {
+            flags.push_str(",s_down");
+        }
+
+        if self.odown {
+            flags.push_str(",o_down");
+        }
+
+        if self.failover.is_some() {
+            flags.push_str(",failover_in_progress");
+        }
+
+        flags
+    }
+}
+
+
+/// A hello announcement from another sentinel.
+struct Hello {
+    ip: String,
+    port: u16,
+    id: String,
+    current_epoch: u64,
+    master: String,
+    master_ip: String,
+    master_port: u16,
+    config_epoch: u64,
+}
+
+
+impl Hello {
+    fn parse(payload: &[u8]) -> Option<Self> {
+        let payload = std::str::from_utf8(payload).ok()?;
+        let [ip, port, id, current_epoch, master, master_ip, master_port, config_epoch] = <[&str; 8]>::try_from(payload.split(',').collect::<Vec<_>>()).ok()?;
+
+        Some(Hello {
+            ip: ip.into(),
+            port: port.parse().ok()?,
+            id: id.into(),
+            current_epoch: current_epoch.parse().ok()?,
+            master: master.into(),
+            master_ip: master_ip.into(),
+            master_port: master_port.parse().ok()?,
+            config_epoch: config_epoch.parse().ok()?,
+        })
+    }
+}
+
+
+/// The state of a server running in sentinel mode.
+///
+/// A sentinel pings the primaries it monitors, their replicas and the other sentinels, and
+/// considers an instance subjectively down once it has not answered for `down_after`. Once
+/// enough sentinels agree that a primary is down it is objectively down, and the sentinels
+/// elect one of themselves to promote a replica in its place. This follows the design of
+/// Redis Sentinel.
+pub struct Sentinel {
+    pub id: String,
+    pub current_epoch: u64,
+    pub masters: HashMap<String, Master>,
+    /// The port we listen on, which is announced to other sentinels.
+    port: u16,
+    links: Vec<Option<Link>>,
+    /// Events to publish to our clients, by channel.
+    events: Vec<(Bytes, Bytes)>,
+    /// Whether our state has changed since it was last written to the config file.
+    config_changed: bool,
+}
+
+
+impl Sentinel {
+    pub fn new(port: u16) -> Self {
+        Sentinel {
+            id: generate_id(),
+            current_epoch: 0,
+            masters: HashMap::new(),
+            port,
+            links: Vec::new(),
+            events: Vec::new(),
+            config_changed: false,
+        }
+    }
+
+    pub fn monitor(&mut self, name: String, ip: String, port: u16, quorum: usize) -> Result<(), &'static str> {
+        if self.masters.contains_key(&name) {
+            return Err("Duplicated master name");
+        }
+
+        if quorum == 0 {
+            return Err("Quorum must be 1 or greater.");
+        }
+
+        self.masters.insert(name.clone(), Master::new(name.clone(), ip, port, quorum));
+        self.config_changed = true;
+        self.event("+monitor", &InstanceRef::Master(name), &format!("quorum {}", quorum));
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        if !self.masters.contains_key(name) {
+            return false;
+        }
+
+        self.event("-monitor", &InstanceRef::Master(name.into()), "");
+        self.close_links(|owner| owner.master() == name);
+        self.masters.remove(name);
+        self.config_changed = true;
+
+        true
+    }
+
+    /// Change one of a monitored primary's options, as SENTINEL SET does.
+    pub fn set_option(&mut self, name: &str, option: &str, value: &str) -> Result<(), String> {
+        let Some(master) = self.masters.get_mut(name) else {
+            return Err("No such master with that name".into());
+        };
+
+        let value = value.parse::<u64>().ok().filter(|v| *v > 0);
+
+        match (option.to_ascii_lowercase().as_str(), value) {
+            ("down-after-milliseconds", Some(v)) => master.down_after = Duration::from_millis(v),
+            ("failover-timeout", Some(v)) => master.failover_timeout = Duration::from_millis(v),
+            ("quorum", Some(v)) => master.quorum = v as usize,
+            (option @ ("down-after-milliseconds" | "failover-timeout" | "quorum"), None) => {
+                return Err(format!("Invalid argument for SENTINEL SET '{}'", option));
+            },
+            (option, _) => return Err(format!("Invalid argument '{}' to SENTINEL SET", option)),
+        }
+
+        self.config_changed = true;
+
+        Ok(())
+    }
+
+    /// Apply a `sentinel` directive from the configuration file, like
+    /// `sentinel monitor <name> <ip> <port> <quorum>` or `sentinel <option> <name> <value>`.
+    pub fn apply_directive(&mut self, args: &[String]) -> Result<(), String> {
+        let invalid = || format!("Invalid sentinel directive: sentinel {}", args.join(" "));
+
+        match args {
+            [myid, id] if myid.eq_ignore_ascii_case("myid") => {
+                if id.len() != self.id.len() {
+                    return Err(invalid());
+                }
+
+                self.id = id.clone();
+                Ok(())
+            },
+            [current_epoch, epoch] if current_epoch.eq_ignore_ascii_case("current-epoch") => {
+                self.current_epoch = epoch.parse().map_err(|_| invalid())?;
+                Ok(())
+            },
+            [option, name, epoch] if option.eq_ignore_ascii_case("config-epoch") || option.eq_ignore_ascii_case("leader-epoch") => {
+                let epoch = epoch.parse().map_err(|_| invalid())?;
+                let master = self.masters.get_mut(name).ok_or("No such master with that name")?;
+
+                match option.eq_ignore_ascii_case("config-epoch") {
+                    true => master.config_epoch = epoch,
+                    false => master.leader_epoch = epoch,
+                }
+
+                Ok(())
+            },
+            [monitor, name, ip, port, quorum] if monitor.eq_ignore_ascii_case("monitor") => {
+                let (Ok(port), Ok(quorum)) = (port.parse::<u16>(), quorum.parse::<usize>()) else {
+                    return Err(invalid());
+                };
+
+                Ok(self.monitor(name.clone(), ip.clone(), port, quorum)?)
+            },
+            [option, name, value] => self.set_option(name, option, value),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The `sentinel` directives which restore our current state, for writing to the config
+    /// file: the primaries with their current addresses and options, and the epochs, so that
+    /// after a restart we neither monitor a demoted primary nor vote twice in an epoch.
+    pub fn directives(&self) -> Vec<Vec<String>> {
+        let mut directives = Vec::new();
+        let mut masters: Vec<_> = self.masters.values().collect();
+        masters.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for master in masters {
+            let name = master.name.clone();
+
+            directives.push(vec!["monitor".into(), name.clone(), master.instance.ip.clone(), master.instance.port.to_string(), master.quorum.to_string()]);
+
+            if master.down_after != DEFAULT_DOWN_AFTER {
+                directives.push(vec!["down-after-milliseconds".into(), name.clone(), master.down_after.as_millis().to_string()]);
+            }
+
+            if master.failover_timeout != DEFAULT_FAILOVER_TIMEOUT {
+                directives.push(vec!["failover-timeout".into(), name.clone(), master.failover_timeout.as_millis().to_string()]);
+            }
+
+            if master.config_epoch > 0 {
+                directives.push(vec!["config-epoch".into(), name.clone(), master.config_epoch.to_string()]);
+            }
+
+            if master.leader_epoch > 0 {
+                directives.push(vec!["leader-epoch".into(), name, master.leader_epoch.to_string()]);
+            }
+        }
+
+        directives.push(vec!["current-epoch".into(), self.current_epoch.to_string()]);
+        directives.push(vec!["myid".into(), self.id.clone()]);
+
+        directives
+    }
+
+    /// Whether our state has changed since the last call, and needs writing to the config file.
+    pub fn take_config_changed(&mut self) -> bool {
+        std::mem::take(&mut self.config_changed)
+    }
+
+    /// Take the events which have happened since the last call, for publishing.
+    pub fn take_events(&mut self) -> Vec<(Bytes, Bytes)> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn instance(&self, r: &InstanceRef) -> Option<&Instance> {
+        let master = self.masters.get(r.master())?;
+
+        match r {
+            InstanceRef::Master(_) => Some(&master.instance),
+            InstanceRef::Replica(_, address) => master.replicas.get(address),
+            InstanceRef::Sentinel(_, id) => master.sentinels.get(id),
+        }
+    }
+
+    fn instance_mut(&mut self, r: &InstanceRef) -> Option<&mut Instance> {
+        let master = self.masters.get_mut(r.master())?;
+
+        match r {
+            InstanceRef::Master(_) => Some(&mut master.instance),
+            InstanceRef::Replica(_, address) => master.replicas.get_mut(address),
+            InstanceRef::Sentinel(_, id) => master.sentinels.get_mut(id),
+        }
+    }
+
+    /// Record an event about an instance, in the same format as Redis Sentinel.
+    fn event(&mut self, kind: &str, r: &InstanceRef, detail: &str) {
+        let Some(master) = self.masters.get(r.master()) else {
+            return;
+        };
+
+        let description = match r {
+            InstanceRef::Master(name) => format!("master {} {} {}", name, master.instance.ip, master.instance.port),
+            InstanceRef::Replica(name, address) | InstanceRef::Sentinel(name, address) => {
+                let Some(instance) = self.instance(r) else {
+                    return;
+                };
+
+                let kind = if matches!(r, InstanceRef::Replica(..)) { "slave" } else { "sentinel" };
+
+                format!("{} {} {} {} @ {} {} {}", kind, address, instance.ip, instance.port, name, master.instance.ip, master.instance.port)
+            },
+        };
+
+        let message = match detail.is_empty() {
+            true => description,
+            false => format!("{} {}", description, detail),
+        };
+
+        info!("{} {}", kind, message);
+
+        self.events.push((Bytes::copy_from_slice(kind.as_bytes()), message.into()));
+    }
+
+    fn connect(&mut self, r: &InstanceRef, pubsub: bool, registry: &Registry) -> io::Result<usize> {
+        let instance = self.instance(r).unwrap();
+        let Some(address) = (instance.ip.as_str(), instance.port).to_socket_addrs()?.next() else {
+            return Err(io::Error::new(ErrorKind::NotFound, "could not resolve the instance's address"));
+        };
+
+        let mut stream = TcpStream::connect(address)?;
+        let index = self.links.len();
+        registry.register(&mut stream, Token(FIRST_LINK + index), Interest::READABLE | Interest::WRITABLE)?;
+
+        self.links.push(Some(Link {
+            stream,
+            connected: false,
+            input: QueryBuffer::new(),
+            output: Vec::new(),
+            pending: VecDeque::new(),
+            owner: r.clone(),
+            pubsub,
+        }));
+
+        if pubsub {
+            self.send(index, &[b"SUBSCRIBE", HELLO_CHANNEL.as_bytes()], Request::Other);
+        }
+
+        Ok(index)
+    }
+
+    /// Send a command on a link. The command is queued until the link has connected.
+    fn send(&mut self, index: usize, args: &[&[u8]], request: Request) {
+        let Some(link) = self.links[index].as_mut() else {
+            return;
+        };
+
+        let command = RESPType::Array(args.iter().map(|a| RESPType::BulkString(Bytes::copy_from_slice(a))).collect());
+        serialize(&command, &mut link.output).unwrap();
+
+        if !link.pubsub {
+            link.pending.push_back(request);
+        }
+
+        if let Err(e) = link.flush() {
+            warn!("Error on the link to {:?}: {}", link.owner, e);
+            self.close_link(index);
+        }
+    }
+
+    fn close_link(&mut self, index: usize) {
+        let Some(link) = self.links[index].take() else {
+            return;
+        };
+
+        if let Some(instance) = self.instance_mut(&link.owner) {
+            if link.pubsub {
+                instance.pubsub = None;
+            } else {
+                instance.link = None;
+                instance.ping_sent = None;
+            }
+
+            instance.retry_at = Instant::now() + RECONNECT_INTERVAL;
+        }
+    }
+
+    fn close_links<F: Fn(&InstanceRef) -> bool>(&mut self, f: F) {
+        for i in 0..self.links.len() {
+            if self.links[i].as_ref().is_some_and(|l| f(&l.owner)) {
+                self.close_link(i);
+            }
+        }
+    }
+
+    pub fn link_event(&mut self, token: Token, readable: bool, writable: bool) {
+        let index = token.0 - FIRST_LINK;
+
+        let Some(Some(link)) = self.links.get_mut(index) else {
+            return;
+        };
+
+        let mut result = Ok(true);
+
+        if writable {
+            result = link.writable().map(|_| true);
+        }
+
+        if readable && result.is_ok() {
+            result = link.read();
+        }
+
+        match result {
+            Ok(true) => {},
+            Ok(false) => return self.close_link(index),
+            Err(e) => {
+                warn!("Error on the link to {:?}: {}", link.owner, e);
+                return self.close_link(index);
+            },
+        }
+
+        loop {
+            let Some(Some(link)) = self.links.get_mut(index) else {
+                return;
+            };
+
+            let Some((reply, _)) = link.input.next_value() else {
+                return;
+            };
+
+            if link.pubsub {
+                if let RESPType::Array(mut message) = reply {
+                    if message.len() == 3 && matches!(&message[0], RESPType::BulkString(k) if &k[..] == b"message") {
+                        if let RESPType::BulkString(payload) = message.remove(2) {
+                            self.process_hello(&payload);
+                        }
+                    }
+                }
+
+                continue;
+            }
+
+            let Some(request) = link.pending.pop_front() else {
+                continue;
+            };
+
+            let owner = link.owner.clone();
+
+            self.process_reply(&owner, request, reply);
+        }
+    }
+
+    fn process_reply(&mut self, r: &InstanceRef, request: Request, reply: RESPType<Bytes>) {
+        match request {
+            Request::Ping => {
+                let Some(instance) = self.instance_mut(r) else {
+                    return;
+                };
+
+                // An instance which is loading or has lost its primary is still alive.
+                let valid = match &reply {
+                    RESPType::SimpleString(s) => &s[..] == b"PONG",
+                    RESPType::Error(e) => e.starts_with(b"LOADING") || e.starts_with(b"MASTERDOWN"),
+                    _ => false,
+                };
+
+                if valid {
+                    instance.last_ok_ping = Instant::now();
+                    instance.ping_sent = None;
+                }
+            },
+            Request::Role => self.process_role(r, reply),
+            Request::IsMasterDown => {
+                let RESPType::Array(reply) = reply else {
+                    return;
+                };
+
+                let [RESPType::Integer(down), RESPType::BulkString(leader), RESPType::Integer(epoch)] = &reply[..] else {
+                    return;
+                };
+
+                let Some(instance) = self.instance_mut(r) else {
+                    return;
+                };
+
+                instance.master_down = *down == 1;
+
+                if &leader[..] != b"*" {
+                    instance.leader = Some(String::from_utf8_lossy(leader).into_owned());
+                    instance.leader_epoch = *epoch as u64;
+                }
+            },
+            Request::Other => {
+                if let RESPType::Error(e) = reply {
+                    warn!("{:?} replied with an error: {}", r, String::from_utf8_lossy(&e));
+                }
+            },
+        }
+    }
+
+    fn process_role(&mut self, r: &InstanceRef, reply: RESPType<Bytes>) {
+        let RESPType::Array(reply) = reply else {
+            return;
+        };
+
+        let string = |v: &RESPType<Bytes>| match v {
+            RESPType::BulkString(s) => Some(String::from_utf8_lossy(s).into_owned()),
+            _ => None,
+        };
+
+        let (role, offset, replicas) = match &reply[..] {
+            [kind, RESPType::Integer(offset), RESPType::Array(replicas)] if string(kind).as_deref() == Some("master") => {
+                let replicas: Vec<(String, u16)> = replicas.iter().filter_map(|r| match r {
+                    RESPType::Array(r) if r.len() >= 2 => Some((string(&r[0])?, string(&r[1])?.parse().ok()?)),
+                    _ => None,
+                }).collect();
+
+                (ReportedRole::Master, *offset, replicas)
+            },
+            [kind, host, RESPType::Integer(port), state, RESPType::Integer(offset)] if string(kind).as_deref() == Some("slave") => {
+                let role = ReportedRole::Replica {
+                    host: string(host).unwrap_or_default(),
+                    port: *port as u16,
+                    link_up: string(state).as_deref() == Some("connected"),
+                };
+
+                (role, *offset, vec![])
+            },
+            _ => return,
+        };
+
+        let Some(instance) = self.instance_mut(r) else {
+            return;
+        };
+
+        if instance.role != role {
+            instance.role_changed = Instant::now();
+        }
+
+        instance.role = role.clone();
+        instance.offset = offset as u64;
+
+        let name = r.master().to_string();
+
+        // Replicas are discovered by asking the primary about them.
+        if matches!(r, InstanceRef::Master(_)) {
+            for (ip, port) in replicas {
+                let address = format!("{}:{}", ip, port);
+                let master = self.masters.get_mut(&name).unwrap();
+
+                if !master.replicas.contains_key(&address) {
+                    master.replicas.insert(address.clone(), Instance::new(ip, port));
+                    self.event("+slave", &InstanceRef::Replica(name.clone(), address), "");
+                }
+            }
+
+            return;
+        }
+
+        let InstanceRef::Replica(_, address) = r else {
+            return;
+        };
+
+        let master = self.masters.get_mut(&name).unwrap();
+
+        if let Some(failover) = &mut master.failover {
+            if failover.state == FailoverState::WaitPromotion && failover.promoted.as_ref() == Some(address) && role == ReportedRole::Master {
+                failover.state = FailoverState::ReconfigureReplicas;
+                master.config_epoch = failover.epoch;
+                self.config_changed = true;
+
+                self.event("+promoted-slave", r, "");
+                self.event("+failover-state-reconf-slaves", &InstanceRef::Master(name), "");
+            }
+
+            return;
+        }
+
+        self.check_replica_config(r);
+    }
+
+    /// Point a replica back at the primary if it reports the wrong role or primary, once it has
+    /// done so for long enough that it cannot be part of a failover we have not heard about.
+    fn check_replica_config(&mut self, r: &InstanceRef) {
+        let master = &self.masters[r.master()];
+
+        if master.instance.sdown {
+            return;
+        }
+
+        let (ip, port) = (master.instance.ip.clone(), master.instance.port);
+        let instance = self.instance(r).unwrap();
+
+        let kind = match &instance.role {
+            ReportedRole::Master => "+convert-to-slave",
+            ReportedRole::Replica { host, port: p, .. } if *host != ip || *p != port => "+fix-slave-config",
+            _ => return,
+        };
+
+        let Some(link) = instance.link else {
+            return;
+        };
+
+        if instance.role_changed.elapsed() < RECONFIGURE_DELAY || instance.reconfigured.is_some_and(|t| t.elapsed() < RECONFIGURE_DELAY) {
+            return;
+        }
+
+        self.instance_mut(r).unwrap().reconfigured = Some(Instant::now());
+        self.send(link, &[b"REPLICAOF", ip.as_bytes(), port.to_string().as_bytes()], Request::Other);
+        self.event(kind, r, "");
+    }
+
+    fn process_hello(&mut self, payload: &[u8]) {
+        let Some(hello) = Hello::parse(payload) else {
+            return;
+        };
+
+        if hello.id == self.id {
+            return;
+        }
+
+        if hello.current_epoch > self.current_epoch {
+            self.current_epoch = hello.current_epoch;
+            self.config_changed = true;
+        }
+
+        let Some(master) = self.masters.get_mut(&hello.master) else {
+            return;
+        };
+
+        let r = InstanceRef::Sentinel(hello.master.clone(), hello.id.clone());
+
+        match master.sentinels.get_mut(&hello.id) {
+            Some(sentinel) => {
+                sentinel.ip = hello.ip;
+                sentinel.port = hello.port;
+            },
+            None => {
+                // A sentinel at the same address has restarted with a new ID.
+                let address = format!("{}:{}", hello.ip, hello.port);
+
+                if let Some(old) = master.sentinels.iter().find(|(_, s)| s.address() == address).map(|(id, _)| id.clone()) {
+                    self.close_links(|owner| *owner == InstanceRef::Sentinel(hello.master.clone(), old.clone()));
+                    self.masters.get_mut(&hello.master).unwrap().sentinels.remove(&old);
+                }
+
+                self.masters.get_mut(&hello.master).unwrap().sentinels.insert(hello.id.clone(), Instance::new(hello.ip, hello.port));
+                self.event("+sentinel", &r, "");
+            },
+        }
+
+        let master = &self.masters[&hello.master];
+
+        if hello.config_epoch > master.config_epoch {
+            let moved = master.instance.ip != hello.master_ip || master.instance.port != hello.master_port;
+
+            self.masters.get_mut(&hello.master).unwrap().config_epoch = hello.config_epoch;
+            self.config_changed = true;
+
+            if moved {
+                self.switch_master(&hello.master, hello.master_ip, hello.master_port);
+            }
+        }
+    }
+
+    /// Start monitoring a new primary in place of the old one, which becomes one of its
+    /// replicas.
+    fn switch_master(&mut self, name: &str, ip: String, port: u16) {
+        self.event("+switch-master", &InstanceRef::Master(name.into()), &format!("{} {}", ip, port));
+
+        let master = self.masters.get_mut(name).unwrap();
+        let old_address = master.instance.address();
+        let new_address = format!("{}:{}", ip, port);
+
+        let promoted = master.replicas.remove(&new_address).unwrap_or_else(|| Instance::new(ip, port));
+        let old = std::mem::replace(&mut master.instance, promoted);
+
+        master.replicas.insert(old_address.clone(), old);
+        master.odown = false;
+        master.failover = None;
+        self.config_changed = true;
+
+        for sentinel in master.sentinels.values_mut() {
+            sentinel.master_down = false;
+        }
+
+        // The links are kept rather than reconnected, as closing them could lose commands we
+        // have just sent to the replicas.
+        for link in self.links.iter_mut().flatten() {
+            if link.owner == InstanceRef::Master(name.into()) {
+                link.owner = InstanceRef::Replica(name.into(), old_address.clone());
+            } else if link.owner == InstanceRef::Replica(name.into(), new_address.clone()) {
+                link.owner = InstanceRef::Master(name.into());
+            }
+        }
+    }
+
+    /// Answer another sentinel asking whether we think a primary is down. If it gives its own
+    /// ID it is also asking for our vote to lead the failover, which we give to the first
+    /// sentinel to ask in each epoch. Returns whether the primary is down, and who we voted for
+    /// in which epoch.
+    pub fn is_master_down_by_addr(&mut self, ip: &str, port: u16, epoch: u64, id: &str) -> (bool, String, u64) {
+        let Some(master) = self.masters.values_mut().find(|m| m.instance.ip == ip && m.instance.port == port) else {
+            return (false, "*".into(), 0);
+        };
+
+        let down = master.instance.sdown;
+
+        if id != "*" && master.leader_epoch < epoch && self.current_epoch <= epoch {
+            master.leader = Some(id.into());
+            master.leader_epoch = epoch;
+            self.current_epoch = epoch;
+            self.config_changed = true;
+
+            // Give the sentinel we voted for time to finish before starting a failover
+            // ourselves.
+            if id != self.id {
+                master.last_failover = Some(Instant::now());
+            }
+
+            info!("Voted for {} in epoch {} to fail over {}.", id, epoch, master.name);
+        }
+
+        let master = self.masters.values().find(|m| m.instance.ip == ip && m.instance.port == port).unwrap();
+
+        (down, master.leader.clone().unwrap_or_else(|| "*".into()), master.leader_epoch)
+    }
+
+    /// Start a failover without asking the other sentinels.
+    pub fn force_failover(&mut self, name: &str) -> Result<(), &'static str> {
+        let Some(master) = self.masters.get(name) else {
+            return Err("No such master with that name");
+        };
+
+        if master.failover.is_some() {
+            return Err("INPROG Failover already in progress");
+        }
+
+        if !master.replicas.values().any(Self::is_candidate) {
+            return Err("NOGOODSLAVE No suitable replica to promote");
+        }
+
+        self.start_failover(name, true);
+
+        Ok(())
+    }
+
+    fn start_failover(&mut self, name: &str, forced: bool) {
+        self.current_epoch += 1;
+
+        let epoch = self.current_epoch;
+        let id = self.id.clone();
+        let master = self.masters.get_mut(name).unwrap();
+
+        master.failover = Some(Failover {
+            epoch,
+            state: if forced { FailoverState::SelectReplica } else { FailoverState::WaitStart },
+            started: Instant::now(),
+            promoted: None,
+            forced,
+        });
+
+        master.last_failover = Some(Instant::now());
+        master.leader = Some(id);
+        master.leader_epoch = epoch;
+        self.config_changed = true;
+
+        // Ask for votes straight away.
+        master.last_ask = Instant::now() - ASK_INTERVAL;
+
+        let r = InstanceRef::Master(name.into());
+        self.event("+new-epoch", &r, &epoch.to_string());
+        self.event("+try-failover", &r, "");
+    }
+
+    fn abort_failover(&mut self, name: &str, reason: &str) {
+        self.masters.get_mut(name).unwrap().failover = None;
+        self.event(reason, &InstanceRef::Master(name.into()), "");
+    }
+
+    fn is_candidate(replica: &Instance) -> bool {
+        !replica.sdown && replica.link.is_some() && matches!(replica.role, ReportedRole::Replica { .. })
+    }
+
+    /// Choose the replica to promote, and the link to it. The replica which has processed the
+    /// most of the replication stream is preferred, then the lowest address.
+    fn select_replica(master: &Master) -> Option<(String, usize)> {
+        master.replicas.iter()
+            .filter(|(_, r)| Self::is_candidate(r))
+            .max_by(|(a, x), (b, y)| x.offset.cmp(&y.offset).then_with(|| b.cmp(a)))
+            .map(|(address, r)| (address.clone(), r.link.unwrap()))
+    }
+
+    /// Connect to the instances we have no link to, send the pings, role requests and hellos
+    /// which are due, and move any failover along.
+    pub fn cron(&mut self, registry: &Registry) {
+        let names: Vec<String> = self.masters.keys().cloned().collect();
+
+        for name in names {
+            let master = &self.masters[&name];
+
+            let mut instances = vec![InstanceRef::Master(name.clone())];
+            instances.extend(master.replicas.keys().map(|a| InstanceRef::Replica(name.clone(), a.clone())));
+            instances.extend(master.sentinels.keys().map(|id| InstanceRef::Sentinel(name.clone(), id.clone())));
+
+            for r in instances {
+                self.instance_cron(&r, registry);
+            }
+
+            self.ask_sentinels(&name);
+            self.check_odown(&name);
+            self.failover_cron(&name);
+        }
+    }
+
+    fn instance_cron(&mut self, r: &InstanceRef, registry: &Registry) {
+        let now = Instant::now();
+        let master = &self.masters[r.master()];
+        let down_after = master.down_after;
+        let role_interval = if master.instance.sdown || master.failover.is_some() { FAST_ROLE_INTERVAL } else { ROLE_INTERVAL };
+        let is_sentinel = matches!(r, InstanceRef::Sentinel(..));
+
+        let instance = self.instance(r).unwrap();
+
+        if instance.link.is_none() && instance.retry_at <= now {
+            match self.connect(r, false, registry) {
+                Ok(link) => self.instance_mut(r).unwrap().link = Some(link),
+                Err(e) => {
+                    warn!("Could not connect to {:?}: {}", r, e);
+                    self.instance_mut(r).unwrap().retry_at = now + RECONNECT_INTERVAL;
+                },
+            }
+        }
+
+        let instance = self.instance(r).unwrap();
+
+        // Sentinels announce themselves on the monitored instances, not to each other.
+        if !is_sentinel && instance.pubsub.is_none() && instance.retry_at <= now {
+            if let Ok(link) = self.connect(r, true, registry) {
+                self.instance_mut(r).unwrap().pubsub = Some(link);
+            }
+        }
+
+        let instance = self.instance(r).unwrap();
+
+        if let Some(link) = instance.link {
+            if instance.ping_sent.is_none() && instance.last_ping.elapsed() >= PING_INTERVAL {
+                let instance = self.instance_mut(r).unwrap();
+                instance.ping_sent = Some(now);
+                instance.last_ping = now;
+
+                self.send(link, &[b"PING"], Request::Ping);
+            }
+
+            let instance = self.instance(r).unwrap();
+
+            if !is_sentinel && instance.last_role.is_none_or(|t| t.elapsed() >= role_interval) {
+                self.instance_mut(r).unwrap().last_role = Some(now);
+                self.send(link, &[b"ROLE"], Request::Role);
+            }
+
+            let instance = self.instance(r).unwrap();
+
+            if !is_sentinel && instance.last_hello.elapsed() >= HELLO_INTERVAL {
+                self.instance_mut(r).unwrap().last_hello = now;
+                self.send_hello(r, link);
+            }
+        }
+
+        let Some(instance) = self.instance_mut(r) else {
+            return;
+        };
+
+        let down = instance.last_ok_ping.elapsed() > down_after;
+
+        if down != instance.sdown {
+            instance.sdown = down;
+            self.event(if down { "+sdown" } else { "-sdown" }, r, "");
+        }
+    }
+
+    fn send_hello(&mut self, r: &InstanceRef, link: usize) {
+        let master = &self.masters[r.master()];
+
+        // Other sentinels reach us at the address the instance sees us connecting from.
+        let Some(ip) = self.links[link].as_ref().and_then(|l| l.stream.local_addr().ok()).map(|a| a.ip()) else {
+            return;
+        };
+
+        let hello = format!(
+            "{},{},{},{},{},{},{},{}",
+            ip, self.port, self.id, self.current_epoch,
+            master.name, master.instance.ip, master.instance.port, master.config_epoch,
+        );
+
+        self.send(link, &[b"PUBLISH", HELLO_CHANNEL.as_bytes(), hello.as_bytes()], Request::Other);
+    }
+
+    /// Ask the other sentinels whether they agree the primary is down, and for their votes if
+    /// we are waiting to be elected to fail it over.
+    fn ask_sentinels(&mut self, name: &str) {
+        let master = self.masters.get_mut(name).unwrap();
+
+        if !master.instance.sdown {
+            for sentinel in master.sentinels.values_mut() {
+                sentinel.master_down = false;
+            }
+
+            return;
+        }
+
+        if master.last_ask.elapsed() < ASK_INTERVAL {
+            return;
+        }
+
+        master.last_ask = Instant::now();
+
+        let id = match &master.failover {
+            Some(f) if f.state == FailoverState::WaitStart => self.id.clone(),
+            _ => "*".into(),
+        };
+
+        let args = [
+            b"SENTINEL".to_vec(),
+            b"is-master-down-by-addr".to_vec(),
+            master.instance.ip.clone().into_bytes(),
+            master.instance.port.to_string().into_bytes(),
+            self.current_epoch.to_string().into_bytes(),
+            id.into_bytes(),
+        ];
+
+        let links: Vec<usize> = master.sentinels.values().filter_map(|s| s.link).collect();
+
+        for link in links {
+            self.send(link, &args.iter().map(|a| &a[..]).collect::<Vec<_>>(), Request::IsMasterDown);
+        }
+    }
+
+    fn check_odown(&mut self, name: &str) {
+        let master = &self.masters[name];
+
+        let agreeing = master.sentinels.values().filter(|s| s.master_down).count() + 1;
+        let odown = master.instance.sdown && agreeing >= master.quorum;
+
+        if odown != master.odown {
+            let detail = format!("#quorum {}/{}", agreeing, master.quorum);
+
+            self.masters.get_mut(name).unwrap().odown = odown;
+            self.event(if odown { "+odown" } else { "-odown" }, &InstanceRef::Master(name.into()), &detail);
+        }
+    }
+
+    fn failover_cron(&mut self, name: &str) {
+        let master = &self.masters[name];
+
+        let Some(failover) = &master.failover else {
+            let retry_after = master.failover_timeout * 2;
+
+            if master.odown && master.last_failover.is_none_or(|t| t.elapsed() > retry_after) {
+                self.start_failover(name, false);
+            }
+
+            return;
+        };
+
+        let r = InstanceRef::Master(name.into());
+        let timed_out = failover.started.elapsed() > master.failover_timeout;
+
+        // The primary has come back before a replica was promoted, so there is nothing to fail
+        // over from any more, unless the failover was forced.
+        let electing = matches!(failover.state, FailoverState::WaitStart | FailoverState::SelectReplica);
+
+        if electing && !failover.forced && !master.odown {
+            return self.abort_failover(name, "-failover-abort-not-elected");
+        }
+
+        match failover.state {
+            FailoverState::WaitStart => {
+                let votes = 1 + master.sentinels.values()
+                    .filter(|s| s.leader.as_ref() == Some(&self.id) && s.leader_epoch == failover.epoch)
+                    .count();
+
+                // A majority of all the sentinels, including us, has to agree.
+                let voters = master.sentinels.len() + 1;
+                let needed = master.quorum.max(voters / 2 + 1);
+
+                if votes >= needed || failover.forced {
+                    self.masters.get_mut(name).unwrap().failover.as_mut().unwrap().state = FailoverState::SelectReplica;
+                    self.event("+elected-leader", &r, "");
+                } else if timed_out {
+                    self.abort_failover(name, "-failover-abort-not-elected");
+                }
+            },
+            FailoverState::SelectReplica => {
+                let Some((address, link)) = Self::select_replica(master) else {
+                    return self.abort_failover(name, "-failover-abort-no-good-slave");
+                };
+
+                let failover = self.masters.get_mut(name).unwrap().failover.as_mut().unwrap();
+                failover.state = FailoverState::WaitPromotion;
+                failover.promoted = Some(address.clone());
+
+                let replica = InstanceRef::Replica(name.into(), address);
+
+                self.event("+selected-slave", &replica, "");
+                self.send(link, &[b"REPLICAOF", b"NO", b"ONE"], Request::Other);
+                self.event("+failover-state-send-slaveof-noone", &replica, "");
+            },
+            FailoverState::WaitPromotion => {
+                if timed_out {
+                    self.abort_failover(name, "-failover-abort-slave-timeout");
+                }
+            },
+            FailoverState::ReconfigureReplicas => {
+                let promoted = failover.promoted.clone().unwrap();
+                let new_master = &master.replicas[&promoted];
+                let (ip, port) = (new_master.ip.clone(), new_master.port);
+
+                let others: Vec<(String, usize)> = master.replicas.iter()
+                    .filter(|(address, _)| **address != promoted)
+                    .filter_map(|(address, r)| Some((address.clone(), r.link?)))
+                    .collect();
+
+                for (address, link) in others {
+                    self.send(link, &[b"REPLICAOF", ip.as_bytes(), port.to_string().as_bytes()], Request::Other);
+                    self.event("+slave-reconf-sent", &InstanceRef::Replica(name.into(), address), "");
+                }
+
+                self.event("+failover-end", &r, "");
+                self.switch_master(name, ip, port);
+            },
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::sentinel::{FailoverState, Hello, Instance, ReportedRole, Sentinel};
+
+    #[test]
+    fn test_parse_hello() {
+        let hello = Hello::parse(b"127.0.0.1,26379,abc,3,mymaster,127.0.0.1,6379,2").unwrap();
+
+        assert_eq!((hello.ip.as_str(), hello.port, hello.id.as_str()), ("127.0.0.1", 26379, "abc"));
+        assert_eq!((hello.current_epoch, hello.master.as_str(), hello.master_port, hello.config_epoch), (3, "mymaster", 6379, 2));
+        assert!(Hello::parse(b"127.0.0.1,26379,abc").is_none());
+    }
+
+    #[test]
+    fn test_vote_once_per_epoch() {
+        let mut sentinel = Sentinel::new(26379);
+        sentinel.monitor("mymaster".into(), "127.0.0.1".into(), 6379, 2).unwrap();
+
+        assert_eq!(sentinel.is_master_down_by_addr("127.0.0.1", 6379, 1, "a"), (false, "a".into(), 1));
+        assert_eq!(sentinel.is_master_down_by_addr("127.0.0.1", 6379, 1, "b"), (false, "a".into(), 1));
+        assert_eq!(sentinel.is_master_down_by_addr("127.0.0.1", 6379, 2, "b"), (false, "b".into(), 2));
+        assert_eq!(sentinel.is_master_down_by_addr("127.0.0.1", 6379, 2, "*"), (false, "b".into(), 2));
+    }
+
+    /// A sentinel monitoring one primary, with other sentinels by ID.
+    fn sentinel_with(quorum: usize, others: &[&str]) -> Sentinel {
+        let mut sentinel = Sentinel::new(26379);
+        sentinel.monitor("mymaster".into(), "127.0.0.1".into(), 6379, quorum).unwrap();
+
+        let master = sentinel.masters.get_mut("mymaster").unwrap();
+
+        for (i, id) in others.iter().enumerate() {
+            master.sentinels.insert(id.to_string(), Instance::new("127.0.0.1".into(), 26380 + i as u16));
+        }
+
+        sentinel
+    }
+
+    #[test]
+    fn test_odown_quorum() {
+        let mut sentinel = sentinel_with(3, &["a", "b", "c"]);
+        let master = sentinel.masters.get_mut("mymaster").unwrap();
+        master.instance.sdown = true;
+        master.sentinels.get_mut("a").unwrap().master_down = true;
+
+        // We and one other agree, which is short of the quorum of 3.
+        sentinel.check_odown("mymaster");
+        assert!(!sentinel.masters["mymaster"].odown);
+
+        sentinel.masters.get_mut("mymaster").unwrap().sentinels.get_mut("b").unwrap().master_down = true;
+        sentinel.check_odown("mymaster");
+        assert!(sentinel.masters["mymaster"].odown);
+
+        // Others agreeing is not enough if we can reach the primary ourselves.
+        sentinel.masters.get_mut("mymaster").unwrap().instance.sdown = false;
+        sentinel.check_odown("mymaster");
+        assert!(!sentinel.masters["mymaster"].odown);
+    }
+
+    #[test]
+    fn test_election_needs_majority() {
+        // With a quorum of 2 out of 5 sentinels, a majority of 3 is still needed to lead.
+        let mut sentinel = sentinel_with(2, &["a", "b", "c", "d"]);
+        sentinel.masters.get_mut("mymaster").unwrap().odown = true;
+        sentinel.start_failover("mymaster", false);
+
+        let epoch = sentinel.current_epoch;
+        let id = sentinel.id.clone();
+        let vote = |sentinel: &mut Sentinel, other: &str| {
+            let instance = sentinel.masters.get_mut("mymaster").unwrap().sentinels.get_mut(other).unwrap();
+            instance.leader = Some(id.clone());
+            instance.leader_epoch = epoch;
+        };
+
+        vote(&mut sentinel, "a");
+        sentinel.failover_cron("mymaster");
+        assert_eq!(sentinel.masters["mymaster"].failover.as_ref().unwrap().state, FailoverState::WaitStart);
+
+        vote(&mut sentinel, "b");
+        sentinel.failover_cron("mymaster");
+        assert_eq!(sentinel.masters["mymaster"].failover.as_ref().unwrap().state, FailoverState::SelectReplica);
+    }
+
+    #[test]
+    fn test_election_needs_quorum() {
+        // With a quorum of 3 out of 3 sentinels, a majority of 2 is not enough.
+        let mut sentinel = sentinel_with(3, &["a", "b"]);
+        sentinel.masters.get_mut("mymaster").unwrap().odown = true;
+        sentinel.start_failover("mymaster", false);
+
+        let instance = sentinel.masters.get_mut("mymaster").unwrap().sentinels.get_mut("a").unwrap();
+        instance.leader = Some(sentinel.id.clone());
+        instance.leader_epoch = sentinel.current_epoch;
+
+        sentinel.failover_cron("mymaster");
+        assert_eq!(sentinel.masters["mymaster"].failover.as_ref().unwrap().state, FailoverState::WaitStart);
+    }
+
+    #[test]
+    fn test_abort_when_primary_returns() {
+        let mut sentinel = sentinel_with(1, &[]);
+        sentinel.masters.get_mut("mymaster").unwrap().odown = true;
+        sentinel.start_failover("mymaster", false);
+        sentinel.take_events();
+
+        sentinel.masters.get_mut("mymaster").unwrap().odown = false;
+        sentinel.failover_cron("mymaster");
+
+        assert!(sentinel.masters["mymaster"].failover.is_none());
+        assert_eq!(sentinel.take_events().last().unwrap().0, "-failover-abort-not-elected");
+    }
+
+    #[test]
+    fn test_select_replica() {
+        let mut sentinel = sentinel_with(1, &[]);
+        let master = sentinel.masters.get_mut("mymaster").unwrap();
+
+        for (port, offset, link, sdown) in [(6380, 100, Some(0), false), (6381, 200, Some(1), false), (6382, 200, Some(2), false), (6383, 300, Some(3), true), (6384, 400, None, false)] {
+            let mut replica = Instance::new("127.0.0.1".into(), port);
+            replica.role = ReportedRole::Replica { host: "127.0.0.1".into(), port: 6379, link_up: true };
+            replica.offset = offset;
+            replica.link = link;
+            replica.sdown = sdown;
+            master.replicas.insert(replica.address(), replica);
+        }
+
+        // Down and unreachable replicas are skipped, and ties go to the lowest address.
+        assert_eq!(Sentinel::select_replica(master), Some(("127.0.0.1:6381".into(), 1)));
+
+        master.replicas.clear();
+        assert_eq!(Sentinel::select_replica(master), None);
+    }
+
+    #[test]
+    fn test_directives() {
+        let mut sentinel = sentinel_with(2, &[]);
+        sentinel.set_option("mymaster", "failover-timeout", "5000").unwrap();
+        sentinel.current_epoch = 7;
+        sentinel.switch_master("mymaster", "127.0.0.1".into(), 6380);
+        assert!(sentinel.take_config_changed());
+
+        let mut restored = Sentinel::new(26379);
+
+        for directive in sentinel.directives() {
+            restored.apply_directive(&directive).unwrap();
+        }
+
+        let master = &restored.masters["mymaster"];
+        assert_eq!((master.instance.port, master.quorum, master.failover_timeout.as_millis()), (6380, 2, 5000));
+        assert_eq!((restored.current_epoch, &restored.id), (7, &sentinel.id));
+    }
+}
@@ -1,73 +1,411 @@
+use std::collections::VecDeque;
+use std::io::{Error, IoSlice, Write};
 
-use std::io::{Error, Write};
-
-use bytes::Bytes;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use sider_command::RESPType;
 
 
+/// Common replies, written as they are rather than formatted each time.
+const OK: &[u8] = b"+OK\r\n";
+const ZERO: &[u8] = b":0\r\n";
+const ONE: &[u8] = b":1\r\n";
+const NULL: &[u8] = b"$-1\r\n";
+
+/// Bulk strings at least this long are sent from the value itself, rather than being copied
+/// into the output buffer.
+const SHARED_BULK_LEN: usize = 16 * 1024;
+/// The most chunks passed to one vectored write.
+const MAX_IO_SLICES: usize = 64;
+
+
+/// Serialize a value to a writer. Replies to clients go through `Output` instead, which
+/// avoids the copy.
 pub fn serialize<O: Write>(v: &RESPType<Bytes>, output: &mut O) -> Result<(), Error> {
+    let mut buffer = BytesMut::new();
+    serialize_into(v, &mut buffer);
+    output.write_all(&buffer)
+}
+
+
+/// Serialize a value onto the end of a buffer.
+pub fn serialize_into(v: &RESPType<Bytes>, output: &mut BytesMut) {
     match v {
-        RESPType::SimpleString(s) => serialize_simple_string(s, output),
-        RESPType::Error(s) => serialize_error(s, output),
-        RESPType::Integer(n) => serialize_integer(n, output),
-        RESPType::BulkString(s) => serialize_bulk_string(s, output),
-        RESPType::Array(a) => serialize_array(a, output),
-        RESPType::Null => serialize_null(output),
+        RESPType::SimpleString(s) if s == &b"OK"[..] => output.put_slice(OK),
+        RESPType::SimpleString(s) => put_line(b'+', s, output),
+        RESPType::Error(s) => put_line(b'-', s, output),
+        RESPType::Integer(0) => output.put_slice(ZERO),
+        RESPType::Integer(1) => output.put_slice(ONE),
+        RESPType::Integer(n) => put_header(b':', *n, output),
+        RESPType::BulkString(s) => {
+            output.reserve(s.len() + 25);
+            put_header(b'$', s.len() as i64, output);
+            output.put_slice(s);
+            output.put_slice(b"\r\n");
+        },
+        RESPType::Array(a) => {
+            put_header(b'*', a.len() as i64, output);
+            a.iter().for_each(|v| serialize_into(v, output));
+        },
+        RESPType::Null => output.put_slice(NULL),
+        RESPType::Map(m) => {
+            put_header(b'%', m.len() as i64, output);
+
+            for (key, value) in m {
+                serialize_into(key, output);
+                serialize_into(value, output);
+            }
+        },
+        RESPType::Push(v) => {
+            put_header(b'>', v.len() as i64, output);
+            v.iter().for_each(|v| serialize_into(v, output));
+        },
     }
 }
 
-fn serialize_simple_string<O: Write>(v: &Bytes, output: &mut O) -> Result<(), Error> {
-    output.write_all(b"+")?;
-    output.write_all(v)?;
-    output.write_all(b"\r")?;
-    output.write_all(b"\n")
+
+fn put_line(prefix: u8, s: &[u8], output: &mut BytesMut) {
+    output.reserve(s.len() + 3);
+    output.put_u8(prefix);
+    output.put_slice(s);
+    output.put_slice(b"\r\n");
 }
 
 
-fn serialize_error<O: Write>(v: &Bytes, output: &mut O) -> Result<(), Error>  {
-    output.write_all(b"-")?;
-    output.write_all(v)?;
-    output.write_all(b"\r")?;
-    output.write_all(b"\n")
+/// Write a type byte followed by a number, like `*3\r\n` or `:42\r\n`. The header is built
+/// on the stack and copied in one go, since each write to the buffer checks its capacity.
+fn put_header(prefix: u8, n: i64, output: &mut BytesMut) {
+    // The prefix, a sign, 19 digits and the line ending.
+    let mut header = [0; 23];
+    let mut start = header.len() - 2;
+    let mut rest = n.unsigned_abs();
+
+    header[start..].copy_from_slice(b"\r\n");
+
+    loop {
+        start -= 1;
+        header[start] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+
+        if rest == 0 {
+            break;
+        }
+    }
+
+    if n < 0 {
+        start -= 1;
+        header[start] = b'-';
+    }
+
+    start -= 1;
+    header[start] = prefix;
+
+    output.put_slice(&header[start..]);
 }
 
 
-fn serialize_integer<O: Write>(v: &i64, output: &mut O) -> Result<(), Error> {
-    output.write_all(b":")?;
-    output.write_all(v.to_string().as_bytes())?;
-    output.write_all(b"\r")?;
-    output.write_all(b"\n")
-} 
+/// Output waiting to be sent on a connection. Small values are serialized into a buffer
+/// which is reused once it has been sent, and large bulk strings are queued as they are, so
+/// that a value read from the database is never copied.
+#[derive(Debug, Default)]
+pub struct Output {
+    /// Chunks which were queued before `buffer`, in order.
+    chunks: VecDeque<Bytes>,
+    buffer: BytesMut,
+}
+
+
+impl Output {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of bytes waiting to be sent.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|c| c.len()).sum::<usize>() + self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty() && self.buffer.is_empty()
+    }
+
+    /// The memory used by the output buffer.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn push(&mut self, v: &RESPType<Bytes>) {
+        match v {
+            RESPType::BulkString(s) if s.len() >= SHARED_BULK_LEN => {
+                put_header(b'$', s.len() as i64, &mut self.buffer);
+                self.chunks.push_back(self.buffer.split().freeze());
+                self.chunks.push_back(s.clone());
+                self.buffer.put_slice(b"\r\n");
+            },
+            RESPType::Array(a) if a.iter().any(is_shared) => {
+                put_header(b'*', a.len() as i64, &mut self.buffer);
+                a.iter().for_each(|v| self.push(v));
+            },
+            v => serialize_into(v, &mut self.buffer),
+        }
+    }
+
+    /// Write as much as the writer takes in one vectored write, returning the number of bytes
+    /// written.
+    pub fn write_to<W: Write>(&mut self, writer: &mut W) -> Result<usize, Error> {
+        let mut slices = Vec::with_capacity(MAX_IO_SLICES.min(self.chunks.len() + 1));
+        slices.extend(self.chunks.iter().take(MAX_IO_SLICES - 1).map(|c| IoSlice::new(c)));
 
+        if slices.len() == self.chunks.len() {
+            slices.push(IoSlice::new(&self.buffer));
+        }
+
+        let written = writer.write_vectored(&slices)?;
+        self.advance(written);
+
+        Ok(written)
+    }
 
-fn serialize_bulk_string<O: Write>(v: &Bytes, output: &mut O) -> Result<(), Error> {
-    output.write_all(b"$")?;
-    output.write_all(v.len().to_string().as_bytes())?;
-    output.write_all(b"\r")?;
-    output.write_all(b"\n")?;
+    fn advance(&mut self, mut n: usize) {
+        while let Some(chunk) = self.chunks.front_mut() {
+            if n < chunk.len() {
+                chunk.advance(n);
+                return;
+            }
 
-    output.write_all(v)?;
-    output.write_all(b"\r")?;
-    output.write_all(b"\n")
+            n -= chunk.len();
+            self.chunks.pop_front();
+        }
+
+        self.buffer.advance(n);
+    }
 }
 
 
-fn serialize_array<O: Write>(v: &[RESPType<Bytes>], output: &mut O) -> Result<(), Error> {
-    output.write_all(b"*")?;
-    output.write_all(v.len().to_string().as_bytes())?;
-    output.write_all(b"\r")?;
-    output.write_all(b"\n")?;
+/// Whether a value is queued as it is by `Output::push`, rather than being copied.
+fn is_shared(v: &RESPType<Bytes>) -> bool {
+    match v {
+        RESPType::BulkString(s) => s.len() >= SHARED_BULK_LEN,
+        RESPType::Array(a) => a.iter().any(is_shared),
+        _ => false,
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+
+    use sider_command::RESPType;
+    use crate::serializer::{serialize_into, Output};
+
+    fn serialized(v: &RESPType<Bytes>) -> BytesMut {
+        let mut output = BytesMut::new();
+        serialize_into(v, &mut output);
+        output
+    }
 
-    for value in v {
-        serialize(value, output)?;
+    #[test]
+    fn test_serialize() {
+        assert_eq!(serialized(&RESPType::SimpleString("OK".into())), &b"+OK\r\n"[..]);
+        assert_eq!(serialized(&RESPType::Integer(0)), &b":0\r\n"[..]);
+        assert_eq!(serialized(&RESPType::Integer(i64::MIN)), &b":-9223372036854775808\r\n"[..]);
+        assert_eq!(serialized(&RESPType::Null), &b"$-1\r\n"[..]);
+        assert_eq!(
+            serialized(&RESPType::Array(vec![RESPType::BulkString("hello".into()), RESPType::Error("ERR bad".into())])),
+            &b"*2\r\n$5\r\nhello\r\n-ERR bad\r\n"[..],
+        );
+        assert_eq!(
+            serialized(&RESPType::Map(vec![(RESPType::BulkString("proto".into()), RESPType::Integer(3))])),
+            &b"%1\r\n$5\r\nproto\r\n:3\r\n"[..],
+        );
     }
 
-    Ok(())
+    #[test]
+    fn test_output() {
+        let large = Bytes::from(vec![b'x'; 20000]);
+        let reply = RESPType::Array(vec![RESPType::Integer(42), RESPType::BulkString(large)]);
+
+        let mut output = Output::new();
+        output.push(&reply);
+        output.extend_from_slice(b"+PONG\r\n");
+        assert_eq!(output.len(), serialized(&reply).len() + 7);
+
+        // A writer which takes a few bytes at a time, as a busy socket does.
+        let mut written = Vec::new();
+
+        while !output.is_empty() {
+            let n = output.write_to(&mut Limited(&mut written, 7000)).unwrap();
+            assert!(n > 0);
+        }
+
+        let mut expected = serialized(&reply).to_vec();
+        expected.extend_from_slice(b"+PONG\r\n");
+        assert_eq!(written, expected);
+    }
+
+    /// Takes at most this many bytes per write.
+    struct Limited<'a, W>(&'a mut W, usize);
+
+    impl<W: std::io::Write> std::io::Write for Limited<'_, W> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(&buf[..buf.len().min(self.1)])
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.flush()
+        }
+    }
 }
 
-fn serialize_null<O: Write>(output: &mut O) -> Result<(), Error> {
-    output.write_all(b"$")?;
-    output.write_all(b"-1")?;
-    output.write_all(b"\r")?;
-    output.write_all(b"\n")
-}
\ No newline at end of file
+
+#[cfg(test)]
+mod benches {
+    extern crate test;
+
+    use std::io::Write;
+
+    use bytes::{Bytes, BytesMut};
+    use test::Bencher;
+
+    use sider_command::RESPType;
+    use crate::serializer::{serialize_into, Output};
+
+    /// The serializer replies went through before `Output`, kept as a baseline: every reply
+    /// was written into a new `Vec` piece by piece, formatting numbers through a `String`.
+    mod baseline {
+        use std::io::{Error, Write};
+
+        use bytes::Bytes;
+        use sider_command::RESPType;
+
+        pub fn serialize<O: Write>(v: &RESPType<Bytes>, output: &mut O) -> Result<(), Error> {
+            match v {
+                RESPType::SimpleString(s) => line(b"+", s, output),
+                RESPType::Error(s) => line(b"-", s, output),
+                RESPType::Integer(n) => line(b":", n.to_string().as_bytes(), output),
+                RESPType::BulkString(s) => {
+                    line(b"$", s.len().to_string().as_bytes(), output)?;
+                    line(b"", s, output)
+                },
+                RESPType::Array(a) | RESPType::Push(a) => {
+                    let prefix = if matches!(v, RESPType::Array(_)) { b"*" } else { b">" };
+                    line(prefix, a.len().to_string().as_bytes(), output)?;
+                    a.iter().try_for_each(|v| serialize(v, output))
+                },
+                RESPType::Null => line(b"$", b"-1", output),
+                RESPType::Map(m) => {
+                    line(b"%", m.len().to_string().as_bytes(), output)?;
+                    m.iter().try_for_each(|(k, v)| serialize(k, output).and_then(|_| serialize(v, output)))
+                },
+            }
+        }
+
+        fn line<O: Write>(prefix: &[u8], s: &[u8], output: &mut O) -> Result<(), Error> {
+            output.write_all(prefix)?;
+            output.write_all(s)?;
+            output.write_all(b"\r")?;
+            output.write_all(b"\n")
+        }
+    }
+
+    fn small_replies() -> [RESPType<Bytes>; 5] {
+        [
+            RESPType::SimpleString("OK".into()),
+            RESPType::Integer(1),
+            RESPType::Integer(1234567),
+            RESPType::Null,
+            RESPType::BulkString("hello".into()),
+        ]
+    }
+
+    fn array() -> RESPType<Bytes> {
+        RESPType::Array((0..100).map(|i| RESPType::BulkString(format!("element:{:03}", i).into())).collect())
+    }
+
+    fn large_bulk() -> RESPType<Bytes> {
+        RESPType::BulkString(Bytes::from(vec![b'x'; 1 << 20]))
+    }
+
+    #[bench]
+    fn bench_small_replies(b: &mut Bencher) {
+        let replies = small_replies();
+        let mut output = BytesMut::new();
+
+        b.iter(|| {
+            for reply in &replies {
+                output.clear();
+                serialize_into(reply, &mut output);
+            }
+
+            test::black_box(&output);
+        });
+    }
+
+    #[bench]
+    fn bench_small_replies_baseline(b: &mut Bencher) {
+        let replies = small_replies();
+
+        b.iter(|| {
+            for reply in &replies {
+                let mut output = Vec::new();
+                baseline::serialize(reply, &mut output).unwrap();
+                test::black_box(&output);
+            }
+        });
+    }
+
+    #[bench]
+    fn bench_array(b: &mut Bencher) {
+        let reply = array();
+        let mut output = BytesMut::new();
+
+        b.iter(|| {
+            output.clear();
+            serialize_into(&reply, &mut output);
+            test::black_box(&output);
+        });
+    }
+
+    #[bench]
+    fn bench_array_baseline(b: &mut Bencher) {
+        let reply = array();
+
+        b.iter(|| {
+            let mut output = Vec::new();
+            baseline::serialize(&reply, &mut output).unwrap();
+            test::black_box(&output);
+        });
+    }
+
+    #[bench]
+    fn bench_large_bulk(b: &mut Bencher) {
+        let reply = large_bulk();
+        let mut sink = std::io::sink();
+
+        b.iter(|| {
+            let mut output = Output::new();
+            output.push(&reply);
+
+            while !output.is_empty() {
+                output.write_to(&mut sink).unwrap();
+            }
+        });
+    }
+
+    #[bench]
+    fn bench_large_bulk_baseline(b: &mut Bencher) {
+        let reply = large_bulk();
+        let mut sink = std::io::sink();
+
+        b.iter(|| {
+            let mut output = Vec::new();
+            baseline::serialize(&reply, &mut output).unwrap();
+            sink.write_all(&output).unwrap();
+        });
+    }
+}
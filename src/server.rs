@@ -1,162 +1,2561 @@
 
-
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
-use std::io::{BufWriter, Read, ErrorKind, Write};
-use std::time::{Duration, Instant};
+use std::fs;
+use std::io::{self, Read, ErrorKind, Write};
+use std::net::SocketAddr;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use bytes::{Bytes, BytesMut};
-use mio::{Poll, Events, Token, Interest, Waker};
-use mio::net::{TcpListener, TcpStream};
+use chrono::Utc;
+use log::{debug, info, warn};
+use mio::{Poll, Events, Token, Interest, Registry, Waker};
+use rustls::ServerConfig;
 
-use sider_command::RESPType;
-use crate::command::COMMAND_TABLE;
+use sider_command::{Flag, RESPType};
+use crate::cluster::{self, Cluster};
+use crate::config::{self, Config, MAX_BIND_ADDRESSES};
+use crate::command::{Command, COMMAND_TABLE, Handler};
 use crate::db::DB;
-use crate::parser::RESPParser;
-use crate::serializer::serialize;
+use crate::latency::LatencyMonitor;
+use crate::net::{Address, Listener};
+use crate::notify;
+use crate::parser::{Limits, QueryBuffer};
+use crate::replication::{generate_id, Backlog, LinkEvent, LinkState, PrimaryLink, ReplicaClient, Replication, ACK_INTERVAL, BACKLOG_SIZE, PING_INTERVAL, TIMEOUT};
+use crate::sentinel::{self, Sentinel};
+use crate::signal;
+use crate::serializer::{serialize_into, Output};
+use crate::slab::Slab;
+use crate::slowlog::{self, SlowLog};
+use crate::snapshot;
+use crate::stats::Stats;
+use crate::tls::{self, AuthClients, AuthClientsUser, Stream};
+use crate::tracking::{Tracking, TrackingTable};
+use crate::util;
+
+
+
+const CLIENT_REQUEST_QUEUE: Token = Token(0);
+/// The connection to our primary, when we are a replica.
+const PRIMARY: Token = Token(1);
+const CLUSTER_BUS: Token = Token(2);
+/// Listener tokens are their index in the list of listeners, offset by this.
+const FIRST_LISTENER: usize = 3;
+/// A plain and a TLS listener for each bind address, and the Unix socket.
+const MAX_LISTENERS: usize = MAX_BIND_ADDRESSES * 2 + 1;
+/// Client tokens are their key in the slab of clients, offset by this.
+const FIRST_CLIENT: usize = FIRST_LISTENER + MAX_LISTENERS;
+
+
+/// A listener, with the TLS configuration for its clients if they connect over TLS.
+type TlsListener = (Listener, Option<Arc<ServerConfig>>);
+
+
+/// The error protected mode refuses clients from other machines with.
+const PROTECTED_MODE_ERROR: &[u8] = b"-DENIED sider is running in protected mode because protected mode is enabled and no password is set for the default user. \
+In this mode connections are only accepted from the loopback interface. If you want to connect from external computers to sider you may \
+adopt one of the following solutions: 1) Just disable protected mode sending the command 'CONFIG SET protected-mode no' from the loopback \
+interface by connecting to sider from the same host the server is running, however MAKE SURE sider is not publicly accessible from internet \
+if you do so. Use CONFIG REWRITE to make this change permanent. 2) Alternatively you can just disable the protected mode by editing the sider \
+configuration file, and setting the protected mode option to 'no', and then restarting the server. 3) If you started the server manually \
+just for testing, restart it with the '--protected-mode no' option. 4) Set up an authentication password for the default user. NOTE: You \
+only need to do one of the above things in order for the server to start accepting connections from the outside.\r\n";
+
+
+const MAX_CLIENTS_ERROR: &[u8] = b"-ERR max number of clients reached\r\n";
+
+
+/// Commands which are not shown to monitors, as in Redis. Their arguments can carry
+/// passwords, and MONITOR would otherwise show itself to the client running it.
+const UNMONITORED_COMMANDS: &[&str] = &["auth", "hello", "migrate", "config", "monitor"];
+
+
+/// How to shut down, as given to SHUTDOWN.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ShutdownFlags {
+    /// Whether to save the data set to `dbfilename`. By default it is saved if there is one.
+    pub save: Option<bool>,
+    /// Don't wait for lagging replicas.
+    pub now: bool,
+    /// Shut down even if the data set could not be saved.
+    pub force: bool,
+}
+
 
+/// A shutdown which is waiting for lagging replicas to catch up.
+#[derive(Debug)]
+struct PendingShutdown {
+    flags: ShutdownFlags,
+    deadline: Instant,
+    /// The client which ran SHUTDOWN, which is blocked until the shutdown fails. Shutdowns
+    /// started by signals have none.
+    client: Option<Token>,
+}
+
+
+/// Whether replies are sent to a client, as set by CLIENT REPLY.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ReplyMode {
+    On,
+    Off,
+    /// Skip the reply to the command after this one.
+    SkipNext,
+    /// Skip the reply to the command being processed.
+    Skip,
+}
 
 
-const SERVER: Token = Token(0);
-const CLIENT_REQUEST_QUEUE: Token = Token(1);
+/// Which commands CLIENT PAUSE holds back.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub(crate) enum PauseMode {
+    Write,
+    All,
+}
 
 
 #[derive(Debug)]
-struct Client {
-    output_buffer: BufWriter<TcpStream>,
+pub(crate) struct Client {
+    stream: Stream,
+    /// Unique for the life of the server, unlike the token, which is reused.
+    pub(crate) id: u64,
+    pub(crate) address: Address,
+    /// Set by CLIENT SETNAME.
+    pub(crate) name: Option<Bytes>,
+    created: Instant,
+    last_interaction: Instant,
+    /// The name of the last command the client ran.
+    last_command: Option<&'static str>,
+    query_buffer: QueryBuffer,
+    /// Output which could not be written to the socket yet without blocking.
+    output: Output,
+    /// Set while the client is blocked by WAIT. The client's commands are not processed
+    /// until it is unblocked.
+    blocked: bool,
+    /// The replication offset just after the client's most recent write, which WAIT waits
+    /// for replicas to reach.
+    pub(crate) write_offset: u64,
+    /// Set once the client has identified itself as one of our replicas.
+    pub(crate) replica: Option<ReplicaClient>,
+    /// Set by ASKING, allowing the next command to use a slot being imported.
+    pub(crate) asking: bool,
+    /// The Pub/Sub channels the client is subscribed to. While there are any, the client can
+    /// only manage its subscriptions.
+    pub(crate) subscriptions: HashSet<Bytes>,
+    pub(crate) reply_mode: ReplyMode,
+    /// Set by CLIENT NO-EVICT. There is no memory limit, so this is only reported.
+    pub(crate) no_evict: bool,
+    /// Set when the client has been killed by its own command, so that it is disconnected
+    /// once it has been sent the reply.
+    pub(crate) close_after_reply: bool,
+    /// A command held back by CLIENT PAUSE, which is run once the pause ends.
+    paused_command: Option<RESPType<Bytes>>,
+    /// Set when reading stopped because the query buffer reached `client-query-buffer-limit`.
+    /// Reading resumes once the commands in it have been processed.
+    read_paused: bool,
+    /// Set by MONITOR.
+    pub(crate) monitor: bool,
+    /// The version of the protocol the client speaks, as chosen with HELLO.
+    pub(crate) protocol: u8,
+    /// Set once the client has given the password with AUTH or HELLO, or straight away if
+    /// there is none.
+    pub(crate) authenticated: bool,
+    /// The user the client is authenticated as.
+    pub(crate) user: String,
+    /// Set when the client is to be authenticated by its TLS certificate, as soon as the
+    /// handshake is done.
+    identify_by_certificate: bool,
+    /// Set by CLIENT TRACKING ON.
+    pub(crate) tracking: Option<Tracking>,
 }
 
 
-fn handle_command(db: &mut DB, command: RESPType<Bytes>) -> RESPType<Bytes> {
-    let RESPType::Array(mut v) = command else {
-        return RESPType::Error(Bytes::from("Invalid command format, expecting array of bulk strings."));
-    };
+impl Client {
+    fn new(stream: Stream, id: u64, address: Address, limits: Limits) -> Self {
+        Client {
+            stream,
+            id,
+            address,
+            name: None,
+            created: Instant::now(),
+            last_interaction: Instant::now(),
+            last_command: None,
+            query_buffer: QueryBuffer::with_limits(limits),
+            output: Output::new(),
+            blocked: false,
+            write_offset: 0,
+            replica: None,
+            asking: false,
+            subscriptions: HashSet::new(),
+            reply_mode: ReplyMode::On,
+            no_evict: false,
+            close_after_reply: false,
+            paused_command: None,
+            read_paused: false,
+            monitor: false,
+            protocol: 2,
+            authenticated: false,
+            user: "default".into(),
+            identify_by_certificate: false,
+            tracking: None,
+        }
+    }
+
+    /// The address of our end of the connection.
+    pub(crate) fn local_address(&self) -> Option<Address> {
+        self.stream.local_addr().ok()
+    }
+
+    pub(crate) fn age(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    /// The kind of client, as CLIENT LIST and CLIENT KILL name it.
+    pub(crate) fn kind(&self) -> &'static str {
+        if self.replica.is_some() {
+            "replica"
+        } else if !self.subscriptions.is_empty() {
+            "pubsub"
+        } else {
+            "normal"
+        }
+    }
+
+    /// Describe the client in the format of CLIENT LIST.
+    pub(crate) fn describe(&self) -> String {
+        let mut flags = String::new();
+
+        if self.replica.is_some() {
+            flags.push('S');
+        }
+
+        if !self.subscriptions.is_empty() {
+            flags.push('P');
+        }
+
+        if self.blocked || self.paused_command.is_some() {
+            flags.push('b');
+        }
+
+        if self.monitor {
+            flags.push('O');
+        }
+
+        if self.no_evict {
+            flags.push('e');
+        }
+
+        if self.close_after_reply {
+            flags.push('A');
+        }
+
+        if self.tracking.is_some() {
+            flags.push('t');
+        }
+
+        if matches!(self.address, Address::Unix(_)) {
+            flags.push('U');
+        }
+
+        if flags.is_empty() {
+            flags.push('N');
+        }
+
+        let local_address = self.local_address().map(|a| a.to_string()).unwrap_or_default();
+        let events = if self.output.is_empty() { "r" } else { "rw" };
+
+        format!(
+            "id={} addr={} laddr={} fd={} name={} age={} idle={} flags={} db=0 sub={} psub=0 ssub=0 multi=-1 qbuf={} obl={} oll=0 omem={} events={} cmd={} user={} redir={} resp={}",
+            self.id,
+            self.address,
+            local_address,
+            self.stream.as_raw_fd(),
+            String::from_utf8_lossy(self.name.as_deref().unwrap_or_default()),
+            self.created.elapsed().as_secs(),
+            self.last_interaction.elapsed().as_secs(),
+            flags,
+            self.subscriptions.len(),
+            self.query_buffer.len(),
+            self.output.len(),
+            self.output.capacity(),
+            events,
+            self.last_command.map_or("NULL".into(), |c| c.replace('-', "|")),
+            self.user,
+            self.tracking.as_ref().map_or(-1, |t| t.redirect.unwrap_or(0) as i64),
+            self.protocol,
+        )
+    }
+
+    pub(crate) fn is_blocked(&self) -> bool {
+        self.blocked
+    }
+
+    /// Whether the client is a replica which is receiving the replication stream.
+    pub(crate) fn is_online_replica(&self) -> bool {
+        self.replica.as_ref().is_some_and(|r| r.online)
+    }
+
+    /// Read everything that is available into the query buffer, or until it reaches `limit`.
+    /// Returns false if the client closed the connection.
+    fn read(&mut self, buffer_size: usize, limit: usize) -> io::Result<bool> {
+        let mut read_buffer = vec![0; buffer_size];
+
+        loop {
+            if self.query_buffer.len() >= limit {
+                self.read_paused = true;
+                return Ok(true);
+            }
+
+            match self.stream.read(&mut read_buffer) {
+                Ok(0) => return Ok(false),
+                Ok(n) => {
+                    self.query_buffer.extend_from_slice(&read_buffer[..n]);
+                    self.last_interaction = Instant::now();
+                    self.identify();
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Authenticate the client as the user its certificate names, if it is to be. Clients
+    /// without a certificate stay as they are.
+    fn identify(&mut self) {
+        if !std::mem::take(&mut self.identify_by_certificate) {
+            return;
+        }
+
+        if let Some(user) = self.stream.peer_common_name() {
+            debug!("Client {} authenticated by its certificate as {}.", self.address, user);
+            self.user = user;
+            self.authenticated = true;
+        }
+    }
+
+    /// Queue output for the client and send as much of the queued output as possible without
+    /// blocking. The rest is sent once the socket becomes writable again.
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.extend_from_slice(bytes);
+        self.flush()
+    }
 
-    if v.len() == 0 {
-        return RESPType::Error(Bytes::from("Invalid command format, array must have at least one element."));
+    /// Like `write`, serializing a value straight into the output.
+    fn write_value(&mut self, value: &RESPType<Bytes>) -> io::Result<()> {
+        self.output.push(value);
+        self.flush()
     }
 
-    let command_name = v.remove(0);
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.output.is_empty() {
+            match self.output.write_to(&mut self.stream) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(_) => {},
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Encrypted output may be left over from the handshake or an earlier write.
+        self.stream.flush()
+    }
+}
 
-    let RESPType::BulkString(s) = command_name else {
-        return RESPType::Error(Bytes::from("Invalid command format, expecting array of bulk strings."));
-    };
 
-    let s = s.to_ascii_lowercase();
+/// A client blocked by WAIT.
+struct Waiter {
+    token: Token,
+    /// The replication offset the replicas need to acknowledge.
+    offset: u64,
+    replicas: usize,
+    deadline: Option<Instant>,
+}
 
-    let Some(command) = COMMAND_TABLE.get(&s) else {
-        return RESPType::Error(Bytes::from("Invalid command."));
-    };
 
-    return (command.handler)(v, db);
+/// Whether an IO error means that the other end of the connection has gone away, rather
+/// than there being something wrong with the server.
+fn is_disconnect(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe | ErrorKind::WriteZero)
 }
 
 
 
+/// What the server runs as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Standalone,
+    Cluster,
+    /// Monitoring other servers rather than storing data. Only commands flagged for sentinel
+    /// mode are available.
+    Sentinel,
+}
+
+
 pub struct Server {
-    clients: Vec<Option<Client>>,
+    pub(crate) db: DB,
+    pub(crate) config: Config,
+    pub(crate) stats: Stats,
+    pub(crate) slowlog: SlowLog,
+    pub(crate) latency: LatencyMonitor,
+    /// Identifies this run of the server, and changes every time it starts.
+    pub(crate) run_id: String,
+    pub(crate) started: Instant,
+    clients: Slab<Client>,
+    pub(crate) replication: Replication,
+    /// Our view of the cluster, when cluster mode is enabled.
+    pub(crate) cluster: Option<Cluster>,
+    /// The primaries we monitor, when running as a sentinel.
+    pub(crate) sentinel: Option<Sentinel>,
+    /// The clients subscribed to each Pub/Sub channel.
+    channels: HashMap<Bytes, Vec<Token>>,
+    /// Clients which may have commands waiting to be processed.
+    ready: VecDeque<Token>,
+    waiting: Vec<Waiter>,
+    /// The clients which are sent every command, by MONITOR.
+    monitors: Vec<Token>,
+    /// Reused to serialize each command propagated to our replicas.
+    propagated: BytesMut,
+    next_client_id: u64,
+    /// The token of each client, by its ID.
+    client_ids: HashMap<u64, Token>,
+    /// The keys to tell tracking clients about, by CLIENT TRACKING.
+    pub(crate) tracking: TrackingTable,
+    /// Set by CLIENT PAUSE, until the deadline.
+    pause: Option<(PauseMode, Instant)>,
+    shutdown: Option<PendingShutdown>,
+    /// Set once we have shut down, for the event loop to stop.
+    exiting: bool,
 }
 
 
 impl Server {
-    pub fn build() -> Self {
+    pub fn build(config: Config, mode: Mode) -> Self {
+        let mut replication = Replication::new();
+        replication.primary = config.replicaof.clone().map(|(host, port)| PrimaryLink::new(host, port));
+
+        let mut db = DB::new();
+        db.notifier.flags = config.notify_keyspace_events;
+        db.max_string_length = config.proto_max_bulk_len;
+
         Server {
-            clients: Vec::with_capacity(100),
+            db,
+            clients: Slab::with_capacity(config.maxclients),
+            replication,
+            cluster: (mode == Mode::Cluster).then(|| Cluster::new(config.port)),
+            sentinel: (mode == Mode::Sentinel).then(|| Sentinel::new(config.port)),
+            config,
+            stats: Stats::default(),
+            slowlog: SlowLog::new(),
+            latency: LatencyMonitor::new(),
+            run_id: generate_id(),
+            started: Instant::now(),
+            channels: HashMap::new(),
+            ready: VecDeque::new(),
+            waiting: Vec::new(),
+            monitors: Vec::new(),
+            propagated: BytesMut::new(),
+            next_client_id: 1,
+            client_ids: HashMap::new(),
+            tracking: TrackingTable::new(),
+            pause: None,
+            shutdown: None,
+            exiting: false,
         }
     }
 
     pub fn start(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut db = DB::new();
         let mut poll = Poll::new()?;
         let mut events = Events::with_capacity(128);
-        let mut client_request_queue: VecDeque<(Token, RESPType<Bytes>)> = VecDeque::new();
 
-        let mut listener = TcpListener::bind("127.0.0.1:6379".parse().unwrap())?;
         let client_request_waker = Waker::new(poll.registry(), CLIENT_REQUEST_QUEUE)?;
-        
-        poll.registry().register(&mut listener, SERVER, Interest::READABLE).unwrap();
-        
-        let background_task_frequency = Duration::from_millis(100);
 
-        loop {
-            let next_background_task = Instant::now() + background_task_frequency;
+        if let Some(path) = self.config.dbfilename.clone() {
+            self.load_dump(&path)?;
+        }
 
-            poll.poll(&mut events, Some(background_task_frequency))?;
+        let listeners = self.listen(poll.registry())?;
+        signal::install()?;
 
-            for event in &mut events.iter() {
-                match event.token() {
-                    SERVER => loop {
-                        let (mut connection, _) = match listener.accept() {
-                            Ok((connection, address)) => (connection, address),
-                            Err(e) => {
-                                if e.kind() == ErrorKind::WouldBlock {
-                                    break;
-                                }
+        if let Some(path) = &self.config.pidfile {
+            if let Err(e) = fs::write(path, format!("{}\n", std::process::id())) {
+                warn!("Failed to write the PID file {}: {}", path.display(), e);
+            }
+        }
 
-                                return Err(e.into());
-                            }
-                        };
-                        
-                        poll.registry().register(&mut connection, Token(self.clients.len() + 2), Interest::READABLE | Interest::WRITABLE)?;
+        if let Some(cluster) = &mut self.cluster {
+            cluster.listen(poll.registry(), CLUSTER_BUS)?;
+        }
+
+        if let Some(sentinel) = &mut self.sentinel {
+            for directive in &self.config.sentinel {
+                sentinel.apply_directive(directive)?;
+            }
+        }
+
+        let mut next_background_task = Instant::now() + self.background_task_interval();
 
-                        self.clients.push(Some(Client {
-                            output_buffer: BufWriter::new(connection),
-                        }));
+        loop {
+            match poll.poll(&mut events, Some(next_background_task.saturating_duration_since(Instant::now()))) {
+                // A signal arrived, which is handled below.
+                Err(e) if e.kind() == ErrorKind::Interrupted => events.clear(),
+                result => result?,
+            }
 
+            for event in &mut events.iter() {
+                match event.token() {
+                    token if (FIRST_LISTENER..FIRST_CLIENT).contains(&token.0) => {
+                        if let Some((listener, tls_config)) = listeners.get(token.0 - FIRST_LISTENER) {
+                            self.accept_clients(listener, tls_config.as_ref(), poll.registry())?;
+                        }
                     },
                     CLIENT_REQUEST_QUEUE => {
-                        while let Some((client_token, request)) = client_request_queue.pop_front() {    
-                            let response = match request {
-                                RESPType::Error(e) => RESPType::Error(Bytes::from(e)),
-                                r => handle_command(&mut db, r)
-                            };
-
-                            let output = match &mut self.clients[client_token.0 - 2] {
-                                Some(client) => &mut client.output_buffer,
-                                None => continue,
-                            };
-        
-                            serialize(&response, output)?;
-                            output.flush()?;
+                        self.process_ready_clients();
+                    },
+                    PRIMARY => {
+                        self.handle_primary_event(event.is_readable(), event.is_writable());
+                    },
+                    CLUSTER_BUS => {
+                        if let Some(cluster) = &mut self.cluster {
+                            cluster.accept(poll.registry())?;
+                        }
+                    },
+                    token if token.0 >= sentinel::FIRST_LINK => {
+                        if let Some(sentinel) = &mut self.sentinel {
+                            sentinel.link_event(token, event.is_readable(), event.is_writable());
+                        }
 
+                        self.publish_sentinel_events();
+                        self.save_sentinel_config();
+                    },
+                    token if token.0 >= cluster::FIRST_LINK => {
+                        if let Some(cluster) = &mut self.cluster {
+                            cluster.link_event(token, event.is_readable(), event.is_writable());
                         }
                     },
                     token => {
-                        let client = match &mut self.clients[token.0 - 2] {
-                            None => {
-                                continue;
-                            },
-                            Some(client) => client,
-                        };
+                        let (buffer_size, limit) = (self.config.read_buffer_size, self.config.client_query_buffer_limit);
 
-                        if event.is_readable() {
-                            let mut query_buffer = BytesMut::zeroed(1024*16);
+                        let Some(client) = self.client_mut(token) else {
+                            continue;
+                        };
 
-                            let num_bytes_read = match client.output_buffer.get_mut().read(&mut query_buffer) {
-                                Ok(n) => n,
-                                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
-                                Err(e) => return Err(e.into()),
-                            };
+                        let mut result = Ok(true);
 
-                            if num_bytes_read == 0 {
-                                poll.registry().deregister(client.output_buffer.get_mut())?;
-                                self.clients[token.0 - 2] = None;
-                                continue;
-                            }
+                        if event.is_writable() {
+                            result = client.flush().map(|_| true);
+                        }
 
-                            let result = RESPParser::parse(query_buffer.into());
+                        if event.is_readable() && result.is_ok() {
+                            result = client.read(buffer_size, limit);
+                        }
 
-                            client_request_queue.push_back((token, result));
-                            client_request_waker.wake()?;
+                        match result {
+                            Ok(true) => {
+                                self.ready.push_back(token);
+                                client_request_waker.wake()?;
+                            },
+                            Ok(false) => self.disconnect_client(token),
+                            Err(e) if is_disconnect(&e) => self.disconnect_client(token),
+                            // A failed TLS handshake, or input which could not be decrypted.
+                            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                                warn!("Error on the TLS connection from {}: {}", client.address, e);
+                                self.disconnect_client(token);
+                            },
+                            // Only this client is affected, so the rest carry on.
+                            Err(e) => {
+                                warn!("Error on the connection from {}: {}", client.address, e);
+                                self.disconnect_client(token);
+                            },
                         }
                     }
                 }
             }
 
-            if next_background_task < Instant::now() {
-                db.expire_keys();
+            if let Some(name) = signal::take() {
+                info!("Received {} scheduling shutdown...", name);
+
+                if let Err(e) = self.shutdown(ShutdownFlags::default(), None) {
+                    warn!("{} received but errors trying to shut down the server: {}", name, e);
+                    signal::reset();
+                }
+            }
+
+            if next_background_task <= Instant::now() {
+                self.background_tasks(poll.registry());
+                next_background_task = Instant::now() + self.background_task_interval();
+            }
+
+            if self.exiting {
+                return Ok(());
+            }
+
+            // Clients may have been unblocked while handling the events.
+            if !self.ready.is_empty() {
+                client_request_waker.wake()?;
             }
         }
     }
-}
 
+    /// Start listening on the `bind` addresses, on the port and the TLS port, and on the Unix
+    /// socket.
+    fn listen(&mut self, registry: &Registry) -> Result<Vec<TlsListener>, Box<dyn Error>> {
+        let mut listeners = Vec::new();
+
+        let mut ports = vec![(self.config.port, None)];
+
+        if self.config.tls_port != 0 {
+            ports.push((self.config.tls_port, Some(tls::server_config(&self.config)?)));
+        }
+
+        for (port, tls_config) in ports {
+            // A port of zero picks a free port, which the other addresses then share.
+            let mut port = port;
+
+            for entry in &self.config.bind {
+                let (ip, optional) = config::parse_bind_address(entry).ok_or_else(|| format!("Invalid bind address '{}'", entry))?;
+
+                let listener = match Listener::bind_tcp(SocketAddr::new(ip, port), self.config.tcp_backlog) {
+                    Ok(listener) => listener,
+                    Err(e) if optional => {
+                        warn!("Skipping the optional bind address {}: {}", entry, e);
+                        continue;
+                    },
+                    Err(e) => return Err(format!("Could not listen on {}: {}", SocketAddr::new(ip, port), e).into()),
+                };
+
+                if let Ok(Address::Tcp(address)) = listener.local_addr() {
+                    port = address.port();
+                    info!("Listening on {}{}.", address, if tls_config.is_some() { " for TLS" } else { "" });
+                }
+
+                listeners.push((listener, tls_config.clone()));
+            }
+
+            if tls_config.is_none() {
+                self.config.port = port;
+            } else {
+                self.config.tls_port = port;
+            }
+        }
+
+        if let Some(path) = &self.config.unixsocket {
+            listeners.push((Listener::bind_unix(path, self.config.unixsocketperm)?, None));
+            info!("Listening on the Unix socket {}.", path.display());
+        }
+
+        if listeners.is_empty() {
+            return Err("Configured to not listen anywhere, exiting.".into());
+        }
+
+        for (i, (listener, _)) in listeners.iter_mut().enumerate() {
+            registry.register(listener, Token(FIRST_LISTENER + i), Interest::READABLE)?;
+        }
+
+        Ok(listeners)
+    }
+
+    /// Whether protected mode turns a client away: it is on, there is no password to keep
+    /// other machines out, and the client is not connecting over the loopback interface or a
+    /// Unix socket.
+    fn is_protected_from(&self, address: &Address) -> bool {
+        let Address::Tcp(address) = address else {
+            return false;
+        };
+
+        self.config.protected_mode && self.config.requirepass.is_none() && !address.ip().to_canonical().is_loopback()
+    }
+
+    /// Accept the clients waiting on a listener, encrypting their connections if there is a
+    /// TLS configuration.
+    fn accept_clients(&mut self, listener: &Listener, tls_config: Option<&Arc<ServerConfig>>, registry: &Registry) -> io::Result<()> {
+        loop {
+            let (mut connection, address) = match listener.accept() {
+                Ok((connection, address)) => (connection, address),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                // The client gave up before we got to it.
+                Err(e) if is_disconnect(&e) || e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            // New clients are turned away once we have started shutting down.
+            if self.shutdown.is_some() {
+                continue;
+            }
+
+            if self.is_protected_from(&address) {
+                warn!("Refused a connection from {} in protected mode.", address);
+                // The client will not have sent anything yet, so the error fits in the
+                // socket's buffer.
+                let _ = connection.write(PROTECTED_MODE_ERROR);
+                continue;
+            }
+
+            if self.clients.len() >= self.config.maxclients {
+                // Sent before any TLS handshake, as Redis does.
+                let _ = connection.write(MAX_CLIENTS_ERROR);
+                self.stats.rejected_connections += 1;
+                continue;
+            }
+
+            if self.config.tcp_keepalive > 0 {
+                if let Err(e) = connection.set_keepalive(self.config.tcp_keepalive) {
+                    warn!("Could not turn on TCP keepalive for {}: {}", address, e);
+                }
+            }
+
+            let stream = match tls_config {
+                Some(tls_config) => Stream::accept(connection, tls_config.clone()),
+                None => Ok(Stream::plain(connection)),
+            };
+
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Error accepting a client connection from {}: {}", address, e);
+                    continue;
+                },
+            };
+
+            let identify_by_certificate = tls_config.is_some()
+                && self.config.tls_auth_clients != AuthClients::No
+                && self.config.tls_auth_clients_user == AuthClientsUser::CommonName;
+
+            let token = self.add_client(stream, address);
+            let client = self.client_mut(token).unwrap();
+            client.identify_by_certificate = identify_by_certificate;
+
+            if let Err(e) = registry.register(&mut client.stream, token, Interest::READABLE | Interest::WRITABLE) {
+                warn!("Error registering the client connection from {}: {}", client.address, e);
+                self.disconnect_client(token);
+                continue;
+            }
+
+            self.stats.total_connections_received += 1;
+        }
+    }
+
+    /// Add a client which has connected, returning its token.
+    fn add_client(&mut self, stream: Stream, address: Address) -> Token {
+        let id = self.next_client_id;
+        self.next_client_id += 1;
+
+        let mut client = Client::new(stream, id, address, self.client_limits());
+        client.authenticated = self.config.requirepass.is_none();
+
+        let key = self.clients.insert(client);
+        let token = Token(key + FIRST_CLIENT);
+        self.client_ids.insert(id, token);
+
+        token
+    }
+
+    /// Load the data set saved by the last run, if there is one.
+    fn load_dump(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e).into()),
+        };
+
+        let start = Instant::now();
+        let db = snapshot::load(&bytes).map_err(|e| format!("Could not load {}: {:?}", path.display(), e))?;
+
+        self.db = db;
+        self.db.notifier.flags = self.config.notify_keyspace_events;
+        self.db.max_string_length = self.config.proto_max_bulk_len;
+        info!("DB loaded from disk: {:.3} seconds", start.elapsed().as_secs_f64());
+
+        Ok(())
+    }
+
+    /// Write the data set to `dbfilename`, through a temporary file so that a failed save
+    /// leaves the last one intact.
+    fn save_dump(&self) -> Result<(), String> {
+        let Some(path) = &self.config.dbfilename else {
+            return Err("There is no dbfilename to save the DB to".into());
+        };
+
+        let temporary = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+
+        fs::write(&temporary, snapshot::dump(&self.db))
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&temporary);
+                format!("Failed saving the DB to {}: {}", path.display(), e)
+            })?;
+
+        info!("DB saved on disk");
+        Ok(())
+    }
+
+    /// Start shutting down. Unless NOW is given, writes are paused for up to
+    /// `shutdown-timeout` first, for lagging replicas to catch up. `client` is the client
+    /// which ran SHUTDOWN, which is blocked while we wait.
+    pub(crate) fn shutdown(&mut self, flags: ShutdownFlags, client: Option<Token>) -> Result<(), String> {
+        if self.shutdown.is_some() {
+            return Err("Shutdown already in progress".into());
+        }
+
+        if flags.now || self.config.shutdown_timeout == 0 || self.lagging_replicas() == 0 {
+            return self.finish_shutdown(flags);
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(self.config.shutdown_timeout);
+        info!("Waiting for replicas before shutting down.");
+
+        self.pause_clients(PauseMode::Write, deadline);
+        self.shutdown = Some(PendingShutdown { flags, deadline, client });
+
+        if let Some(client) = client.and_then(|t| self.client_mut(t)) {
+            client.blocked = true;
+        }
+
+        // Ask for acknowledgements now, rather than waiting for the replicas to send them.
+        self.propagate(vec![
+            RESPType::BulkString(Bytes::from("REPLCONF")),
+            RESPType::BulkString(Bytes::from("GETACK")),
+            RESPType::BulkString(Bytes::from("*")),
+        ]);
+
+        Ok(())
+    }
+
+    /// Give up on a shutdown which is waiting for replicas, for SHUTDOWN ABORT.
+    pub(crate) fn abort_shutdown(&mut self) -> bool {
+        let Some(pending) = self.shutdown.take() else {
+            return false;
+        };
+
+        info!("Shutdown manually aborted.");
+        self.fail_shutdown(pending.client);
+        true
+    }
+
+    /// The online replicas which have not acknowledged the whole replication stream.
+    fn lagging_replicas(&self) -> usize {
+        self.clients().filter(|c| c.is_online_replica()).count() - self.count_acks(self.replication.offset)
+    }
+
+    /// Finish a pending shutdown once the replicas have caught up, or the wait is over.
+    fn check_shutdown(&mut self) {
+        let Some(pending) = &self.shutdown else {
+            return;
+        };
+
+        if self.lagging_replicas() > 0 && pending.deadline > Instant::now() {
+            return;
+        }
+
+        if self.lagging_replicas() > 0 {
+            warn!("{} replicas did not catch up before shutting down.", self.lagging_replicas());
+        }
+
+        let pending = self.shutdown.take().unwrap();
+
+        if let Err(e) = self.finish_shutdown(pending.flags) {
+            warn!("Errors trying to shut down the server: {}", e);
+            self.fail_shutdown(pending.client);
+        }
+    }
+
+    /// Save the data set if asked to, and clean up the files we made. The event loop stops
+    /// once this succeeds.
+    fn finish_shutdown(&mut self, flags: ShutdownFlags) -> Result<(), String> {
+        if flags.save.unwrap_or(self.config.dbfilename.is_some()) {
+            match self.save_dump() {
+                Err(e) if flags.force => warn!("{}, shutting down anyway as FORCE was given.", e),
+                result => result?,
+            }
+        }
+
+        // Send the replicas whatever they have not been sent yet.
+        for key in self.clients.iter().map(|(key, _)| key).collect::<Vec<_>>() {
+            let _ = self.clients.get_mut(key).unwrap().flush();
+        }
+
+        if let Some(path) = &self.config.pidfile {
+            let _ = fs::remove_file(path);
+        }
+
+        if let Some(path) = &self.config.unixsocket {
+            let _ = fs::remove_file(path);
+        }
+
+        info!("sider is now ready to exit, bye bye...");
+        self.exiting = true;
+        Ok(())
+    }
+
+    /// Go back to running normally, telling the client which ran SHUTDOWN.
+    fn fail_shutdown(&mut self, client: Option<Token>) {
+        self.unpause_clients();
+        signal::reset();
+
+        if let Some(token) = client {
+            if let Some(client) = self.client_mut(token) {
+                client.blocked = false;
+                self.reply(token, &RESPType::Error("Errors trying to SHUTDOWN. Check logs.".into()));
+                self.ready.push_back(token);
+            }
+        }
+    }
+
+    /// The time between runs of the background tasks, which is set by `hz`.
+    fn background_task_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.config.hz
+    }
+
+    /// Put changes to the configuration into effect. Most parameters are read as they are
+    /// needed, so only a few need anything doing.
+    pub(crate) fn apply_config(&mut self) {
+        log::set_max_level(self.config.loglevel);
+        self.db.notifier.flags = self.config.notify_keyspace_events;
+        self.db.max_string_length = self.config.proto_max_bulk_len;
+
+        let limits = self.client_limits();
+
+        for client in self.clients.values_mut() {
+            client.query_buffer.limits = limits;
+        }
+    }
+
+    /// The limits on what clients may send, from the `proto-max-*` parameters.
+    fn client_limits(&self) -> Limits {
+        Limits {
+            max_bulk_len: self.config.proto_max_bulk_len,
+            max_multibulk_len: self.config.proto_max_multibulk_len,
+            max_depth: self.config.proto_max_nesting_depth,
+            ..Limits::default()
+        }
+    }
+
+    /// Record a spike in latency, if it is over `latency-monitor-threshold`.
+    pub(crate) fn record_latency(&mut self, event: &'static str, duration: Duration) {
+        let threshold = self.config.latency_monitor_threshold;
+
+        if threshold > 0 && duration.as_millis() >= threshold as u128 {
+            self.latency.record(event, duration);
+        }
+    }
+
+    pub(crate) fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+        self.db.stats = Default::default();
+    }
+
+    pub(crate) fn client(&self, token: Token) -> Option<&Client> {
+        self.clients.get(token.0.checked_sub(FIRST_CLIENT)?)
+    }
+
+    pub(crate) fn client_mut(&mut self, token: Token) -> Option<&mut Client> {
+        self.clients.get_mut(token.0.checked_sub(FIRST_CLIENT)?)
+    }
+
+    pub(crate) fn clients(&self) -> impl Iterator<Item = &Client> {
+        self.clients.iter().map(|(_, c)| c)
+    }
+
+    pub(crate) fn clients_by_token(&self) -> impl Iterator<Item = (Token, &Client)> {
+        self.clients.iter().map(|(key, c)| (Token(key + FIRST_CLIENT), c))
+    }
+
+    pub(crate) fn disconnect_client(&mut self, token: Token) {
+        self.disable_tracking(token);
+
+        if let Some(client) = token.0.checked_sub(FIRST_CLIENT).and_then(|key| self.clients.remove(key)) {
+            for channel in client.subscriptions {
+                self.remove_subscriber(&channel, token);
+            }
+
+            self.client_ids.remove(&client.id);
+        }
+
+        self.waiting.retain(|w| w.token != token);
+        self.monitors.retain(|t| *t != token);
+    }
+
+    /// Disconnect all of our replicas, for when the history they are following changes. They
+    /// will reconnect and find out about the new history.
+    pub(crate) fn disconnect_replicas(&mut self) {
+        let replicas: Vec<_> = self.clients_by_token().filter(|(_, c)| c.replica.is_some()).map(|(token, _)| token).collect();
+
+        for token in replicas {
+            self.disconnect_client(token);
+        }
+    }
+
+    /// Find a client by its ID.
+    pub(crate) fn client_by_id(&self, id: u64) -> Option<Token> {
+        self.client_ids.get(&id).copied()
+    }
+
+    /// Build a reply from pairs of fields and values, as a map for clients speaking RESP3 and
+    /// as a flat array for the rest.
+    pub(crate) fn map_reply(&self, token: Token, pairs: Vec<(RESPType<Bytes>, RESPType<Bytes>)>) -> RESPType<Bytes> {
+        if self.client(token).is_some_and(|c| c.protocol >= 3) {
+            return RESPType::Map(pairs);
+        }
+
+        RESPType::Array(pairs.into_iter().flat_map(|(k, v)| [k, v]).collect())
+    }
+
+    /// Send raw bytes to a client, disconnecting it if it has gone away.
+    pub(crate) fn send(&mut self, token: Token, bytes: &[u8]) {
+        let Some(client) = self.client_mut(token) else {
+            return;
+        };
+
+        if let Err(e) = client.write(bytes) {
+            if !is_disconnect(&e) {
+                warn!("Error writing to client {}: {}", client.address, e);
+            }
+
+            self.disconnect_client(token);
+        }
+    }
+
+    /// Like `send`, serializing the value straight into the client's output.
+    fn send_value(&mut self, token: Token, value: &RESPType<Bytes>) {
+        let Some(client) = self.client_mut(token) else {
+            return;
+        };
+
+        if let Err(e) = client.write_value(value) {
+            if !is_disconnect(&e) {
+                warn!("Error writing to client {}: {}", client.address, e);
+            }
+
+            self.disconnect_client(token);
+        }
+    }
+
+    /// Send a reply to a client. Replicas are not sent replies, as the connection to them
+    /// carries the replication stream.
+    pub(crate) fn reply(&mut self, token: Token, response: &RESPType<Bytes>) {
+        if let RESPType::Error(e) = response {
+            self.stats.record_error(e);
+        }
+
+        let Some(client) = self.client(token) else {
+            return;
+        };
+
+        if client.is_online_replica() || matches!(client.reply_mode, ReplyMode::Off | ReplyMode::Skip) {
+            return;
+        }
+
+        self.send_value(token, response);
+    }
+
+    /// Start sending a client every command which is run.
+    pub(crate) fn add_monitor(&mut self, token: Token) {
+        let Some(client) = self.client_mut(token) else {
+            return;
+        };
+
+        if !client.monitor {
+            client.monitor = true;
+            self.monitors.push(token);
+        }
+    }
+
+    /// Send a command to the monitors, in the format `+1339518083.107412 [0 127.0.0.1:60866]
+    /// "keys" "*"`.
+    fn feed_monitors(&mut self, token: Token, command: &[RESPType<Bytes>]) {
+        if self.monitors.is_empty() {
+            return;
+        }
+
+        let Some(client) = self.client(token) else {
+            return;
+        };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut line = format!("+{}.{:06} [0 {}]", now.as_secs(), now.subsec_micros(), client.address);
+
+        for arg in command {
+            if let RESPType::BulkString(arg) = arg {
+                line.push(' ');
+                line.push_str(&util::quote(arg));
+            }
+        }
+
+        line.push_str("\r\n");
+
+        for monitor in self.monitors.clone() {
+            self.send(monitor, line.as_bytes());
+        }
+    }
+
+    /// The number of channels with at least one subscriber.
+    pub(crate) fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Subscribe a client to a channel, returning the number of channels it is subscribed to.
+    pub(crate) fn subscribe(&mut self, token: Token, channel: Bytes) -> usize {
+        let Some(client) = self.client_mut(token) else {
+            return 0;
+        };
+
+        if client.subscriptions.insert(channel.clone()) {
+            self.channels.entry(channel).or_default().push(token);
+        }
+
+        self.client(token).map_or(0, |c| c.subscriptions.len())
+    }
+
+    /// Unsubscribe a client from a channel, returning the number of channels it is still
+    /// subscribed to.
+    pub(crate) fn unsubscribe(&mut self, token: Token, channel: &Bytes) -> usize {
+        let Some(client) = self.client_mut(token) else {
+            return 0;
+        };
+
+        let removed = client.subscriptions.remove(channel);
+        let count = client.subscriptions.len();
+
+        if removed {
+            self.remove_subscriber(channel, token);
+        }
+
+        count
+    }
+
+    fn remove_subscriber(&mut self, channel: &Bytes, token: Token) {
+        if let Some(subscribers) = self.channels.get_mut(channel) {
+            subscribers.retain(|t| *t != token);
+
+            if subscribers.is_empty() {
+                self.channels.remove(channel);
+            }
+        }
+    }
+
+    /// Send a message to the subscribers of a channel, returning how many there were.
+    pub(crate) fn publish(&mut self, channel: &Bytes, message: Bytes) -> usize {
+        let Some(subscribers) = self.channels.get(channel).cloned() else {
+            return 0;
+        };
+
+        let message = RESPType::Array(vec![
+            RESPType::BulkString(Bytes::from("message")),
+            RESPType::BulkString(channel.clone()),
+            RESPType::BulkString(message),
+        ]);
+
+        for token in &subscribers {
+            self.send_value(*token, &message);
+        }
+
+        subscribers.len()
+    }
+
+    /// Turn on CLIENT TRACKING for a client. A client in broadcast mode without any prefixes
+    /// is told about every key.
+    pub(crate) fn enable_tracking(&mut self, token: Token, mut tracking: Tracking) {
+        let Some(client) = self.client_mut(token) else {
+            return;
+        };
+
+        let id = client.id;
+
+        // Turning tracking on again adds prefixes to those already given.
+        if let Some(current) = client.tracking.take() {
+            tracking.prefixes.splice(0..0, current.prefixes);
+        }
+
+        if tracking.bcast && tracking.prefixes.is_empty() {
+            tracking.prefixes.push(Bytes::new());
+        }
+
+        for prefix in &tracking.prefixes {
+            self.tracking.add_prefix(prefix.clone(), id);
+        }
+
+        if let Some(client) = self.client_mut(token) {
+            client.tracking = Some(tracking);
+        }
+
+        self.db.notifier.tracking = true;
+    }
+
+    pub(crate) fn disable_tracking(&mut self, token: Token) {
+        let Some((id, tracking)) = self.client_mut(token).and_then(|c| Some((c.id, c.tracking.take()?))) else {
+            return;
+        };
+
+        for prefix in &tracking.prefixes {
+            self.tracking.remove_prefix(prefix, id);
+        }
+
+        let tracking = self.clients().any(|c| c.tracking.is_some());
+        self.db.notifier.tracking = tracking;
+    }
+
+    /// Tell the tracking clients about the keys modified by the last command or by expiry.
+    /// `origin` is the client which ran the command, which clients with NOLOOP set are not
+    /// told about.
+    fn invalidate_modified(&mut self, origin: Option<Token>) {
+        for key in self.db.notifier.take_modified() {
+            for id in self.tracking.invalidate(&key) {
+                let Some(token) = self.client_by_id(id) else {
+                    continue;
+                };
+
+                if origin == Some(token) && self.client(token).and_then(|c| c.tracking.as_ref()).is_some_and(|t| t.noloop) {
+                    continue;
+                }
+
+                self.send_invalidation(token, RESPType::Array(vec![RESPType::BulkString(key.clone())]));
+            }
+        }
+    }
+
+    /// Tell the tracking clients to drop everything they have cached, as the whole data set
+    /// has been replaced.
+    fn invalidate_all(&mut self) {
+        self.db.notifier.take_modified();
+        self.tracking = TrackingTable::new();
+
+        let tracking: Vec<(Token, Vec<Bytes>)> = self.clients_by_token()
+            .filter_map(|(token, c)| Some((token, c.tracking.as_ref()?.prefixes.clone())))
+            .collect();
+
+        for (token, prefixes) in tracking {
+            let id = self.client(token).map_or(0, |c| c.id);
+
+            for prefix in prefixes {
+                self.tracking.add_prefix(prefix, id);
+            }
+
+            self.send_invalidation(token, RESPType::Null);
+        }
+    }
+
+    /// Send an invalidation message to a tracking client, or to the client its messages are
+    /// redirected to. `keys` is an array of keys, or null for all of them.
+    fn send_invalidation(&mut self, token: Token, keys: RESPType<Bytes>) {
+        let Some(tracking) = self.client(token).and_then(|c| c.tracking.as_ref()) else {
+            return;
+        };
+
+        let target = match tracking.redirect {
+            Some(id) => match self.client_by_id(id) {
+                Some(target) => target,
+                None => {
+                    if self.client(token).is_some_and(|c| c.protocol >= 3) {
+                        let message = RESPType::Push(vec![RESPType::BulkString("tracking-redir-broken".into()), RESPType::Integer(id as i64)]);
+                        self.send_push(token, &message);
+                    }
+
+                    return;
+                },
+            },
+            None => token,
+        };
+
+        let Some(client) = self.client(target) else {
+            return;
+        };
+
+        let channel = Bytes::from_static(b"__redis__:invalidate");
+
+        if client.protocol >= 3 {
+            self.send_push(target, &RESPType::Push(vec![RESPType::BulkString("invalidate".into()), keys]));
+        } else if client.subscriptions.contains(&channel) {
+            // Clients speaking RESP2 can only be sent invalidations over Pub/Sub, on a
+            // separate connection.
+            self.send_push(target, &RESPType::Array(vec![RESPType::BulkString("message".into()), RESPType::BulkString(channel), keys]));
+        }
+    }
+
+    fn send_push(&mut self, token: Token, message: &RESPType<Bytes>) {
+        self.send_value(token, message);
+    }
+
+    /// Hold back clients' commands until the deadline. A pause which is already in effect is
+    /// only ever made longer or stricter.
+    pub(crate) fn pause_clients(&mut self, mode: PauseMode, deadline: Instant) {
+        self.pause = Some(match self.pause {
+            Some((m, d)) => (if m > mode { m } else { mode }, d.max(deadline)),
+            None => (mode, deadline),
+        });
+    }
+
+    /// End a pause, running the commands which were held back.
+    pub(crate) fn unpause_clients(&mut self) {
+        self.pause = None;
+
+        let paused: Vec<Token> = self.clients_by_token().filter(|(_, c)| c.paused_command.is_some()).map(|(t, _)| t).collect();
+        self.ready.extend(paused);
+    }
+
+    /// Whether a command from a client is held back by CLIENT PAUSE. Replicas are never
+    /// paused, so that they can catch up.
+    fn is_paused(&self, token: Token, command: &RESPType<Bytes>) -> bool {
+        let Some((mode, deadline)) = self.pause else {
+            return false;
+        };
+
+        if deadline <= Instant::now() || self.client(token).is_none_or(|c| c.replica.is_some()) {
+            return false;
+        }
+
+        match mode {
+            PauseMode::All => true,
+            PauseMode::Write => {
+                let name = match command {
+                    RESPType::Array(v) => match v.first() {
+                        Some(RESPType::BulkString(s)) => s.to_ascii_lowercase(),
+                        _ => return false,
+                    },
+                    _ => return false,
+                };
+
+                COMMAND_TABLE.get(&name).is_some_and(|c| c.flags.iter().any(|f| matches!(f, Flag::Write)))
+            },
+        }
+    }
+
+    /// Process the commands waiting in the query buffers of the ready clients, stopping at a
+    /// client's first incomplete command or if it becomes blocked.
+    fn process_ready_clients(&mut self) {
+        while let Some(token) = self.ready.pop_front() {
+            while let Some(client) = self.client_mut(token) {
+                if client.blocked {
+                    break;
+                }
+
+                let request = match client.paused_command.take() {
+                    Some(request) => request,
+                    None => match client.query_buffer.next_value() {
+                        Some((request, _)) => request,
+                        None => break,
+                    },
+                };
+
+                if self.is_paused(token, &request) {
+                    if let Some(client) = self.client_mut(token) {
+                        client.paused_command = Some(request);
+                    }
+
+                    break;
+                }
+
+                if let Some(client) = self.client_mut(token) {
+                    if client.reply_mode == ReplyMode::SkipNext {
+                        client.reply_mode = ReplyMode::Skip;
+                    }
+                }
+
+                let response = match request {
+                    RESPType::Error(e) => {
+                        // There is no way to find the start of the next command once the input
+                        // cannot be parsed, so the client is disconnected, as in Redis.
+                        if let Some(client) = self.client_mut(token) {
+                            debug!("Protocol error ({}) from client {}", String::from_utf8_lossy(&e), client.address);
+                            client.query_buffer.clear();
+                            client.close_after_reply = true;
+                        }
+
+                        Some(RESPType::Error(format!("ERR Protocol error: {}", String::from_utf8_lossy(&e)).into()))
+                    },
+                    r => self.handle_command(token, r),
+                };
+
+                if let Some(response) = response {
+                    self.reply(token, &response);
+                }
+
+                let Some(client) = self.client_mut(token) else {
+                    break;
+                };
+
+                if client.reply_mode == ReplyMode::Skip {
+                    client.reply_mode = ReplyMode::On;
+                }
+
+                if client.close_after_reply {
+                    self.disconnect_client(token);
+                }
+            }
+
+            self.resume_reading(token);
+        }
+    }
+
+    /// Read more from a client which stopped at `client-query-buffer-limit`, once the
+    /// commands in its query buffer have been processed. What is left is part of a single
+    /// command, so if that is still over the limit the client is disconnected.
+    fn resume_reading(&mut self, token: Token) {
+        let (buffer_size, limit) = (self.config.read_buffer_size, self.config.client_query_buffer_limit);
+
+        let Some(client) = self.client_mut(token) else {
+            return;
+        };
+
+        if !client.read_paused || client.blocked || client.paused_command.is_some() {
+            return;
+        }
+
+        if client.query_buffer.len() >= limit {
+            warn!("Closing client {} that reached max query buffer length ({} bytes)", client.address, client.query_buffer.len());
+            self.disconnect_client(token);
+            return;
+        }
+
+        client.read_paused = false;
+
+        match client.read(buffer_size, limit) {
+            Ok(true) => self.ready.push_back(token),
+            Ok(false) => self.disconnect_client(token),
+            Err(e) => {
+                if !is_disconnect(&e) {
+                    warn!("Error on the connection from {}: {}", client.address, e);
+                }
+
+                self.disconnect_client(token);
+            },
+        }
+    }
+
+    fn handle_command(&mut self, token: Token, command: RESPType<Bytes>) -> Option<RESPType<Bytes>> {
+        let RESPType::Array(mut v) = command else {
+            return Some(RESPType::Error(Bytes::from("Invalid command format, expecting array of bulk strings.")));
+        };
+
+        if v.is_empty() {
+            return Some(RESPType::Error(Bytes::from("Invalid command format, array must have at least one element.")));
+        }
+
+        let RESPType::BulkString(s) = &v[0] else {
+            return Some(RESPType::Error(Bytes::from("Invalid command format, expecting array of bulk strings.")));
+        };
+
+        let s = s.to_ascii_lowercase();
+
+        let Some(command) = COMMAND_TABLE.get(&s) else {
+            return Some(RESPType::Error(Bytes::from("Invalid command.")));
+        };
+
+        if let Some(client) = self.client_mut(token) {
+            client.last_command = Some(command.name);
+        }
+
+        if let Some(e) = self.check_command(token, command, &v) {
+            self.stats.command(command.name).rejected_calls += 1;
+            return Some(e);
+        }
+
+        // Monitors are shown the commands which are run, once they have been accepted.
+        let monitored = (!self.monitors.is_empty() && !UNMONITORED_COMMANDS.contains(&command.name)).then(|| v.clone());
+
+        let is_write = command.flags.iter().any(|f| matches!(f, Flag::Write));
+
+        // The arguments are moved into the handler, so keep a copy of writes to send on to
+        // our replicas. Server handlers propagate their own writes.
+        let propagate = (is_write && self.replication.backlog.is_some() && matches!(command.handler, Handler::Keyspace(_))).then(|| v.clone());
+
+        // The slow log keeps the arguments it would log, which share their bytes with the
+        // command rather than copying it.
+        let logged = (self.config.slowlog_log_slower_than >= 0).then(|| slowlog::Arguments::new(&v));
+
+        // CLIENT CACHING only applies to the command after it, and its own reply does not
+        // count.
+        let caching = self.client_mut(token).and_then(|c| c.tracking.as_mut()?.caching.take());
+        let tracked = self.client(token)
+            .and_then(|c| c.tracking.as_ref())
+            .filter(|t| t.tracks_reads(caching) && command.flags.iter().any(|f| matches!(f, Flag::ReadOnly)))
+            .map(|_| command.keys(&v).into_iter().cloned().collect::<Vec<Bytes>>());
+
+        v.remove(0);
+
+        let start = Instant::now();
+
+        let response = match command.handler {
+            Handler::Keyspace(handler) => Some(handler(v, &mut self.db)),
+            Handler::Server(handler) => handler(v, self, token),
+        };
+
+        let elapsed = start.elapsed();
+
+        if let Some(monitored) = monitored {
+            self.feed_monitors(token, &monitored);
+        }
+
+        self.stats.record_call(command.name, elapsed, matches!(response, Some(RESPType::Error(_))));
+        self.record_latency(if command.flags.iter().any(|f| matches!(f, Flag::Fast)) { "fast-command" } else { "command" }, elapsed);
+
+        if let Some(logged) = logged.filter(|_| elapsed.as_micros() >= self.config.slowlog_log_slower_than as u128) {
+            let (address, name) = self.client(token)
+                .map(|c| (c.address.to_string(), c.name.clone().unwrap_or_default()))
+                .unwrap_or_default();
+
+            self.slowlog.record(&logged, elapsed, address, name, self.config.slowlog_max_len);
+        }
+
+        if is_write && !matches!(response, Some(RESPType::Error(_))) {
+            if let Some(command) = propagate {
+                let command = self.absolute_expiry(command);
+                self.propagate(command);
+            }
+
+            let offset = self.replication.offset;
+
+            if let Some(client) = self.client_mut(token) {
+                client.write_offset = offset;
+            }
+        }
+
+        self.propagate_expired();
+        self.publish_notifications();
+        self.invalidate_modified(Some(token));
+
+        // Keys are remembered after the invalidations, so that a read which expires a key
+        // does not invalidate it for the reader.
+        if let Some(keys) = tracked.filter(|_| !matches!(response, Some(RESPType::Error(_)))) {
+            let id = self.client(token).map_or(0, |c| c.id);
+
+            for key in keys {
+                self.tracking.remember(key, id);
+            }
+        }
+
+        response
+    }
+
+    /// Check whether a command can run, returning the error to reply with if it cannot.
+    fn check_command(&mut self, token: Token, command: &Command, args: &[RESPType<Bytes>]) -> Option<RESPType<Bytes>> {
+        // A positive arity is the exact number of arguments, including the command name, and a
+        // negative one the minimum.
+        let arity_ok = match command.arity {
+            n if n < 0 => args.len() as i64 >= -n,
+            n => args.len() as i64 == n,
+        };
+
+        if !arity_ok {
+            return Some(RESPType::Error(Bytes::from("wrong number of arguments")));
+        }
+
+        let authenticated = self.client(token).is_none_or(|c| c.authenticated);
+
+        if !authenticated && !command.flags.iter().any(|f| matches!(f, Flag::NoAuth)) {
+            return Some(RESPType::Error(Bytes::from("NOAUTH Authentication required.")));
+        }
+
+        if self.sentinel.is_some() && !command.flags.iter().any(|f| matches!(f, Flag::Sentinel)) {
+            return Some(RESPType::Error(Bytes::from("Invalid command.")));
+        }
+
+        // Clients speaking RESP3 can tell replies from messages, so can run any command.
+        let subscribed = self.client(token).is_some_and(|c| !c.subscriptions.is_empty() && c.protocol < 3);
+
+        if subscribed && !matches!(command.name, "subscribe" | "unsubscribe" | "ping") {
+            return Some(RESPType::Error(format!("Can't execute '{}': only SUBSCRIBE / UNSUBSCRIBE / PING are allowed in this context", command.name).into()));
+        }
+
+        let asking = self.client_mut(token).is_some_and(|c| std::mem::take(&mut c.asking))
+            || command.flags.iter().any(|f| matches!(f, Flag::Asking));
+
+        if let Some(e) = self.check_slot(command, args, asking) {
+            return Some(e);
+        }
+
+        let is_write = command.flags.iter().any(|f| matches!(f, Flag::Write));
+
+        if is_write && self.replication.is_replica() && self.config.replica_read_only {
+            return Some(RESPType::Error(Bytes::from("READONLY You can't write against a read only replica.")));
+        }
+
+        None
+    }
+
+    /// Check that the keys of a command are served by this node, returning the redirect or
+    /// error to reply with if they are not.
+    fn check_slot(&self, command: &Command, args: &[RESPType<Bytes>], asking: bool) -> Option<RESPType<Bytes>> {
+        let cluster = self.cluster.as_ref()?;
+        let keys = command.keys(args);
+        let slot = cluster::key_slot(keys.first()?);
+
+        if keys.iter().any(|k| cluster::key_slot(k) != slot) {
+            return Some(RESPType::Error(Bytes::from("CROSSSLOT Keys in request don't hash to the same slot")));
+        }
+
+        let Some(owner) = cluster.owner(slot) else {
+            return Some(RESPType::Error(Bytes::from("CLUSTERDOWN Hash slot not served")));
+        };
+
+        if owner.id != cluster.myself {
+            if asking && cluster.importing.contains_key(&slot) {
+                return None;
+            }
+
+            return Some(RESPType::Error(format!("MOVED {} {}", slot, owner.endpoint()).into()));
+        }
+
+        // Keys in a migrating slot which we no longer have have been moved to the target.
+        let target = cluster.migrating.get(&slot).and_then(|id| cluster.node(id))?;
+
+        match keys.iter().filter(|k| !self.db.exists(k)).count() {
+            0 => None,
+            n if n == keys.len() => Some(RESPType::Error(format!("ASK {} {}", slot, target.endpoint()).into())),
+            _ => Some(RESPType::Error(Bytes::from("TRYAGAIN Multiple keys request during rehashing of slot"))),
+        }
+    }
+
+    /// Add a command to the replication stream.
+    pub(crate) fn propagate(&mut self, command: Vec<RESPType<Bytes>>) {
+        let mut bytes = std::mem::take(&mut self.propagated);
+        bytes.clear();
+        serialize_into(&RESPType::Array(command), &mut bytes);
+
+        self.feed_replicas(&bytes);
+        self.propagated = bytes;
+    }
+
+    /// Rewrite the relative expiry time in a write to the absolute time the key was given, as
+    /// Redis does, so that replicas agree with us however late they apply it, including when
+    /// they replay it from the backlog.
+    fn absolute_expiry(&self, mut command: Vec<RESPType<Bytes>>) -> Vec<RESPType<Bytes>> {
+        let (Some(RESPType::BulkString(name)), Some(RESPType::BulkString(key))) = (command.first(), command.get(1)) else {
+            return command;
+        };
+
+        let name = name.to_ascii_lowercase();
+        let bulk = |s: &'static str| RESPType::BulkString(Bytes::from_static(s.as_bytes()));
+        // A key which is already gone was given a time in the past.
+        let at = self.db.expiry(key).unwrap_or_else(Utc::now).timestamp_millis();
+        let at = RESPType::BulkString(at.to_string().into());
+
+        match &name[..] {
+            b"set" => {
+                let option = command.iter().skip(3).position(|a| matches!(a, RESPType::BulkString(o) if o.eq_ignore_ascii_case(b"EX") || o.eq_ignore_ascii_case(b"PX")));
+
+                if let Some(i) = option.map(|i| i + 3).filter(|i| i + 1 < command.len()) {
+                    command[i] = bulk("PXAT");
+                    command[i + 1] = at;
+                }
+            },
+            b"setex" if command.len() == 4 => {
+                let (value, key) = (command.swap_remove(3), command.swap_remove(1));
+                command = vec![bulk("SET"), key, value, bulk("PXAT"), at];
+            },
+            b"restore" if command.len() >= 4 => {
+                let relative = !matches!(&command[2], RESPType::BulkString(t) if &t[..] == b"0");
+                let absolute = command[4..].iter().any(|a| matches!(a, RESPType::BulkString(o) if o.eq_ignore_ascii_case(b"ABSTTL")));
+
+                if relative && !absolute {
+                    command[2] = at;
+                    command.push(bulk("ABSTTL"));
+                }
+            },
+            _ => {},
+        }
+
+        command
+    }
+
+    /// Add raw bytes to the replication stream, and send them to our replicas.
+    fn feed_replicas(&mut self, bytes: &[u8]) {
+        if !self.replication.feed(bytes) {
+            return;
+        }
+
+        let replicas: Vec<_> = self.clients_by_token().filter(|(_, c)| c.is_online_replica()).map(|(token, _)| token).collect();
+
+        for token in replicas {
+            self.send(token, bytes);
+        }
+    }
+
+    /// Send deletions for expired keys to our replicas, which do not expire keys themselves.
+    fn propagate_expired(&mut self) {
+        let expired = self.db.take_expired();
+
+        // Replicas are sent the deletions by our own primary.
+        if self.replication.is_replica() {
+            return;
+        }
+
+        for key in expired {
+            self.propagate(vec![RESPType::BulkString(Bytes::from("DEL")), RESPType::BulkString(key)]);
+        }
+    }
+
+    /// Publish the keyspace notifications queued by the database.
+    fn publish_notifications(&mut self) {
+        let flags = self.db.notifier.flags;
+
+        for (event, key) in self.db.notifier.take() {
+            if flags & notify::KEYSPACE != 0 {
+                let mut channel = b"__keyspace@0__:".to_vec();
+                channel.extend_from_slice(&key);
+                self.publish(&channel.into(), Bytes::from_static(event.as_bytes()));
+            }
+
+            if flags & notify::KEYEVENT != 0 {
+                self.publish(&format!("__keyevent@0__:{}", event).into(), key);
+            }
+        }
+    }
+
+    /// Count the replicas which have acknowledged processing the replication stream up to
+    /// `offset`.
+    pub(crate) fn count_acks(&self, offset: u64) -> usize {
+        self.clients()
+            .filter_map(|c| c.replica.as_ref())
+            .filter(|r| r.online && r.ack_offset >= offset)
+            .count()
+    }
+
+    /// Block a client until enough replicas have acknowledged `offset`, or until the
+    /// deadline has passed.
+    pub(crate) fn block_for_acks(&mut self, token: Token, offset: u64, replicas: usize, deadline: Option<Instant>) {
+        let Some(client) = self.client_mut(token) else {
+            return;
+        };
+
+        client.blocked = true;
+        self.waiting.push(Waiter { token, offset, replicas, deadline });
+
+        // Ask for acknowledgements now, rather than waiting for the replicas to send them.
+        self.propagate(vec![
+            RESPType::BulkString(Bytes::from("REPLCONF")),
+            RESPType::BulkString(Bytes::from("GETACK")),
+            RESPType::BulkString(Bytes::from("*")),
+        ]);
+    }
+
+    /// Unblock the clients which have enough acknowledgements, or which have timed out,
+    /// replying with the number of replicas which acknowledged their writes.
+    pub(crate) fn check_waiters(&mut self) {
+        let now = Instant::now();
+        let mut i = 0;
+
+        while i < self.waiting.len() {
+            let waiter = &self.waiting[i];
+            let acks = self.count_acks(waiter.offset);
+
+            if acks < waiter.replicas && waiter.deadline.is_none_or(|d| d > now) {
+                i += 1;
+                continue;
+            }
+
+            let waiter = self.waiting.remove(i);
+
+            if let Some(client) = self.client_mut(waiter.token) {
+                client.blocked = false;
+                self.reply(waiter.token, &RESPType::Integer(acks as i64));
+
+                // Commands may have arrived while the client was blocked.
+                self.ready.push_back(waiter.token);
+            }
+        }
+    }
+
+    fn handle_primary_event(&mut self, readable: bool, writable: bool) {
+        let Some(link) = &mut self.replication.primary else {
+            return;
+        };
+
+        let mut result = Ok(true);
+
+        if writable {
+            result = link.writable().map(|_| true);
+        }
+
+        if readable && result.is_ok() {
+            result = link.read();
+        }
+
+        match result {
+            Ok(true) => self.process_primary_input(),
+            Ok(false) => {
+                warn!("Lost the connection to the primary.");
+                link.disconnect();
+            },
+            Err(e) => {
+                warn!("Error on the connection to the primary: {}", e);
+                link.disconnect();
+            },
+        }
+    }
+
+    fn process_primary_input(&mut self) {
+        loop {
+            let Some(link) = &mut self.replication.primary else {
+                return;
+            };
+
+            let event = match link.next_event(self.config.port, self.config.masterauth.as_deref(), &self.replication.id, self.replication.offset) {
+                Ok(Some(event)) => event,
+                Ok(None) => return,
+                Err(e) => {
+                    warn!("Replication with the primary failed: {}", e);
+                    link.disconnect();
+                    return;
+                },
+            };
+
+            match event {
+                LinkEvent::FullResync(id, offset) => {
+                    info!("Full resync with the primary, from replication ID {} offset {}.", id, offset);
+
+                    self.replication.id = id;
+                    self.replication.offset = offset;
+                    self.replication.second_id_offset = None;
+                    self.replication.backlog = Some(Backlog::new(BACKLOG_SIZE, offset));
+
+                    // Our replicas were following a history which we no longer have.
+                    self.disconnect_replicas();
+                },
+                LinkEvent::Snapshot(bytes) => match snapshot::load(&bytes) {
+                    Ok(db) => {
+                        let tracking = self.db.notifier.tracking;
+
+                        self.db = db;
+                        self.db.notifier.flags = self.config.notify_keyspace_events;
+                        self.db.notifier.tracking = tracking;
+                        self.db.max_string_length = self.config.proto_max_bulk_len;
+                        self.invalidate_all();
+                    },
+                    Err(e) => {
+                        warn!("Could not load the snapshot from the primary: {:?}", e);
+                        link.disconnect();
+                        return;
+                    },
+                },
+                LinkEvent::Continue(id) => {
+                    info!("Partial resync with the primary, from offset {}.", self.replication.offset);
+
+                    if let Some(id) = id.filter(|id| id != &self.replication.id) {
+                        self.replication.second_id = std::mem::replace(&mut self.replication.id, id);
+                        self.replication.second_id_offset = Some(self.replication.offset + 1);
+                        self.disconnect_replicas();
+                    }
+
+                    if self.replication.backlog.is_none() {
+                        self.replication.backlog = Some(Backlog::new(BACKLOG_SIZE, self.replication.offset));
+                    }
+                },
+                LinkEvent::Command(command, raw) => {
+                    self.apply_replicated(command);
+                    self.feed_replicas(&raw);
+                },
+            }
+        }
+    }
+
+    /// Apply a command from our primary's replication stream. Nothing is sent back, apart from
+    /// acknowledgements when the primary asks for them. Commands which need the server run
+    /// without a client, as the primary's token belongs to none.
+    fn apply_replicated(&mut self, command: RESPType<Bytes>) {
+        let RESPType::Array(mut v) = command else {
+            return;
+        };
+
+        let Some(RESPType::BulkString(name)) = v.first() else {
+            return;
+        };
+
+        let name = name.to_ascii_lowercase();
+
+        if name == b"replconf" {
+            if matches!(v.get(1), Some(RESPType::BulkString(s)) if s.eq_ignore_ascii_case(b"getack")) {
+                self.send_ack();
+            }
+
+            return;
+        }
+
+        match COMMAND_TABLE.get(&name) {
+            Some(command) => {
+                v.remove(0);
+
+                match command.handler {
+                    Handler::Keyspace(handler) => {
+                        handler(v, &mut self.db);
+                    },
+                    Handler::Server(handler) => {
+                        handler(v, self, PRIMARY);
+                    },
+                }
+            },
+            None => warn!("Unknown command in the replication stream: {}", String::from_utf8_lossy(&name)),
+        }
+
+        self.db.take_expired();
+        self.publish_notifications();
+        self.invalidate_modified(None);
+    }
+
+    /// Tell our primary how much of the replication stream we have processed.
+    fn send_ack(&mut self) {
+        let offset = self.replication.offset.to_string();
+
+        let Some(link) = &mut self.replication.primary else {
+            return;
+        };
+
+        if link.state != LinkState::Connected {
+            return;
+        }
+
+        if let Err(e) = link.send(&[b"REPLCONF", b"ACK", offset.as_bytes()]) {
+            warn!("Error on the connection to the primary: {}", e);
+            link.disconnect();
+        }
+    }
+
+    fn background_tasks(&mut self, registry: &Registry) {
+        if self.pause.is_some_and(|(_, deadline)| deadline <= Instant::now()) {
+            self.unpause_clients();
+        }
+
+        // Replicas leave expiry to their primary, so that they stay consistent with it. Keys
+        // are not expired during a pause either, as that would change the data set.
+        if !self.replication.is_replica() && self.pause.is_none() {
+            let start = Instant::now();
+            self.db.expire_keys();
+            self.record_latency("expire-cycle", start.elapsed());
+        }
+
+        self.propagate_expired();
+        self.publish_notifications();
+        self.invalidate_modified(None);
+        self.stats.sample_ops();
+        self.replication_cron(registry);
+
+        if let Some(cluster) = &mut self.cluster {
+            cluster.cron(registry);
+        }
+
+        if let Some(sentinel) = &mut self.sentinel {
+            sentinel.cron(registry);
+        }
+
+        self.publish_sentinel_events();
+        self.save_sentinel_config();
+        self.check_waiters();
+        self.disconnect_idle_clients();
+        self.check_shutdown();
+    }
+
+    /// Disconnect the clients which have been idle for longer than `timeout`. Replicas,
+    /// monitors and subscribers are left alone, as they wait for us rather than the other way
+    /// around, as are blocked clients, which have timeouts of their own.
+    fn disconnect_idle_clients(&mut self) {
+        if self.config.timeout == 0 {
+            return;
+        }
+
+        let timeout = Duration::from_secs(self.config.timeout);
+
+        let idle: Vec<_> = self.clients_by_token()
+            .filter(|(_, c)| c.replica.is_none() && !c.monitor && c.subscriptions.is_empty() && !c.is_blocked())
+            .filter(|(_, c)| c.last_interaction.elapsed() > timeout)
+            .map(|(token, _)| token)
+            .collect();
+
+        for token in idle {
+            debug!("Closing idle client {}.", self.client(token).unwrap().address);
+            self.disconnect_client(token);
+        }
+    }
+
+    /// Publish what has happened to the primaries we monitor, on a channel per kind of event.
+    fn publish_sentinel_events(&mut self) {
+        let Some(sentinel) = &mut self.sentinel else {
+            return;
+        };
+
+        for (channel, message) in sentinel.take_events() {
+            self.publish(&channel, message);
+        }
+    }
+
+    /// Write the sentinel state to the config file when it has changed, so that a restarted
+    /// sentinel monitors the current primary and remembers the epochs it has voted in.
+    fn save_sentinel_config(&mut self) {
+        let Some(sentinel) = &mut self.sentinel else {
+            return;
+        };
+
+        if !sentinel.take_config_changed() || self.config.file.is_none() {
+            return;
+        }
+
+        self.config.sentinel = sentinel.directives();
+
+        if let Err(e) = self.config.rewrite() {
+            warn!("Failed to save the sentinel state: {}", e);
+        }
+    }
+
+    fn replication_cron(&mut self, registry: &Registry) {
+        if let Some(link) = &mut self.replication.primary {
+            let tls_config = match self.config.tls_replication && link.is_due() {
+                true => tls::client_config(&self.config).map(Some),
+                false => Ok(None),
+            };
+
+            let connected = match tls_config {
+                Ok(tls_config) => link.connect(registry, PRIMARY, tls_config),
+                Err(e) => Err(io::Error::other(e)),
+            };
+
+            if let Err(e) = connected {
+                warn!("Could not connect to the primary at {}:{}: {}", link.host, link.port, e);
+                link.disconnect();
+            }
+
+            if link.state != LinkState::Connect && link.last_received.elapsed() > TIMEOUT {
+                warn!("Timed out waiting for the primary.");
+                link.disconnect();
+            }
+        }
+
+        if self.replication.last_ack.elapsed() >= ACK_INTERVAL {
+            self.replication.last_ack = Instant::now();
+            self.send_ack();
+        }
+
+        let has_replicas = self.clients().any(|c| c.is_online_replica());
+
+        if !self.replication.is_replica() && has_replicas && self.replication.last_ping.elapsed() >= PING_INTERVAL {
+            self.replication.last_ping = Instant::now();
+            self.propagate(vec![RESPType::BulkString(Bytes::from("PING"))]);
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::{IpAddr, TcpListener, TcpStream, UdpSocket};
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use mio::Token;
+    use socket2::SockRef;
+
+    use sider_command::RESPType;
+    use crate::config::Config;
+    use crate::db::{DBEntry, DBString};
+    use crate::net::{Address, Socket};
+    use crate::parser::QueryBuffer;
+    use crate::replication::{Backlog, ReplicaClient, BACKLOG_SIZE};
+    use crate::serializer::serialize;
+    use crate::server::{Mode, Server};
+    use crate::snapshot;
+    use crate::tls::Stream;
+
+    /// Connect a client over a loopback socket, returning its token and the other end of the
+    /// connection.
+    fn connect(server: &mut Server) -> (Token, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let theirs = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        theirs.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let (ours, address) = listener.accept().unwrap();
+        ours.set_nonblocking(true).unwrap();
+
+        let stream = Stream::plain(Socket::Tcp(mio::net::TcpStream::from_std(ours)));
+        (server.add_client(stream, Address::Tcp(address)), theirs)
+    }
+
+    fn arguments(args: &[&str]) -> Vec<RESPType<Bytes>> {
+        args.iter().map(|a| RESPType::BulkString(Bytes::copy_from_slice(a.as_bytes()))).collect()
+    }
+
+    fn command(args: &[&str]) -> RESPType<Bytes> {
+        RESPType::Array(arguments(args))
+    }
+
+    /// Send a command from a client, and process it.
+    fn send(server: &mut Server, token: Token, args: &[&str]) {
+        let mut request = Vec::new();
+        serialize(&command(args), &mut request).unwrap();
+
+        server.client_mut(token).unwrap().query_buffer.extend_from_slice(&request);
+        server.ready.push_back(token);
+        server.process_ready_clients();
+    }
+
+    /// Connect an online replica, which has acknowledged nothing yet.
+    fn connect_replica(server: &mut Server) -> (Token, TcpStream) {
+        server.replication.backlog.get_or_insert_with(|| Backlog::new(BACKLOG_SIZE, 0));
+
+        let (token, theirs) = connect(server);
+        server.client_mut(token).unwrap().replica = Some(ReplicaClient { online: true, ..ReplicaClient::new() });
+
+        (token, theirs)
+    }
+
+    /// A non-loopback address of this machine, to connect from.
+    fn external_ip() -> Option<IpAddr> {
+        // Connecting a UDP socket sends nothing, but picks the address packets would come from.
+        let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+        socket.connect("192.0.2.1:9").ok()?;
+
+        Some(socket.local_addr().ok()?.ip()).filter(|ip| !ip.is_loopback())
+    }
+
+    /// Read the next value sent to a client.
+    fn receive(theirs: &mut TcpStream, input: &mut QueryBuffer) -> RESPType<Bytes> {
+        let mut buffer = [0; 4096];
+
+        loop {
+            if let Some((value, _)) = input.next_value() {
+                return value;
+            }
+
+            let n = theirs.read(&mut buffer).unwrap();
+            assert!(n > 0, "the connection was closed");
+            input.extend_from_slice(&buffer[..n]);
+        }
+    }
+
+    #[test]
+    fn test_apply_replicated() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (subscriber, mut theirs) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        server.subscribe(subscriber, "news".into());
+
+        // Commands handled by the server, as well as the keyspace, are applied.
+        server.apply_replicated(command(&["PUBLISH", "news", "hello"]));
+        server.apply_replicated(command(&["SET", "key", "1"]));
+
+        assert_eq!(receive(&mut theirs, &mut input), command(&["message", "news", "hello"]));
+        assert_eq!(server.db.get(&"key".into()), Some(&DBEntry::String(DBString::String("1".into()))));
+    }
+
+    #[test]
+    fn test_propagate() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (_replica, mut theirs) = connect_replica(&mut server);
+        let (client, _other) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        // Each command is sent on its own, although they share a buffer.
+        send(&mut server, client, &["SET", "key", "1"]);
+        send(&mut server, client, &["DEL", "key"]);
+
+        assert_eq!(receive(&mut theirs, &mut input), command(&["SET", "key", "1"]));
+        assert_eq!(receive(&mut theirs, &mut input), command(&["DEL", "key"]));
+        assert_eq!(server.replication.offset, 51);
+    }
+
+    #[test]
+    fn test_propagate_absolute_expiry() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let mut replica = Server::build(Config::default(), Mode::Standalone);
+        let (_replica, mut theirs) = connect_replica(&mut server);
+        let (client, _other) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        let payload = Bytes::from(snapshot::dump_value(&DBEntry::String(DBString::Integer(1))));
+        let mut restore = arguments(&["RESTORE", "d", "100000"]);
+        restore.insert(3, RESPType::BulkString(payload.clone()));
+
+        let requests = [
+            (arguments(&["SET", "a", "1", "EX", "100"]), vec!["SET", "a", "1", "PXAT", "{}"]),
+            (arguments(&["SETEX", "b", "100", "1"]), vec!["SET", "b", "1", "PXAT", "{}"]),
+            (arguments(&["SET", "c", "1", "PX", "100000", "GET"]), vec!["SET", "c", "1", "PXAT", "{}", "GET"]),
+            (restore, vec!["RESTORE", "d", "{}", "", "ABSTTL"]),
+        ];
+
+        for (request, expected) in requests {
+            let mut bytes = Vec::new();
+            serialize(&RESPType::Array(request), &mut bytes).unwrap();
+            server.client_mut(client).unwrap().query_buffer.extend_from_slice(&bytes);
+            server.ready.push_back(client);
+            server.process_ready_clients();
+
+            let key = Bytes::from(expected[1]);
+            let at = server.db.expiry(&key).unwrap().timestamp_millis().to_string();
+
+            let mut expected = arguments(&expected.iter().map(|a| if *a == "{}" { &at[..] } else { a }).collect::<Vec<_>>());
+
+            if expected[0] == RESPType::BulkString("RESTORE".into()) {
+                expected[3] = RESPType::BulkString(payload.clone());
+            }
+
+            let propagated = receive(&mut theirs, &mut input);
+            assert_eq!(propagated, RESPType::Array(expected));
+
+            // However late the replica applies the command, the key expires when ours does.
+            std::thread::sleep(Duration::from_millis(5));
+            replica.apply_replicated(propagated);
+
+            assert_eq!(replica.db.expiry(&key).map(|e| e.timestamp_millis()), server.db.expiry(&key).map(|e| e.timestamp_millis()));
+        }
+    }
+
+    #[test]
+    fn test_disconnect_replicas() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (replica, _theirs) = connect(&mut server);
+        let (client, _) = connect(&mut server);
+
+        let id = server.client(replica).unwrap().id;
+        server.client_mut(replica).unwrap().replica = Some(ReplicaClient::new());
+        server.add_monitor(replica);
+        server.subscribe(replica, "news".into());
+
+        server.disconnect_replicas();
+
+        // Nothing is left behind which would find the next client to take the slot.
+        assert!(server.client(replica).is_none());
+        assert!(server.client_by_id(id).is_none());
+        assert!(server.monitors.is_empty());
+        assert_eq!(server.channel_count(), 0);
+        assert!(server.client(client).is_some());
+    }
+
+    #[test]
+    fn test_client_list() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (a, mut theirs) = connect(&mut server);
+        let (b, _) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        send(&mut server, b, &["CLIENT", "SETNAME", "other"]);
+        send(&mut server, a, &["CLIENT", "LIST"]);
+
+        let RESPType::BulkString(list) = receive(&mut theirs, &mut input) else {
+            panic!("expected a bulk string");
+        };
+
+        let list = String::from_utf8_lossy(&list);
+        let lines: Vec<&str> = list.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(&format!("id={} ", server.client(a).unwrap().id)));
+        assert!(lines[1].contains(" name=other "));
+
+        send(&mut server, a, &["CLIENT", "LIST", "TYPE", "pubsub"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::BulkString("".into()));
+    }
+
+    #[test]
+    fn test_client_kill() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (a, mut theirs) = connect(&mut server);
+        let (b, mut other) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        let address = server.client(b).unwrap().address.to_string();
+        send(&mut server, a, &["CLIENT", "KILL", &address]);
+
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::SimpleString("OK".into()));
+        assert!(server.client(b).is_none());
+        assert_eq!(other.read(&mut [0; 16]).unwrap(), 0);
+
+        send(&mut server, a, &["CLIENT", "KILL", &address]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::Error("No such client".into()));
+
+        // A client killing itself gets the reply first.
+        let id = server.client(a).unwrap().id.to_string();
+        send(&mut server, a, &["CLIENT", "KILL", "ID", &id, "SKIPME", "no"]);
+
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::Integer(1));
+        assert!(server.client(a).is_none());
+    }
+
+    #[test]
+    fn test_client_errors() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (a, mut theirs) = connect(&mut server);
+        let (b, reset) = connect(&mut server);
+        let (c, mut other) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        // Input which cannot be parsed gets a reply, and then the client is disconnected.
+        server.client_mut(a).unwrap().query_buffer.extend_from_slice(b"*1\r\n$x\r\n");
+        server.ready.push_back(a);
+        server.process_ready_clients();
+
+        assert!(matches!(receive(&mut theirs, &mut input), RESPType::Error(e) if e.starts_with(b"ERR Protocol error")));
+        assert!(server.client(a).is_none());
+        assert_eq!(theirs.read(&mut [0; 16]).unwrap(), 0);
+
+        // Replying to a connection which was reset fails, closing only that client.
+        SockRef::from(&reset).set_linger(Some(Duration::ZERO)).unwrap();
+        drop(reset);
+
+        for _ in 0..10 {
+            if server.client(b).is_none() {
+                break;
+            }
+
+            send(&mut server, b, &["PING"]);
+        }
+
+        assert!(server.client(b).is_none());
+
+        send(&mut server, c, &["PING"]);
+        assert_eq!(receive(&mut other, &mut input), RESPType::SimpleString("PONG".into()));
+        assert!(server.client(c).is_some());
+    }
+
+    #[test]
+    fn test_proto_limits() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (a, mut theirs) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+        let too_large = RESPType::Error("string exceeds maximum allowed size (proto-max-bulk-len)".into());
+
+        for (name, value) in [("proto-max-bulk-len", "1mb"), ("proto-max-multibulk-len", "1024"), ("proto-max-nesting-depth", "1")] {
+            send(&mut server, a, &["CONFIG", "SET", name, value]);
+            assert_eq!(receive(&mut theirs, &mut input), RESPType::SimpleString("OK".into()));
+        }
+
+        // Strings cannot grow past proto-max-bulk-len, however they grow.
+        send(&mut server, a, &["SETRANGE", "key", "1048575", "x"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::Integer(1024 * 1024));
+
+        send(&mut server, a, &["APPEND", "key", "y"]);
+        assert_eq!(receive(&mut theirs, &mut input), too_large);
+
+        send(&mut server, a, &["SETRANGE", "key", "1048576", "y"]);
+        assert_eq!(receive(&mut theirs, &mut input), too_large);
+
+        send(&mut server, a, &["SETBIT", "key", "8388608", "1"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::Error("bit offset is not an integer or out of range".into()));
+
+        // Arrays which are too long or nested too deeply are protocol errors.
+        for request in [&b"*1025\r\n"[..], b"*1\r\n*1\r\n$1\r\na\r\n"] {
+            let (b, mut other) = connect(&mut server);
+            server.client_mut(b).unwrap().query_buffer.extend_from_slice(request);
+            server.ready.push_back(b);
+            server.process_ready_clients();
+
+            assert!(matches!(receive(&mut other, &mut input), RESPType::Error(e) if e.starts_with(b"ERR Protocol error")));
+            assert!(server.client(b).is_none());
+        }
+    }
+
+    #[test]
+    fn test_client_pause() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (a, mut theirs) = connect(&mut server);
+        let (b, mut other) = connect(&mut server);
+        let (mut input, mut other_input) = (QueryBuffer::new(), QueryBuffer::new());
+
+        send(&mut server, a, &["CLIENT", "PAUSE", "100000", "WRITE"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::SimpleString("OK".into()));
+
+        // Reads go ahead, but writes wait for the pause to end.
+        send(&mut server, b, &["GET", "key"]);
+        assert_eq!(receive(&mut other, &mut other_input), RESPType::Null);
+
+        send(&mut server, b, &["SET", "key", "1"]);
+        assert!(server.db.get(&"key".into()).is_none());
+
+        send(&mut server, a, &["CLIENT", "UNPAUSE"]);
+
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::SimpleString("OK".into()));
+        assert_eq!(receive(&mut other, &mut other_input), RESPType::SimpleString("OK".into()));
+        assert!(server.db.get(&"key".into()).is_some());
+    }
+
+    #[test]
+    fn test_client_reply() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (a, mut theirs) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        send(&mut server, a, &["CLIENT", "REPLY", "OFF"]);
+        send(&mut server, a, &["SET", "key", "1"]);
+        send(&mut server, a, &["CLIENT", "REPLY", "ON"]);
+        send(&mut server, a, &["CLIENT", "REPLY", "SKIP"]);
+        send(&mut server, a, &["ECHO", "skipped"]);
+        send(&mut server, a, &["ECHO", "sent"]);
+
+        // Only the replies to turning replies back on and the last command are sent.
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::SimpleString("OK".into()));
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::BulkString("sent".into()));
+        assert!(server.db.get(&"key".into()).is_some());
+    }
+
+    #[test]
+    fn test_monitor() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (monitor, mut theirs) = connect(&mut server);
+        let (client, _other) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        send(&mut server, monitor, &["MONITOR"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::SimpleString("OK".into()));
+
+        // Rejected commands and those which could carry passwords are left out.
+        send(&mut server, client, &["GET"]);
+        send(&mut server, client, &["CONFIG", "GET", "hz"]);
+        send(&mut server, client, &["GET", "key"]);
+
+        let RESPType::SimpleString(line) = receive(&mut theirs, &mut input) else {
+            panic!("expected a simple string");
+        };
+
+        assert!(line.ends_with(b"\"GET\" \"key\""), "{:?}", line);
+    }
+
+    #[test]
+    fn test_protected_mode() {
+        let config = Config { bind: vec!["0.0.0.0".into()], port: 0, ..Default::default() };
+        let mut server = Server::build(config, Mode::Standalone);
+        let external = Address::Tcp("192.0.2.2:5000".parse().unwrap());
+
+        assert!(server.is_protected_from(&external));
+        assert!(!server.is_protected_from(&Address::Tcp("127.0.0.1:5000".parse().unwrap())));
+        assert!(!server.is_protected_from(&Address::Unix(String::new())));
+
+        let Some(ip) = external_ip() else {
+            eprintln!("Skipping the connection from another address, as there is none.");
+            return;
+        };
+
+        let poll = mio::Poll::new().unwrap();
+        let listeners = server.listen(poll.registry()).unwrap();
+
+        let mut theirs = TcpStream::connect((ip, server.config.port)).unwrap();
+        theirs.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        server.accept_clients(&listeners[0].0, None, poll.registry()).unwrap();
+
+        let mut reply = String::new();
+        theirs.read_to_string(&mut reply).unwrap();
+
+        assert!(reply.starts_with("-DENIED "), "{}", reply);
+        assert_eq!(server.clients.len(), 0);
+
+        // A password keeps other machines out instead.
+        server.config.requirepass = Some("secret".into());
+        assert!(!server.is_protected_from(&external));
+
+        let _theirs = TcpStream::connect((ip, server.config.port)).unwrap();
+        server.accept_clients(&listeners[0].0, None, poll.registry()).unwrap();
+
+        assert_eq!(server.clients.len(), 1);
+    }
+
+    #[test]
+    fn test_auth() {
+        let config = Config { requirepass: Some("secret".into()), ..Default::default() };
+        let mut server = Server::build(config, Mode::Standalone);
+        let (client, mut theirs) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        send(&mut server, client, &["PING"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::Error("NOAUTH Authentication required.".into()));
+
+        send(&mut server, client, &["AUTH", "wrong"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::Error("WRONGPASS invalid username-password pair or user is disabled.".into()));
+
+        send(&mut server, client, &["AUTH", "default", "secret"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::SimpleString("OK".into()));
+
+        send(&mut server, client, &["PING"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::SimpleString("PONG".into()));
+
+        // Without a password, every client is let in.
+        server.config.requirepass = None;
+        let (other, mut theirs) = connect(&mut server);
+
+        send(&mut server, other, &["AUTH", "secret"]);
+        assert!(matches!(receive(&mut theirs, &mut input), RESPType::Error(e) if e.starts_with(b"AUTH <password> called without")));
+    }
+
+    #[test]
+    fn test_shutdown_waits_for_replicas() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (replica, mut stream) = connect_replica(&mut server);
+        let (client, mut theirs) = connect(&mut server);
+        let (mut input, mut replica_input) = (QueryBuffer::new(), QueryBuffer::new());
+
+        send(&mut server, client, &["SET", "key", "1"]);
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::SimpleString("OK".into()));
+
+        // The replica has not acknowledged the write, so it is asked to, and waited for.
+        send(&mut server, client, &["SHUTDOWN", "NOSAVE"]);
+        server.check_shutdown();
+
+        assert!(!server.exiting);
+        assert_eq!(receive(&mut stream, &mut replica_input), command(&["SET", "key", "1"]));
+        assert_eq!(receive(&mut stream, &mut replica_input), command(&["REPLCONF", "GETACK", "*"]));
+
+        let offset = server.replication.offset.to_string();
+        send(&mut server, replica, &["REPLCONF", "ACK", &offset]);
+        server.check_shutdown();
+
+        assert!(server.exiting);
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (_, _stream) = connect_replica(&mut server);
+        let (client, _theirs) = connect(&mut server);
+        let (other, _other) = connect(&mut server);
+
+        server.propagate(arguments(&["SET", "key", "1"]));
+        send(&mut server, client, &["SHUTDOWN", "NOSAVE"]);
+
+        // Writes wait along with the shutdown.
+        send(&mut server, other, &["SET", "other", "1"]);
+        assert!(server.db.get(&"other".into()).is_none());
+
+        server.check_shutdown();
+        assert!(!server.exiting);
+
+        // The replica gets until `shutdown-timeout` to catch up.
+        server.shutdown.as_mut().unwrap().deadline = Instant::now();
+        server.check_shutdown();
+
+        assert!(server.exiting);
+    }
+
+    #[test]
+    fn test_shutdown_abort() {
+        let mut server = Server::build(Config::default(), Mode::Standalone);
+        let (_, _stream) = connect_replica(&mut server);
+        let (client, mut theirs) = connect(&mut server);
+        let (other, mut other_stream) = connect(&mut server);
+        let (mut input, mut other_input) = (QueryBuffer::new(), QueryBuffer::new());
+
+        server.propagate(arguments(&["SET", "key", "1"]));
+        send(&mut server, client, &["SHUTDOWN", "NOSAVE"]);
+        send(&mut server, other, &["SHUTDOWN", "ABORT"]);
+
+        assert_eq!(receive(&mut other_stream, &mut other_input), RESPType::SimpleString("OK".into()));
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::Error("Errors trying to SHUTDOWN. Check logs.".into()));
+        assert!(server.shutdown.is_none() && server.pause.is_none());
+
+        // Without waiting, there is nothing to abort.
+        send(&mut server, client, &["SHUTDOWN", "NOW", "NOSAVE"]);
+        assert!(server.exiting);
+    }
+
+    #[test]
+    fn test_shutdown_save_failure() {
+        let config = Config { dbfilename: Some("/nonexistent/dump.rdb".into()), ..Default::default() };
+        let mut server = Server::build(config, Mode::Standalone);
+        let (client, mut theirs) = connect(&mut server);
+        let mut input = QueryBuffer::new();
+
+        send(&mut server, client, &["SHUTDOWN"]);
+
+        assert_eq!(receive(&mut theirs, &mut input), RESPType::Error("Errors trying to SHUTDOWN. Check logs.".into()));
+        assert!(!server.exiting);
+
+        send(&mut server, client, &["SHUTDOWN", "FORCE"]);
+        assert!(server.exiting);
+    }
+}
@@ -0,0 +1,89 @@
+//! Shutting down on SIGTERM and SIGINT. The handlers only record the signal, which the event
+//! loop picks up on its next pass.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+
+/// The signal which has not been picked up yet, or zero.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+/// Set from the first signal until a shutdown fails.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+
+extern "C" fn handle(signal: libc::c_int) {
+    // A second SIGINT while shutting down means that whoever pressed Ctrl-C is done waiting.
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) && signal == libc::SIGINT {
+        // SAFETY: _exit is async signal safe, unlike the exit which runs destructors.
+        unsafe { libc::_exit(1) };
+    }
+
+    RECEIVED.store(signal, Ordering::SeqCst);
+}
+
+
+/// Install the handlers. They are installed without SA_RESTART, so a signal also interrupts
+/// the wait for events.
+pub fn install() -> io::Result<()> {
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        // SAFETY: the handler only uses atomics and _exit.
+        let result = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut())
+        };
+
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+
+/// The name of the signal received since the last call, if any.
+pub fn take() -> Option<&'static str> {
+    match RECEIVED.swap(0, Ordering::SeqCst) {
+        libc::SIGTERM => Some("SIGTERM"),
+        libc::SIGINT => Some("SIGINT"),
+        _ => None,
+    }
+}
+
+
+/// Go back to running normally after a shutdown failed, so that a second SIGINT does not
+/// exit at once.
+pub fn reset() {
+    SHUTTING_DOWN.store(false, Ordering::SeqCst);
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use crate::signal::{self, SHUTTING_DOWN};
+
+    #[test]
+    fn test_signals() {
+        signal::install().unwrap();
+        assert_eq!(signal::take(), None);
+
+        // SAFETY: raising a signal is safe, and the handler stops it from ending the process.
+        unsafe { libc::raise(libc::SIGTERM) };
+
+        assert_eq!(signal::take(), Some("SIGTERM"));
+        assert_eq!(signal::take(), None);
+        assert!(SHUTTING_DOWN.load(Ordering::SeqCst));
+
+        // Once a shutdown has failed, a SIGINT is recorded like the first rather than exiting.
+        signal::reset();
+        unsafe { libc::raise(libc::SIGINT) };
+
+        assert_eq!(signal::take(), Some("SIGINT"));
+        signal::reset();
+    }
+}
@@ -0,0 +1,129 @@
+//! A list of values which reuses the slots of removed values. Values are found by keys which
+//! include the generation of their slot, so a key kept after its value was removed does not
+//! find the value which took the slot later.
+
+
+/// The low bits of a key are the index of the slot, and the bits above them its generation.
+const INDEX_BITS: u32 = 32;
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+/// Generations wrap before they reach the top bits, which are left free for the tokens of
+/// other kinds of connections.
+const GENERATION_MASK: usize = (1 << 30) - 1;
+
+
+#[derive(Debug)]
+struct Slot<T> {
+    generation: usize,
+    value: Option<T>,
+}
+
+
+#[derive(Debug)]
+pub struct Slab<T> {
+    slots: Vec<Slot<T>>,
+    /// The indexes of the empty slots, the most recently emptied last.
+    free: Vec<usize>,
+    len: usize,
+}
+
+
+impl<T> Slab<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Slab { slots: Vec::with_capacity(capacity), free: Vec::new(), len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Store a value in an empty slot, returning its key.
+    pub fn insert(&mut self, value: T) -> usize {
+        self.len += 1;
+
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot { generation: 0, value: None });
+                self.slots.len() - 1
+            },
+        };
+
+        let slot = &mut self.slots[index];
+        slot.value = Some(value);
+
+        (slot.generation << INDEX_BITS) | index
+    }
+
+    pub fn get(&self, key: usize) -> Option<&T> {
+        let slot = self.slots.get(key & INDEX_MASK)?;
+
+        if slot.generation != key >> INDEX_BITS {
+            return None;
+        }
+
+        slot.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut T> {
+        let slot = self.slots.get_mut(key & INDEX_MASK)?;
+
+        if slot.generation != key >> INDEX_BITS {
+            return None;
+        }
+
+        slot.value.as_mut()
+    }
+
+    /// Take a value out, making its key stale.
+    pub fn remove(&mut self, key: usize) -> Option<T> {
+        let index = key & INDEX_MASK;
+        let slot = self.slots.get_mut(index)?;
+
+        if slot.generation != key >> INDEX_BITS {
+            return None;
+        }
+
+        let value = slot.value.take()?;
+        slot.generation = (slot.generation + 1) & GENERATION_MASK;
+
+        self.free.push(index);
+        self.len -= 1;
+
+        Some(value)
+    }
+
+    /// The values with their keys, in the order of their slots.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, s)| Some(((s.generation << INDEX_BITS) | i, s.value.as_ref()?)))
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().filter_map(|s| s.value.as_mut())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::slab::Slab;
+
+    #[test]
+    fn test_reuse() {
+        let mut slab = Slab::with_capacity(2);
+
+        let a = slab.insert("a");
+        let b = slab.insert("b");
+        assert_eq!((slab.get(a), slab.get(b), slab.len()), (Some(&"a"), Some(&"b"), 2));
+
+        assert_eq!(slab.remove(a), Some("a"));
+        assert_eq!(slab.remove(a), None);
+
+        // The slot is reused, but the stale key does not find the new value.
+        let c = slab.insert("c");
+        assert_ne!(a, c);
+        assert_eq!((slab.get(a), slab.get(c)), (None, Some(&"c")));
+        assert_eq!(slab.iter().collect::<Vec<_>>(), vec![(c, &"c"), (b, &"b")]);
+        assert_eq!(slab.len(), 2);
+    }
+}
@@ -0,0 +1,156 @@
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use sider_command::RESPType;
+
+
+/// Long commands are cut down to this many arguments, with the last one saying how many were
+/// left out, so that the log does not take up too much memory.
+const MAX_ARGS: usize = 32;
+/// Long arguments are cut down to this many bytes.
+const MAX_ARG_LEN: usize = 128;
+
+
+/// A command which took longer than `slowlog-log-slower-than` to run.
+#[derive(Debug)]
+pub struct Entry {
+    pub id: u64,
+    /// When the command was run, as a Unix timestamp.
+    pub time: u64,
+    pub duration: Duration,
+    /// The command and its arguments, cut down to size.
+    pub args: Vec<Bytes>,
+    pub address: String,
+    pub name: Bytes,
+}
+
+
+/// The slowest recent commands, newest first, as reported by SLOWLOG.
+#[derive(Debug, Default)]
+pub struct SlowLog {
+    entries: VecDeque<Entry>,
+    next_id: u64,
+}
+
+
+/// The arguments of a command, kept while it runs in case it turns out to be slow. Only the
+/// arguments which would be logged are kept, and they share their bytes with the command, so
+/// nothing is allocated.
+pub struct Arguments {
+    args: [Option<Bytes>; MAX_ARGS],
+    /// The number of arguments in the whole command.
+    count: usize,
+}
+
+
+impl Arguments {
+    pub fn new(command: &[RESPType<Bytes>]) -> Self {
+        let args = std::array::from_fn(|i| match command.get(i) {
+            Some(RESPType::BulkString(s) | RESPType::SimpleString(s)) => Some(s.clone()),
+            Some(_) => Some(Bytes::new()),
+            None => None,
+        });
+
+        Arguments { args, count: command.len() }
+    }
+
+    /// Cut the command down to the size it is logged at.
+    fn truncate(&self) -> Vec<Bytes> {
+        let kept = if self.count > MAX_ARGS { MAX_ARGS - 1 } else { self.count };
+
+        let mut args: Vec<Bytes> = self.args[..kept].iter()
+            .flatten()
+            .map(|arg| {
+                if arg.len() <= MAX_ARG_LEN {
+                    return arg.clone();
+                }
+
+                let mut cut = arg[..MAX_ARG_LEN].to_vec();
+                cut.extend_from_slice(format!("... ({} more bytes)", arg.len() - MAX_ARG_LEN).as_bytes());
+                cut.into()
+            })
+            .collect();
+
+        if kept < self.count {
+            args.push(format!("... ({} more arguments)", self.count - kept).into());
+        }
+
+        args
+    }
+}
+
+
+impl SlowLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Log a slow command, dropping the oldest entries to keep to `max_len`.
+    pub fn record(&mut self, command: &Arguments, duration: Duration, address: String, name: Bytes, max_len: usize) {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        self.entries.push_front(Entry {
+            id: self.next_id,
+            time,
+            duration,
+            args: command.truncate(),
+            address,
+            name,
+        });
+
+        self.next_id += 1;
+        self.entries.truncate(max_len);
+    }
+
+    /// The entries, newest first.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use sider_command::RESPType;
+    use crate::slowlog::{Arguments, SlowLog};
+
+    #[test]
+    fn test_record() {
+        let mut log = SlowLog::new();
+        let bulk = |s: String| RESPType::BulkString(Bytes::from(s));
+
+        let mut command = vec![bulk("RPUSH".into()), bulk("x".repeat(200))];
+        command.extend((0..40).map(|i| bulk(i.to_string())));
+
+        log.record(&Arguments::new(&command), Duration::from_millis(20), "127.0.0.1:1234".into(), Bytes::new(), 2);
+        log.record(&Arguments::new(&[bulk("GET".into())]), Duration::from_millis(30), "127.0.0.1:1234".into(), Bytes::new(), 2);
+        log.record(&Arguments::new(&[bulk("SET".into())]), Duration::from_millis(40), "127.0.0.1:1234".into(), Bytes::new(), 2);
+
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.entries().map(|e| e.id).collect::<Vec<_>>(), vec![2, 1]);
+
+        let mut log = SlowLog::new();
+        log.record(&Arguments::new(&command), Duration::from_millis(20), "127.0.0.1:1234".into(), Bytes::new(), 2);
+
+        let args = &log.entries().next().unwrap().args;
+        assert_eq!(args.len(), 32);
+        assert_eq!(args[1], Bytes::from(format!("{}... (72 more bytes)", "x".repeat(128))));
+        assert_eq!(args[31], Bytes::from("... (11 more arguments)"));
+    }
+}
@@ -0,0 +1,299 @@
+
+use std::collections::VecDeque;
+
+use bytes::{BufMut, Bytes};
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::db::{DBEntry, DBString, DB};
+use crate::sorted_set::SortedSet;
+
+
+const MAGIC: &[u8] = b"SIDER";
+const VERSION: u8 = 1;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_INTEGER: u8 = 1;
+const TYPE_LIST: u8 = 2;
+const TYPE_SORTED_SET: u8 = 3;
+
+/// Precedes an entry which has an expiry time, given in milliseconds since the epoch.
+const OPCODE_EXPIRY: u8 = 0xfc;
+const OPCODE_EOF: u8 = 0xff;
+
+
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+    /// The data does not start with the snapshot header, or was written by an unknown version.
+    InvalidHeader,
+    /// The data ends part way through an entry.
+    Truncated,
+    /// An entry has a type or an expiry time that could not be understood.
+    Corrupted,
+}
+
+
+/// Serialize every entry in the database.
+///
+/// The layout is loosely based on the RDB format. A header of the magic string "SIDER" and a
+/// version byte is followed by the entries, and then an EOF opcode. Each entry is an optional
+/// expiry opcode and time, a type byte, the key, and the value. Strings are written as a
+/// little endian 64 bit length followed by the bytes, and collections as a 64 bit count
+/// followed by their elements.
+pub fn dump(db: &DB) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    out.put_slice(MAGIC);
+    out.put_u8(VERSION);
+
+    for (key, entry, expiry) in db.entries() {
+        if entry.is_nil() {
+            continue;
+        }
+
+        if let Some(e) = expiry {
+            out.put_u8(OPCODE_EXPIRY);
+            out.put_i64_le(e.timestamp_millis());
+        }
+
+        out.put_u8(type_byte(entry));
+        put_string(&mut out, key);
+        put_value(&mut out, entry);
+    }
+
+    out.put_u8(OPCODE_EOF);
+
+    out
+}
+
+
+/// Serialize a single value, for DUMP and MIGRATE. The value is followed by the version, so
+/// that a payload from an incompatible version is rejected rather than misread.
+pub fn dump_value(entry: &DBEntry) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    out.put_u8(type_byte(entry));
+    put_value(&mut out, entry);
+    out.put_u8(VERSION);
+
+    out
+}
+
+
+/// Read a value serialized by `dump_value`.
+pub fn load_value(bytes: &[u8]) -> Result<DBEntry, SnapshotError> {
+    let Some((&VERSION, bytes)) = bytes.split_last() else {
+        return Err(SnapshotError::InvalidHeader);
+    };
+
+    let mut reader = Reader { bytes, position: 0 };
+    let kind = reader.u8()?;
+    let entry = read_value(&mut reader, kind)?;
+
+    if reader.position != bytes.len() {
+        return Err(SnapshotError::Corrupted);
+    }
+
+    Ok(entry)
+}
+
+
+fn type_byte(entry: &DBEntry) -> u8 {
+    match entry {
+        DBEntry::Nil => unreachable!(),
+        DBEntry::String(DBString::Integer(_)) => TYPE_INTEGER,
+        DBEntry::String(_) => TYPE_STRING,
+        DBEntry::List(_) => TYPE_LIST,
+        DBEntry::SortedSet(_) => TYPE_SORTED_SET,
+    }
+}
+
+
+fn put_value(out: &mut Vec<u8>, entry: &DBEntry) {
+    match entry {
+        DBEntry::Nil => unreachable!(),
+        DBEntry::String(DBString::Integer(i)) => out.put_i64_le(*i),
+        DBEntry::String(s) => put_string(out, &s.as_slice()),
+        DBEntry::List(l) => {
+            out.put_u64_le(l.len() as u64);
+
+            for item in l {
+                put_string(out, item);
+            }
+        },
+        DBEntry::SortedSet(z) => {
+            out.put_u64_le(z.len() as u64);
+
+            for (member, score) in z.iter() {
+                put_string(out, member);
+                out.put_f64_le(score);
+            }
+        },
+    }
+}
+
+
+fn put_string(out: &mut Vec<u8>, s: &[u8]) {
+    out.put_u64_le(s.len() as u64);
+    out.put_slice(s);
+}
+
+
+/// Build a database from the output of `dump`.
+pub fn load(bytes: &[u8]) -> Result<DB, SnapshotError> {
+    if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC || bytes[MAGIC.len()] != VERSION {
+        return Err(SnapshotError::InvalidHeader);
+    }
+
+    let mut reader = Reader { bytes, position: MAGIC.len() + 1 };
+    let mut db = DB::new();
+
+    loop {
+        let mut opcode = reader.u8()?;
+        let mut expiry = None;
+
+        if opcode == OPCODE_EOF {
+            return Ok(db);
+        }
+
+        if opcode == OPCODE_EXPIRY {
+            expiry = Some(reader.timestamp()?);
+            opcode = reader.u8()?;
+        }
+
+        let key = reader.string()?;
+
+        let entry = read_value(&mut reader, opcode)?;
+
+        db.insert(key, entry, expiry);
+    }
+}
+
+
+fn read_value(reader: &mut Reader, kind: u8) -> Result<DBEntry, SnapshotError> {
+    Ok(match kind {
+        TYPE_STRING => DBEntry::String(DBString::String(reader.string()?)),
+        TYPE_INTEGER => DBEntry::String(DBString::Integer(reader.i64()?)),
+        TYPE_LIST => {
+            let len = reader.u64()?;
+            let mut l = VecDeque::new();
+
+            for _ in 0..len {
+                l.push_back(reader.string()?.to_vec());
+            }
+
+            DBEntry::List(l)
+        },
+        TYPE_SORTED_SET => {
+            let len = reader.u64()?;
+            let mut z = SortedSet::new();
+
+            for _ in 0..len {
+                let member = reader.string()?;
+                z.insert(member, f64::from_bits(reader.u64()?));
+            }
+
+            DBEntry::SortedSet(z)
+        },
+        _ => return Err(SnapshotError::Corrupted),
+    })
+}
+
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self.position.checked_add(len).ok_or(SnapshotError::Truncated)?;
+        let b = self.bytes.get(self.position..end).ok_or(SnapshotError::Truncated)?;
+
+        self.position = end;
+
+        Ok(b)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64, SnapshotError> {
+        Ok(self.u64()? as i64)
+    }
+
+    fn string(&mut self) -> Result<Bytes, SnapshotError> {
+        let len = usize::try_from(self.u64()?).map_err(|_| SnapshotError::Truncated)?;
+
+        Ok(Bytes::copy_from_slice(self.take(len)?))
+    }
+
+    fn timestamp(&mut self) -> Result<DateTime<Utc>, SnapshotError> {
+        Utc.timestamp_millis_opt(self.i64()?).single().ok_or(SnapshotError::Corrupted)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::db::{DBEntry, DBString, DB};
+    use crate::snapshot::{dump, dump_value, load, load_value, SnapshotError};
+    use crate::sorted_set::SortedSet;
+
+    #[test]
+    fn test_round_trip() {
+        let mut db = DB::new();
+        let expiry = Utc::now() + Duration::hours(1);
+
+        let mut z = SortedSet::new();
+        z.insert(Bytes::from("a"), 1.5);
+
+        db.insert(Bytes::from("s"), DBEntry::String(DBString::String("hello".into())), Some(expiry));
+        db.insert(Bytes::from("i"), DBEntry::String(DBString::Integer(-7)), None);
+        db.insert(Bytes::from("l"), DBEntry::List(vec![b"x".to_vec(), b"y".to_vec()].into()), None);
+        db.insert(Bytes::from("z"), DBEntry::SortedSet(z.clone()), None);
+
+        let mut loaded = load(&dump(&db)).unwrap();
+
+        assert_eq!(loaded.get(&Bytes::from("s")), Some(&DBEntry::String(DBString::String("hello".into()))));
+        assert_eq!(loaded.get(&Bytes::from("i")), Some(&DBEntry::String(DBString::Integer(-7))));
+        assert_eq!(loaded.get(&Bytes::from("l")), Some(&DBEntry::List(vec![b"x".to_vec(), b"y".to_vec()].into())));
+        assert_eq!(loaded.get(&Bytes::from("z")), Some(&DBEntry::SortedSet(z)));
+
+        let expiries: Vec<_> = loaded.entries().filter_map(|(_, _, e)| e).collect();
+        assert_eq!(expiries, vec![Utc.timestamp_millis_opt(expiry.timestamp_millis()).unwrap()]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut db = DB::new();
+        db.insert(Bytes::from("k"), DBEntry::String(DBString::String("v".into())), None);
+
+        let bytes = dump(&db);
+
+        assert_eq!(load(&bytes[..bytes.len() - 3]).err(), Some(SnapshotError::Truncated));
+        assert_eq!(load(b"REDIS").err(), Some(SnapshotError::InvalidHeader));
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let entry = DBEntry::List(vec![b"x".to_vec(), b"y".to_vec()].into());
+        let bytes = dump_value(&entry);
+
+        let mut padded = bytes.clone();
+        padded.insert(bytes.len() - 1, 0);
+
+        assert_eq!(load_value(&bytes), Ok(entry));
+        assert_eq!(load_value(&padded).err(), Some(SnapshotError::Corrupted));
+        assert_eq!(load_value(&bytes[..bytes.len() - 1]).err(), Some(SnapshotError::InvalidHeader));
+    }
+}
@@ -0,0 +1,108 @@
+
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+
+/// A sorted set score. Scores are ordered using the IEEE 754 total order so that they can be
+/// used as keys in ordered collections.
+#[derive(Debug, Clone, Copy)]
+pub struct Score(pub f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+
+/// A set of unique members, each with a score, ordered by score and then by member.
+///
+/// Members are indexed both by name, to look up scores, and by score, to find ranges of
+/// members. This is the same structure Redis uses for large sorted sets, except with a B-tree
+/// in place of the skip list.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    ordered: BTreeSet<(Score, Bytes)>,
+}
+
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Set the score of a member, returning its previous score if it was already present.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        let previous = self.scores.insert(member.clone(), score);
+
+        if let Some(p) = previous {
+            self.ordered.remove(&(Score(p), member.clone()));
+        }
+
+        self.ordered.insert((Score(score), member));
+
+        previous
+    }
+
+    /// Iterate over the members with scores in the range `min..max`, in order.
+    pub fn range_by_score(&self, min: f64, max: f64) -> impl Iterator<Item = (&Bytes, f64)> {
+        // The empty member sorts before every other member with the same score.
+        let range = (Bound::Included((Score(min), Bytes::new())), Bound::Excluded((Score(max), Bytes::new())));
+
+        self.ordered.range(range).map(|(s, m)| (m, s.0))
+    }
+
+    /// Iterate over every member, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> {
+        self.ordered.iter().map(|(s, m)| (m, s.0))
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::sorted_set::SortedSet;
+
+    #[test]
+    fn test_insert_and_range() {
+        let mut z = SortedSet::new();
+
+        assert_eq!(z.insert(Bytes::from("a"), 1.0), None);
+        assert_eq!(z.insert(Bytes::from("b"), 2.0), None);
+        assert_eq!(z.insert(Bytes::from("c"), 3.0), None);
+        assert_eq!(z.insert(Bytes::from("a"), 4.0), Some(1.0));
+
+        let members: Vec<&[u8]> = z.range_by_score(2.0, 4.0).map(|(m, _)| &m[..]).collect();
+        assert_eq!(members, vec![&b"b"[..], &b"c"[..]]);
+        assert_eq!(z.len(), 3);
+    }
+}
@@ -0,0 +1,159 @@
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+
+/// How often the number of commands processed is sampled for the instantaneous rate, and how
+/// many samples it is averaged over.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+const SAMPLES: usize = 16;
+
+
+/// The counters INFO commandstats reports for each command.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CommandStats {
+    pub calls: u64,
+    /// The total time spent running the command, in microseconds.
+    pub usec: u64,
+    /// Calls refused before the command ran, for example because the keys are served by
+    /// another cluster node.
+    pub rejected_calls: u64,
+    /// Calls where the command ran and replied with an error.
+    pub failed_calls: u64,
+}
+
+
+/// Counters reported by INFO, which CONFIG RESETSTAT sets back to zero.
+#[derive(Debug)]
+pub struct Stats {
+    pub total_connections_received: u64,
+    /// Connections refused because `maxclients` were already connected.
+    pub rejected_connections: u64,
+    pub total_commands_processed: u64,
+    pub total_error_replies: u64,
+    pub commands: HashMap<&'static str, CommandStats>,
+    /// The number of error replies by error code, like `ERR` or `WRONGTYPE`.
+    pub errors: HashMap<String, u64>,
+    /// Recent rates of commands processed per second, as a ring buffer.
+    ops_samples: [u64; SAMPLES],
+    next_sample: usize,
+    last_sample: Instant,
+    last_sample_commands: u64,
+}
+
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            total_connections_received: 0,
+            rejected_connections: 0,
+            total_commands_processed: 0,
+            total_error_replies: 0,
+            commands: HashMap::new(),
+            errors: HashMap::new(),
+            ops_samples: [0; SAMPLES],
+            next_sample: 0,
+            last_sample: Instant::now(),
+            last_sample_commands: 0,
+        }
+    }
+}
+
+
+/// The code an error reply starts with, which is its first word if that is in capitals, as in
+/// `WRONGTYPE Operation against a key holding the wrong kind of value`. Other errors are
+/// counted as `ERR`.
+pub fn error_code(message: &[u8]) -> &str {
+    let word = message.split(|c| *c == b' ').next().unwrap_or_default();
+
+    match std::str::from_utf8(word) {
+        Ok(code) if !code.is_empty() && code.bytes().all(|c| c.is_ascii_uppercase() || c == b'_') => code,
+        _ => "ERR",
+    }
+}
+
+
+impl Stats {
+    pub fn command(&mut self, name: &'static str) -> &mut CommandStats {
+        self.commands.entry(name).or_default()
+    }
+
+    pub fn record_call(&mut self, name: &'static str, duration: Duration, failed: bool) {
+        self.total_commands_processed += 1;
+
+        let stats = self.command(name);
+        stats.calls += 1;
+        stats.usec += duration.as_micros() as u64;
+        stats.failed_calls += failed as u64;
+    }
+
+    pub fn record_error(&mut self, message: &Bytes) {
+        let code = error_code(message);
+
+        // Redirects are part of how clients find keys in a cluster rather than failures.
+        if code == "MOVED" || code == "ASK" {
+            return;
+        }
+
+        self.total_error_replies += 1;
+        *self.errors.entry(code.to_string()).or_default() += 1;
+    }
+
+    /// Take a sample of the rate commands are being processed at, if one is due. This is
+    /// called from the background tasks.
+    pub fn sample_ops(&mut self) {
+        let elapsed = self.last_sample.elapsed();
+
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+
+        let commands = self.total_commands_processed - self.last_sample_commands;
+
+        self.ops_samples[self.next_sample] = commands * 1000 / (elapsed.as_millis() as u64).max(1);
+        self.next_sample = (self.next_sample + 1) % SAMPLES;
+        self.last_sample = Instant::now();
+        self.last_sample_commands = self.total_commands_processed;
+    }
+
+    pub fn instantaneous_ops_per_sec(&self) -> u64 {
+        self.ops_samples.iter().sum::<u64>() / SAMPLES as u64
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use crate::stats::{error_code, Stats};
+
+    #[test]
+    fn test_error_code() {
+        assert_eq!(error_code(b"WRONGTYPE Operation against a key holding the wrong kind of value"), "WRONGTYPE");
+        assert_eq!(error_code(b"wrong number of arguments"), "ERR");
+        assert_eq!(error_code(b"Invalid command."), "ERR");
+        assert_eq!(error_code(b""), "ERR");
+    }
+
+    #[test]
+    fn test_record() {
+        let mut stats = Stats::default();
+
+        stats.record_call("get", Duration::from_micros(10), false);
+        stats.record_call("get", Duration::from_micros(20), true);
+        stats.command("get").rejected_calls += 1;
+        stats.record_error(&Bytes::from("MOVED 1 127.0.0.1:7000"));
+        stats.record_error(&Bytes::from("READONLY You can't write against a read only replica."));
+
+        let get = stats.commands["get"];
+        assert_eq!((get.calls, get.usec, get.failed_calls, get.rejected_calls), (2, 30, 1, 1));
+        assert_eq!((stats.total_commands_processed, stats.total_error_replies), (2, 1));
+        assert_eq!(stats.errors["READONLY"], 1);
+    }
+}
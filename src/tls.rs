@@ -0,0 +1,470 @@
+//! TLS for client connections and replication links, configured by the `tls-*` parameters.
+
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, IoSlice, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::Path;
+use std::sync::Arc;
+
+use mio::event::Source;
+use mio::{Interest, Registry, Token};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, ClientConnection, Connection, RootCertStore, ServerConfig, ServerConnection};
+
+use crate::config::Config;
+use crate::net::{Address, Socket};
+
+
+/// Whether clients connecting over TLS have to present a certificate, as set by
+/// `tls-auth-clients`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthClients {
+    No,
+    Yes,
+    /// Clients which present a certificate have it checked, but they do not have to.
+    Optional,
+}
+
+
+/// Who a client with a verified certificate is taken to be, as set by
+/// `tls-auth-clients-user`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthClientsUser {
+    /// The certificate only gets the client through the handshake.
+    Off,
+    /// The client is authenticated as the user named by the certificate's Common Name.
+    CommonName,
+}
+
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    let certs: Vec<_> = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Could not read certificates from {}: {}", path.display(), e))?;
+
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", path.display()));
+    }
+
+    Ok(certs)
+}
+
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("Could not read the private key from {}: {}", path.display(), e))?
+        .ok_or_else(|| format!("No private key found in {}", path.display()))
+}
+
+
+fn load_roots(config: &Config) -> Result<Arc<RootCertStore>, String> {
+    let Some(path) = &config.tls_ca_cert_file else {
+        return Err("tls-ca-cert-file is needed to check the certificates of peers".into());
+    };
+
+    let mut roots = RootCertStore::empty();
+
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(|e| format!("Invalid CA certificate in {}: {}", path.display(), e))?;
+    }
+
+    Ok(Arc::new(roots))
+}
+
+
+/// The certificate and key we identify ourselves with, both to clients and to our primary.
+fn load_identity(config: &Config) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), String> {
+    let (Some(cert), Some(key)) = (&config.tls_cert_file, &config.tls_key_file) else {
+        return Err("tls-cert-file and tls-key-file are needed for TLS".into());
+    };
+
+    Ok((load_certs(cert)?, load_key(key)?))
+}
+
+
+/// Build the configuration for accepting clients on `tls-port`.
+pub fn server_config(config: &Config) -> Result<Arc<ServerConfig>, String> {
+    let (certs, key) = load_identity(config)?;
+
+    let builder = match config.tls_auth_clients {
+        AuthClients::No => ServerConfig::builder().with_no_client_auth(),
+        auth => {
+            let mut verifier = WebPkiClientVerifier::builder(load_roots(config)?);
+
+            if auth == AuthClients::Optional {
+                verifier = verifier.allow_unauthenticated();
+            }
+
+            ServerConfig::builder().with_client_cert_verifier(verifier.build().map_err(|e| e.to_string())?)
+        },
+    };
+
+    let server_config = builder.with_single_cert(certs, key).map_err(|e| format!("Invalid certificate or key: {}", e))?;
+
+    Ok(Arc::new(server_config))
+}
+
+
+/// Build the configuration for connecting to our primary when `tls-replication` is on. The
+/// primary's certificate has to be signed by our CA and name the host in `replicaof`, which
+/// may be an IP address. We present our own certificate, in case the primary checks those of
+/// its clients.
+pub fn client_config(config: &Config) -> Result<Arc<ClientConfig>, String> {
+    let (certs, key) = load_identity(config)?;
+
+    let client_config = ClientConfig::builder()
+        .with_root_certificates(load_roots(config)?)
+        .with_client_auth_cert(certs, key)
+        .map_err(|e| format!("Invalid certificate or key: {}", e))?;
+
+    Ok(Arc::new(client_config))
+}
+
+
+/// Split the next DER element off the front of `input`, returning its tag, its contents and
+/// what follows it.
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+
+    // Lengths from 128 up are given in the number of bytes the first byte's low bits say.
+    let (len, rest) = match first {
+        n if n < 0x80 => (n as usize, rest),
+        n => {
+            let count = (n & 0x7f) as usize;
+
+            if count == 0 || count > 4 || rest.len() < count {
+                return None;
+            }
+
+            (rest[..count].iter().fold(0, |len, b| len << 8 | *b as usize), &rest[count..])
+        },
+    };
+
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+
+/// The Common Name in the subject of a DER encoded certificate.
+fn common_name(cert: &[u8]) -> Option<String> {
+    const VERSION: u8 = 0xa0;
+    const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+    let (_, certificate, _) = der_element(cert)?;
+    let (_, mut fields, _) = der_element(certificate)?;
+
+    // Skip the version if there is one, then the serial number, signature algorithm, issuer
+    // and validity, to get to the subject.
+    if fields.first() == Some(&VERSION) {
+        fields = der_element(fields)?.2;
+    }
+
+    for _ in 0..4 {
+        fields = der_element(fields)?.2;
+    }
+
+    let (_, mut subject, _) = der_element(fields)?;
+    let mut name = None;
+
+    // The subject is a sequence of sets, each holding an attribute's type and value.
+    while !subject.is_empty() {
+        let (_, set, rest) = der_element(subject)?;
+        let (_, attribute, _) = der_element(set)?;
+        let (_, kind, value) = der_element(attribute)?;
+
+        if kind == COMMON_NAME {
+            name = Some(String::from_utf8_lossy(der_element(value)?.1).into_owned());
+        }
+
+        subject = rest;
+    }
+
+    name
+}
+
+
+/// A connection which may be encrypted. Both kinds are non-blocking: reads and writes return
+/// `WouldBlock` when the socket is not ready, and encrypted output which could not be sent
+/// yet is sent by `flush` once it is writable again.
+#[derive(Debug)]
+pub struct Stream {
+    socket: Socket,
+    tls: Option<Box<Connection>>,
+}
+
+
+impl Stream {
+    pub fn plain(socket: Socket) -> Self {
+        Stream { socket, tls: None }
+    }
+
+    /// Accept a client on the TLS listener.
+    pub fn accept(socket: Socket, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let connection = ServerConnection::new(config).map_err(io::Error::other)?;
+        Ok(Stream { socket, tls: Some(Box::new(connection.into())) })
+    }
+
+    /// Connect to a server over TLS, starting the handshake once the socket is connected.
+    pub fn connect(socket: Socket, config: Arc<ClientConfig>, host: &str) -> io::Result<Self> {
+        let name = ServerName::try_from(host.to_string()).map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+        let connection = ClientConnection::new(config, name).map_err(io::Error::other)?;
+        Ok(Stream { socket, tls: Some(Box::new(connection.into())) })
+    }
+
+    pub fn socket(&self) -> &Socket {
+        &self.socket
+    }
+
+    pub fn local_addr(&self) -> io::Result<Address> {
+        self.socket.local_addr()
+    }
+
+    /// The Common Name of the certificate the peer presented, once the handshake is done. The
+    /// certificate has been checked against `tls-ca-cert-file` by then.
+    pub fn peer_common_name(&self) -> Option<String> {
+        common_name(self.tls.as_ref()?.peer_certificates()?.first()?)
+    }
+
+    /// Send as much encrypted output as the socket takes.
+    fn write_tls(&mut self) -> io::Result<()> {
+        let Some(tls) = &mut self.tls else {
+            return Ok(());
+        };
+
+        while tls.wants_write() {
+            tls.write_tls(&mut self.socket)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like `write_tls`, for when the output can wait for the socket to become writable.
+    fn try_write_tls(&mut self) -> io::Result<()> {
+        match self.write_tls() {
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            result => result,
+        }
+    }
+}
+
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(tls) = &mut self.tls else {
+                return self.socket.read(buf);
+            };
+
+            match tls.reader().read(buf) {
+                Ok(n) => return Ok(n),
+                // The peer closed the connection without saying so first.
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(0),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {},
+                Err(e) => return Err(e),
+            }
+
+            if tls.read_tls(&mut self.socket)? == 0 {
+                return Ok(0);
+            }
+
+            let processed = tls.process_new_packets();
+
+            // Send the handshake messages, or the alert if the input was bad.
+            self.try_write_tls()?;
+            processed.map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        }
+    }
+}
+
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let Some(tls) = &mut self.tls else {
+                return self.socket.write(buf);
+            };
+
+            let written = tls.writer().write(buf)?;
+
+            if written > 0 || buf.is_empty() {
+                self.try_write_tls()?;
+                return Ok(written);
+            }
+
+            // The connection's buffer is full, so wait for the socket to take some of it.
+            self.write_tls()?;
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match &mut self.tls {
+            None => self.socket.write_vectored(bufs),
+            // The encrypted records are made from one buffer at a time anyway.
+            Some(_) => match bufs.iter().find(|b| !b.is_empty()) {
+                Some(buf) => self.write(buf),
+                None => Ok(0),
+            },
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &self.tls {
+            Some(_) => self.try_write_tls(),
+            None => self.socket.flush(),
+        }
+    }
+}
+
+
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+
+impl Source for Stream {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        self.socket.register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        self.socket.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.socket.deregister(registry)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig, StreamOwned};
+
+    use crate::config::Config;
+    use crate::net::Socket;
+    use crate::tls::{self, common_name, load_certs, load_key, AuthClients, Stream};
+
+    /// The certificates made by `tests/tls/generate.sh`.
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/tls").join(name)
+    }
+
+    fn config(auth: AuthClients, identity: &str) -> Config {
+        Config {
+            tls_cert_file: Some(fixture(&format!("{}.crt", identity))),
+            tls_key_file: Some(fixture(&format!("{}.key", identity))),
+            tls_ca_cert_file: Some(fixture("ca.crt")),
+            tls_auth_clients: auth,
+            ..Default::default()
+        }
+    }
+
+    /// A client which trusts our CA, presenting the certificate `identity` if there is one.
+    fn client_config(identity: Option<&str>) -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add(load_certs(&fixture("ca.crt")).unwrap().remove(0)).unwrap();
+
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+
+        Arc::new(match identity {
+            Some(identity) => {
+                let certs = load_certs(&fixture(&format!("{}.crt", identity))).unwrap();
+                builder.with_client_auth_cert(certs, load_key(&fixture(&format!("{}.key", identity))).unwrap()).unwrap()
+            },
+            None => builder.with_no_client_auth(),
+        })
+    }
+
+    /// Connect a client to a server over loopback, returning the Common Name of the client's
+    /// certificate once a request has made it through, or the error which ended the handshake.
+    fn handshake(server: Arc<ServerConfig>, client: Arc<ClientConfig>, name: &'static str) -> Result<Option<String>, String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let peer = thread::spawn(move || {
+            let connection = ClientConnection::new(client, name.try_into().unwrap()).unwrap();
+            let mut stream = StreamOwned::new(connection, TcpStream::connect(address).unwrap());
+
+            // Failures are seen from the server's side.
+            let _ = stream.write_all(b"PING\r\n");
+            let _ = stream.read(&mut [0; 16]);
+        });
+
+        let (socket, _) = listener.accept().unwrap();
+        socket.set_nonblocking(true).unwrap();
+
+        let mut stream = Stream::accept(Socket::Tcp(mio::net::TcpStream::from_std(socket)), server).unwrap();
+
+        let result = loop {
+            match stream.read(&mut [0; 16]) {
+                Ok(0) => break Err("closed".into()),
+                Ok(_) => break Ok(stream.peer_common_name()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(1)),
+                Err(e) => break Err(e.to_string()),
+            }
+        };
+
+        drop(stream);
+        peer.join().unwrap();
+
+        result
+    }
+
+    fn accept(auth: AuthClients, identity: Option<&str>) -> Result<Option<String>, String> {
+        handshake(tls::server_config(&config(auth, "server")).unwrap(), client_config(identity), "localhost")
+    }
+
+    #[test]
+    fn test_auth_clients_yes() {
+        assert_eq!(accept(AuthClients::Yes, Some("client")), Ok(Some("alice".into())));
+        assert!(accept(AuthClients::Yes, None).is_err());
+        assert!(accept(AuthClients::Yes, Some("other")).is_err());
+    }
+
+    #[test]
+    fn test_auth_clients_optional() {
+        assert_eq!(accept(AuthClients::Optional, Some("client")), Ok(Some("alice".into())));
+        assert_eq!(accept(AuthClients::Optional, None), Ok(None));
+        assert!(accept(AuthClients::Optional, Some("other")).is_err());
+    }
+
+    #[test]
+    fn test_auth_clients_no() {
+        // Certificates are not asked for, so even one the CA did not sign is never sent.
+        assert_eq!(accept(AuthClients::No, Some("other")), Ok(None));
+        assert_eq!(accept(AuthClients::No, None), Ok(None));
+    }
+
+    #[test]
+    fn test_replication_verifies_host() {
+        let server = || tls::server_config(&config(AuthClients::Yes, "server")).unwrap();
+        let client = || tls::client_config(&config(AuthClients::Yes, "client")).unwrap();
+
+        assert_eq!(handshake(server(), client(), "127.0.0.1"), Ok(Some("alice".into())));
+        assert_eq!(handshake(server(), client(), "localhost"), Ok(Some("alice".into())));
+        assert!(handshake(server(), client(), "sider.example.com").is_err());
+    }
+
+    #[test]
+    fn test_common_name() {
+        let name = |file: &str| common_name(&load_certs(&fixture(file)).unwrap()[0]);
+
+        assert_eq!(name("client.crt").as_deref(), Some("alice"));
+        assert_eq!(name("server.crt").as_deref(), Some("sider test server"));
+        assert_eq!(common_name(b"\x30\x05\x30\x03"), None);
+    }
+}
@@ -0,0 +1,154 @@
+//! Client side caching. Clients which turn on CLIENT TRACKING are told when keys they may have
+//! cached are modified, so that they can drop them.
+
+use std::collections::{HashMap, HashSet};
+
+use bytes::Bytes;
+
+
+/// How a client is tracking keys, as set by CLIENT TRACKING ON.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Tracking {
+    /// The ID of the client which is sent the invalidation messages instead of this one.
+    pub redirect: Option<u64>,
+    /// Broadcast mode, where the client is told about every key with one of its prefixes,
+    /// whether it read it or not.
+    pub bcast: bool,
+    pub prefixes: Vec<Bytes>,
+    /// Only track the keys read by the command after CLIENT CACHING YES.
+    pub optin: bool,
+    /// Track keys apart from those read by the command after CLIENT CACHING NO.
+    pub optout: bool,
+    /// Don't tell the client about keys it modified itself.
+    pub noloop: bool,
+    /// Set by CLIENT CACHING, for the next command only.
+    pub caching: Option<bool>,
+}
+
+
+impl Tracking {
+    /// Whether the keys read by a command should be remembered, with the setting of CLIENT
+    /// CACHING which was in effect for it.
+    pub fn tracks_reads(&self, caching: Option<bool>) -> bool {
+        if self.bcast {
+            return false;
+        }
+
+        if self.optin {
+            caching == Some(true)
+        } else if self.optout {
+            caching != Some(false)
+        } else {
+            true
+        }
+    }
+}
+
+
+/// The clients to tell about modifications to each key, as client IDs.
+#[derive(Debug, Default)]
+pub(crate) struct TrackingTable {
+    /// The keys read by clients in the default mode. A key is forgotten once the clients
+    /// which read it have been told that it was modified, until they read it again.
+    keys: HashMap<Bytes, HashSet<u64>>,
+    /// The prefixes of clients in broadcast mode.
+    prefixes: HashMap<Bytes, HashSet<u64>>,
+}
+
+
+impl TrackingTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember that a client read a key.
+    pub fn remember(&mut self, key: Bytes, client: u64) {
+        self.keys.entry(key).or_default().insert(client);
+    }
+
+    pub fn add_prefix(&mut self, prefix: Bytes, client: u64) {
+        self.prefixes.entry(prefix).or_default().insert(client);
+    }
+
+    pub fn remove_prefix(&mut self, prefix: &Bytes, client: u64) {
+        if let Some(clients) = self.prefixes.get_mut(prefix) {
+            clients.remove(&client);
+
+            if clients.is_empty() {
+                self.prefixes.remove(prefix);
+            }
+        }
+    }
+
+    /// Find the clients to tell that a key was modified, forgetting the clients which read
+    /// it.
+    pub fn invalidate(&mut self, key: &Bytes) -> HashSet<u64> {
+        let mut clients = self.keys.remove(key).unwrap_or_default();
+
+        for (prefix, subscribers) in &self.prefixes {
+            if key.starts_with(prefix) {
+                clients.extend(subscribers);
+            }
+        }
+
+        clients
+    }
+
+    /// The number of keys which are being tracked for clients in the default mode.
+    pub fn key_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn prefix_count(&self) -> usize {
+        self.prefixes.len()
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bytes::Bytes;
+
+    use crate::tracking::{Tracking, TrackingTable};
+
+    #[test]
+    fn test_invalidate() {
+        let mut table = TrackingTable::new();
+
+        table.remember(Bytes::from("user:1"), 1);
+        table.remember(Bytes::from("user:1"), 2);
+        table.remember(Bytes::from("post:1"), 2);
+        table.add_prefix(Bytes::from("user:"), 3);
+        table.add_prefix(Bytes::new(), 4);
+
+        assert_eq!(table.invalidate(&Bytes::from("user:1")), HashSet::from([1, 2, 3, 4]));
+        // The readers are only told once, until they read the key again.
+        assert_eq!(table.invalidate(&Bytes::from("user:1")), HashSet::from([3, 4]));
+        assert_eq!(table.invalidate(&Bytes::from("post:1")), HashSet::from([2, 4]));
+
+        table.remove_prefix(&Bytes::new(), 4);
+        assert_eq!(table.invalidate(&Bytes::from("other")), HashSet::new());
+        assert_eq!(table.key_count(), 0);
+        assert_eq!(table.prefix_count(), 1);
+    }
+
+    #[test]
+    fn test_tracks_reads() {
+        let default = Tracking::default();
+        assert!(default.tracks_reads(None));
+
+        let optin = Tracking { optin: true, ..Default::default() };
+        assert!(!optin.tracks_reads(None));
+        assert!(optin.tracks_reads(Some(true)));
+
+        let optout = Tracking { optout: true, ..Default::default() };
+        assert!(optout.tracks_reads(None));
+        assert!(!optout.tracks_reads(Some(false)));
+
+        let bcast = Tracking { bcast: true, ..Default::default() };
+        assert!(!bcast.tracks_reads(None));
+    }
+}
@@ -31,24 +31,487 @@ pub fn from_decimal_bytes(b: &[u8]) -> Result<i64, ()> {
 
     let mut result: u64 = 0;
 
-    for byte in b {
+    for byte in &b[start..] {
         if *byte < b'0' || *byte > b'9' {
             return Err(());
         }
 
-        result *= 10;
-        result += (byte - b'0') as u64;
+        result = match result.checked_mul(10).and_then(|r| r.checked_add((byte - b'0') as u64)) {
+            Some(r) => r,
+            None => return Err(()),
+        };
     }
 
     if is_negative {
-        if result > -(i64::MIN + 1) as u64 {
+        if result > i64::MIN.unsigned_abs() {
             return Err(());
         }
 
-        Ok(-(result as i64))
+        Ok((result as i64).wrapping_neg())
     } else if result > i64::MAX as u64 {
         Err(())
     } else {
         Ok(result as i64)
     }
 }
+
+
+/// Parse a floating point number in the same format accepted by Redis. Whitespace, NaN and
+/// empty strings are rejected.
+pub fn from_float_bytes(b: &[u8]) -> Result<f64, ()> {
+    let Ok(s) = std::str::from_utf8(b) else {
+        return Err(());
+    };
+
+    if s.is_empty() || s.trim() != s {
+        return Err(());
+    }
+
+    match s.parse::<f64>() {
+        Ok(f) if !f.is_nan() => Ok(f),
+        _ => Err(()),
+    }
+}
+
+
+/// Format a floating point number the way Redis does with `%.17Lg`: at most 17 significant
+/// digits without trailing zeros, and an exponent for very large or small numbers. The digits
+/// are the shortest which read back as the same number, so 0.1 is not shown as
+/// 0.10000000000000001.
+pub fn format_float(f: f64) -> String {
+    match Decimal::parse(format!("{:e}", f).as_bytes()) {
+        Some(d) => d.format(),
+        // Infinity and NaN.
+        None => f.to_string(),
+    }
+}
+
+
+/// Add two numbers given as decimal strings, as INCRBYFLOAT does, and format the result like
+/// `format_float`. Redis adds long doubles, whose extra precision hides the rounding error of
+/// sums like 0.1 + 0.2, so the digits are added exactly here instead. Returns None if the
+/// numbers are not plain decimals, or have too many digits to add exactly.
+pub fn add_decimal_strings(a: &[u8], b: &[u8]) -> Option<String> {
+    Decimal::parse(a)?.checked_add(Decimal::parse(b)?).map(Decimal::format)
+}
+
+
+/// The most significant digits in a formatted floating point number.
+const FLOAT_DIGITS: u32 = 17;
+
+
+/// A decimal number, `mantissa * 10^exponent`.
+#[derive(Debug, Clone, Copy)]
+struct Decimal {
+    negative: bool,
+    mantissa: u128,
+    exponent: i32,
+}
+
+
+impl Decimal {
+    /// Parse a number like `-1.25` or `3e-7`.
+    fn parse(s: &[u8]) -> Option<Self> {
+        let (negative, s) = match s.first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+
+        let (s, exponent) = match s.iter().position(|c| matches!(c, b'e' | b'E')) {
+            Some(i) => (&s[..i], std::str::from_utf8(&s[i + 1..]).ok()?.parse::<i32>().ok().filter(|e| e.abs() <= 10_000)?),
+            None => (s, 0),
+        };
+
+        let (integer, fraction) = match s.iter().position(|c| *c == b'.') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, &s[s.len()..]),
+        };
+
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+
+        let mut mantissa: u128 = 0;
+
+        for c in integer.iter().chain(fraction) {
+            if !c.is_ascii_digit() {
+                return None;
+            }
+
+            mantissa = mantissa.checked_mul(10)?.checked_add((c - b'0') as u128)?;
+        }
+
+        let exponent = exponent.checked_sub(i32::try_from(fraction.len()).ok()?)?;
+
+        Some(Decimal { negative, mantissa, exponent })
+    }
+
+    /// Add exactly, or return None if the digits do not fit.
+    fn checked_add(self, other: Self) -> Option<Self> {
+        if self.mantissa == 0 {
+            return Some(other);
+        }
+
+        if other.mantissa == 0 {
+            return Some(self);
+        }
+
+        let exponent = self.exponent.min(other.exponent);
+        let scale = |d: Self| 10u128.checked_pow((d.exponent - exponent) as u32).and_then(|p| d.mantissa.checked_mul(p));
+        let (a, b) = (scale(self)?, scale(other)?);
+
+        let (negative, mantissa) = if self.negative == other.negative {
+            (self.negative, a.checked_add(b)?)
+        } else if a > b {
+            (self.negative, a - b)
+        } else if a < b {
+            (other.negative, b - a)
+        } else {
+            (false, 0)
+        };
+
+        Some(Decimal { negative, mantissa, exponent })
+    }
+
+    /// Format with at most FLOAT_DIGITS significant digits, as `%g` does.
+    fn format(self) -> String {
+        let sign = if self.negative { "-" } else { "" };
+
+        if self.mantissa == 0 {
+            return format!("{}0", sign);
+        }
+
+        let (mut mantissa, mut exponent) = (self.mantissa, self.exponent);
+        let len = mantissa.ilog10() + 1;
+
+        if len > FLOAT_DIGITS {
+            let p = 10u128.pow(len - FLOAT_DIGITS);
+            let (q, r) = (mantissa / p, mantissa % p);
+
+            // Round half away from zero.
+            mantissa = if r >= p - r { q + 1 } else { q };
+            exponent += (len - FLOAT_DIGITS) as i32;
+        }
+
+        while mantissa % 10 == 0 {
+            mantissa /= 10;
+            exponent += 1;
+        }
+
+        let digits = mantissa.to_string();
+        // The exponent in scientific notation, with one digit before the point.
+        let x = exponent + digits.len() as i32 - 1;
+
+        if x < -4 || x >= FLOAT_DIGITS as i32 {
+            let (first, rest) = digits.split_at(1);
+            let point = if rest.is_empty() { "" } else { "." };
+
+            format!("{}{}{}{}e{}{:02}", sign, first, point, rest, if x < 0 { '-' } else { '+' }, x.abs())
+        } else if exponent >= 0 {
+            format!("{}{}{}", sign, digits, "0".repeat(exponent as usize))
+        } else if x >= 0 {
+            let (integer, fraction) = digits.split_at(x as usize + 1);
+            format!("{}{}.{}", sign, integer, fraction)
+        } else {
+            format!("{}0.{}{}", sign, "0".repeat((-x - 1) as usize), digits)
+        }
+    }
+}
+
+
+/// Match a string against a glob style pattern, using the same rules as Redis.
+///
+/// Supported syntax:
+///   * `?` matches any single byte.
+///   * `*` matches any sequence of bytes, including an empty one.
+///   * `[abc]` matches any one of the listed bytes, `[^abc]` any byte not listed, and `[a-z]` a range.
+///   * `\x` matches `x` literally.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let mut p = 0;
+    let mut s = 0;
+
+    // The position in the pattern just after the most recent star, and the position in the
+    // string that star is currently matched up to. Used to backtrack when a match fails.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        let matched = match pattern.get(p) {
+            Some(b'*') => {
+                while pattern.get(p) == Some(&b'*') {
+                    p += 1;
+                }
+
+                if p == pattern.len() {
+                    return true;
+                }
+
+                backtrack = Some((p, s));
+                continue;
+            },
+            Some(b'?') => {
+                p += 1;
+                true
+            },
+            Some(b'[') => match match_class(&pattern[p + 1..], string[s]) {
+                Some((is_match, length)) => {
+                    p += 1 + length;
+                    is_match
+                },
+                None => false,
+            },
+            Some(b'\\') if p + 1 < pattern.len() => {
+                p += 2;
+                pattern[p - 1] == string[s]
+            },
+            Some(c) => {
+                p += 1;
+                *c == string[s]
+            },
+            None => false,
+        };
+
+        if matched {
+            s += 1;
+        } else if let Some((star_p, star_s)) = backtrack {
+            p = star_p;
+            s = star_s + 1;
+            backtrack = Some((star_p, star_s + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+
+/// Match a single byte against a character class, where `class` starts just after the
+/// opening bracket. Returns whether the byte matched and the number of pattern bytes consumed,
+/// including the closing bracket, or None if the class is not terminated.
+fn match_class(class: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 0;
+    let negate = class.first() == Some(&b'^');
+
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+
+    loop {
+        match class.get(i)? {
+            b']' => break,
+            b'\\' if i + 1 < class.len() => {
+                matched |= class[i + 1] == c;
+                i += 2;
+            },
+            start if class.get(i + 1) == Some(&b'-') && class.get(i + 2).is_some_and(|e| *e != b']') => {
+                let (low, high) = (*start.min(&class[i + 2]), *start.max(&class[i + 2]));
+                matched |= low <= c && c <= high;
+                i += 3;
+            },
+            other => {
+                matched |= *other == c;
+                i += 1;
+            },
+        }
+    }
+
+    Some((matched != negate, i + 1))
+}
+
+
+/// Split a line into arguments the way redis.conf and redis-cli do. Arguments are separated by
+/// whitespace and may be quoted. Double quoted arguments support the escapes `\n`, `\r`, `\t`,
+/// `\b`, `\a` and `\xhh`, and single quoted ones only `\'`. A closing quote must be followed
+/// by whitespace or the end of the line. Returns None if the quotes are unbalanced.
+pub fn split_args(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while line.get(i).is_some_and(|c| c.is_ascii_whitespace()) {
+            i += 1;
+        }
+
+        if i == line.len() {
+            return Some(args);
+        }
+
+        let mut arg = Vec::new();
+        let mut quote = None;
+
+        loop {
+            let c = line.get(i).copied();
+
+            match (quote, c) {
+                (None, None) => break,
+                (None, Some(c)) if c.is_ascii_whitespace() => break,
+                (None, Some(c @ (b'"' | b'\''))) => quote = Some(c),
+                (None, Some(c)) => arg.push(c),
+                (Some(_), None) => return None,
+                (Some(q), Some(c)) if c == q => {
+                    // The closing quote has to end the argument.
+                    if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                        return None;
+                    }
+
+                    i += 1;
+                    break;
+                },
+                (Some(b'"'), Some(b'\\')) if i + 1 < line.len() => {
+                    let hex = line.get(i + 2..i + 4)
+                        .and_then(|h| std::str::from_utf8(h).ok())
+                        .and_then(|h| u8::from_str_radix(h, 16).ok());
+
+                    match (line[i + 1], hex) {
+                        (b'x', Some(byte)) => {
+                            arg.push(byte);
+                            i += 2;
+                        },
+                        (b'n', _) => arg.push(b'\n'),
+                        (b'r', _) => arg.push(b'\r'),
+                        (b't', _) => arg.push(b'\t'),
+                        (b'b', _) => arg.push(8),
+                        (b'a', _) => arg.push(7),
+                        (other, _) => arg.push(other),
+                    }
+
+                    i += 1;
+                },
+                (Some(b'\''), Some(b'\\')) if line.get(i + 1) == Some(&b'\'') => {
+                    arg.push(b'\'');
+                    i += 1;
+                },
+                (Some(_), Some(c)) => arg.push(c),
+            }
+
+            i += 1;
+        }
+
+        args.push(arg);
+    }
+}
+
+
+/// Quote a string the way MONITOR and redis-cli show it, so that `split_args` reads it back
+/// as it was.
+pub fn quote(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+
+    for &c in bytes {
+        match c {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            7 => out.push_str("\\a"),
+            8 => out.push_str("\\b"),
+            c if c.is_ascii_graphic() || c == b' ' => out.push(c as char),
+            c => out.push_str(&format!("\\x{:02x}", c)),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::util::{add_decimal_strings, format_float, from_decimal_bytes, glob_match, quote, split_args};
+
+    #[test]
+    fn test_decimal_negative() {
+        assert_eq!(from_decimal_bytes(b"-42"), Ok(-42));
+        assert_eq!(from_decimal_bytes(b"-9223372036854775808"), Ok(i64::MIN));
+        assert_eq!(from_decimal_bytes(b"9223372036854775808"), Err(()));
+        assert_eq!(from_decimal_bytes(b"-0"), Err(()));
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(0.1), "0.1");
+        assert_eq!(format_float(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format_float(-2.5), "-2.5");
+        assert_eq!(format_float(3.0), "3");
+        assert_eq!(format_float(1e16), "10000000000000000");
+        assert_eq!(format_float(1e17), "1e+17");
+        assert_eq!(format_float(1e300), "1e+300");
+        assert_eq!(format_float(1.5e-5), "1.5e-05");
+        assert_eq!(format_float(0.0001), "0.0001");
+        assert_eq!(format_float(13.361389338970184), "13.361389338970184");
+    }
+
+    #[test]
+    fn test_add_decimal_strings() {
+        let add = |a: &str, b: &str| add_decimal_strings(a.as_bytes(), b.as_bytes());
+
+        assert_eq!(add("0.1", "0.2").as_deref(), Some("0.3"));
+        assert_eq!(add("10.50", "0.1").as_deref(), Some("10.6"));
+        assert_eq!(add("5.0e3", "-5000").as_deref(), Some("0"));
+        assert_eq!(add("-1", "0.25").as_deref(), Some("-0.75"));
+        assert_eq!(add("1e300", "0").as_deref(), Some("1e+300"));
+        // Rounded to 17 significant digits.
+        assert_eq!(add("1", "0.000000000000000001").as_deref(), Some("1"));
+        assert_eq!(add("0.99999999999999999999", "0").as_deref(), Some("1"));
+        assert_eq!(add("1e300", "1e-300"), None);
+        assert_eq!(add("inf", "1"), None);
+    }
+
+    #[test]
+    fn test_glob_literal() {
+        assert!(glob_match(b"hello", b"hello"));
+        assert!(!glob_match(b"hello", b"hell"));
+    }
+
+    #[test]
+    fn test_glob_star() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"h*o", b"hello"));
+        assert!(glob_match(b"user:*:name", b"user:1000:name"));
+        assert!(!glob_match(b"h*x", b"hello"));
+    }
+
+    #[test]
+    fn test_glob_question_mark() {
+        assert!(glob_match(b"h?llo", b"hallo"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+    }
+
+    #[test]
+    fn test_glob_class() {
+        assert!(glob_match(b"h[ae]llo", b"hello"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(!glob_match(b"h[a-b]llo", b"hcllo"));
+    }
+
+    #[test]
+    fn test_glob_escape() {
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(split_args(b"  set a  b "), Some(vec![b"set".to_vec(), b"a".to_vec(), b"b".to_vec()]));
+        assert_eq!(split_args(br#"echo "a b\n\x41" 'c\'d'"#), Some(vec![b"echo".to_vec(), b"a b\nA".to_vec(), b"c'd".to_vec()]));
+        assert_eq!(split_args(b"echo \"\""), Some(vec![b"echo".to_vec(), vec![]]));
+        assert_eq!(split_args(b"echo \"a"), None);
+        assert_eq!(split_args(b"echo \"a\"b"), None);
+        assert_eq!(split_args(b""), Some(vec![]));
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote(b"set"), "\"set\"");
+        assert_eq!(quote(b"a \"b\"\n\x01\\"), r#""a \"b\"\n\x01\\""#);
+
+        let value = b"\x00\xff\t\x07\x08 '\"";
+        assert_eq!(split_args(quote(value).as_bytes()), Some(vec![value.to_vec()]));
+    }
+}
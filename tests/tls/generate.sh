@@ -0,0 +1,33 @@
+#!/bin/sh
+# Generate the certificates the TLS tests use: a CA, a server certificate for 127.0.0.1
+# and localhost, a client certificate for the user "alice" signed by the CA, and a
+# self-signed client certificate which the CA knows nothing about.
+set -e
+cd "$(dirname "$0")"
+
+key() {
+    openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 -out "$1.key"
+}
+
+key ca
+openssl req -x509 -new -key ca.key -out ca.crt -days 36500 -subj "/CN=sider test CA" \
+    -addext "basicConstraints=critical,CA:TRUE" -addext "keyUsage=critical,keyCertSign,cRLSign"
+
+sign() {
+    name=$1 subject=$2 extensions=$3
+    key "$name"
+    openssl req -new -key "$name.key" -subj "$subject" -out "$name.csr"
+    printf '%s\n' "$extensions" > "$name.ext"
+    openssl x509 -req -in "$name.csr" -CA ca.crt -CAkey ca.key -CAcreateserial -days 36500 -extfile "$name.ext" -out "$name.crt"
+    rm "$name.csr" "$name.ext"
+}
+
+sign server "/CN=sider test server" "subjectAltName=IP:127.0.0.1,DNS:localhost
+extendedKeyUsage=serverAuth"
+sign client "/O=sider/CN=alice" "extendedKeyUsage=clientAuth"
+
+key other
+openssl req -x509 -new -key other.key -out other.crt -days 36500 -subj "/CN=mallory" \
+    -addext "basicConstraints=critical,CA:FALSE" -addext "extendedKeyUsage=clientAuth"
+
+rm -f ca.srl ca.key